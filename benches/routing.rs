@@ -0,0 +1,111 @@
+//! Routing hot-path benchmarks
+//!
+//! Run with `cargo bench`. To compare a refactor against the current tree:
+//! `cargo bench -- --save-baseline before`, apply the change, then
+//! `cargo bench -- --baseline before`.
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+
+use mcp_citadel::bench::{echo_server_config, mock_echo_config, ECHO_SERVER};
+use mcp_citadel::config::{RouterConfig, ServerConfig};
+use mcp_citadel::router::message::{splice, Envelope};
+use mcp_citadel::router::{extract_server_name, HubManager};
+use mcp_citadel::transport::durable::EventIds;
+use mcp_citadel::transport::http::{HttpSession, SessionMap};
+
+const TOOLS_CALL: &[u8] = br#"{"jsonrpc":"2.0","id":42,"method":"tools/call","params":{"server":"github","name":"search_repositories","arguments":{"query":"mcp","per_page":10}}}"#;
+
+fn server_name_extraction(c: &mut Criterion) {
+    c.bench_function("extract_server_name", |b| {
+        b.iter(|| extract_server_name(black_box(TOOLS_CALL)))
+    });
+}
+
+fn envelope_inspection(c: &mut Criterion) {
+    c.bench_function("envelope_splice_id", |b| {
+        b.iter(|| {
+            let message = black_box(TOOLS_CALL);
+            let envelope = Envelope::parse(message).unwrap();
+            let span = envelope.id_span(message).unwrap();
+            splice(message, span, b"1234")
+        })
+    });
+}
+
+fn route_message(c: &mut Criterion) {
+    // Through an echo process, and through the in-hub mock (routing alone)
+    for (group, config) in [
+        ("route_message", echo_server_config(env!("CARGO_BIN_EXE_mcp-citadel"))),
+        ("route_message_mock", mock_echo_config()),
+    ] {
+        route_message_to(c, group, config);
+    }
+}
+
+fn route_message_to(c: &mut Criterion, group: &str, config: ServerConfig) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let manager = runtime
+        .block_on(HubManager::new(vec![config], RouterConfig::default()))
+        .unwrap();
+
+    let mut group = c.benchmark_group(group);
+    for size in [64, 4096, 65536] {
+        let message = Bytes::from(format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"bench/echo\",\"params\":{{\"server\":\"{}\",\"payload\":\"{}\"}}}}\n",
+            ECHO_SERVER,
+            "x".repeat(size)
+        ));
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &message, |b, message| {
+            b.to_async(&runtime)
+                .iter(|| manager.route_message(ECHO_SERVER, message.clone()))
+        });
+    }
+    group.finish();
+
+    runtime.block_on(manager.stop_all()).unwrap();
+}
+
+fn sse_buffering(c: &mut Criterion) {
+    let data = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}".to_string();
+    let replay = HttpSession::new(Arc::new(EventIds::in_memory())).replay();
+
+    c.bench_function("sse_buffer_message", |b| {
+        b.iter(|| {
+            let event_id = replay.next_event_id();
+            replay.push(event_id, None, data.clone(), 100, usize::MAX);
+        })
+    });
+}
+
+fn session_lookup(c: &mut Criterion) {
+    let sessions = SessionMap::new();
+    let event_ids = Arc::new(EventIds::in_memory());
+    for _ in 0..1000 {
+        let session = HttpSession::new(Arc::clone(&event_ids));
+        sessions.insert(session.id().to_string(), session);
+    }
+    let ids: Vec<String> = sessions.iter().map(|s| s.key().clone()).collect();
+
+    let mut i = 0;
+    c.bench_function("session_lookup", |b| {
+        b.iter(|| {
+            i = (i + 1) % ids.len();
+            if let Some(mut session) = sessions.get_mut(&ids[i]) {
+                session.touch();
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    server_name_extraction,
+    envelope_inspection,
+    route_message,
+    sse_buffering,
+    session_lookup
+);
+criterion_main!(benches);
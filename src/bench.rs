@@ -0,0 +1,152 @@
+//! Local benchmark harness
+//! Drives a HubManager against a built-in echo backend: an in-hub mock for
+//! `bench --local`, or a process (`mcp-citadel echo-server`) to include stdio
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::config::{MockConfig, RouterConfig, ServerConfig};
+use crate::router::HubManager;
+
+/// Name the echo backend is registered under
+pub const ECHO_SERVER: &str = "echo";
+
+/// Minimal stdio MCP server that answers every request with its own params
+pub async fn run_echo_server() -> Result<()> {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    let mut stdout = tokio::io::stdout();
+    let mut line = String::new();
+
+    while stdin.read_line(&mut line).await? > 0 {
+        if let Ok(request) = serde_json::from_str::<serde_json::Value>(&line) {
+            if let (Some(id), Some(_)) = (request.get("id"), request.get("method")) {
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": request.get("params").cloned().unwrap_or_default(),
+                });
+                let mut bytes = serde_json::to_vec(&response)?;
+                bytes.push(b'\n');
+                stdout.write_all(&bytes).await?;
+                stdout.flush().await?;
+            }
+        }
+        line.clear();
+    }
+
+    Ok(())
+}
+
+/// Server config that runs the echo backend from the given binary
+pub fn echo_server_config(binary: impl Into<String>) -> ServerConfig {
+    ServerConfig {
+        name: ECHO_SERVER.to_string(),
+        command: binary.into(),
+        args: vec!["echo-server".to_string()],
+        env: HashMap::new(),
+        ..Default::default()
+    }
+}
+
+/// Server config for a mock that echoes every request's params, so routing
+/// is measured without a process or pipe in the way
+pub fn mock_echo_config() -> ServerConfig {
+    ServerConfig {
+        name: ECHO_SERVER.to_string(),
+        mock: Some(MockConfig {
+            echo: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Results of a local benchmark run
+#[derive(Debug)]
+pub struct BenchReport {
+    pub requests: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl BenchReport {
+    /// Completed requests per second
+    pub fn throughput(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Route `requests` messages through a HubManager to the mock echo backend
+/// using `concurrency` concurrent callers
+pub async fn run_local(requests: usize, concurrency: usize, payload_size: usize) -> Result<BenchReport> {
+    let manager = Arc::new(HubManager::new(vec![mock_echo_config()], RouterConfig::default()).await?);
+
+    let payload = "x".repeat(payload_size);
+    let next = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+
+    let workers: Vec<_> = (0..concurrency.max(1))
+        .map(|_| {
+            let manager = Arc::clone(&manager);
+            let next = Arc::clone(&next);
+            let payload = payload.clone();
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let mut errors = 0;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= requests {
+                        break;
+                    }
+                    let message = Bytes::from(format!(
+                        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"bench/echo\",\"params\":{{\"server\":\"{}\",\"payload\":\"{}\"}}}}\n",
+                        i, ECHO_SERVER, payload
+                    ));
+                    let sent = Instant::now();
+                    match manager.route_message(ECHO_SERVER, message).await {
+                        Ok(_) => latencies.push(sent.elapsed()),
+                        Err(_) => errors += 1,
+                    }
+                }
+                (latencies, errors)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(requests);
+    let mut errors = 0;
+    for worker in workers {
+        let (worker_latencies, worker_errors) = worker.await.context("Benchmark worker panicked")?;
+        latencies.extend(worker_latencies);
+        errors += worker_errors;
+    }
+    let elapsed = start.elapsed();
+
+    manager.stop_all().await?;
+
+    latencies.sort();
+    let percentile = |p: f64| -> Duration {
+        if latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len()) - 1;
+        latencies[index]
+    };
+
+    Ok(BenchReport {
+        requests: latencies.len(),
+        errors,
+        elapsed,
+        p50: percentile(0.50),
+        p95: percentile(0.95),
+        p99: percentile(0.99),
+    })
+}
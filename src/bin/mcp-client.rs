@@ -6,6 +6,17 @@
 //! Usage:
 //!   mcp-client <server-name>
 //!
+//! Set MCP_CITADEL_WORKSPACE to ask the hub for a specific workspace.
+//!
+//! The adapter lists itself with the hub (see `mcp-citadel clients`) under
+//! the name of the process that launched it, or MCP_CITADEL_CLIENT if set.
+//!
+//! When started inside a project with a `.mcp-citadel.toml` or `.mcp.json`, the
+//! hub also starts that project's servers for this client.
+//!
+//! Speaks the framed (v2) routing protocol, falling back to injecting
+//! `params.server` into each message for hubs that predate it.
+//!
 //! Example in Claude config:
 //!   {
 //!     "mcpServers": {
@@ -17,10 +28,18 @@
 //!   }
 
 use anyhow::{Context, Result};
+use mcp_citadel::control::ControlClient;
+use mcp_citadel::router::clients::ClientInfo;
+use mcp_citadel::router::framed::Hello;
+use mcp_citadel::router::keepalive::{self, Control};
 use std::env;
+use std::path::PathBuf;
 use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::UnixStream;
 
+const HUB_SOCKET: &str = "/tmp/mcp-citadel.sock";
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Get server name from args
@@ -32,13 +51,159 @@ async fn main() -> Result<()> {
     }
     
     let server_name = &args[1];
+    let workspace = env::var("MCP_CITADEL_WORKSPACE").ok();
+    let project = env::current_dir()
+        .ok()
+        .and_then(|dir| mcp_citadel::router::project::discover(&dir));
+    let _registration = register(server_name, workspace.clone()).await;
     
-    // Connect to hub
-    let hub_socket = "/tmp/mcp-citadel.sock";
-    let mut stream = UnixStream::connect(hub_socket)
+    // Prefer the framed protocol, which also delivers the server's notifications
+    if let Some((hub_reader, hub_write)) = negotiate(workspace.clone(), project.clone()).await? {
+        return run_framed(hub_reader, hub_write, server_name).await;
+    }
+    run_inline(connect().await?, server_name, workspace, project).await
+}
+
+/// List this adapter with the hub; kept registered while the returned
+/// connection is open. Best effort: hubs that predate the registry, or whose
+/// control socket belongs to another user, just don't list it
+async fn register(server_name: &str, workspace: Option<String>) -> Option<ControlClient> {
+    let info = ClientInfo {
+        client: env::var("MCP_CITADEL_CLIENT").ok().unwrap_or_else(parent_name),
+        pid: std::process::id(),
+        server: server_name.to_string(),
+        workspace,
+        started_at: chrono::Utc::now().to_rfc3339(),
+    };
+    ControlClient::connect().await.ok()?.register(&info).await.ok()
+}
+
+/// Executable name of the process that launched the adapter
+fn parent_name() -> String {
+    let parent = std::os::unix::process::parent_id().to_string();
+    std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &parent])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| {
+            // A full path on macOS, which may contain spaces
+            let comm = String::from_utf8_lossy(&o.stdout).trim().to_string();
+            let name = std::path::Path::new(&comm).file_name()?.to_string_lossy().into_owned();
+            (!name.is_empty()).then_some(name)
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+async fn connect() -> Result<UnixStream> {
+    UnixStream::connect(HUB_SOCKET)
         .await
-        .context("Failed to connect to MCP Citadel. Is it running?")?;
+        .context("Failed to connect to MCP Citadel. Is it running?")
+}
+
+/// Open a framed (v2) connection; None if the hub predates it
+async fn negotiate(
+    workspace: Option<String>,
+    project: Option<PathBuf>,
+) -> Result<Option<(BufReader<OwnedReadHalf>, OwnedWriteHalf)>> {
+    let (hub_read, mut hub_write) = connect().await?.into_split();
+    let mut hello = serde_json::to_vec(&Hello {
+        keepalive: true,
+        ..Hello::new(workspace, project)
+    })?;
+    hello.push(b'\n');
+    hub_write.write_all(&hello).await?;
+
+    let mut hub_reader = BufReader::new(hub_read);
+    let mut line = Vec::new();
+    hub_reader.read_until(b'\n', &mut line).await?;
+
+    match Hello::parse(&line) {
+        Some(Hello { error: Some(error), .. }) => anyhow::bail!("MCP Citadel refused the connection: {}", error),
+        Some(hello) if hello.protocol >= 2 => Ok(Some((hub_reader, hub_write))),
+        // Older hubs answer the hello with a routing error
+        _ => Ok(None),
+    }
+}
+
+/// Wrap stdin messages in frames for `server_name` and unwrap the hub's frames
+async fn run_framed(
+    mut hub_reader: BufReader<OwnedReadHalf>,
+    mut hub_write: OwnedWriteHalf,
+    server_name: &str,
+) -> Result<()> {
+    let mut stdin_reader = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+    
+    let mut stdin_line = String::new();
+    let mut hub_line = Vec::new();
     
+    loop {
+        tokio::select! {
+            result = stdin_reader.read_line(&mut stdin_line) => {
+                match result {
+                    Ok(0) => break, // EOF
+                    Ok(_) => {
+                        match serde_json::from_str::<serde_json::Value>(&stdin_line) {
+                            Ok(message) => {
+                                // The JSON-RPC id doubles as the frame's rid
+                                let rid = message.get("id").cloned().unwrap_or_default();
+                                let frame = serde_json::json!({ "server": server_name, "rid": rid, "message": message });
+                                hub_write.write_all(format!("{}\n", frame).as_bytes()).await?;
+                                hub_write.flush().await?;
+                            }
+                            Err(e) => eprintln!("Dropping non-JSON input: {}", e),
+                        }
+                        stdin_line.clear();
+                    }
+                    Err(e) => {
+                        eprintln!("stdin error: {}", e);
+                        break;
+                    }
+                }
+            }
+            
+            result = hub_reader.read_until(b'\n', &mut hub_line) => {
+                match result {
+                    Ok(0) => break, // Hub disconnected
+                    Ok(_) => {
+                        if Control::parse(&hub_line) == Some(Control::Ping) {
+                            hub_write.write_all(keepalive::PONG).await?;
+                            hub_line.clear();
+                            continue;
+                        }
+                        let mut frame: serde_json::Value = serde_json::from_slice(&hub_line).unwrap_or_default();
+                        let message = match frame.get_mut("message") {
+                            Some(message) => message.take(),
+                            None => serde_json::json!({
+                                "jsonrpc": "2.0",
+                                "id": frame.get("rid").cloned().unwrap_or_default(),
+                                "error": frame.get("error").cloned().unwrap_or_default(),
+                            }),
+                        };
+                        stdout.write_all(format!("{}\n", message).as_bytes()).await?;
+                        stdout.flush().await?;
+                        hub_line.clear();
+                    }
+                    Err(e) => {
+                        eprintln!("hub error: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// v1 protocol: inject the server name into each message's params
+async fn run_inline(
+    mut stream: UnixStream,
+    server_name: &str,
+    workspace: Option<String>,
+    project: Option<PathBuf>,
+) -> Result<()> {
     let (hub_read, mut hub_write) = stream.split();
     let mut hub_reader = BufReader::new(hub_read);
     
@@ -67,6 +232,12 @@ async fn main() -> Result<()> {
                                 
                                 if let Some(params_obj) = params.as_object_mut() {
                                     params_obj.insert("server".to_string(), serde_json::json!(server_name));
+                                    if let Some(workspace) = &workspace {
+                                        params_obj.insert("workspace".to_string(), serde_json::json!(workspace));
+                                    }
+                                    if let Some(project) = &project {
+                                        params_obj.insert("project".to_string(), serde_json::json!(project));
+                                    }
                                 }
                             }
                             
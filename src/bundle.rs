@@ -0,0 +1,300 @@
+//! Extension bundle import
+//! Unpacks Claude Desktop extension bundles (.mcpb / .dxt) under ~/.mcp-citadel/servers/
+//! and turns their manifest into a Claude config server entry
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Bundle manifest (the fields the hub needs)
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub server: ManifestServer,
+    /// Settings the user supplies at install time
+    #[serde(default)]
+    pub user_config: BTreeMap<String, UserConfigOption>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestServer {
+    pub mcp_config: McpConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Per-platform replacements keyed by `darwin` / `linux` / `win32`
+    #[serde(default)]
+    pub platform_overrides: HashMap<String, McpConfigOverride>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpConfigOverride {
+    pub command: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub env: Option<HashMap<String, String>>,
+}
+
+/// A setting declared in `user_config`
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserConfigOption {
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub default: Option<Value>,
+    /// Accepts several values, passed as separate arguments
+    #[serde(default)]
+    pub multiple: bool,
+}
+
+/// Directory extension bundles are unpacked into (~/.mcp-citadel/servers)
+pub fn servers_dir() -> PathBuf {
+    config::data_dir().join("servers")
+}
+
+/// Server name for a bundle, which is also its directory under servers/
+///
+/// An explicit `name` must be a single path component. Either name has
+/// characters other than ASCII alphanumerics, `-` and `_` replaced by `-`.
+pub fn server_name(manifest: &Manifest, name: Option<&str>) -> Result<String> {
+    if let Some(name) = name {
+        let mut components = Path::new(name).components();
+        if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+            anyhow::bail!("Invalid server name {:?}: must be a single path component", name);
+        }
+    }
+
+    let server_name: String = name
+        .unwrap_or(&manifest.name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    if server_name.is_empty() {
+        anyhow::bail!("Bundle {:?} has no usable server name; pass --name", manifest.name);
+    }
+    Ok(server_name)
+}
+
+/// Read the manifest from a bundle without unpacking it
+pub fn read_manifest(bundle: &Path) -> Result<Manifest> {
+    let file = fs::File::open(bundle).context(format!("Failed to open bundle {:?}", bundle))?;
+    let mut archive = zip::ZipArchive::new(file).context(format!("{:?} is not a valid bundle", bundle))?;
+
+    let mut content = String::new();
+    archive
+        .by_name(MANIFEST_FILE)
+        .context(format!("Bundle {:?} has no {}", bundle, MANIFEST_FILE))?
+        .read_to_string(&mut content)?;
+
+    serde_json::from_str(&content).context("Invalid bundle manifest")
+}
+
+/// Unpack a bundle into `dir`, replacing any previous version
+pub fn unpack(bundle: &Path, dir: &Path) -> Result<()> {
+    let file = fs::File::open(bundle).context(format!("Failed to open bundle {:?}", bundle))?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    if dir.exists() {
+        fs::remove_dir_all(dir).context(format!("Failed to remove previous install at {:?}", dir))?;
+    }
+    fs::create_dir_all(dir)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // Reject entries that would escape the install directory
+        let Some(relative) = entry.enclosed_name() else {
+            anyhow::bail!("Bundle entry {:?} has an unsafe path", entry.name());
+        };
+        let path = dir.join(relative);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = fs::File::create(&path)?;
+        std::io::copy(&mut entry, &mut out)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Names of required settings that have neither a value nor a default
+pub fn missing_settings(manifest: &Manifest, values: &HashMap<String, String>) -> Vec<String> {
+    manifest
+        .user_config
+        .iter()
+        .filter(|(key, option)| option.required && option.default.is_none() && !values.contains_key(*key))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// Build the server entry for a bundle unpacked in `dir`
+///
+/// Substitutes `${__dirname}`, `${user_config.KEY}`, `${HOME}`, and `${/}`
+/// placeholders. An argument that is exactly a `multiple` setting expands into
+/// one argument per comma-separated value.
+pub fn server_entry(manifest: &Manifest, dir: &Path, values: &HashMap<String, String>) -> Value {
+    let mcp_config = &manifest.server.mcp_config;
+    let overrides = mcp_config.platform_overrides.get(current_platform());
+
+    let command = overrides
+        .and_then(|o| o.command.clone())
+        .unwrap_or_else(|| mcp_config.command.clone());
+    let args = overrides
+        .and_then(|o| o.args.clone())
+        .unwrap_or_else(|| mcp_config.args.clone());
+    let env = overrides
+        .and_then(|o| o.env.clone())
+        .unwrap_or_else(|| mcp_config.env.clone());
+
+    let value_of = |key: &str| -> Option<String> {
+        values.get(key).cloned().or_else(|| {
+            manifest.user_config.get(key)?.default.as_ref().map(|d| match d {
+                Value::String(s) => s.clone(),
+                Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map(String::from).unwrap_or_else(|| i.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => other.to_string(),
+            })
+        })
+    };
+
+    let home = dirs::home_dir().unwrap_or_default();
+    let substitute = |text: &str| -> String {
+        let mut out = text
+            .replace("${__dirname}", &dir.to_string_lossy())
+            .replace("${HOME}", &home.to_string_lossy())
+            .replace("${/}", std::path::MAIN_SEPARATOR_STR)
+            .replace("${pathSeparator}", std::path::MAIN_SEPARATOR_STR);
+        for key in manifest.user_config.keys() {
+            let placeholder = format!("${{user_config.{}}}", key);
+            if out.contains(&placeholder) {
+                out = out.replace(&placeholder, &value_of(key).unwrap_or_default());
+            }
+        }
+        out
+    };
+
+    let mut expanded_args = Vec::new();
+    for arg in &args {
+        let multiple = manifest.user_config.iter().find(|(key, option)| {
+            option.multiple && *arg == format!("${{user_config.{}}}", key)
+        });
+        match multiple {
+            Some((key, _)) => expanded_args.extend(
+                value_of(key)
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(String::from),
+            ),
+            None => expanded_args.push(substitute(arg)),
+        }
+    }
+
+    let env: BTreeMap<String, String> = env.iter().map(|(k, v)| (k.clone(), substitute(v))).collect();
+
+    let mut entry = serde_json::json!({
+        "command": substitute(&command),
+        "args": expanded_args,
+    });
+    if !env.is_empty() {
+        entry["env"] = serde_json::json!(env);
+    }
+    entry
+}
+
+fn current_platform() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "darwin",
+        "windows" => "win32",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_entry_substitution() {
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "name": "files",
+            "server": {
+                "type": "node",
+                "mcp_config": {
+                    "command": "node",
+                    "args": ["${__dirname}/server/index.js", "${user_config.dirs}"],
+                    "env": { "API_KEY": "${user_config.api_key}" }
+                }
+            },
+            "user_config": {
+                "api_key": { "type": "string", "required": true, "sensitive": true },
+                "dirs": { "type": "directory", "multiple": true, "default": ["/a", "/b"] }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(missing_settings(&manifest, &HashMap::new()), ["api_key"]);
+
+        let values = HashMap::from([("api_key".to_string(), "secret".to_string())]);
+        let entry = server_entry(&manifest, Path::new("/opt/files"), &values);
+        assert_eq!(entry["args"], serde_json::json!(["/opt/files/server/index.js", "/a", "/b"]));
+        assert_eq!(entry["env"]["API_KEY"], "secret");
+    }
+
+    #[test]
+    fn test_server_name() {
+        let mut manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "name": "@acme/files",
+            "server": { "mcp_config": { "command": "node" } }
+        }))
+        .unwrap();
+
+        assert_eq!(server_name(&manifest, None).unwrap(), "-acme-files");
+        assert_eq!(server_name(&manifest, Some("my files")).unwrap(), "my-files");
+        for name in ["", ".", "..", "a/b", "../../x", "/abs"] {
+            assert!(server_name(&manifest, Some(name)).is_err(), "{:?} was accepted", name);
+        }
+
+        manifest.name = String::new();
+        assert!(server_name(&manifest, None).is_err());
+    }
+}
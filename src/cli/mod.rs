@@ -1,6 +1,8 @@
 //! CLI module for MCP Citadel
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use mcp_citadel::connect::ClientApp;
+use mcp_citadel::export::ToolFormat;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -8,6 +10,14 @@ use std::path::PathBuf;
 #[command(about = "MCP Citadel - Centralized MCP server management", long_about = None)]
 #[command(version)]
 pub struct Cli {
+    /// Hub config file (default: config.toml in the data directory) [env: MCP_CITADEL_CONFIG]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// State directory (default: ~/.mcp-citadel) [env: MCP_CITADEL_DATA_DIR]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -15,38 +25,453 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Start the MCP hub
-    Start {
-        /// Run in foreground (don't daemonize)
+    Start(StartArgs),
+
+    /// Stop the MCP hub
+    Stop,
+
+    /// Show hub status and the state of each server
+    Status {
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List MCP servers (live state when the hub is running)
+    Servers,
+
+    /// Start, stop, or restart one server in the running hub
+    Server {
+        #[command(subcommand)]
+        action: ServerCommands,
+    },
+
+    /// Reload the Claude config into the running hub, restarting only changed servers
+    Reload,
+
+    /// Stream server lifecycle events and backend notifications from the running hub
+    Watch {
+        /// Print raw JSON events
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show recent hub events (server starts, crashes, restarts, new sessions,
+    /// failed requests), or follow them as they happen
+    Events {
+        /// Keep printing events until interrupted
         #[arg(short, long)]
-        foreground: bool,
-        
-        /// Log file path (default: stdout)
+        follow: bool,
+
+        /// Print raw JSON events
         #[arg(long)]
-        log_file: Option<PathBuf>,
-        
-        /// Enable HTTP transport
+        json: bool,
+    },
+
+    /// Show LLM token usage of sampling requests, per server and per session
+    Usage {
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List the mcp-client adapters attached to the running hub
+    Clients {
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show a compact health summary of this hub, or of a remote one
+    Summary {
+        /// Base URL of a remote hub's HTTP transport (e.g. http://host:3000)
+        #[arg(long)]
+        remote: Option<String>,
+
+        /// Admin (or workspace) API key of the remote hub
+        #[arg(long, requires = "remote")]
+        key: Option<String>,
+
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Inspect client sessions and export their transcripts
+    Session {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+
+    /// Check the hub config and that every server's command can be found
+    Doctor,
+
+    /// Remove stale PID files, dead sockets, orphaned servers, expired session
+    /// stores, and old rotated logs left by hubs that didn't shut down cleanly
+    Prune {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Keep rotated logs for this many days
+        #[arg(long, default_value = "14")]
+        log_days: u64,
+    },
+
+    /// Manage what the hub keeps in its data dir
+    State {
+        #[command(subcommand)]
+        command: StateCommands,
+    },
+
+    /// Refuse client requests for a while, keeping the servers running
+    Maintenance {
+        #[command(subcommand)]
+        command: MaintenanceCommands,
+    },
+
+    /// List, approve, or deny tool calls waiting for approval
+    Approvals {
+        #[command(subcommand)]
+        command: ApprovalCommands,
+    },
+
+    /// Show or change the running hub's log filter, like `debug` or
+    /// `info,router=debug,transport=warn` (until it restarts or reloads)
+    LogLevel {
+        /// New filter; shows the current one if omitted
+        level: Option<String>,
+    },
+
+    /// Search the request history for past requests (needs `[router.audit]` enabled)
+    History {
+        /// Only requests to this server
+        #[arg(long)]
+        server: Option<String>,
+
+        /// Only calls of this tool
+        #[arg(long)]
+        tool: Option<String>,
+
+        /// Only requests of this client session (or an id prefix)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Only requests within this long, like 30m, 1h, or 2d
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only requests that errored or failed
+        #[arg(long)]
+        failed: bool,
+
+        /// Show the latest this many
+        #[arg(short = 'n', long, default_value = "100")]
+        limit: usize,
+
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List tools from the cached backend catalogs
+    Tools {
+        /// Only show tools for this server
+        server: Option<String>,
+    },
+
+    /// Point another MCP client's config at the hub for all managed servers
+    Connect {
+        #[arg(value_enum)]
+        client: ClientApp,
+    },
+
+    /// Revert `connect`, restoring the client's previous server entries
+    Disconnect {
+        #[arg(value_enum)]
+        client: ClientApp,
+    },
+
+    /// Export the aggregated tool catalog for agent frameworks
+    ExportTools {
+        /// Output format
+        #[arg(long, value_enum, default_value = "json-schema")]
+        format: ToolFormat,
+
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Only export tools from this server
+        #[arg(long)]
+        server: Option<String>,
+    },
+
+    /// Generate deployment files for the managed servers
+    Export {
+        #[command(subcommand)]
+        target: ExportCommands,
+    },
+
+    /// Install an MCP server from the registry and add it to the Claude config
+    Install {
+        /// Registry server name (full or last segment) or search term
+        name: String,
+
+        /// Register the server under this name (default: last segment of the registry name)
+        #[arg(long = "as")]
+        server_name: Option<String>,
+
+        /// Environment variable for the server (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+
+        /// Registry base URL (default: registry.url in config.toml)
+        #[arg(long)]
+        registry: Option<String>,
+
+        /// Only write the config entry, don't run the package manager
+        #[arg(long)]
+        no_install: bool,
+
+        /// Restart a running hub so the server is loaded immediately
         #[arg(long)]
-        enable_http: bool,
-        
-        /// HTTP port (default: 3000)
+        reload: bool,
+    },
+
+    /// Import a Claude Desktop extension bundle (.mcpb / .dxt) as a managed server
+    Import {
+        /// Path to the bundle
+        bundle: PathBuf,
+
+        /// Register the server under this name (default: the manifest name)
+        #[arg(long = "as")]
+        server_name: Option<String>,
+
+        /// Value for a user_config setting (repeatable)
+        #[arg(long = "set", value_name = "KEY=VALUE")]
+        settings: Vec<String>,
+
+        /// Restart a running hub so the server is loaded immediately
+        #[arg(long)]
+        reload: bool,
+    },
+
+    /// Check npm/PyPI servers for newer versions and upgrade them
+    Update {
+        /// Only update these servers (default: all)
+        servers: Vec<String>,
+
+        /// Only report available updates
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Benchmark the routing path
+    Bench {
+        /// Route through an in-process hub to the built-in echo server
+        #[arg(long)]
+        local: bool,
+
+        /// Total requests to send
+        #[arg(long, default_value = "10000")]
+        requests: usize,
+
+        /// Concurrent callers
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+
+        /// Payload bytes per request
+        #[arg(long, default_value = "256")]
+        payload_size: usize,
+    },
+
+    /// Built-in stdio echo MCP server used by `cargo bench`
+    #[command(hide = true)]
+    EchoServer,
+
+    /// Manage hub configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+}
+
+#[derive(Args)]
+pub struct StartArgs {
+    /// Run in foreground (don't daemonize)
+    #[arg(short, long)]
+    pub foreground: bool,
+
+    /// Log file path (default: stdout)
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Enable HTTP transport
+    #[arg(long)]
+    pub enable_http: bool,
+
+    /// HTTP port (default: 3000)
+    #[arg(long, default_value = "3000")]
+    pub http_port: u16,
+
+    /// HTTP host (default: 127.0.0.1)
+    #[arg(long, default_value = "127.0.0.1")]
+    pub http_host: String,
+
+    /// Refuse calls to destructive tools on every server (see `readOnly`)
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Message buffer size per session (default: 100)
+    #[arg(long, default_value = "100")]
+    pub message_buffer_size: usize,
+
+    /// Run as a container/Kubernetes workload: foreground, JSON logs on stdout,
+    /// no PID file, state in /data unless --data-dir says otherwise, and HTTP
+    /// (with /healthz and /readyz) on 0.0.0.0 unless --enable-http sets the address
+    #[arg(long)]
+    pub cloud: bool,
+
+    /// Seconds to let open HTTP requests finish after SIGTERM (keep below
+    /// terminationGracePeriodSeconds)
+    #[arg(long, default_value = "25")]
+    pub drain_timeout: u64,
+}
+
+#[derive(Subcommand)]
+pub enum ServerCommands {
+    /// Start a stopped server
+    Start { name: String },
+
+    /// Stop a server (it stays stopped until started again)
+    Stop { name: String },
+
+    /// Restart a server
+    Restart { name: String },
+
+    /// Record every message to and from a server in ~/.mcp-citadel/debug/<name>.log
+    Debug {
+        name: String,
+
+        /// Stop recording
+        #[arg(long)]
+        off: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum StateCommands {
+    /// Remove config history, rotated logs and stale catalog caches past the
+    /// `[state]` retention limits
+    Compact {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Override `state.max_age_days`
+        #[arg(long)]
+        max_age_days: Option<u64>,
+
+        /// Override `state.max_size_mb`
+        #[arg(long)]
+        max_size_mb: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MaintenanceCommands {
+    /// Answer new client requests with a maintenance error until the window ends
+    On {
+        /// How long, like 90s, 10m or 1h (traffic resumes by itself after)
+        #[arg(long)]
+        duration: String,
+
+        /// Told to clients in the error and notice
+        #[arg(long)]
+        reason: Option<String>,
+    },
+
+    /// End the maintenance window now
+    Off,
+
+    /// Show whether a maintenance window is active
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum ApprovalCommands {
+    /// List the held tool calls
+    List {
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Let a held call through to its server
+    Approve { id: u64 },
+
+    /// Refuse a held call
+    Deny {
+        id: u64,
+
+        /// Told to the client in the error
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommands {
+    /// List live and recently closed client sessions
+    List {
+        /// Print raw JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Bundle a session's transcript, related hub logs, its servers' stderr
+    /// and the config (secrets redacted) into a zip for bug reports
+    Export {
+        /// Session id, or a unique prefix of one
+        id: String,
+
+        /// Archive path (default: mcp-citadel-session-<id>.zip)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Hub log to take related lines from (default: ~/.mcp-citadel/hub.log)
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExportCommands {
+    /// docker-compose.yml running each npx/uvx server plus the hub with HTTP enabled
+    Compose {
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Port the hub's HTTP transport is published on
         #[arg(long, default_value = "3000")]
         http_port: u16,
-        
-        /// HTTP host (default: 127.0.0.1)
-        #[arg(long, default_value = "127.0.0.1")]
-        http_host: String,
-        
-        /// Message buffer size per session (default: 100)
-        #[arg(long, default_value = "100")]
-        message_buffer_size: usize,
     },
+}
 
-    /// Stop the MCP hub
-    Stop,
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Edit ~/.mcp-citadel/config.toml in $EDITOR (snapshots first)
+    Edit,
 
-    /// Show hub status
-    Status,
+    /// List saved configuration snapshots
+    History,
 
-    /// List configured MCP servers
-    Servers,
+    /// Restore a previous configuration snapshot and reload the hub
+    Rollback {
+        /// Snapshot to restore (1 = most recent)
+        #[arg(default_value = "1")]
+        n: usize,
+    },
 }
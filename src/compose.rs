@@ -0,0 +1,289 @@
+//! docker-compose generation
+//! Runs each containerizable server in its own container with its stdio exposed on a
+//! TCP port (socat), and a hub container that reaches them over the compose network
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::config::ServerConfig;
+
+/// Port every server container listens on
+pub const SERVER_PORT: u16 = 9000;
+
+/// Image the hub service runs (override with MCP_CITADEL_IMAGE)
+const HUB_IMAGE: &str = "${MCP_CITADEL_IMAGE:-mcp-citadel:latest}";
+
+/// Where the hub container reads its configuration
+const HUB_CONFIG_TARGET: &str = "/etc/mcp-citadel/config.toml";
+const CLAUDE_CONFIG_TARGET: &str = "/etc/mcp-citadel/claude.json";
+
+/// Generated compose file
+#[derive(Debug)]
+pub struct Compose {
+    pub yaml: String,
+    /// Servers given their own service
+    pub services: Vec<String>,
+    /// Servers left out because their runtime has no known image
+    pub skipped: Vec<String>,
+}
+
+/// Base image for a server's launcher (`npx` and `uvx` servers only)
+fn runtime_image(command: &str) -> Option<&'static str> {
+    let program = std::path::Path::new(command).file_name()?.to_str()?;
+    match program {
+        "npx" => Some("node:22-alpine"),
+        "uvx" => Some("ghcr.io/astral-sh/uv:python3.12-alpine"),
+        _ => None,
+    }
+}
+
+/// Build a docker-compose.yml for `servers` with the hub's HTTP transport on `http_port`
+pub fn render(servers: &[ServerConfig], http_port: u16) -> Compose {
+    let mut servers: Vec<&ServerConfig> = servers.iter().collect();
+    servers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut services = Map::new();
+    let mut configs = Map::new();
+    let mut hub_servers = Map::new();
+    let mut depends_on = Map::new();
+    let mut included = Vec::new();
+    let mut skipped = Vec::new();
+
+    for server in servers {
+        let Some(image) = runtime_image(&server.command) else {
+            skipped.push(server.name.clone());
+            continue;
+        };
+        let service = service_name(&server.name);
+
+        // The server's command line runs from a script so socat never has to
+        // parse its arguments
+        let script_config = format!("{}-command", service);
+        let script = format!(
+            "#!/bin/sh\nexec {}\n",
+            std::iter::once(&server.command)
+                .chain(&server.args)
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        configs.insert(script_config.clone(), serde_json::json!({ "content": escape(&script) }));
+
+        let environment: BTreeMap<&String, String> = server.env.iter().map(|(k, v)| (k, escape(v))).collect();
+
+        let mut definition = serde_json::json!({
+            "image": image,
+            "command": [
+                "sh",
+                "-c",
+                format!(
+                    "apk add --no-cache socat >/dev/null && exec socat TCP-LISTEN:{},reuseaddr,fork EXEC:'sh /mcp-server.sh'",
+                    SERVER_PORT
+                ),
+            ],
+            "configs": [{ "source": script_config, "target": "/mcp-server.sh" }],
+            "healthcheck": {
+                "test": ["CMD", "nc", "-z", "127.0.0.1", SERVER_PORT.to_string()],
+                "interval": "5s",
+                "retries": 30,
+            },
+            "restart": "unless-stopped",
+        });
+        if !environment.is_empty() {
+            definition["environment"] = serde_json::json!(environment);
+        }
+        services.insert(service.clone(), definition);
+
+        hub_servers.insert(
+            server.name.clone(),
+            serde_json::json!({
+                "command": "socat",
+                "args": ["-", format!("TCP:{}:{}", service, SERVER_PORT)],
+            }),
+        );
+        depends_on.insert(service, serde_json::json!({ "condition": "service_healthy" }));
+        included.push(server.name.clone());
+    }
+
+    let claude_config = serde_json::json!({ "mcpServers": hub_servers });
+    configs.insert(
+        "claude-config".to_string(),
+        serde_json::json!({
+            "content": escape(&(serde_json::to_string_pretty(&claude_config).unwrap_or_default() + "\n")),
+        }),
+    );
+    configs.insert(
+        "hub-config".to_string(),
+        serde_json::json!({ "content": format!("claude_config_path = \"{}\"\n", CLAUDE_CONFIG_TARGET) }),
+    );
+
+    let mut hub = serde_json::json!({
+        "image": HUB_IMAGE,
+        "command": [
+            "mcp-citadel", "--config", HUB_CONFIG_TARGET, "start", "--cloud",
+            "--enable-http", "--http-host", "0.0.0.0", "--http-port", http_port.to_string(),
+        ],
+        "configs": [
+            { "source": "hub-config", "target": HUB_CONFIG_TARGET },
+            { "source": "claude-config", "target": CLAUDE_CONFIG_TARGET },
+        ],
+        "ports": [format!("{}:{}", http_port, http_port)],
+        "restart": "unless-stopped",
+    });
+    if !depends_on.is_empty() {
+        hub["depends_on"] = Value::Object(depends_on);
+    }
+    services.insert("hub".to_string(), hub);
+
+    let document = serde_json::json!({ "services": services, "configs": configs });
+    let mut yaml = String::from("# Generated by `mcp-citadel export compose`\n");
+    write_yaml(&document, 0, &mut yaml);
+
+    Compose {
+        yaml,
+        services: included,
+        skipped,
+    }
+}
+
+/// Compose service name for a server (lowercase letters, digits, `-` and `_`)
+fn service_name(server: &str) -> String {
+    let name: String = server
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    // "hub" is taken by the hub itself
+    if name == "hub" {
+        "hub-server".to_string()
+    } else {
+        name
+    }
+}
+
+/// Quote an argument for `sh`
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:@=+,".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Stop compose from interpolating `$` in values taken from the Claude config
+fn escape(value: &str) -> String {
+    value.replace('$', "$$")
+}
+
+/// Write `value` as block-style YAML
+///
+/// Strings are emitted as JSON strings (valid YAML double-quoted scalars), or
+/// as literal blocks when they span several lines.
+fn write_yaml(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map {
+                out.push_str(&format!("{}{}:", pad, key));
+                write_nested(item, indent, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                out.push_str(&format!("{}-", pad));
+                match item {
+                    Value::Object(map) if !map.is_empty() => {
+                        // First key shares the line with the dash
+                        let mut nested = String::new();
+                        write_yaml(item, indent + 2, &mut nested);
+                        out.push(' ');
+                        out.push_str(&nested[indent + 2..]);
+                    }
+                    _ => write_nested(item, indent, out),
+                }
+            }
+        }
+        _ => {
+            out.push_str(&pad);
+            out.push_str(&scalar(value));
+            out.push('\n');
+        }
+    }
+}
+
+fn write_nested(item: &Value, indent: usize, out: &mut String) {
+    match item {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_yaml(item, indent + 2, out);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_yaml(item, indent + 2, out);
+        }
+        Value::String(s) if is_literal_block(s) => {
+            out.push_str(" |\n");
+            for line in s.lines() {
+                if !line.is_empty() {
+                    out.push_str(&" ".repeat(indent + 2));
+                    out.push_str(line);
+                }
+                out.push('\n');
+            }
+        }
+        _ => {
+            out.push(' ');
+            out.push_str(&scalar(item));
+            out.push('\n');
+        }
+    }
+}
+
+/// Multi-line text that round-trips through a `|` block unchanged
+fn is_literal_block(s: &str) -> bool {
+    s.ends_with('\n')
+        && !s.ends_with("\n\n")
+        && !s.starts_with([' ', '\t'])
+        && !s.contains(['\r', '\t'])
+        && s.lines().all(|line| line == line.trim_end())
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render_compose() {
+        let server = |name: &str, command: &str, args: &[&str]| ServerConfig {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: HashMap::from([("TOKEN".to_string(), "a$b".to_string())]),
+            ..Default::default()
+        };
+        let compose = render(
+            &[
+                server("Files", "npx", &["-y", "@modelcontextprotocol/server-filesystem", "/my docs"]),
+                server("local", "/usr/bin/python3", &["server.py"]),
+            ],
+            3000,
+        );
+
+        assert_eq!(compose.services, ["Files"]);
+        assert_eq!(compose.skipped, ["local"]);
+        assert!(compose.yaml.contains("  files:\n"));
+        assert!(compose.yaml.contains("exec npx -y @modelcontextprotocol/server-filesystem '/my docs'\n"));
+        assert!(compose.yaml.contains("TOKEN: \"a$$b\""));
+        assert!(compose.yaml.contains("\"TCP:files:9000\""));
+        assert!(compose.yaml.contains("- \"3000:3000\""));
+    }
+}
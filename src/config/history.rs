@@ -0,0 +1,296 @@
+//! Configuration history
+//! Timestamped snapshots of the hub and Claude configs under ~/.mcp-citadel/history/
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use super::{hub_config_path, HubConfig};
+
+/// Number of snapshots kept before the oldest are pruned
+const MAX_SNAPSHOTS: usize = 50;
+
+const HUB_CONFIG_FILE: &str = "config.toml";
+const CLAUDE_CONFIG_FILE: &str = "claude_desktop_config.json";
+const META_FILE: &str = "snapshot.json";
+
+/// Copies of the configs the running hub applied, under the history dir
+const APPLIED_DIR: &str = "applied";
+const APPLIED_META_FILE: &str = "applied.json";
+
+/// Snapshot metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub reason: String,
+    pub timestamp: String,
+    /// Where the Claude config was read from when the snapshot was taken
+    pub claude_config_path: PathBuf,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// History directory (~/.mcp-citadel/history)
+pub fn history_dir() -> PathBuf {
+    super::data_dir().join("history")
+}
+
+/// Save a snapshot of the current configuration before it is changed
+pub fn snapshot(hub_config: &HubConfig, reason: &str) -> Result<Snapshot> {
+    take(&history_dir(), &hub_config_path(), &hub_config.claude_config_path, reason, None)
+}
+
+/// Where the applied Claude config was read from
+#[derive(Serialize, Deserialize)]
+struct Applied {
+    claude_config_path: PathBuf,
+}
+
+/// Remember the configs the hub now runs with, for [`snapshot_applied`]
+pub fn record_applied(hub_config: &HubConfig) -> Result<()> {
+    record(&history_dir(), &hub_config_path(), &hub_config.claude_config_path)
+}
+
+fn record(dir: &Path, hub_config_path: &Path, claude_config_path: &Path) -> Result<()> {
+    let path = dir.join(APPLIED_DIR);
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).context(format!("Failed to create {:?}", path))?;
+    copy_if_exists(hub_config_path, &path.join(HUB_CONFIG_FILE))?;
+    copy_if_exists(claude_config_path, &path.join(CLAUDE_CONFIG_FILE))?;
+    let applied = Applied { claude_config_path: claude_config_path.to_path_buf() };
+    fs::write(path.join(APPLIED_META_FILE), serde_json::to_string_pretty(&applied)?)?;
+    Ok(())
+}
+
+/// Snapshot the configs the hub runs with (as last recorded) before a reload
+/// replaces them with what's on disk now; None if none were recorded or the
+/// newest snapshot already holds them, as after `config edit`
+pub fn snapshot_applied(reason: &str) -> Result<Option<Snapshot>> {
+    take_applied(&history_dir(), reason)
+}
+
+fn take_applied(dir: &Path, reason: &str) -> Result<Option<Snapshot>> {
+    let path = dir.join(APPLIED_DIR);
+    let Ok(meta) = fs::read_to_string(path.join(APPLIED_META_FILE)) else {
+        return Ok(None);
+    };
+    let applied: Applied = serde_json::from_str(&meta)?;
+    let (hub_config, claude_config) = (path.join(HUB_CONFIG_FILE), path.join(CLAUDE_CONFIG_FILE));
+
+    if let Some(newest) = list_in(dir)?.first() {
+        let same = |file: &str, applied: &Path| -> Result<bool> {
+            Ok(read_if_exists(&newest.path.join(file))? == read_if_exists(applied)?)
+        };
+        if newest.claude_config_path == applied.claude_config_path && same(HUB_CONFIG_FILE, &hub_config)? && same(CLAUDE_CONFIG_FILE, &claude_config)? {
+            return Ok(None);
+        }
+    }
+    take_from(dir, &hub_config, &claude_config, &applied.claude_config_path, reason, None).map(Some)
+}
+
+/// Snapshot the configs into `dir`, then prune all but the newest snapshots
+/// (and `protect`)
+fn take(dir: &Path, hub_config_path: &Path, claude_config_path: &Path, reason: &str, protect: Option<&Path>) -> Result<Snapshot> {
+    take_from(dir, hub_config_path, claude_config_path, claude_config_path, reason, protect)
+}
+
+/// [`take`] from copies of the configs: `claude_config` is recorded as read
+/// from `claude_config_path`
+fn take_from(dir: &Path, hub_config: &Path, claude_config: &Path, claude_config_path: &Path, reason: &str, protect: Option<&Path>) -> Result<Snapshot> {
+    let now = chrono::Utc::now();
+    let id = now.format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let path = dir.join(&id);
+    fs::create_dir_all(&path).context(format!("Failed to create snapshot dir {:?}", path))?;
+
+    copy_if_exists(hub_config, &path.join(HUB_CONFIG_FILE))?;
+    copy_if_exists(claude_config, &path.join(CLAUDE_CONFIG_FILE))?;
+
+    let snapshot = Snapshot {
+        id,
+        reason: reason.to_string(),
+        timestamp: now.to_rfc3339(),
+        claude_config_path: claude_config_path.to_path_buf(),
+        path: path.clone(),
+    };
+    fs::write(path.join(META_FILE), serde_json::to_string_pretty(&snapshot)?)?;
+
+    prune(dir, MAX_SNAPSHOTS, protect);
+
+    Ok(snapshot)
+}
+
+/// List snapshots, newest first
+pub fn list() -> Result<Vec<Snapshot>> {
+    list_in(&history_dir())
+}
+
+fn list_in(dir: &Path) -> Result<Vec<Snapshot>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots: Vec<Snapshot> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            let content = fs::read_to_string(path.join(META_FILE)).ok()?;
+            let mut snapshot: Snapshot = serde_json::from_str(&content).ok()?;
+            snapshot.path = path;
+            Some(snapshot)
+        })
+        .collect();
+
+    snapshots.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(snapshots)
+}
+
+/// Restore the n-th most recent snapshot (1 = newest)
+///
+/// The current state is snapshotted first, so a rollback can itself be undone
+/// with `rollback 1`.
+pub fn rollback(hub_config: &HubConfig, n: usize) -> Result<Snapshot> {
+    restore(&history_dir(), &hub_config_path(), hub_config, n)
+}
+
+fn restore(dir: &Path, hub_config_path: &Path, hub_config: &HubConfig, n: usize) -> Result<Snapshot> {
+    let snapshots = list_in(dir)?;
+    let target = n
+        .checked_sub(1)
+        .and_then(|i| snapshots.get(i))
+        .cloned()
+        .context(format!(
+            "No snapshot #{} ({} snapshots available)",
+            n,
+            snapshots.len()
+        ))?;
+
+    // Read before the safety snapshot, whose pruning may reach the target
+    if !target.path.join(META_FILE).exists() {
+        anyhow::bail!("Snapshot {} is gone from {:?}", target.id, target.path);
+    }
+    let saved_hub_config = read_if_exists(&target.path.join(HUB_CONFIG_FILE))?;
+    let saved_claude_config = read_if_exists(&target.path.join(CLAUDE_CONFIG_FILE))?;
+
+    take(
+        dir,
+        hub_config_path,
+        &hub_config.claude_config_path,
+        &format!("rollback to {}", target.id),
+        Some(&target.path),
+    )?;
+
+    // A missing hub config in the snapshot means defaults were in effect
+    match saved_hub_config {
+        Some(content) => fs::write(hub_config_path, content)?,
+        None if hub_config_path.exists() => fs::remove_file(hub_config_path)?,
+        None => {}
+    }
+
+    if let Some(content) = saved_claude_config {
+        fs::write(&target.claude_config_path, content).context(format!(
+            "Failed to restore Claude config at {:?}",
+            target.claude_config_path
+        ))?;
+    }
+
+    Ok(target)
+}
+
+/// Remove all but the newest `keep` snapshots, and never `protect`
+fn prune(dir: &Path, keep: usize, protect: Option<&Path>) {
+    let Ok(snapshots) = list_in(dir) else {
+        return;
+    };
+
+    for snapshot in snapshots.into_iter().skip(keep).filter(|s| Some(s.path.as_path()) != protect) {
+        if let Err(e) = fs::remove_dir_all(&snapshot.path) {
+            warn!("Failed to prune snapshot {}: {}", snapshot.id, e);
+        }
+    }
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(format!("Failed to read {:?}", path)),
+    }
+}
+
+fn copy_if_exists(from: &Path, to: &Path) -> Result<()> {
+    if from.exists() {
+        fs::copy(from, to).context(format!("Failed to snapshot {:?}", from))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_to_oldest_snapshot() {
+        let root = std::env::temp_dir().join(format!("mcp-citadel-history-rollback-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("history");
+        let hub_config_path = root.join("config.toml");
+        let hub_config = HubConfig { claude_config_path: root.join("claude.json"), ..Default::default() };
+
+        for n in 0..MAX_SNAPSHOTS {
+            fs::create_dir_all(&root).unwrap();
+            fs::write(&hub_config_path, format!("log_level = \"{}\"", n)).unwrap();
+            fs::write(&hub_config.claude_config_path, format!("{{\"n\": {}}}", n)).unwrap();
+            take(&dir, &hub_config_path, &hub_config.claude_config_path, "test", None).unwrap();
+            // Snapshot ids are stamped to the millisecond
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(list_in(&dir).unwrap().len(), MAX_SNAPSHOTS);
+
+        let restored = restore(&dir, &hub_config_path, &hub_config, MAX_SNAPSHOTS).unwrap();
+        assert_eq!(fs::read_to_string(&hub_config_path).unwrap(), "log_level = \"0\"");
+        assert_eq!(fs::read_to_string(&hub_config.claude_config_path).unwrap(), "{\"n\": 0}");
+        // The target outlives the prune; the safety snapshot is the newest
+        let snapshots = list_in(&dir).unwrap();
+        assert!(snapshots.iter().any(|s| s.id == restored.id));
+        assert_eq!(snapshots[0].reason, format!("rollback to {}", restored.id));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_reload_snapshot_restores_applied_config() {
+        let root = std::env::temp_dir().join(format!("mcp-citadel-history-reload-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let dir = root.join("history");
+        let hub_config_path = root.join("config.toml");
+        let hub_config = HubConfig { claude_config_path: root.join("claude.json"), ..Default::default() };
+        fs::write(&hub_config_path, "log_level = \"info\"").unwrap();
+        fs::write(&hub_config.claude_config_path, "{}").unwrap();
+
+        // The hub starts with these; nothing to snapshot before it has
+        assert!(take_applied(&dir, "reload").unwrap().is_none());
+        record(&dir, &hub_config_path, &hub_config.claude_config_path).unwrap();
+
+        // Edited by hand and reloaded: the running config is what a rollback restores
+        fs::write(&hub_config_path, "log_level = \"debug\"").unwrap();
+        let snapshot = take_applied(&dir, "reload").unwrap().unwrap();
+        assert_eq!(snapshot.claude_config_path, hub_config.claude_config_path);
+        record(&dir, &hub_config_path, &hub_config.claude_config_path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        restore(&dir, &hub_config_path, &hub_config, 1).unwrap();
+        assert_eq!(fs::read_to_string(&hub_config_path).unwrap(), "log_level = \"info\"");
+
+        // The rollback's safety snapshot, and `config edit`, hold the running config already
+        assert!(take_applied(&dir, "reload").unwrap().is_none());
+        record(&dir, &hub_config_path, &hub_config.claude_config_path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        take(&dir, &hub_config_path, &hub_config.claude_config_path, "config edit", None).unwrap();
+        fs::write(&hub_config_path, "log_level = \"warn\"").unwrap();
+        assert!(take_applied(&dir, "reload").unwrap().is_none());
+        assert_eq!(list_in(&dir).unwrap().len(), 3);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,163 @@
+//! Server lockfile
+//! Pinned package versions for managed servers (~/.mcp-citadel/servers.lock.json)
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use super::ServerConfig;
+use crate::registry::Installer;
+
+/// Package a server was installed from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub installer: Installer,
+    pub package: String,
+    pub version: Option<String>,
+}
+
+impl LockedPackage {
+    /// Package spec pinned to the locked version
+    pub fn spec(&self) -> String {
+        self.installer.spec(&self.package, self.version.as_deref())
+    }
+}
+
+/// A server's package and where its spec sits in the server's args
+#[derive(Debug, Clone)]
+pub struct ManagedPackage {
+    pub package: LockedPackage,
+    /// Index of the package spec in `args` for `npx`/`uvx` servers
+    pub arg_index: Option<usize>,
+}
+
+/// Work out which package a server runs
+///
+/// `npx`/`uvx` servers are recognized from their args; anything else must have
+/// been pinned in the lockfile (e.g. by `mcp-citadel install`).
+pub fn managed_package(config: &ServerConfig, lockfile: &Lockfile) -> Option<ManagedPackage> {
+    let installer = match config.command.as_str() {
+        "npx" => Installer::Npm,
+        "uvx" => Installer::Uv,
+        _ => {
+            return lockfile.get(&config.name).map(|package| ManagedPackage {
+                package: package.clone(),
+                arg_index: None,
+            })
+        }
+    };
+
+    let (index, spec) = config
+        .args
+        .iter()
+        .enumerate()
+        .find(|(_, arg)| !arg.starts_with('-'))?;
+    let (package, version) = split_spec(installer, spec);
+
+    Some(ManagedPackage {
+        package: LockedPackage {
+            installer,
+            package: package.to_string(),
+            version: version.map(String::from),
+        },
+        arg_index: Some(index),
+    })
+}
+
+/// Split `pkg@1.2.3` / `@scope/pkg@1.2.3` / `pkg==1.2.3` into name and version
+fn split_spec(installer: Installer, spec: &str) -> (&str, Option<&str>) {
+    let split = match installer {
+        // Skip the leading '@' of scoped packages
+        Installer::Npm => spec
+            .get(1..)
+            .and_then(|rest| rest.find('@'))
+            .map(|i| (&spec[..i + 1], &spec[i + 2..])),
+        _ => spec.split_once("=="),
+    };
+    match split {
+        Some((package, version)) if !version.is_empty() && version != "latest" => (package, Some(version)),
+        Some((package, _)) => (package, None),
+        None => (spec, None),
+    }
+}
+
+/// Pinned packages keyed by server name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub servers: BTreeMap<String, LockedPackage>,
+}
+
+/// Lockfile path (~/.mcp-citadel/servers.lock.json)
+pub fn lockfile_path() -> PathBuf {
+    super::data_dir().join("servers.lock.json")
+}
+
+impl Lockfile {
+    /// Load the lockfile, or an empty one if none exists yet
+    pub fn load() -> Result<Self> {
+        let path = lockfile_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .context(format!("Failed to read lockfile at {:?}", path))?;
+        serde_json::from_str(&content).context(format!("Failed to parse lockfile at {:?}", path))
+    }
+
+    /// Write the lockfile
+    pub fn save(&self) -> Result<()> {
+        let path = lockfile_path();
+        std::fs::create_dir_all(super::data_dir())?;
+        std::fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .context(format!("Failed to write lockfile at {:?}", path))
+    }
+
+    /// Pinned package for a server
+    pub fn get(&self, server: &str) -> Option<&LockedPackage> {
+        self.servers.get(server)
+    }
+
+    /// Pin a server's package
+    pub fn insert(&mut self, server: &str, package: LockedPackage) {
+        self.servers.insert(server.to_string(), package);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn server(command: &str, args: &[&str]) -> ServerConfig {
+        ServerConfig {
+            name: "test".to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: HashMap::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_managed_package_from_args() {
+        let lockfile = Lockfile::default();
+
+        let managed = managed_package(&server("npx", &["-y", "@acme/fs@1.2.0", "/tmp"]), &lockfile).unwrap();
+        assert_eq!(managed.arg_index, Some(1));
+        assert_eq!(managed.package.package, "@acme/fs");
+        assert_eq!(managed.package.version.as_deref(), Some("1.2.0"));
+
+        let managed = managed_package(&server("npx", &["-y", "@acme/fs"]), &lockfile).unwrap();
+        assert_eq!(managed.package.package, "@acme/fs");
+        assert_eq!(managed.package.version, None);
+
+        let managed = managed_package(&server("uvx", &["mcp-server-fetch==0.6.2"]), &lockfile).unwrap();
+        assert_eq!(managed.package.installer, Installer::Uv);
+        assert_eq!(managed.package.spec(), "mcp-server-fetch==0.6.2");
+
+        assert!(managed_package(&server("python3", &["server.py"]), &lockfile).is_none());
+    }
+}
@@ -1,13 +1,29 @@
 //! Configuration module for MCP Citadel
 //! Loads server configurations from Claude Desktop config
 
+pub mod history;
+pub mod lockfile;
+pub mod resolve;
+pub mod rewrite;
+pub mod schedule;
+pub mod template;
+pub mod tools;
+
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rewrite::RewriteRule;
+use tools::ToolFilter;
+use schedule::RestartSchedule;
+use template::{ServerTemplate, TemplateInstance};
 
 /// Hub configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HubConfig {
     /// Unix socket path for the hub
     pub socket_path: String,
@@ -17,10 +33,352 @@ pub struct HubConfig {
     pub claude_config_path: PathBuf,
     /// HTTP transport configuration (optional)
     pub http: Option<HttpConfig>,
+    /// Routing defaults applied to every server
+    pub router: RouterConfig,
+    /// Tokio runtime sizing
+    pub runtime: RuntimeConfig,
+    /// MCP server registry used by `install`
+    pub registry: RegistryConfig,
+    /// Retention for what accumulates in the data dir
+    pub state: StateConfig,
+    /// Named server subsets, each reachable only by the clients mapped to it
+    pub workspaces: BTreeMap<String, WorkspaceConfig>,
+    /// Parameterized server definitions
+    pub templates: BTreeMap<String, ServerTemplate>,
+    /// Servers instantiated from templates, alongside the Claude config's
+    pub servers: BTreeMap<String, TemplateInstance>,
+    /// Where to post alerts about crashed and unresponsive servers (`[[webhooks]]`)
+    pub webhooks: Vec<WebhookConfig>,
+    /// Rotation of the hub log and the servers' stderr logs
+    pub logging: LoggingConfig,
+    /// Where metrics go besides `/metrics`
+    pub metrics: MetricsConfig,
+}
+
+/// Exporting metrics (`[metrics]`, see `crate::metrics`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub exporter: MetricsExporter,
+    /// UDP address of the StatsD agent
+    pub statsd_addr: String,
+    /// How often metrics are sent to StatsD
+    pub flush_interval_secs: u64,
+    /// Tags added to every StatsD metric (`env:prod`)
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsExporter {
+    /// Only scraped from `/metrics`
+    #[default]
+    Prometheus,
+    /// Also sent to a StatsD agent, with DogStatsD tags
+    Statsd,
+}
+
+/// Rotating log files (`[logging]`, see `crate::logging`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Start a new file each day
+    pub daily: bool,
+    /// Size at which a log is rotated (0: unlimited)
+    pub max_file_mb: u64,
+    /// Rotated files kept per log, oldest removed first
+    pub max_files: usize,
+}
+
+/// A webhook alerted when servers crash, stop responding, or run out of restarts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Shape of the posted body
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Alerts to send (`crashed`, `unresponsive`, `gave_up`); all if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Extra request headers, like `Authorization`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// The alert as JSON
+    #[default]
+    Generic,
+    /// A Slack incoming webhook message
+    Slack,
+    /// A Discord webhook message
+    Discord,
+}
+
+/// A workspace: the servers it exposes and the clients that land in it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// Servers (Claude config names) visible in this workspace
+    pub servers: Vec<String>,
+    /// HTTP API keys (`Authorization: Bearer` or `X-API-Key`) mapped to this workspace
+    pub api_keys: Vec<String>,
+    /// Unix users whose socket connections are mapped to this workspace
+    pub unix_users: Vec<String>,
+    /// Queue priority of this workspace's clients' requests
+    pub priority: Option<Priority>,
+    /// Only allow this workspace's clients to list and read, not call tools
+    pub read_only: bool,
+    /// Names this workspace's clients use for servers (`fs = "filesystem-home"`);
+    /// an alias reaches its target even if `servers` doesn't list it
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// Server registry configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegistryConfig {
+    /// Base URL of an MCP registry exposing `/v0/servers`
+    pub url: String,
+}
+
+/// Routing configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouterConfig {
+    /// Requests pipelined to one backend at once (overridable per server)
+    pub max_in_flight: usize,
+    /// Read buffer size in bytes for backend stdout and client socket connections
+    pub read_buffer_size: usize,
+    /// Longest line read from a backend; longer messages are discarded and
+    /// the request they answer fails with an error (0: unlimited)
+    pub max_message_bytes: usize,
+    /// How long a backend has to answer a request before the client gets a
+    /// timeout error (overridable per server)
+    pub request_timeout_secs: u64,
+    /// How long a request waits for a lazy server to start before it fails
+    /// (overridable per server; the server keeps starting)
+    pub spawn_timeout_secs: u64,
+    /// Unsolicited backend messages queued before slow subscribers lag
+    pub event_channel_capacity: usize,
+    /// Install a missing runtime or pinned package when a server's command is not found
+    pub auto_provision: bool,
+    /// Start a replacement and switch to it before stopping the old instance
+    /// on restarts (overridable per server)
+    pub warm_restarts: bool,
+    /// Append each server's stderr to `logs/{server}.log` in the data dir
+    /// (overridable per server)
+    pub stderr_logs: bool,
+    /// Largest `tools/call` response passed to clients (overridable per server; unset: unlimited)
+    pub max_response_bytes: Option<usize>,
+    /// What happens to responses over the limit (overridable per server)
+    pub oversized_responses: OversizedResponse,
+    /// Silence after which the hub pings Unix socket clients that opted into
+    /// keepalive; those that stay silent for another interval are disconnected
+    /// (0 disables hub pings)
+    pub client_keepalive_secs: u64,
+    /// Client requests handled across all servers at once (unset: unlimited)
+    pub max_concurrent_requests: Option<usize>,
+    /// How long a request over a concurrency limit waits for a slot before
+    /// it is rejected (0 rejects immediately)
+    pub queue_timeout_ms: u64,
+    /// How long the response to a `tools/call` carrying an idempotency key is
+    /// returned for retries with that key instead of calling the tool again
+    /// (0 disables)
+    pub idempotency_window_secs: u64,
+    /// Queue priority by method (without the `server/` prefix), ahead of the
+    /// client's workspace priority
+    pub method_priorities: BTreeMap<String, Priority>,
+    /// How long a `tools/list`, `resources/list` or `prompts/list` result is
+    /// answered from the catalog cache while its server runs, by method
+    /// (overridable per server; unset: always asked)
+    pub list_cache_ttl_secs: BTreeMap<String, u64>,
+    /// Latest messages kept per client session for `mcp-citadel session
+    /// export` (0 disables transcripts)
+    pub session_transcript_messages: usize,
+    /// Send `citadel/server_restarted` to clients of a backend the hub restarts
+    pub restart_notifications: bool,
+    /// Only allow every client to list and read, not call tools
+    pub read_only: bool,
+    /// Refuse calls to tools annotated as destructive or matching
+    /// `write_tools`, on every server (overridable per server)
+    pub read_only_servers: bool,
+    /// Tools treated as destructive on read-only servers, by name pattern
+    pub write_tools: Vec<String>,
+    /// How long a call to a tool that needs approval waits for a decision
+    /// before it is refused
+    pub approval_timeout_secs: u64,
+    /// Shape what clients are sent to the capabilities their `initialize` declared
+    pub capability_shaping: bool,
+    /// When and how quickly failed servers are restarted
+    pub restarts: RestartConfig,
+    /// Log of every routed request
+    pub audit: AuditConfig,
+    /// Secrets masked in logs, errors, and the audit log
+    pub redaction: RedactionConfig,
+}
+
+/// The audit log (`[router.audit]`, see `router::audit`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// Also record each request's params and response
+    pub payloads: bool,
+    /// Size at which the log is rotated
+    pub max_file_mb: u64,
+    /// Rotated logs kept, oldest removed first
+    pub max_files: usize,
+    /// Days requests are kept in the request history (0: forever)
+    pub history_days: u64,
+}
+
+/// Masking secrets (`[router.redaction]`, see `crate::redact`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Env and header names whose values are secrets, besides the ones that
+    /// look like it (`*TOKEN*`, `*PASSWORD*`, ...)
+    pub keys: Vec<String>,
+    /// Also mask well-known token formats (`ghp_…`, `sk-…`, `Bearer …`)
+    pub patterns: bool,
+}
+
+/// Restarting servers that exit or stop responding (`[router.restarts]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestartConfig {
+    /// Which failures are restarted (overridable per server)
+    pub policy: RestartPolicy,
+    /// Restarts in a row before the hub gives up on a server (overridable per server)
+    pub max_attempts: u32,
+    /// Delay before the first restart, doubled for each further attempt
+    pub backoff_initial_ms: u64,
+    /// Longest delay before a restart
+    pub backoff_max_secs: u64,
+    /// Uptime after which a restarted server's attempts are forgotten
+    pub cooldown_secs: u64,
+    /// How often servers are checked
+    pub check_interval_secs: u64,
+    /// How long a server has to answer the `ping` each check sends it (0: no
+    /// ping; servers with a method `healthCheck` get that instead)
+    pub probe_timeout_ms: u64,
+    /// Pings in a row a server may miss before it's restarted
+    pub probe_failures: u32,
+}
+
+/// Which failures of a server the hub restarts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Any exit, and hung or unhealthy servers
+    #[default]
+    Always,
+    /// Exits with a failure status, and hung or unhealthy servers
+    OnFailure,
+    /// Leave failed servers stopped
+    Never,
+}
+
+/// Whether client sessions share a server's process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Isolation {
+    /// One process serves every client
+    #[default]
+    Shared,
+    /// Each client session gets a process of its own, for servers that keep
+    /// per-conversation state
+    PerSession,
+}
+
+impl RouterConfig {
+    /// How long a server's result for a list method stays fresh
+    pub fn list_cache_ttl(&self, config: &ServerConfig, method: &str) -> std::time::Duration {
+        let secs = config.list_cache_ttl_secs.or_else(|| self.list_cache_ttl_secs.get(method).copied());
+        std::time::Duration::from_secs(secs.unwrap_or(0))
+    }
+
+    /// Fill in per-server settings the server's own config leaves unset
+    pub fn apply_defaults(&self, config: &mut ServerConfig) {
+        config.max_in_flight.get_or_insert(self.max_in_flight);
+        config.read_buffer_size.get_or_insert(self.read_buffer_size);
+        config.max_message_bytes.get_or_insert(self.max_message_bytes);
+        config.request_timeout_secs.get_or_insert(self.request_timeout_secs);
+        config.spawn_timeout_secs.get_or_insert(self.spawn_timeout_secs);
+        config.warm_restart.get_or_insert(self.warm_restarts);
+        config.stderr_log.get_or_insert(self.stderr_logs);
+        config.restart_policy.get_or_insert(self.restarts.policy);
+        config.max_restarts.get_or_insert(self.restarts.max_attempts);
+        if config.max_response_bytes.is_none() {
+            config.max_response_bytes = self.max_response_bytes;
+        }
+        config.oversized_response.get_or_insert(self.oversized_responses);
+    }
+}
+
+/// Handling of tool results over `maxResponseBytes`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedResponse {
+    /// Cut the content down and append a truncation marker
+    #[default]
+    Truncate,
+    /// Replace the response with an error stating its size
+    Reject,
+}
+
+/// How messages to and from a server's stdio are delimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Framing {
+    /// One JSON message per line
+    #[default]
+    Newline,
+    /// LSP-style `Content-Length` headers before each message
+    ContentLength,
+}
+
+/// Where a request waits when a server or the hub is at capacity: higher
+/// classes are let through first, in arrival order within a class
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Batch and background agents
+    Background,
+    #[default]
+    Normal,
+    /// Editors and other clients a person is waiting on
+    Interactive,
+}
+
+/// Tokio runtime configuration (unset fields use tokio's defaults)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Worker threads (default: one per CPU core)
+    pub worker_threads: Option<usize>,
+    /// Maximum threads in the blocking pool (default: 512)
+    pub max_blocking_threads: Option<usize>,
+}
+
+/// Retention for the data dir's history, rotated logs and caches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateConfig {
+    /// Remove entries older than this (0: keep regardless of age)
+    pub max_age_days: u64,
+    /// Total size the compactable state is kept under, oldest removed first (0: unlimited)
+    pub max_size_mb: u64,
+    /// How often the hub compacts in the background (0: only `mcp-citadel state compact`)
+    pub compact_interval_secs: u64,
 }
 
 /// HTTP transport configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HttpConfig {
     /// Enable HTTP transport
     pub enabled: bool,
@@ -32,17 +390,156 @@ pub struct HttpConfig {
     pub session_timeout_secs: u64,
     /// Message buffer size per session
     pub message_buffer_size: usize,
+    /// Events queued per SSE stream before the sender waits
+    pub stream_channel_capacity: usize,
+    /// Bytes of replayable messages kept per session, on top of the message
+    /// count limit
+    pub message_buffer_bytes: usize,
+    /// Largest request body or incoming WebSocket message
+    pub max_request_bytes: usize,
+    /// JSON responses larger than this are sent with chunked transfer
+    /// encoding, and WebSocket clients that asked for it get them in pieces
+    pub chunk_size_bytes: usize,
+    /// Serve the inspector UI at /ui/inspector
+    pub inspector: bool,
+    /// Expose tools as POST /api/{server}/{tool} with an OpenAPI document
+    pub rest_bridge: bool,
+    /// API keys (`Authorization: Bearer` or `X-API-Key`) that open the
+    /// `/admin/*` routes on every server; without any, only a hub bound to
+    /// loopback serves them to clients outside a workspace
+    pub admin_keys: Vec<String>,
 }
 
 impl Default for HubConfig {
     fn default() -> Self {
-        let home = dirs::home_dir().expect("Could not find home directory");
+        // ~/Library/Application Support on macOS, %APPDATA% on Windows,
+        // ~/.config on Linux; the data dir in cloud mode
+        let config_dir = if CLOUD_MODE.load(Ordering::Relaxed) {
+            data_dir()
+        } else {
+            dirs::config_dir().unwrap_or_default().join("Claude")
+        };
         Self {
             socket_path: "/tmp/mcp-citadel.sock".to_string(),
             log_level: "info".to_string(),
-            claude_config_path: home
-                .join("Library/Application Support/Claude/claude_desktop_config.json"),
+            claude_config_path: config_dir.join("claude_desktop_config.json"),
             http: Some(HttpConfig::default()),
+            router: RouterConfig::default(),
+            runtime: RuntimeConfig::default(),
+            registry: RegistryConfig::default(),
+            state: StateConfig::default(),
+            workspaces: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            servers: BTreeMap::new(),
+            webhooks: Vec::new(),
+            logging: LoggingConfig::default(),
+            metrics: MetricsConfig::default(),
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            exporter: MetricsExporter::Prometheus,
+            statsd_addr: "127.0.0.1:8125".to_string(),
+            flush_interval_secs: 10,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            daily: true,
+            max_file_mb: 100,
+            max_files: 7,
+        }
+    }
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            url: crate::registry::DEFAULT_REGISTRY_URL.to_string(),
+        }
+    }
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: 30,
+            max_size_mb: 256,
+            compact_interval_secs: 3600,
+        }
+    }
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 1,
+            read_buffer_size: 8 * 1024,
+            max_message_bytes: 64 * 1024 * 1024,
+            request_timeout_secs: 120,
+            spawn_timeout_secs: 30,
+            event_channel_capacity: 256,
+            auto_provision: false,
+            warm_restarts: false,
+            stderr_logs: false,
+            max_response_bytes: None,
+            oversized_responses: OversizedResponse::Truncate,
+            client_keepalive_secs: 30,
+            max_concurrent_requests: None,
+            queue_timeout_ms: 5000,
+            idempotency_window_secs: 600,
+            method_priorities: BTreeMap::new(),
+            list_cache_ttl_secs: BTreeMap::new(),
+            session_transcript_messages: 200,
+            restart_notifications: true,
+            read_only: false,
+            read_only_servers: false,
+            write_tools: Vec::new(),
+            approval_timeout_secs: 300,
+            capability_shaping: true,
+            restarts: RestartConfig::default(),
+            audit: AuditConfig::default(),
+            redaction: RedactionConfig::default(),
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            payloads: false,
+            max_file_mb: 100,
+            max_files: 10,
+            history_days: 30,
+        }
+    }
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self { keys: Vec::new(), patterns: true }
+    }
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            policy: RestartPolicy::Always,
+            max_attempts: 3,
+            backoff_initial_ms: 1000,
+            backoff_max_secs: 60,
+            cooldown_secs: 300,
+            check_interval_secs: 30,
+            probe_timeout_ms: 5000,
+            probe_failures: 3,
         }
     }
 }
@@ -55,6 +552,13 @@ impl Default for HttpConfig {
             port: 3000,
             session_timeout_secs: 3600, // 1 hour
             message_buffer_size: 100,    // 100 messages per session
+            stream_channel_capacity: 100,
+            message_buffer_bytes: 16 * 1024 * 1024,
+            max_request_bytes: 64 * 1024 * 1024,
+            chunk_size_bytes: 256 * 1024,
+            inspector: true,
+            rest_bridge: false,
+            admin_keys: Vec::new(),
         }
     }
 }
@@ -69,20 +573,444 @@ struct ClaudeConfig {
 /// MCP server definition from Claude config
 #[derive(Debug, Deserialize)]
 struct ServerDefinition {
+    /// `"mock"` for the built-in mock backend, `"http"` for a remote server at
+    /// `url` (implied by `url`), `"sse"` for one on the older HTTP+SSE
+    /// transport, `"websocket"` (implied by a `ws://` url) for one over a
+    /// WebSocket; unset or `"stdio"` runs `command`
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
     command: String,
     #[serde(default)]
     args: Vec<String>,
     #[serde(default)]
     env: HashMap<String, String>,
+    #[serde(default, rename = "maxInFlight")]
+    max_in_flight: Option<usize>,
+    #[serde(default, rename = "maxConcurrentRequests")]
+    max_concurrent_requests: Option<usize>,
+    #[serde(default, rename = "maxRequestsPerSecond", alias = "max_requests_per_second")]
+    max_requests_per_second: Option<f64>,
+    #[serde(default, rename = "maxResponseBytes")]
+    max_response_bytes: Option<usize>,
+    #[serde(default, rename = "maxMessageBytes")]
+    max_message_bytes: Option<usize>,
+    #[serde(default, rename = "requestTimeoutSecs")]
+    request_timeout_secs: Option<u64>,
+    #[serde(default)]
+    framing: Option<Framing>,
+    #[serde(default)]
+    lazy: bool,
+    #[serde(default, rename = "spawnTimeoutSecs")]
+    spawn_timeout_secs: Option<u64>,
+    #[serde(default, rename = "listCacheTtlSecs")]
+    list_cache_ttl_secs: Option<u64>,
+    #[serde(default, rename = "idleTimeoutSecs")]
+    idle_timeout_secs: Option<u64>,
+    #[serde(default, rename = "oversizedResponse")]
+    oversized_response: Option<OversizedResponse>,
+    /// Max age (`"6h"`) or cron expression (`"0 4 * * *"`)
+    #[serde(default, rename = "restartSchedule")]
+    restart_schedule: Option<String>,
+    #[serde(default, rename = "warmRestart")]
+    warm_restart: Option<bool>,
+    #[serde(default, rename = "restartPolicy")]
+    restart_policy: Option<RestartPolicy>,
+    #[serde(default, rename = "maxRestarts")]
+    max_restarts: Option<u32>,
+    #[serde(default, rename = "warmUp")]
+    warm_up: Vec<WarmUpRequest>,
+    #[serde(default)]
+    rewrite: Vec<RewriteRule>,
+    #[serde(default)]
+    tools: Option<ToolsSetting>,
+    #[serde(default, rename = "readOnly")]
+    read_only: Option<bool>,
+    #[serde(default, rename = "healthCheck")]
+    health_check: Option<HealthCheck>,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default, rename = "debugTranscript")]
+    debug_transcript: bool,
+    #[serde(default)]
+    isolation: Isolation,
+    #[serde(default, rename = "stderrLog")]
+    stderr_log: Option<bool>,
+    /// Working directory; `~` is the home directory, and relative paths are
+    /// relative to the config file's directory
+    #[serde(default)]
+    cwd: Option<String>,
+    /// Octal file mode creation mask (`"027"`)
+    #[serde(default)]
+    umask: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    limits: ResourceLimits,
+    #[serde(default, rename = "dependsOn", alias = "depends_on")]
+    depends_on: Vec<String>,
+    /// `"docker"` (or `"podman"`) runs `image` in a container
+    #[serde(default)]
+    runtime: Option<String>,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default, rename = "runtimeArgs")]
+    runtime_args: Vec<String>,
+    /// A remote server's Streamable HTTP endpoint
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// Connection pooling, keep-alive, and `authCommand` of a remote server
+    #[serde(flatten)]
+    upstream: UpstreamConfig,
+    /// `"tcp"` connects to a daemon at `host` (default 127.0.0.1) and `port`
+    #[serde(default)]
+    transport: Option<String>,
+    #[serde(default)]
+    host: Option<String>,
+    #[serde(default)]
+    port: Option<u16>,
+    /// Catalog and replies of a mock server
+    #[serde(flatten)]
+    mock: MockConfig,
+}
+
+/// A server's `tools`: the filter on what clients see, or a mock's tool list
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ToolsSetting {
+    Filter(ToolFilter),
+    Mock(Vec<serde_json::Value>),
+}
+
+/// A server's `limits` on its process (see `router::resources`)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceLimits {
+    /// Memory ceiling of the process and the ones it starts; over it, the
+    /// kernel (with a cgroup) or the health check restarts the server
+    #[serde(default, alias = "memory_mb")]
+    pub memory_mb: Option<u64>,
+    /// CPU time, in percent of one core (200 = two cores; Linux cgroups only)
+    #[serde(default, alias = "cpu_percent")]
+    pub cpu_percent: Option<u32>,
+    /// Open file descriptors (`RLIMIT_NOFILE`; Unix only)
+    #[serde(default, alias = "max_open_files")]
+    pub max_open_files: Option<u64>,
+}
+
+/// A server run in a container (see `router::container`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Container {
+    /// Container CLI: `docker` or `podman`
+    pub runtime: String,
+    pub image: String,
+    /// Extra `run` options, like volumes (`-v`) or `--network`
+    pub run_args: Vec<String>,
+}
+
+/// A server reached over HTTP (see `router::remote`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteServer {
+    pub url: String,
+    /// Sent with every request, like `Authorization`
+    pub headers: HashMap<String, String>,
+    pub transport: RemoteTransport,
+    /// How its connections are pooled, kept alive, and authenticated
+    pub upstream: UpstreamConfig,
+}
+
+/// How a remote server speaks MCP over HTTP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteTransport {
+    /// One endpoint, answering POSTs with JSON or an SSE stream
+    StreamableHttp,
+    /// The older HTTP+SSE transport: a GET event stream that names the
+    /// endpoint to POST to, and carries every answer
+    Sse,
+    /// A WebSocket (`ws://`, `wss://`), a message per frame
+    WebSocket,
+    /// A TCP connection (`tcp://host:port`) carrying JSON lines
+    Tcp,
+}
+
+/// One of a server's `warmUp` requests, sent after the hub initializes a
+/// restarted instance
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WarmUpRequest {
+    pub method: String,
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+}
+
+/// Per-server `healthCheck`, run on every health interval while the process is alive
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum HealthCheck {
+    /// Call an MCP method; an error response or timeout is a failure
+    Method {
+        method: String,
+        #[serde(default)]
+        params: serde_json::Value,
+        #[serde(default, rename = "timeoutSecs")]
+        timeout_secs: Option<u64>,
+    },
+    /// Run a command (with the server's env and working directory); a non-zero exit is a failure
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default, rename = "timeoutSecs")]
+        timeout_secs: Option<u64>,
+    },
+}
+
+/// Per-server `hooks`: commands the hub runs around the server's lifecycle
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hooks {
+    /// Before the process is spawned; a failure aborts the start
+    pub pre_start: Option<HookCommand>,
+    /// Once the process is up
+    pub post_start: Option<HookCommand>,
+    /// Before the process is stopped
+    pub pre_stop: Option<HookCommand>,
+    /// After the process exited unexpectedly
+    pub on_crash: Option<HookCommand>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self == &Hooks::default()
+    }
+}
+
+/// A `"type": "mock"` server's catalog and replies
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MockConfig {
+    /// Tools listed by `tools/list`
+    pub tools: Vec<serde_json::Value>,
+    /// Resources listed by `resources/list`; a `text` or `blob` field is the
+    /// content `resources/read` returns
+    pub resources: Vec<serde_json::Value>,
+    /// Prompts listed by `prompts/list`; a `messages` field is what
+    /// `prompts/get` returns
+    pub prompts: Vec<serde_json::Value>,
+    /// Canned and scripted replies, checked in order
+    pub responses: Vec<MockRule>,
+    /// Answer requests nothing else handles with their params instead of an error
+    pub echo: bool,
+    /// Where to record the requests the mock receives (set by the test harness)
+    #[serde(skip)]
+    pub journal: Option<Journal>,
+}
+
+/// Messages a mock server has received, shared with whoever configured it
+#[derive(Debug, Clone, Default)]
+pub struct Journal(Arc<std::sync::Mutex<Vec<serde_json::Value>>>);
+
+impl Journal {
+    pub fn record(&self, message: serde_json::Value) {
+        self.0.lock().unwrap().push(message);
+    }
+
+    /// Everything recorded so far, oldest first
+    pub fn messages(&self) -> Vec<serde_json::Value> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Journals are equal when they are the same journal
+impl PartialEq for Journal {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Replies to requests for one method (and tool, prompt, or resource URI)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MockRule {
+    pub method: String,
+    /// Tool or prompt name, or resource URI (default: any)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Replies to successive matching requests; the last one repeats
+    pub replies: Vec<MockReply>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MockReply {
+    pub result: Option<serde_json::Value>,
+    /// A JSON-RPC error object, sent instead of a result
+    pub error: Option<serde_json::Value>,
+    pub delay_ms: Option<u64>,
+    /// Notifications (`method` and `params`) sent right away, before the
+    /// reply, like the progress or log messages of a long call; progress
+    /// without a `progressToken` gets the request's
+    pub notifications: Vec<serde_json::Value>,
+    /// Requests to the client (`id`, `method`, `params`) sent right away too,
+    /// like `sampling/createMessage`; the reply doesn't wait for their answers
+    pub requests: Vec<serde_json::Value>,
+}
+
+/// A hook command, run with the server's env and working directory
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HookCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default, rename = "timeoutSecs")]
+    pub timeout_secs: Option<u64>,
 }
 
 /// Processed server configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ServerConfig {
     pub name: String,
     pub command: String,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    /// Requests pipelined to this backend at once (1 = strict lockstep)
+    pub max_in_flight: Option<usize>,
+    /// Client requests handled by this server at once (unset: unlimited)
+    pub max_concurrent_requests: Option<usize>,
+    /// Client requests sent to this server per second, in bursts of up to a
+    /// second's worth (unset: unlimited)
+    pub max_requests_per_second: Option<f64>,
+    /// Largest `tools/call` response passed to clients
+    pub max_response_bytes: Option<usize>,
+    pub oversized_response: Option<OversizedResponse>,
+    /// Backend stdout read buffer size in bytes
+    pub read_buffer_size: Option<usize>,
+    /// Longest message read from the backend (0: unlimited)
+    pub max_message_bytes: Option<usize>,
+    /// How long the backend has to answer a request
+    pub request_timeout_secs: Option<u64>,
+    /// Framing of the server's stdio (unset: newline, unless the server's
+    /// output uses `Content-Length` headers)
+    pub framing: Option<Framing>,
+    /// Start the server for its first request instead of at hub start
+    pub lazy: bool,
+    /// How long a request waits for the lazy server to start
+    pub spawn_timeout_secs: Option<u64>,
+    /// How long its list results are answered from the catalog cache, for
+    /// every list method
+    pub list_cache_ttl_secs: Option<u64>,
+    /// Stop the server after this long without requests; its next request
+    /// starts it again
+    pub idle_timeout_secs: Option<u64>,
+    /// Working directory for the server process (default: the hub's)
+    pub cwd: Option<PathBuf>,
+    /// File mode creation mask for the server process (default: the hub's)
+    pub umask: Option<u32>,
+    /// User to run the server process as, by name or uid (default: the hub's)
+    pub user: Option<String>,
+    /// Group to run the server process as, by name or gid (default: the
+    /// user's primary group)
+    pub group: Option<String>,
+    /// Memory, CPU and open file caps on the server process
+    pub limits: ResourceLimits,
+    /// Run `command` (if any) and `args` in a container of this image
+    /// instead of as a process of the hub's
+    pub container: Option<Container>,
+    /// Served by a remote MCP server instead of a process
+    pub remote: Option<RemoteServer>,
+    /// Servers started, and answering, before this one
+    pub depends_on: Vec<String>,
+    /// Proactively restart the server by age or on a cron schedule
+    pub restart_schedule: Option<RestartSchedule>,
+    /// Restart by starting a standby instance first, so routing never has a gap
+    pub warm_restart: Option<bool>,
+    /// Which failures the health check restarts
+    pub restart_policy: Option<RestartPolicy>,
+    /// Restarts in a row before the health check gives up
+    pub max_restarts: Option<u32>,
+    /// Requests sent to a restarted instance before it takes client traffic
+    pub warm_up: Vec<WarmUpRequest>,
+    /// Edits applied to client requests before they reach the server
+    pub rewrite: Vec<RewriteRule>,
+    /// Tools clients may see and call
+    pub tools: ToolFilter,
+    /// Refuse calls to destructive tools (unset: `router.read_only_servers`)
+    pub read_only: Option<bool>,
+    /// Liveness check beyond the process running
+    pub health_check: Option<HealthCheck>,
+    /// Commands run around the server's lifecycle
+    pub hooks: Hooks,
+    /// Served by the built-in mock backend instead of a process
+    pub mock: Option<MockConfig>,
+    /// Record every message to and from the server in its debug transcript
+    pub debug_transcript: bool,
+    /// Whether client sessions share the server's process
+    pub isolation: Isolation,
+    /// Append the server's stderr to its log file (see `stderr::log_path`)
+    pub stderr_log: Option<bool>,
+}
+
+impl ServerConfig {
+    /// Whether the shared process only starts when a request needs it: lazy
+    /// servers, and per-session ones, whose clients get their own
+    pub fn starts_on_demand(&self) -> bool {
+        self.lazy || self.isolation == Isolation::PerSession
+    }
+}
+
+/// How the hub connects to a remote upstream server
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct UpstreamConfig {
+    /// Idle HTTP connections kept for reuse (default: no limit, 0: a connection per request)
+    #[serde(default, rename = "poolMaxIdlePerHost")]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle HTTP connection is kept (default: 90s)
+    #[serde(default, rename = "poolIdleTimeoutSecs")]
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Interval of TCP keep-alive probes (default: 60s, 0: off)
+    #[serde(default, rename = "tcpKeepaliveSecs")]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Prints a fresh token for `Authorization`, run at connect and after a 401
+    #[serde(default, rename = "authCommand")]
+    pub auth_command: Option<AuthCommand>,
+}
+
+/// A command printing a server's token
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AuthCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default, rename = "timeoutSecs")]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Data directory of `start --cloud` when none is given
+pub const CLOUD_DATA_DIR: &str = "/data";
+
+static CLOUD_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Make no home directory assumptions (`start --cloud`): state defaults to
+/// [`CLOUD_DATA_DIR`] and the Claude config to the data dir
+///
+/// Call before any threads exist, since it may set `MCP_CITADEL_DATA_DIR`.
+pub fn enter_cloud_mode() {
+    if std::env::var_os("MCP_CITADEL_DATA_DIR").is_none() {
+        std::env::set_var("MCP_CITADEL_DATA_DIR", CLOUD_DATA_DIR);
+    }
+    CLOUD_MODE.store(true, Ordering::Relaxed);
+}
+
+/// Hub state directory ($MCP_CITADEL_DATA_DIR, default ~/.mcp-citadel)
+pub fn data_dir() -> PathBuf {
+    match std::env::var_os("MCP_CITADEL_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()
+            .expect("Could not find home directory (set MCP_CITADEL_DATA_DIR)")
+            .join(".mcp-citadel"),
+    }
 }
 
 /// Load Claude Desktop MCP server configurations
@@ -93,25 +1021,213 @@ pub fn load_claude_config(path: &Path) -> Result<Vec<ServerConfig>> {
     let claude_config: ClaudeConfig = serde_json::from_str(&content)
         .context("Failed to parse Claude config JSON")?;
 
-    let configs: Vec<ServerConfig> = claude_config
+    claude_config
         .mcp_servers
         .into_iter()
-        .map(|(name, def)| ServerConfig {
-            name,
-            command: def.command,
-            args: def.args,
-            env: def.env,
+        .map(|(name, mut def)| {
+            for rule in &mut def.rewrite {
+                rule.interpolate(&def.env)
+                    .context(format!("Invalid rewrite rule for server {}", name))?;
+            }
+            let restart_schedule = def
+                .restart_schedule
+                .as_deref()
+                .map(RestartSchedule::parse)
+                .transpose()
+                .context(format!("Invalid restartSchedule for server {}", name))?;
+            let tools = match def.tools {
+                Some(ToolsSetting::Filter(filter)) => filter,
+                Some(ToolsSetting::Mock(tools)) => {
+                    def.mock.tools = tools;
+                    ToolFilter::default()
+                }
+                None => ToolFilter::default(),
+            };
+            let cwd = def.cwd.as_deref().map(|dir| resolve_dir(dir, path.parent()));
+            let umask = def
+                .umask
+                .as_deref()
+                .map(|mask| u32::from_str_radix(mask.trim_start_matches("0o"), 8).ok().filter(|m| *m <= 0o777))
+                .map(|mask| mask.context(format!("Invalid umask for server {} (expected octal, like \"027\")", name)))
+                .transpose()?;
+            let container = match (def.runtime.as_deref(), def.image) {
+                (None, _) => None,
+                (Some(runtime @ ("docker" | "podman")), Some(image)) => Some(Container {
+                    runtime: runtime.to_string(),
+                    image,
+                    run_args: def.runtime_args,
+                }),
+                (Some("docker" | "podman"), None) => anyhow::bail!("Server {} has a container runtime but no image", name),
+                (Some(other), _) => anyhow::bail!("Server {} has unsupported runtime '{}'", name, other),
+            };
+            let url = match (def.transport.as_deref(), def.port) {
+                (None | Some("stdio"), _) => def.url,
+                (Some("tcp"), Some(port)) => Some(format!("tcp://{}:{}", def.host.as_deref().unwrap_or("127.0.0.1"), port)),
+                (Some("tcp"), None) => anyhow::bail!("Server {} has a tcp transport but no port", name),
+                (Some(other), _) => anyhow::bail!("Server {} has unsupported transport '{}'", name, other),
+            };
+            let scheme = url.as_deref().and_then(|url| url.split_once("://")).map(|(scheme, _)| scheme);
+            let transport = match (def.kind.as_deref(), scheme) {
+                (Some("sse"), _) => RemoteTransport::Sse,
+                (Some("websocket"), _) | (None, Some("ws" | "wss")) => RemoteTransport::WebSocket,
+                (None, Some("tcp")) => RemoteTransport::Tcp,
+                _ => RemoteTransport::StreamableHttp,
+            };
+            let remote = match (def.kind.as_deref(), url) {
+                (None | Some("http" | "streamable-http" | "sse" | "websocket"), Some(url)) => Some(RemoteServer { url, headers: def.headers, transport, upstream: def.upstream }),
+                (Some("http" | "streamable-http" | "sse" | "websocket"), None) => anyhow::bail!("Server {} has no url", name),
+                _ => None,
+            };
+            let mock = match def.kind.as_deref() {
+                None | Some("stdio") if def.command.is_empty() && container.is_none() && remote.is_none() => {
+                    anyhow::bail!("Server {} has no command", name)
+                }
+                None | Some("stdio" | "http" | "streamable-http" | "sse" | "websocket") => None,
+                Some("mock") => Some(def.mock),
+                Some(other) => anyhow::bail!("Server {} has unsupported type '{}'", name, other),
+            };
+            Ok(ServerConfig {
+                name,
+                command: def.command,
+                args: def.args,
+                env: def.env,
+                max_in_flight: def.max_in_flight,
+                max_concurrent_requests: def.max_concurrent_requests,
+                max_requests_per_second: def.max_requests_per_second,
+                max_response_bytes: def.max_response_bytes,
+                oversized_response: def.oversized_response,
+                max_message_bytes: def.max_message_bytes,
+                request_timeout_secs: def.request_timeout_secs,
+                framing: def.framing,
+                lazy: def.lazy,
+                spawn_timeout_secs: def.spawn_timeout_secs,
+                list_cache_ttl_secs: def.list_cache_ttl_secs,
+                idle_timeout_secs: def.idle_timeout_secs,
+                restart_schedule,
+                warm_restart: def.warm_restart,
+                restart_policy: def.restart_policy,
+                max_restarts: def.max_restarts,
+                warm_up: def.warm_up,
+                rewrite: def.rewrite,
+                tools,
+                read_only: def.read_only,
+                health_check: def.health_check,
+                hooks: def.hooks,
+                mock,
+                debug_transcript: def.debug_transcript,
+                isolation: def.isolation,
+                stderr_log: def.stderr_log,
+                cwd,
+                umask,
+                user: def.user,
+                group: def.group,
+                limits: def.limits,
+                container,
+                remote,
+                depends_on: def.depends_on,
+                ..Default::default()
+            })
         })
-        .collect();
+        .collect()
+}
 
-    Ok(configs)
+/// A configured directory: `~` expanded, and relative to `base` if relative
+fn resolve_dir(dir: &str, base: Option<&Path>) -> PathBuf {
+    let dir = match dir.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            dirs::home_dir().unwrap_or_default().join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(dir),
+    };
+    match base {
+        Some(base) if dir.is_relative() => base.join(dir),
+        _ => dir,
+    }
 }
 
-/// Load hub configuration
+/// Load every managed server: the Claude config's plus the template instances in the hub config
+pub fn load_servers(hub_config: &HubConfig) -> Result<Vec<ServerConfig>> {
+    let mut servers = load_claude_config(&hub_config.claude_config_path)?;
+    for server in template::instantiate(&hub_config.templates, &hub_config.servers)? {
+        if servers.iter().any(|s| s.name == server.name) {
+            anyhow::bail!("Server {} is defined in both the Claude config and config.toml", server.name);
+        }
+        servers.push(server);
+    }
+    Ok(servers)
+}
+
+/// Add (or replace) a server entry in the Claude config's `mcpServers`
+///
+/// Returns true if an existing entry was replaced. Other content is preserved.
+pub fn add_claude_server(path: &Path, name: &str, entry: serde_json::Value) -> Result<bool> {
+    modify_mcp_servers(path, |servers| Ok(servers.insert(name.to_string(), entry).is_some()))
+}
+
+/// Replace the args of an existing server entry in the Claude config
+pub fn set_claude_server_args(path: &Path, name: &str, args: &[String]) -> Result<()> {
+    modify_mcp_servers(path, |servers| {
+        let entry = servers
+            .get_mut(name)
+            .and_then(|e| e.as_object_mut())
+            .context(format!("Server {} not found in Claude config", name))?;
+        entry.insert("args".to_string(), serde_json::json!(args));
+        Ok(())
+    })
+}
+
+/// Apply `f` to the `mcpServers` map of a Claude-style MCP config and write it back
+///
+/// Claude Desktop, Claude Code, Cursor, and Cline all use this layout.
+pub fn modify_mcp_servers<T>(
+    path: &Path,
+    f: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>) -> Result<T>,
+) -> Result<T> {
+    let mut config: serde_json::Value = if path.exists() {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read MCP config at {:?}", path))?;
+        serde_json::from_str(&content).context(format!("Failed to parse MCP config at {:?}", path))?
+    } else {
+        serde_json::json!({})
+    };
+
+    let servers = config
+        .as_object_mut()
+        .context(format!("MCP config at {:?} is not a JSON object", path))?
+        .entry("mcpServers")
+        .or_insert_with(|| serde_json::json!({}))
+        .as_object_mut()
+        .context("mcpServers is not a JSON object")?;
+    let result = f(servers)?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&config)? + "\n")
+        .context(format!("Failed to write MCP config at {:?}", path))?;
+
+    Ok(result)
+}
+
+/// Hub config file path ($MCP_CITADEL_CONFIG, default config.toml in the data dir)
+pub fn hub_config_path() -> PathBuf {
+    match std::env::var_os("MCP_CITADEL_CONFIG") {
+        Some(path) => PathBuf::from(path),
+        None => data_dir().join("config.toml"),
+    }
+}
+
+/// Load hub configuration, falling back to defaults when no config file exists
 pub fn load_hub_config() -> Result<HubConfig> {
-    // For now, just use defaults
-    // Later: load from ~/.mcp-citadel/config.toml
-    Ok(HubConfig::default())
+    let path = hub_config_path();
+    if !path.exists() {
+        return Ok(HubConfig::default());
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .context(format!("Failed to read hub config at {:?}", path))?;
+
+    toml::from_str(&content).context(format!("Failed to parse hub config at {:?}", path))
 }
 
 #[cfg(test)]
@@ -124,4 +1240,150 @@ mod tests {
         assert_eq!(config.socket_path, "/tmp/mcp-citadel.sock");
         assert_eq!(config.log_level, "info");
     }
+
+    #[test]
+    fn test_partial_hub_config() {
+        let config: HubConfig = toml::from_str("log_level = \"debug\"\n[http]\nport = 8080\n").unwrap();
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.socket_path, "/tmp/mcp-citadel.sock");
+
+        let http = config.http.unwrap();
+        assert_eq!(http.port, 8080);
+        assert_eq!(http.host, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_runtime_tuning_config() {
+        let config: HubConfig =
+            toml::from_str("[runtime]\nworker_threads = 4\n[router]\nread_buffer_size = 65536\n").unwrap();
+        assert_eq!(config.runtime.worker_threads, Some(4));
+        assert_eq!(config.runtime.max_blocking_threads, None);
+        assert_eq!(config.router.read_buffer_size, 65536);
+        assert_eq!(config.router.max_in_flight, 1);
+    }
+
+    #[test]
+    fn test_health_check_definition() {
+        let check: HealthCheck = serde_json::from_str(r#"{"method": "ping"}"#).unwrap();
+        assert!(matches!(check, HealthCheck::Method { ref method, .. } if method == "ping"));
+
+        let check: HealthCheck =
+            serde_json::from_str(r#"{"command": "pg_isready", "args": ["-h", "db"], "timeoutSecs": 5}"#).unwrap();
+        assert_eq!(
+            check,
+            HealthCheck::Command {
+                command: "pg_isready".to_string(),
+                args: vec!["-h".to_string(), "db".to_string()],
+                timeout_secs: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let limits: ResourceLimits = serde_json::from_str(r#"{"memoryMb": 512, "cpu_percent": 50}"#).unwrap();
+        assert_eq!(
+            limits,
+            ResourceLimits {
+                memory_mb: Some(512),
+                cpu_percent: Some(50),
+                max_open_files: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_container_runtime() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-container-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("claude_desktop_config.json");
+        let servers = r#"{"mcpServers": {
+            "github": {"runtime": "docker", "image": "ghcr.io/github/github-mcp-server", "args": ["stdio"], "runtimeArgs": ["--network", "host"]}
+        }}"#;
+        std::fs::write(&path, servers).unwrap();
+        let servers = load_claude_config(&path).unwrap();
+        assert_eq!(
+            servers[0].container,
+            Some(Container {
+                runtime: "docker".to_string(),
+                image: "ghcr.io/github/github-mcp-server".to_string(),
+                run_args: vec!["--network".to_string(), "host".to_string()],
+            })
+        );
+        assert!(servers[0].command.is_empty());
+
+        std::fs::write(&path, r#"{"mcpServers": {"github": {"runtime": "docker"}}}"#).unwrap();
+        assert!(load_claude_config(&path).is_err());
+        std::fs::write(&path, r#"{"mcpServers": {"github": {"runtime": "lxc", "image": "github"}}}"#).unwrap();
+        assert!(load_claude_config(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_remote_servers() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-remotes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("claude_desktop_config.json");
+        let servers = r#"{"mcpServers": {
+            "http": {"url": "https://example.com/mcp"},
+            "sse": {"type": "sse", "url": "https://example.com/sse"},
+            "ws": {"url": "wss://example.com/ws"},
+            "tcp": {"transport": "tcp", "port": 7000}
+        }}"#;
+        std::fs::write(&path, servers).unwrap();
+        let servers = load_claude_config(&path).unwrap();
+        let remote = |name: &str| {
+            let remote = servers.iter().find(|s| s.name == name).unwrap().remote.clone().unwrap();
+            (remote.url, remote.transport)
+        };
+        assert_eq!(remote("http").1, RemoteTransport::StreamableHttp);
+        assert_eq!(remote("sse").1, RemoteTransport::Sse);
+        assert_eq!(remote("ws").1, RemoteTransport::WebSocket);
+        assert_eq!(remote("tcp"), ("tcp://127.0.0.1:7000".to_string(), RemoteTransport::Tcp));
+
+        std::fs::write(&path, r#"{"mcpServers": {"tcp": {"transport": "tcp", "host": "db"}}}"#).unwrap();
+        assert!(load_claude_config(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cwd_and_umask() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("claude_desktop_config.json");
+        let servers = r#"{"mcpServers": {
+            "files": {"command": "fs-mcp", "cwd": "data", "umask": "027"},
+            "notes": {"command": "notes-mcp", "cwd": "/srv/notes"}
+        }}"#;
+        std::fs::write(&path, servers).unwrap();
+        let servers = load_claude_config(&path).unwrap();
+        let files = servers.iter().find(|s| s.name == "files").unwrap();
+        assert_eq!(files.cwd, Some(dir.join("data")));
+        assert_eq!(files.umask, Some(0o027));
+        let notes = servers.iter().find(|s| s.name == "notes").unwrap();
+        assert_eq!((notes.cwd.as_deref(), notes.umask), (Some(Path::new("/srv/notes")), None));
+
+        std::fs::write(&path, r#"{"mcpServers": {"files": {"command": "fs-mcp", "umask": "9"}}}"#).unwrap();
+        assert!(load_claude_config(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_tool_filter_and_mock_tools() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-tools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("claude_desktop_config.json");
+        let servers = r#"{"mcpServers": {
+            "github": {"command": "github-mcp", "tools": {"allow": ["get_*"], "deny": ["get_secret"]}},
+            "mock": {"type": "mock", "tools": [{"name": "echo"}]}
+        }}"#;
+        std::fs::write(&path, servers).unwrap();
+        let servers = load_claude_config(&path).unwrap();
+        let github = servers.iter().find(|s| s.name == "github").unwrap();
+        assert_eq!((github.tools.allow.as_slice(), github.tools.deny.as_slice()), (&["get_*".to_string()][..], &["get_secret".to_string()][..]));
+        let mock = servers.iter().find(|s| s.name == "mock").unwrap();
+        assert!(mock.tools.is_empty());
+        assert_eq!(mock.mock.as_ref().unwrap().tools[0]["name"], "echo");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
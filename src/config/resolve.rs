@@ -0,0 +1,175 @@
+//! Command resolution
+//! Finds server executables (`npx`, `uvx`, `node`, `python`, ...) when the
+//! hub runs with the minimal PATH of launchd or systemd, by also searching the
+//! usual nvm, asdf, Volta, pyenv, uv, Homebrew, and system install locations.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Where executables are looked up, in order
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    dirs: Vec<PathBuf>,
+}
+
+/// A server command located on disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolved {
+    /// Absolute path of the executable
+    pub path: PathBuf,
+    /// PATH for the server: the executable's directory first, so launchers
+    /// like `npx` find the `node` they were installed with
+    pub env_path: OsString,
+}
+
+impl Resolver {
+    /// Search the hub's PATH, then the runtime install locations present on
+    /// this machine; detected once and shared
+    pub fn global() -> &'static Resolver {
+        static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+        RESOLVER.get_or_init(|| {
+            let path = std::env::var_os("PATH").unwrap_or_default();
+            let home = dirs::home_dir().unwrap_or_default();
+            let mut dirs: Vec<PathBuf> = std::env::split_paths(&path).collect();
+            dirs.extend(install_dirs(&home).into_iter().filter(|d| d.is_dir()));
+            Resolver::new(dirs)
+        })
+    }
+
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        let mut unique = Vec::with_capacity(dirs.len());
+        for dir in dirs {
+            if !dir.as_os_str().is_empty() && !unique.contains(&dir) {
+                unique.push(dir);
+            }
+        }
+        Self { dirs: unique }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Locate `command`, trying the server's own PATH (from its `env`) first
+    ///
+    /// Commands containing a path separator are used as given if they exist.
+    pub fn resolve(&self, command: &str, server_path: Option<&str>) -> Option<Resolved> {
+        let path = if Path::new(command).components().count() > 1 {
+            Some(PathBuf::from(command)).filter(|p| is_executable(p))
+        } else {
+            let server_dirs = server_path.map(|p| std::env::split_paths(p).collect::<Vec<_>>()).unwrap_or_default();
+            server_dirs.iter().chain(&self.dirs).find_map(|dir| find_in(dir, command))
+        }?;
+
+        let mut dirs: Vec<PathBuf> = path.parent().map(Path::to_path_buf).into_iter().collect();
+        match server_path {
+            Some(p) => dirs.extend(std::env::split_paths(p)),
+            None => dirs.extend(self.dirs.iter().cloned()),
+        }
+        let env_path = std::env::join_paths(Resolver::new(dirs).dirs).unwrap_or_default();
+        Some(Resolved { path, env_path })
+    }
+}
+
+/// Install locations of node, python, and uv toolchains
+fn install_dirs(home: &Path) -> Vec<PathBuf> {
+    let env_dir = |var: &str, default: PathBuf| std::env::var_os(var).map(PathBuf::from).unwrap_or(default);
+
+    let mut dirs = Vec::new();
+    if cfg!(windows) {
+        if let Some(appdata) = std::env::var_os("APPDATA") {
+            dirs.push(PathBuf::from(appdata).join("npm"));
+        }
+        if let Some(program_files) = std::env::var_os("ProgramFiles") {
+            dirs.push(PathBuf::from(program_files).join("nodejs"));
+        }
+        dirs.push(home.join(".local").join("bin"));
+        dirs.push(home.join(".cargo").join("bin"));
+        dirs.push(home.join(".volta").join("bin"));
+        return dirs;
+    }
+
+    // nvm: newest installed node first
+    let nvm = env_dir("NVM_DIR", home.join(".nvm")).join("versions/node");
+    let mut versions: Vec<PathBuf> = std::fs::read_dir(&nvm)
+        .map(|entries| entries.flatten().map(|e| e.path()).collect())
+        .unwrap_or_default();
+    versions.sort_by_key(|p| std::cmp::Reverse(node_version(p)));
+    dirs.extend(versions.into_iter().map(|v| v.join("bin")));
+
+    dirs.push(env_dir("ASDF_DATA_DIR", home.join(".asdf")).join("shims"));
+    dirs.push(env_dir("VOLTA_HOME", home.join(".volta")).join("bin"));
+    dirs.push(env_dir("PYENV_ROOT", home.join(".pyenv")).join("shims"));
+    // uv's installer and `cargo install uv`
+    dirs.push(home.join(".local/bin"));
+    dirs.push(home.join(".cargo/bin"));
+    // Homebrew (Apple silicon, Intel, Linux)
+    dirs.push(PathBuf::from("/opt/homebrew/bin"));
+    dirs.push(PathBuf::from("/usr/local/bin"));
+    dirs.push(PathBuf::from("/home/linuxbrew/.linuxbrew/bin"));
+    dirs.push(PathBuf::from("/usr/bin"));
+    dirs.push(PathBuf::from("/bin"));
+    dirs
+}
+
+/// `v20.11.1` → (20, 11, 1)
+fn node_version(dir: &Path) -> Vec<u64> {
+    dir.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.trim_start_matches('v').split('.').map(|part| part.parse().unwrap_or(0)).collect())
+        .unwrap_or_default()
+}
+
+fn find_in(dir: &Path, command: &str) -> Option<PathBuf> {
+    if cfg!(windows) && Path::new(command).extension().is_none() {
+        let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+        return pathext
+            .split(';')
+            .map(|ext| dir.join(format!("{}{}", command, ext.to_ascii_lowercase())))
+            .find(|p| is_executable(p));
+    }
+    Some(dir.join(command)).filter(|p| is_executable(p))
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    metadata.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_from_install_dirs() {
+        let root = std::env::temp_dir().join(format!("citadel-resolve-{}", std::process::id()));
+        let node_bin = root.join("versions/node/v20.11.1/bin");
+        std::fs::create_dir_all(&node_bin).unwrap();
+        let npx = node_bin.join(if cfg!(windows) { "npx.cmd" } else { "npx" });
+        std::fs::write(&npx, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&npx, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let resolver = Resolver::new(vec![root.join("empty"), node_bin.clone()]);
+        let resolved = resolver.resolve("npx", None).unwrap();
+        assert_eq!(resolved.path, npx);
+        assert_eq!(std::env::split_paths(&resolved.env_path).next(), Some(node_bin.clone()));
+
+        assert_eq!(resolver.resolve("uvx", None), None);
+        assert_eq!(resolver.resolve(npx.to_str().unwrap(), None).map(|r| r.path), Some(npx));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
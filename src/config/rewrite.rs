@@ -0,0 +1,331 @@
+//! Request and response rewrite rules
+//! Per-server edits applied to client requests before they reach the backend,
+//! or with `"on": "response"` to the backend's responses to them, addressed by
+//! JSON pointer:
+//!
+//! ```json
+//! "rewrite": [
+//!   { "method": "tools/call", "tool": "create_issue", "set": "/params/arguments/owner", "value": "${GITHUB_OWNER}" },
+//!   { "method": "tools/call", "remove": "/params/arguments/debug" },
+//!   { "replace": "/params/arguments/path", "from": "/Users/me/", "to": "/workspace/" },
+//!   { "method": "tools/list", "on": "response", "rename": "/result/tools/0/input_schema", "to": "/result/tools/0/inputSchema" }
+//! ]
+//! ```
+//!
+//! `method` and `tool` always match the request. `${VAR}` in values is
+//! resolved from the server's env, then the hub's.
+
+use anyhow::Result;
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// One rewrite rule from a server's `rewrite` list
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RewriteRule {
+    /// Only requests with this method (without the `server/` prefix)
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Only `tools/call` requests for this tool
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// Whether the rule edits the request or the backend's response to it
+    #[serde(default)]
+    pub on: RewriteTarget,
+    #[serde(flatten)]
+    pub action: RewriteAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewriteTarget {
+    #[default]
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum RewriteAction {
+    /// Set the value at a pointer, creating missing objects on the way
+    Set { set: String, value: Value },
+    /// Remove the value at a pointer
+    Remove { remove: String },
+    /// Replace `from` with `to` in the string (or strings) at a pointer
+    Replace { replace: String, from: String, to: String },
+    /// Move the value at a pointer to another, creating missing objects on the way
+    Rename { rename: String, to: String },
+}
+
+impl RewriteRule {
+    /// Resolve `${VAR}` references in the rule's values
+    pub fn interpolate(&mut self, env: &HashMap<String, String>) -> Result<()> {
+        match &mut self.action {
+            RewriteAction::Set { value, .. } => interpolate_value(value, env),
+            RewriteAction::Replace { from, to, .. } => {
+                *from = interpolate(from, env)?;
+                *to = interpolate(to, env)?;
+                Ok(())
+            }
+            RewriteAction::Remove { .. } | RewriteAction::Rename { .. } => Ok(()),
+        }
+    }
+
+    fn matches(&self, method: &str, message: &Value) -> bool {
+        self.method.as_deref().is_none_or(|m| m == method)
+            && self
+                .tool
+                .as_deref()
+                .is_none_or(|t| message.pointer("/params/name").and_then(Value::as_str) == Some(t))
+    }
+
+    /// Apply the rule, returning whether the message changed
+    fn apply(&self, message: &mut Value) -> bool {
+        match &self.action {
+            RewriteAction::Set { set, value } => match pointer_mut_or_create(message, set) {
+                Some(target) => {
+                    *target = value.clone();
+                    true
+                }
+                None => false,
+            },
+            RewriteAction::Remove { remove } => take(message, remove).is_some(),
+            RewriteAction::Replace { replace, from, to } => {
+                let replace_in = |s: &mut Value| match s {
+                    Value::String(s) if s.contains(from.as_str()) => {
+                        *s = s.replace(from.as_str(), to);
+                        true
+                    }
+                    _ => false,
+                };
+                match message.pointer_mut(replace) {
+                    Some(Value::Array(items)) => items.iter_mut().map(replace_in).fold(false, |a, b| a | b),
+                    Some(value) => replace_in(value),
+                    None => false,
+                }
+            }
+            RewriteAction::Rename { rename, to } => {
+                // Check the destination first, so a rename that can't land leaves the value in place
+                if rename == to || message.pointer(rename).is_none() || pointer_mut_or_create(message, to).is_none() {
+                    return false;
+                }
+                let value = take(message, rename).unwrap_or_default();
+                match pointer_mut_or_create(message, to) {
+                    Some(target) => *target = value,
+                    None => return false,
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Apply a server's request rules to a client message
+///
+/// Responses and messages no rule matches pass through untouched (and uncopied).
+pub fn apply(rules: &[RewriteRule], server: &str, message: Bytes) -> Bytes {
+    if !rules.iter().any(|r| r.on == RewriteTarget::Request) {
+        return message;
+    }
+    let Ok(mut value) = serde_json::from_slice::<Value>(&message) else {
+        return message;
+    };
+    let Some(method) = method(server, &value) else {
+        return message;
+    };
+
+    let mut changed = false;
+    for rule in rules.iter().filter(|r| r.on == RewriteTarget::Request) {
+        if rule.matches(&method, &value) {
+            changed |= rule.apply(&mut value);
+        }
+    }
+    match changed {
+        true => encode(&value),
+        false => message,
+    }
+}
+
+/// The response rules that match a client request, to apply to its response
+/// with [`apply_response`]
+pub fn response_rules<'a>(rules: &'a [RewriteRule], server: &str, request: &[u8]) -> Vec<&'a RewriteRule> {
+    if !rules.iter().any(|r| r.on == RewriteTarget::Response) {
+        return Vec::new();
+    }
+    let Ok(request) = serde_json::from_slice::<Value>(request) else {
+        return Vec::new();
+    };
+    let Some(method) = method(server, &request) else {
+        return Vec::new();
+    };
+    rules
+        .iter()
+        .filter(|r| r.on == RewriteTarget::Response && r.matches(&method, &request))
+        .collect()
+}
+
+/// Apply the response rules of a request to the backend's response
+pub fn apply_response(rules: &[&RewriteRule], response: Bytes) -> Bytes {
+    if rules.is_empty() || response.is_empty() {
+        return response;
+    }
+    let Ok(mut value) = serde_json::from_slice::<Value>(&response) else {
+        return response;
+    };
+    let changed = rules.iter().fold(false, |changed, rule| rule.apply(&mut value) | changed);
+    match changed {
+        true => encode(&value),
+        false => response,
+    }
+}
+
+/// A message's method, without the `server/` prefix
+fn method(server: &str, message: &Value) -> Option<String> {
+    let method = message.get("method").and_then(Value::as_str)?;
+    Some(method.strip_prefix(server).and_then(|m| m.strip_prefix('/')).unwrap_or(method).to_string())
+}
+
+fn encode(message: &Value) -> Bytes {
+    let mut out = serde_json::to_vec(message).unwrap_or_default();
+    out.push(b'\n');
+    Bytes::from(out)
+}
+
+/// Remove and return the value at a pointer
+fn take(message: &mut Value, pointer: &str) -> Option<Value> {
+    let (parent, key) = pointer.rsplit_once('/')?;
+    let key = unescape(key);
+    match message.pointer_mut(parent)? {
+        Value::Object(map) => map.remove(&key),
+        Value::Array(items) => match key.parse::<usize>() {
+            Ok(index) if index < items.len() => Some(items.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Like `Value::pointer_mut`, but creates missing object members along the way
+fn pointer_mut_or_create<'a>(value: &'a mut Value, pointer: &str) -> Option<&'a mut Value> {
+    let mut target = value;
+    for token in pointer.strip_prefix('/')?.split('/') {
+        let token = unescape(token);
+        if target.is_null() {
+            *target = Value::Object(Default::default());
+        }
+        target = match target {
+            Value::Object(map) => map.entry(token).or_insert(Value::Null),
+            Value::Array(items) => items.get_mut(token.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(target)
+}
+
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn interpolate_value(value: &mut Value, env: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(s) => *s = interpolate(s, env)?,
+        Value::Array(items) => items.iter_mut().try_for_each(|v| interpolate_value(v, env))?,
+        Value::Object(map) => map.values_mut().try_for_each(|v| interpolate_value(v, env))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace `${VAR}` with the variable from `env` or the hub's environment
+fn interpolate(text: &str, env: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        let value = env
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+            .ok_or_else(|| anyhow::anyhow!("Environment variable {} is not set", name))?;
+        out.push_str(&rest[..start]);
+        out.push_str(&value);
+        rest = &rest[start + 3 + len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: &str) -> Vec<RewriteRule> {
+        let mut rules: Vec<RewriteRule> = serde_json::from_str(json).unwrap();
+        let env = HashMap::from([("OWNER".to_string(), "acme".to_string())]);
+        for rule in &mut rules {
+            rule.interpolate(&env).unwrap();
+        }
+        rules
+    }
+
+    #[test]
+    fn test_rewrite_tool_call() {
+        let rules = rules(
+            r#"[
+                {"method": "tools/call", "tool": "create_issue", "set": "/params/arguments/owner", "value": "${OWNER}"},
+                {"method": "tools/call", "remove": "/params/arguments/debug"},
+                {"replace": "/params/arguments/paths", "from": "/Users/me/", "to": "/workspace/"}
+            ]"#,
+        );
+        let message = Bytes::from_static(
+            br#"{"jsonrpc":"2.0","id":1,"method":"github/tools/call","params":{"name":"create_issue","arguments":{"debug":true,"paths":["/Users/me/a","/tmp/b"]}}}"#,
+        );
+
+        let rewritten: Value = serde_json::from_slice(&apply(&rules, "github", message)).unwrap();
+        let arguments = &rewritten["params"]["arguments"];
+        assert_eq!(arguments["owner"], "acme");
+        assert!(arguments.get("debug").is_none());
+        assert_eq!(arguments["paths"], serde_json::json!(["/workspace/a", "/tmp/b"]));
+    }
+
+    #[test]
+    fn test_unmatched_passes_through() {
+        let rules = rules(r#"[{"method": "tools/call", "tool": "create_issue", "set": "/params/arguments/owner", "value": 1}]"#);
+        let message = Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"search"}}"#);
+        assert_eq!(apply(&rules, "github", message.clone()), message);
+
+        let mut missing: Vec<RewriteRule> = serde_json::from_str(r#"[{"set": "/x", "value": "${NOPE_NOT_SET}"}]"#).unwrap();
+        assert!(missing[0].interpolate(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_rename_and_response_rules() {
+        let rules = rules(
+            r#"[
+                {"method": "tools/call", "rename": "/params/arguments/q", "to": "/params/arguments/query"},
+                {"method": "tools/list", "on": "response", "rename": "/result/tools/0/input_schema", "to": "/result/tools/0/inputSchema"},
+                {"method": "tools/list", "on": "response", "set": "/result/tools/0/description", "value": "Search"}
+            ]"#,
+        );
+        let call = Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"search","arguments":{"q":"mcp"}}}"#);
+        let rewritten: Value = serde_json::from_slice(&apply(&rules, "github", call.clone())).unwrap();
+        assert_eq!(rewritten["params"]["arguments"], serde_json::json!({ "query": "mcp" }));
+        assert!(response_rules(&rules, "github", &call).is_empty());
+
+        let list = br#"{"jsonrpc":"2.0","id":2,"method":"github/tools/list"}"#;
+        assert_eq!(apply(&rules, "github", Bytes::from_static(list)), Bytes::from_static(list));
+        let matched = response_rules(&rules, "github", list);
+        assert_eq!(matched.len(), 2);
+        let response = Bytes::from_static(br#"{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"search","input_schema":{}}]}}"#);
+        let rewritten: Value = serde_json::from_slice(&apply_response(&matched, response)).unwrap();
+        assert_eq!(rewritten["result"]["tools"][0], serde_json::json!({ "name": "search", "inputSchema": {}, "description": "Search" }));
+
+        // Renaming what isn't there changes nothing
+        let error = Bytes::from_static(br#"{"jsonrpc":"2.0","id":2,"error":{"code":-32601,"message":"nope"}}"#);
+        assert_eq!(apply_response(&matched[..1], error.clone()), error);
+    }
+}
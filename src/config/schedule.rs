@@ -0,0 +1,200 @@
+//! Restart schedules
+//! When a server should be proactively restarted: a maximum process age
+//! (`"6h"`, `"1d12h"`) or a 5-field cron expression in local time
+//! (`"0 4 * * *"`, `"@daily"`)
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, Timelike};
+
+/// Per-server `restartSchedule`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestartSchedule {
+    /// Restart once the process has run this long
+    MaxAge(std::time::Duration),
+    /// Restart at the next matching minute after the process started
+    Cron(Cron),
+}
+
+impl RestartSchedule {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.starts_with('@') || spec.contains(' ') {
+            Ok(Self::Cron(Cron::parse(spec)?))
+        } else {
+            Ok(Self::MaxAge(parse_duration(spec)?))
+        }
+    }
+
+    /// When a process started at `started` is due for a restart
+    pub fn next_restart(&self, started: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::MaxAge(age) => Some(started + Duration::from_std(*age).ok()?),
+            Self::Cron(cron) => cron.next_after(started),
+        }
+    }
+}
+
+/// Parse a duration like `90s`, `30m`, `6h`, `2d`, or `1h30m`
+pub fn parse_duration(spec: &str) -> Result<std::time::Duration> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in spec.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: u64 = digits.parse().context(format!("Invalid duration '{}'", spec))?;
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => anyhow::bail!("Invalid duration '{}': unknown unit '{}' (use s, m, h, d)", spec, c),
+        };
+        total += n * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() || total == 0 {
+        anyhow::bail!("Invalid duration '{}': expected e.g. 30m, 6h, 1d", spec);
+    }
+    Ok(std::time::Duration::from_secs(total))
+}
+
+/// Cron expression: minute hour day-of-month month day-of-week
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Day-of-month and day-of-week both restricted: either may match
+    either_day: bool,
+}
+
+impl Cron {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = match spec {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!("Invalid cron expression '{}': expected 5 fields", spec);
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).context("day-of-week")?;
+        // 7 is Sunday too
+        weekdays[0] |= weekdays[7];
+        weekdays.truncate(7);
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).context("minute")?,
+            hours: parse_field(hour, 0, 23).context("hour")?,
+            days: parse_field(day, 1, 31).context("day-of-month")?,
+            months: parse_field(month, 1, 12).context("month")?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+
+    fn matches_day(&self, t: &DateTime<Local>) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().num_days_from_sunday() as usize];
+        self.months[t.month() as usize] && if self.either_day { day || weekday } else { day && weekday }
+    }
+
+    /// First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Every schedule matches within a leap-year cycle
+        let limit = t + Duration::days(366 * 4);
+        while t < limit {
+            if !self.matches_day(&t) {
+                t = (t + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+                continue;
+            }
+            if self.hours[t.hour() as usize] && self.minutes[t.minute() as usize] {
+                return Some(t);
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+/// Parse one cron field (`*`, `5`, `1-5`, `*/15`, `0-30/10`, lists) into a bitmap indexed by value
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().context(format!("invalid step '{}'", step))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse()?, b.parse()?),
+                None => {
+                    let value: u32 = range.parse().context(format!("invalid value '{}'", range))?;
+                    // `5/15` means from 5 to the end in steps of 15
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            anyhow::bail!("'{}' is out of range {}-{}", part, min, max);
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_max_age() {
+        assert_eq!(
+            RestartSchedule::parse("6h").unwrap(),
+            RestartSchedule::MaxAge(std::time::Duration::from_secs(6 * 3600))
+        );
+        assert_eq!(parse_duration("1h30m").unwrap().as_secs(), 5400);
+        assert!(parse_duration("6").is_err());
+        assert!(parse_duration("6w").is_err());
+    }
+
+    #[test]
+    fn test_cron_next() {
+        let started = Local.with_ymd_and_hms(2025, 3, 10, 4, 0, 30).unwrap();
+
+        let daily = RestartSchedule::parse("0 4 * * *").unwrap();
+        assert_eq!(
+            daily.next_restart(started).unwrap(),
+            Local.with_ymd_and_hms(2025, 3, 11, 4, 0, 0).unwrap()
+        );
+
+        // 2025-03-10 is a Monday; next Sunday is the 16th
+        let weekly = Cron::parse("@weekly").unwrap();
+        assert_eq!(
+            weekly.next_after(started).unwrap(),
+            Local.with_ymd_and_hms(2025, 3, 16, 0, 0, 0).unwrap()
+        );
+
+        let quarter = Cron::parse("*/15 9-17 * * 1-5").unwrap();
+        assert_eq!(
+            quarter.next_after(started).unwrap(),
+            Local.with_ymd_and_hms(2025, 3, 10, 9, 0, 0).unwrap()
+        );
+
+        assert!(Cron::parse("61 * * * *").is_err());
+        assert!(Cron::parse("* * *").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! Server templates
+//! Families of similar servers defined once in `config.toml` and instantiated
+//! with parameters:
+//!
+//! ```toml
+//! [templates.postgres-mcp]
+//! command = "npx"
+//! args = ["-y", "@modelcontextprotocol/server-postgres", "{{dsn}}"]
+//!
+//! [servers.db-prod]
+//! template = "postgres-mcp"
+//! dsn = "postgres://prod.internal/app"
+//! ```
+//!
+//! `{{name}}` expands to the instance's server name.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::ServerConfig;
+
+/// Server definition with `{{param}}` placeholders
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerTemplate {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// A server instantiated from a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInstance {
+    pub template: String,
+    /// Values for the template's placeholders
+    #[serde(flatten)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// Build the server configs for every template instance
+pub fn instantiate(
+    templates: &BTreeMap<String, ServerTemplate>,
+    instances: &BTreeMap<String, TemplateInstance>,
+) -> Result<Vec<ServerConfig>> {
+    instances
+        .iter()
+        .map(|(name, instance)| {
+            let template = templates
+                .get(&instance.template)
+                .context(format!("Server {} uses unknown template {}", name, instance.template))?;
+
+            let mut params = instance.params.clone();
+            params.insert("name".to_string(), name.clone());
+            let expand = |text: &str| {
+                expand(text, &params).context(format!("Server {} (template {})", name, instance.template))
+            };
+
+            Ok(ServerConfig {
+                name: name.clone(),
+                command: expand(&template.command)?,
+                args: template.args.iter().map(|a| expand(a)).collect::<Result<_>>()?,
+                env: template
+                    .env
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), expand(v)?)))
+                    .collect::<Result<_>>()?,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Replace `{{param}}` placeholders
+fn expand(text: &str, params: &BTreeMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let key = rest[start + 2..start + 2 + len].trim();
+        let value = params
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("missing template parameter '{}'", key))?;
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[start + 4 + len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct File {
+        templates: BTreeMap<String, ServerTemplate>,
+        servers: BTreeMap<String, TemplateInstance>,
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let file: File = toml::from_str(
+            r#"
+            [templates.postgres-mcp]
+            command = "npx"
+            args = ["-y", "@modelcontextprotocol/server-postgres", "{{ dsn }}"]
+            env = { PGAPPNAME = "citadel-{{name}}" }
+
+            [servers.db-prod]
+            template = "postgres-mcp"
+            dsn = "postgres://prod/app"
+
+            [servers.db-staging]
+            template = "postgres-mcp"
+            dsn = "postgres://staging/app"
+            "#,
+        )
+        .unwrap();
+
+        let servers = instantiate(&file.templates, &file.servers).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].name, "db-prod");
+        assert_eq!(servers[0].args[2], "postgres://prod/app");
+        assert_eq!(servers[1].env["PGAPPNAME"], "citadel-db-staging");
+    }
+
+    #[test]
+    fn test_missing_parameter() {
+        let templates = BTreeMap::from([(
+            "t".to_string(),
+            ServerTemplate { command: "run".to_string(), args: vec!["{{dsn}}".to_string()], ..Default::default() },
+        )]);
+        let instances = BTreeMap::from([(
+            "s".to_string(),
+            TemplateInstance { template: "t".to_string(), params: BTreeMap::new() },
+        )]);
+        let error = format!("{:#}", instantiate(&templates, &instances).unwrap_err());
+        assert!(error.contains("missing template parameter 'dsn'"));
+    }
+}
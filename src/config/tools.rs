@@ -0,0 +1,105 @@
+//! Tool filters
+//! A server's `tools` setting hides tools from clients by name, with `*`
+//! matching any run of characters:
+//!
+//! ```json
+//! "tools": { "allow": ["get_*", "search_*"], "deny": ["delete_repo"] }
+//! ```
+//!
+//! A tool is exposed when it matches an `allow` pattern (or there are none)
+//! and no `deny` pattern. Calls to exposed tools matching an `approve` pattern
+//! wait for an operator's approval (see `router::approvals`), and on read-only
+//! servers those matching a `write` pattern are refused.
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ToolFilter {
+    /// Only tools matching one of these (unset or empty: every tool)
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Never tools matching one of these, allowed or not
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Tools whose calls are held until approved
+    #[serde(default)]
+    pub approve: Vec<String>,
+    /// Tools that change things, refused when the server is read-only
+    #[serde(default)]
+    pub write: Vec<String>,
+}
+
+impl ToolFilter {
+    /// Whether the filter passes every tool (approval aside)
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Whether clients may see and call `tool`
+    pub fn allows(&self, tool: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|p| matches(p, tool))) && !self.deny.iter().any(|p| matches(p, tool))
+    }
+
+    /// Whether calls to `tool` need an operator's approval
+    pub fn needs_approval(&self, tool: &str) -> bool {
+        matches_any(&self.approve, tool)
+    }
+
+    /// Whether `tool` is one of the server's write tools
+    pub fn writes(&self, tool: &str) -> bool {
+        matches_any(&self.write, tool)
+    }
+}
+
+/// Whether `name` matches one of `patterns`
+pub fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|p| matches(p, name))
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of characters
+fn matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    // Without a `*` the pattern is the whole name
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_filter() {
+        let filter: ToolFilter = serde_json::from_str(r#"{ "allow": ["get_*", "*_issue*"], "deny": ["get_secret", "*_admin"] }"#).unwrap();
+        assert!(filter.allows("get_file"));
+        assert!(filter.allows("create_issue_comment"));
+        assert!(!filter.allows("get_secret"));
+        assert!(!filter.allows("get_admin"));
+        assert!(!filter.allows("delete_repo"));
+
+        let deny_only = ToolFilter { deny: vec!["delete_*".to_string()], ..Default::default() };
+        assert!(deny_only.allows("create_repo"));
+        assert!(!deny_only.allows("delete_repo"));
+        assert!(ToolFilter::default().allows("anything"));
+        let approve = ToolFilter { approve: vec!["delete_*".to_string()], ..Default::default() };
+        assert!(approve.is_empty() && approve.needs_approval("delete_repo") && !approve.needs_approval("create_repo"));
+
+        assert!(matches("a*a", "aa"));
+        assert!(!matches("a*a", "a"));
+        assert!(matches("*", ""));
+        assert!(!matches("get", "get_file"));
+    }
+}
@@ -0,0 +1,121 @@
+//! Client auto-configuration
+//! Points other MCP clients (Claude Code, Cursor, Cline) at the hub via `mcp-client`,
+//! remembering what was replaced so `disconnect` can put it back
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, modify_mcp_servers};
+
+/// MCP client whose configuration can be pointed at the hub
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClientApp {
+    ClaudeCode,
+    Cursor,
+    Cline,
+}
+
+impl ClientApp {
+    /// Name used on the command line
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ClientApp::ClaudeCode => "claude-code",
+            ClientApp::Cursor => "cursor",
+            ClientApp::Cline => "cline",
+        }
+    }
+
+    /// Where the client keeps its MCP servers
+    pub fn config_path(&self) -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        Ok(match self {
+            ClientApp::ClaudeCode => home.join(".claude.json"),
+            ClientApp::Cursor => home.join(".cursor").join("mcp.json"),
+            ClientApp::Cline => dirs::config_dir()
+                .context("Could not find config directory")?
+                .join("Code/User/globalStorage/saoudrizwan.claude-dev/settings/cline_mcp_settings.json"),
+        })
+    }
+}
+
+/// What `connect` changed in a client's config
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConnectState {
+    /// Client config that was edited
+    pub path: PathBuf,
+    /// Copy of the config from before the first `connect`
+    pub backup: Option<PathBuf>,
+    /// Original entry for every server written by `connect` (null = newly added)
+    pub replaced: BTreeMap<String, Option<Value>>,
+}
+
+fn state_dir() -> PathBuf {
+    config::data_dir().join("connect")
+}
+
+fn state_path(client: ClientApp) -> PathBuf {
+    state_dir().join(format!("{}.json", client.slug()))
+}
+
+/// Route every managed server in `client` through the hub
+pub fn connect(client: ClientApp, servers: &[String], mcp_client: &Path) -> Result<ConnectState> {
+    let path = client.config_path()?;
+    let state_file = state_path(client);
+    fs::create_dir_all(state_dir())?;
+
+    // Reconnecting keeps the originals recorded the first time
+    let mut state = match fs::read_to_string(&state_file) {
+        Ok(content) => serde_json::from_str(&content).context(format!("Failed to parse {:?}", state_file))?,
+        Err(_) => {
+            let backup = path.exists().then(|| state_dir().join(format!("{}.backup.json", client.slug())));
+            if let Some(backup) = &backup {
+                fs::copy(&path, backup).context(format!("Failed to back up {:?}", path))?;
+            }
+            ConnectState {
+                path: path.clone(),
+                backup,
+                replaced: BTreeMap::new(),
+            }
+        }
+    };
+
+    modify_mcp_servers(&path, |entries| {
+        for server in servers {
+            let entry = serde_json::json!({
+                "command": mcp_client.to_string_lossy(),
+                "args": [server],
+            });
+            let previous = entries.insert(server.clone(), entry);
+            state.replaced.entry(server.clone()).or_insert(previous);
+        }
+        Ok(())
+    })?;
+
+    fs::write(&state_file, serde_json::to_string_pretty(&state)?)?;
+    Ok(state)
+}
+
+/// Undo `connect`: restore replaced entries and remove added ones
+pub fn disconnect(client: ClientApp) -> Result<ConnectState> {
+    let state_file = state_path(client);
+    let content = fs::read_to_string(&state_file)
+        .map_err(|_| anyhow::anyhow!("{} is not connected to the hub", client.slug()))?;
+    let state: ConnectState = serde_json::from_str(&content).context(format!("Failed to parse {:?}", state_file))?;
+
+    modify_mcp_servers(&state.path, |entries| {
+        for (server, original) in &state.replaced {
+            match original {
+                Some(original) => entries.insert(server.clone(), original.clone()),
+                None => entries.remove(server),
+            };
+        }
+        Ok(())
+    })?;
+
+    fs::remove_file(&state_file)?;
+    Ok(state)
+}
@@ -0,0 +1,406 @@
+//! Control plane
+//! A versioned JSON-lines RPC protocol on `control.sock` in the data directory
+//! that the CLI uses to query and steer a running hub
+//!
+//! Requests are `{"version":1,"id":N,"method":"...","params":{...}}` and every
+//! response echoes the version and id with either `result` or `error`. After
+//! `events.tail` is acknowledged the hub streams `{"version":1,"event":{...}}`
+//! lines until the client disconnects (backend notifications among them unless
+//! `"notifications": false`). A `clients.register` likewise holds the
+//! connection: the adapter stays listed until it closes.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{self, load_hub_config, load_servers};
+use crate::router::approvals::Decision;
+use crate::router::clients::ClientInfo;
+use crate::router::{events, HubManager};
+
+/// Control protocol version spoken by this build
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Control socket path
+pub fn socket_path() -> PathBuf {
+    config::data_dir().join("control.sock")
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    version: u64,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Parse a request line, checking its protocol version
+fn parse_request(line: &str) -> std::result::Result<Request, (Value, String)> {
+    let request: Request = serde_json::from_str(line).map_err(|e| (Value::Null, format!("Invalid request: {}", e)))?;
+    if request.version != PROTOCOL_VERSION {
+        return Err((
+            request.id,
+            format!(
+                "Unsupported control protocol version {} (hub speaks {})",
+                request.version, PROTOCOL_VERSION
+            ),
+        ));
+    }
+    Ok(request)
+}
+
+/// Control socket server
+pub struct ControlServer {
+    socket_path: PathBuf,
+    manager: Arc<HubManager>,
+}
+
+impl ControlServer {
+    pub fn new(socket_path: PathBuf, manager: Arc<HubManager>) -> Self {
+        Self { socket_path, manager }
+    }
+
+    /// Accept control connections until the task is dropped
+    pub async fn start(&self) -> Result<()> {
+        if let Some(dir) = self.socket_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let _ = std::fs::remove_file(&self.socket_path);
+
+        let listener = UnixListener::bind(&self.socket_path).context("Failed to bind control socket")?;
+
+        // Owner only: the control plane can stop and restart servers
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        info!("Control plane listening on {:?}", self.socket_path);
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let manager = Arc::clone(&self.manager);
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, manager).await {
+                            debug!("Control connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Control accept error: {}", e),
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream, manager: Arc<HubManager>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err((id, message)) => {
+                write_line(&mut writer, &json!({ "version": PROTOCOL_VERSION, "id": id, "error": message })).await?;
+                continue;
+            }
+        };
+        debug!("Control request: {}", request.method);
+
+        if request.method == "events.tail" {
+            write_line(&mut writer, &json!({ "version": PROTOCOL_VERSION, "id": request.id, "result": "subscribed" })).await?;
+            let notifications = request.params.get("notifications").and_then(Value::as_bool).unwrap_or(true);
+            return tail_events(&manager, notifications, &mut lines, &mut writer).await;
+        }
+
+        if request.method == "clients.register" {
+            let info: ClientInfo = match serde_json::from_value(request.params) {
+                Ok(info) => info,
+                Err(e) => {
+                    let error = format!("Invalid client registration: {}", e);
+                    write_line(&mut writer, &json!({ "version": PROTOCOL_VERSION, "id": request.id, "error": error })).await?;
+                    continue;
+                }
+            };
+            debug!("Client adapter {} (PID {}) attached to {}", info.client, info.pid, info.server);
+            let registration = manager.clients().register(info);
+            write_line(&mut writer, &json!({ "version": PROTOCOL_VERSION, "id": request.id, "result": { "registered": registration.id } })).await?;
+            // Listed until the adapter disconnects; other input is ignored
+            while lines.next_line().await?.is_some() {}
+            return Ok(());
+        }
+
+        let response = match dispatch(&manager, &request.method, &request.params).await {
+            Ok(result) => json!({ "version": PROTOCOL_VERSION, "id": request.id, "result": result }),
+            Err(e) => json!({ "version": PROTOCOL_VERSION, "id": request.id, "error": format!("{:#}", e) }),
+        };
+        write_line(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+async fn dispatch(manager: &HubManager, method: &str, params: &Value) -> Result<Value> {
+    let server = || {
+        params
+            .get("server")
+            .and_then(|s| s.as_str())
+            .context(format!("{} requires params.server", method))
+    };
+
+    match method {
+        "hello" => Ok(json!({
+            "protocol": PROTOCOL_VERSION,
+            "hub_version": env!("CARGO_PKG_VERSION"),
+        })),
+        "status" => Ok(json!({
+            "pid": std::process::id(),
+            "hub_version": env!("CARGO_PKG_VERSION"),
+            "uptime_secs": manager.uptime().as_secs(),
+            "servers_running": manager.server_count().await,
+            "servers_configured": manager.configured_count().await,
+            "draining": manager.is_draining(),
+            "maintenance": manager.maintenance().current(),
+            "clients": manager.clients().len(),
+        })),
+        "maintenance.begin" => {
+            let secs = params
+                .get("duration_secs")
+                .and_then(|d| d.as_u64())
+                .filter(|&secs| secs > 0)
+                .context("maintenance.begin requires params.duration_secs")?;
+            let reason = params.get("reason").and_then(|r| r.as_str()).map(String::from);
+            Ok(serde_json::to_value(manager.maintenance().begin(std::time::Duration::from_secs(secs), reason))?)
+        }
+        "maintenance.end" => Ok(json!(manager.maintenance().end())),
+        "maintenance.status" => Ok(serde_json::to_value(manager.maintenance().current())?),
+        "log.level" => {
+            if let Some(level) = params.get("level").and_then(Value::as_str) {
+                crate::logging::set(level)?;
+                info!("Log level set to {}", level);
+            }
+            Ok(json!({ "level": crate::logging::current() }))
+        }
+        "events.recent" => Ok(json!(manager.recent_events())),
+        "approvals.list" => Ok(serde_json::to_value(manager.approvals().list())?),
+        "approvals.approve" | "approvals.deny" => {
+            let id = params
+                .get("id")
+                .and_then(|id| id.as_u64())
+                .context(format!("{} requires params.id", method))?;
+            let decision = match method {
+                "approvals.approve" => Decision::Approved,
+                _ => Decision::Denied(params.get("reason").and_then(|r| r.as_str()).map(String::from)),
+            };
+            anyhow::ensure!(manager.approvals().decide(id, decision), "No call #{} is waiting for approval", id);
+            Ok(json!({ "decided": id }))
+        }
+        "summary" => Ok(serde_json::to_value(crate::summary::build(manager, &Default::default()).await)?),
+        "servers.list" => Ok(serde_json::to_value(manager.server_info().await)?),
+        "servers.start" => {
+            manager.start_server(server()?).await?;
+            Ok(json!({ "started": server()? }))
+        }
+        "servers.stop" => {
+            manager.stop_server(server()?).await?;
+            Ok(json!({ "stopped": server()? }))
+        }
+        "servers.restart" => {
+            manager.restart_server(server()?).await?;
+            Ok(json!({ "restarted": server()? }))
+        }
+        "servers.debug" => {
+            let enabled = params.get("enabled").and_then(|e| e.as_bool()).unwrap_or(true);
+            let path = manager.set_debug_transcript(server()?, enabled).await?;
+            Ok(json!({ "server": server()?, "enabled": enabled, "path": path }))
+        }
+        "reload" => {
+            let hub_config = load_hub_config()?;
+            let configs = load_servers(&hub_config)?;
+            // What's running now can be rolled back to
+            config::history::snapshot_applied("reload")?;
+            crate::logging::configure(&hub_config.log_level)?;
+            crate::logging::configure_rotation(&hub_config.logging);
+            let reloaded = manager.reload(configs).await?;
+            if let Err(e) = config::history::record_applied(&hub_config) {
+                warn!("Failed to record the applied config: {:#}", e);
+            }
+            Ok(serde_json::to_value(reloaded)?)
+        }
+        "stats" => {
+            let servers = manager.server_info().await;
+            Ok(json!({
+                "uptime_secs": manager.uptime().as_secs(),
+                "servers_running": servers.iter().filter(|s| s.running).count(),
+                "servers_configured": servers.len(),
+                "requests": servers.iter().map(|s| s.requests).sum::<u64>(),
+                "in_flight": servers.iter().map(|s| s.in_flight).sum::<usize>(),
+                "restarts": servers.iter().map(|s| s.restarts).sum::<u32>(),
+            }))
+        }
+        "usage" => Ok(serde_json::to_value(manager.usage())?),
+        "clients.list" => Ok(serde_json::to_value(manager.clients().list())?),
+        "sessions.list" => Ok(serde_json::to_value(manager.transcripts().list())?),
+        "sessions.export" => {
+            let id = params
+                .get("id")
+                .and_then(|s| s.as_str())
+                .context("sessions.export requires params.id")?;
+            let (session, messages) = manager
+                .transcripts()
+                .get(id)
+                .context(format!("No transcript for session {} (or the id prefix is ambiguous)", id))?;
+            let mut stderr = serde_json::Map::new();
+            for server in &session.servers {
+                stderr.insert(server.clone(), json!(manager.server_stderr(server).await));
+            }
+            Ok(json!({ "session": session, "messages": messages, "stderr": stderr }))
+        }
+        other => anyhow::bail!("Unknown method: {}", other),
+    }
+}
+
+/// Stream lifecycle changes, and backend notifications if asked, until the
+/// client goes away
+async fn tail_events(
+    manager: &HubManager,
+    notifications: bool,
+    lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    writer: &mut OwnedWriteHalf,
+) -> Result<()> {
+    let mut lifecycle = manager.lifecycle_events();
+    let mut backend = manager.backend_events();
+
+    loop {
+        let mut event = tokio::select! {
+            event = lifecycle.recv() => match event {
+                Ok(event) => events::stamp(&event),
+                Err(broadcast::error::RecvError::Lagged(n)) => json!({ "event": "lagged", "skipped": n }),
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            event = backend.recv(), if notifications => match event {
+                Ok(message) => json!({ "event": "notification", "server": message.server, "method": message.method }),
+                Err(broadcast::error::RecvError::Lagged(n)) => json!({ "event": "lagged", "skipped": n }),
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            // Closing the connection ends the subscription; other input is ignored
+            line = lines.next_line() => match line? {
+                Some(_) => continue,
+                None => return Ok(()),
+            },
+        };
+
+        event["timestamp"] = json!(chrono::Utc::now().to_rfc3339());
+        write_line(writer, &json!({ "version": PROTOCOL_VERSION, "event": event })).await?;
+    }
+}
+
+async fn write_line(writer: &mut OwnedWriteHalf, value: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// Control socket client used by the CLI
+pub struct ControlClient {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl ControlClient {
+    /// Connect to the running hub's control socket
+    pub async fn connect() -> Result<Self> {
+        Self::connect_to(&socket_path()).await
+    }
+
+    pub async fn connect_to(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .await
+            .context(format!("Hub control socket {:?} is not reachable", path))?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+            next_id: 1,
+        })
+    }
+
+    /// Call a control method and return its result
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        write_line(
+            &mut self.writer,
+            &json!({ "version": PROTOCOL_VERSION, "id": id, "method": method, "params": params }),
+        )
+        .await?;
+
+        let line = self.lines.next_line().await?.context("Hub closed the control connection")?;
+        let mut response: Value = serde_json::from_str(&line).context("Invalid control response")?;
+        let version = response.get("version").and_then(|v| v.as_u64());
+        if version != Some(PROTOCOL_VERSION) {
+            warn!("Hub answered with control protocol version {:?}", version);
+        }
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("{}", error.as_str().unwrap_or("unknown error"));
+        }
+        response
+            .get_mut("result")
+            .map(Value::take)
+            .context("Control response has no result")
+    }
+
+    /// Subscribe to hub events; read them with `next_event`
+    pub async fn tail(mut self, notifications: bool) -> Result<Self> {
+        self.call("events.tail", json!({ "notifications": notifications })).await?;
+        Ok(self)
+    }
+
+    /// List this adapter with the hub for as long as the connection is kept
+    pub async fn register(mut self, info: &ClientInfo) -> Result<Self> {
+        self.call("clients.register", serde_json::to_value(info)?).await?;
+        Ok(self)
+    }
+
+    /// Next event from a `tail` subscription (None once the hub disconnects)
+    pub async fn next_event(&mut self) -> Result<Option<Value>> {
+        while let Some(line) = self.lines.next_line().await? {
+            let mut message: Value = serde_json::from_str(&line).context("Invalid control event")?;
+            if let Some(event) = message.get_mut("event") {
+                return Ok(Some(event.take()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(r#"{"version":1,"id":7,"method":"servers.restart","params":{"server":"github"}}"#).unwrap();
+        assert_eq!(request.method, "servers.restart");
+        assert_eq!(request.params["server"], "github");
+
+        let (id, message) = parse_request(r#"{"version":2,"id":3,"method":"status"}"#).unwrap_err();
+        assert_eq!(id, 3);
+        assert!(message.contains("version 2"));
+
+        assert!(parse_request("not json").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! State compaction
+//! Retention for what accumulates in the data dir on long-lived installs:
+//! config history snapshots, rotated logs, and catalogs cached for servers
+//! that are no longer configured. Entries past `max_age_days` go first, then
+//! the oldest until the stores fit in `max_size_mb`. The hub runs a pass every
+//! `compact_interval_secs`; `mcp-citadel state compact` runs one on demand.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{HubConfig, StateConfig};
+use crate::router::catalog::{catalog_dir, entry_path};
+
+/// A file or directory a store can give up
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub store: &'static str,
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub modified: SystemTime,
+}
+
+/// What one compaction pass did (or, for a dry run, would do)
+#[derive(Debug, Default)]
+pub struct Report {
+    pub removed: Vec<Item>,
+    /// Bytes each store holds afterwards
+    pub remaining: BTreeMap<&'static str, u64>,
+    /// Entries that couldn't be removed
+    pub failed: Vec<String>,
+}
+
+impl Report {
+    pub fn freed(&self) -> u64 {
+        self.removed.iter().map(|item| item.bytes).sum()
+    }
+}
+
+/// Apply the retention limits to every store
+pub fn compact(hub_config: &HubConfig, limits: &StateConfig, dry_run: bool) -> Result<Report> {
+    let items = items(hub_config);
+    let doomed = select(&items, limits, SystemTime::now());
+
+    let mut report = Report::default();
+    for (i, item) in items.into_iter().enumerate() {
+        if !doomed.contains(&i) {
+            *report.remaining.entry(item.store).or_default() += item.bytes;
+            continue;
+        }
+        let result = match dry_run {
+            true => Ok(()),
+            false if item.path.is_dir() => std::fs::remove_dir_all(&item.path),
+            false => std::fs::remove_file(&item.path),
+        };
+        match result {
+            Ok(()) => report.removed.push(item),
+            Err(e) => report.failed.push(format!("{}: {}", item.path.display(), e)),
+        }
+    }
+    Ok(report)
+}
+
+/// Everything the stores could remove
+fn items(hub_config: &HubConfig) -> Vec<Item> {
+    let data_dir = crate::config::data_dir();
+    let mut items = Vec::new();
+
+    // The newest snapshot is what `config rollback 1` restores
+    let mut snapshots = crate::config::history::list().unwrap_or_default();
+    if !snapshots.is_empty() {
+        snapshots.remove(0);
+    }
+    items.extend(snapshots.into_iter().filter_map(|snapshot| item("history", snapshot.path)));
+
+    for dir in [data_dir.clone(), data_dir.join("logs")] {
+        items.extend(
+            entries(&dir)
+                .filter(|path| path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.contains(".log.")))
+                .filter_map(|path| item("logs", path)),
+        );
+    }
+
+    // Without a readable config every cached catalog might still be in use
+    if let Ok(servers) = crate::config::load_servers(hub_config) {
+        let dir = catalog_dir();
+        let configured: HashSet<PathBuf> = servers.iter().map(|s| entry_path(&dir, &s.name)).collect();
+        items.extend(entries(&dir).filter(|path| !configured.contains(path)).filter_map(|path| item("catalog", path)));
+    }
+    items
+}
+
+/// Indexes of the items to remove: the expired ones, then the oldest until
+/// the rest fit the size limit
+fn select(items: &[Item], limits: &StateConfig, now: SystemTime) -> HashSet<usize> {
+    let max_age = Duration::from_secs(limits.max_age_days * 24 * 3600);
+    let mut doomed: HashSet<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| limits.max_age_days > 0 && now.duration_since(item.modified).is_ok_and(|age| age > max_age))
+        .map(|(i, _)| i)
+        .collect();
+
+    if limits.max_size_mb > 0 {
+        let max_bytes = limits.max_size_mb * 1024 * 1024;
+        let mut oldest_first: Vec<usize> = (0..items.len()).filter(|i| !doomed.contains(i)).collect();
+        oldest_first.sort_by_key(|&i| items[i].modified);
+        let mut total: u64 = oldest_first.iter().map(|&i| items[i].bytes).sum();
+        for i in oldest_first {
+            if total <= max_bytes {
+                break;
+            }
+            total -= items[i].bytes;
+            doomed.insert(i);
+        }
+    }
+    doomed
+}
+
+fn entries(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    std::fs::read_dir(dir).into_iter().flatten().flatten().map(|e| e.path())
+}
+
+fn item(store: &'static str, path: PathBuf) -> Option<Item> {
+    let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+    Some(Item {
+        store,
+        bytes: size(&path),
+        path,
+        modified,
+    })
+}
+
+/// Bytes in a file, or in everything under a directory
+fn size(path: &Path) -> u64 {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => entries(path).map(|p| size(&p)).sum(),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_expired_then_oldest() {
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 3600);
+        let mb = 1024 * 1024;
+        let item = |days: u32, bytes: u64| Item {
+            store: "history",
+            path: PathBuf::new(),
+            bytes,
+            modified: now - day * days,
+        };
+        let items = [item(40, mb), item(10, 2 * mb), item(5, 2 * mb), item(1, mb)];
+
+        let limits = |max_age_days, max_size_mb| StateConfig {
+            max_age_days,
+            max_size_mb,
+            compact_interval_secs: 0,
+        };
+        assert_eq!(select(&items, &limits(30, 0), now), HashSet::from([0]));
+        assert_eq!(select(&items, &limits(30, 3), now), HashSet::from([0, 1]));
+        assert_eq!(select(&items, &limits(0, 4), now), HashSet::from([0, 1]));
+        assert!(select(&items, &limits(0, 0), now).is_empty());
+    }
+}
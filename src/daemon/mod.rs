@@ -1,35 +1,59 @@
 //! Daemon module for background process management
 
+pub mod compact;
+pub mod prune;
+
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
 /// PID file path
 fn pid_file() -> PathBuf {
-    dirs::home_dir()
-        .unwrap()
-        .join(".mcp-citadel")
-        .join("hub.pid")
+    crate::config::data_dir().join("hub.pid")
 }
 
 /// Status file path
 fn status_file() -> PathBuf {
-    dirs::home_dir()
-        .unwrap()
-        .join(".mcp-citadel")
-        .join("status.json")
+    crate::config::data_dir().join("status.json")
+}
+
+/// Server process file path
+fn servers_file() -> PathBuf {
+    crate::config::data_dir().join("servers.pid.json")
+}
+
+/// A server process of the running hub, recorded so `prune` can find it if
+/// the hub dies without stopping it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedServer {
+    pub name: String,
+    pub pid: u32,
+    /// Resolved command, checked against the process before it is stopped
+    #[serde(default)]
+    pub command: Option<PathBuf>,
+    /// PID of the hub that started it
+    #[serde(default)]
+    pub hub: Option<u32>,
+}
+
+impl RecordedServer {
+    /// Whether the hub that started the server is gone (records from before
+    /// hubs were recorded count as gone)
+    pub fn hub_gone(&self) -> bool {
+        self.hub.is_none_or(|pid| pid == std::process::id() || !crate::platform::is_alive(pid))
+    }
 }
 
 /// Ensure .mcp-citadel directory exists
 fn ensure_dir() -> Result<()> {
-    let dir = dirs::home_dir().unwrap().join(".mcp-citadel");
-    fs::create_dir_all(&dir)?;
+    fs::create_dir_all(crate::config::data_dir())?;
     Ok(())
 }
 
-/// Start hub as daemon
-pub fn daemonize() -> Result<()> {
+/// Start hub as daemon (`read_only`: with `--read-only`)
+pub fn daemonize(read_only: bool) -> Result<()> {
     ensure_dir()?;
     
     // Check if already running
@@ -41,8 +65,9 @@ pub fn daemonize() -> Result<()> {
     let binary = std::env::current_exe()?;
     
     // Spawn detached process
-    let child = Command::new(binary)
-        .args(&["start", "--foreground"])
+    let child = crate::platform::detach(&mut Command::new(binary))
+        .args(["start", "--foreground"])
+        .args(read_only.then_some("--read-only"))
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -61,15 +86,8 @@ pub fn daemonize() -> Result<()> {
 pub fn stop() -> Result<()> {
     let pid = read_pid()?;
     
-    // Send SIGTERM
-    #[cfg(unix)]
-    {
-        use nix::sys::signal::{kill, Signal};
-        use nix::unistd::Pid;
-        
-        kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
-            .context("Failed to send SIGTERM")?;
-    }
+    // Send SIGTERM (taskkill on Windows)
+    crate::platform::terminate(pid)?;
     
     // Remove PID file
     let _ = fs::remove_file(pid_file());
@@ -79,28 +97,39 @@ pub fn stop() -> Result<()> {
     Ok(())
 }
 
+/// Restart a running hub so it picks up configuration changes (start
+/// flags like `--read-only` aren't kept; their config.toml settings are)
+pub fn restart() -> Result<()> {
+    let pid = read_pid()?;
+    stop()?;
+    wait_for_exit(pid, std::time::Duration::from_secs(10))?;
+    daemonize(false)
+}
+
+/// Wait for a process to exit after SIGTERM
+fn wait_for_exit(pid: u32, timeout: std::time::Duration) -> Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    while crate::platform::is_alive(pid) {
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!("Hub (PID: {}) did not exit within {:?}", pid, timeout);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    Ok(())
+}
+
 /// Check if hub is running
 pub fn is_running() -> Result<bool> {
     match read_pid() {
         Ok(pid) => {
             // Check if process exists
-            #[cfg(unix)]
-            {
-                use nix::sys::signal::kill;
-                use nix::unistd::Pid;
-                
-                match kill(Pid::from_raw(pid as i32), None) {
-                    Ok(_) => Ok(true),
-                    Err(_) => {
-                        // Process doesn't exist, clean up stale PID file
-                        let _ = fs::remove_file(pid_file());
-                        Ok(false)
-                    }
-                }
+            if crate::platform::is_alive(pid) {
+                Ok(true)
+            } else {
+                // Process doesn't exist, clean up stale PID file
+                let _ = fs::remove_file(pid_file());
+                Ok(false)
             }
-            
-            #[cfg(not(unix))]
-            Ok(true)
         }
         Err(_) => Ok(false),
     }
@@ -115,22 +144,26 @@ fn read_pid() -> Result<u32> {
         .context("Invalid PID file")
 }
 
-/// Get hub status
-pub fn status() -> Result<String> {
+/// Status the running hub last wrote; None if it isn't running. A hub that
+/// hasn't written one yet has only its PID.
+pub fn status() -> Result<Option<serde_json::Value>> {
     if !is_running()? {
-        return Ok("Hub is not running".to_string());
+        return Ok(None);
     }
     
     let pid = read_pid()?;
     
     // Try to read status file
     if let Ok(status_json) = fs::read_to_string(status_file()) {
+        // Unless a hub before this one left it
         if let Ok(status) = serde_json::from_str::<serde_json::Value>(&status_json) {
-            return Ok(serde_json::to_string_pretty(&status)?);
+            if status["pid"] == pid {
+                return Ok(Some(status));
+            }
         }
     }
     
-    Ok(format!("Hub is running (PID: {})", pid))
+    Ok(Some(serde_json::json!({ "pid": pid })))
 }
 
 /// Write PID file
@@ -146,15 +179,16 @@ pub fn remove_pid() -> Result<()> {
         .context("Failed to remove PID file")
 }
 
-/// Write status information
-pub fn write_status(server_count: usize, uptime: std::time::Duration) -> Result<()> {
+/// Write status information: the hub's and each configured server's
+pub fn write_status(socket_path: &str, uptime: std::time::Duration, servers: &[crate::router::ServerInfo]) -> Result<()> {
     ensure_dir()?;
     
     let status = serde_json::json!({
         "pid": std::process::id(),
-        "server_count": server_count,
+        "server_count": servers.iter().filter(|s| s.running).count(),
         "uptime_seconds": uptime.as_secs(),
-        "socket_path": "/tmp/mcp-citadel.sock",
+        "socket_path": socket_path,
+        "servers": servers,
         "timestamp": chrono::Utc::now().to_rfc3339(),
     });
     
@@ -162,3 +196,23 @@ pub fn write_status(server_count: usize, uptime: std::time::Duration) -> Result<
     
     Ok(())
 }
+
+/// Record the hub's server processes
+pub fn write_server_pids(servers: &[RecordedServer]) -> Result<()> {
+    ensure_dir()?;
+    fs::write(servers_file(), serde_json::to_string_pretty(servers)?)?;
+    Ok(())
+}
+
+/// Server processes recorded by the last hub run
+pub fn read_server_pids() -> Vec<RecordedServer> {
+    fs::read_to_string(servers_file())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Remove the server process file
+pub fn remove_server_pids() -> Result<()> {
+    fs::remove_file(servers_file()).context("Failed to remove server PID file")
+}
@@ -0,0 +1,222 @@
+//! State repair
+//! `mcp-citadel prune` removes what a crashed or killed hub leaves behind:
+//! stale PID and status files, dead sockets, server processes that outlived
+//! the hub, expired HTTP session stores, and old rotated logs. A starting hub
+//! stops such server processes itself (see [`reap_orphans`]).
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+use super::RecordedServer;
+
+use crate::config::HubConfig;
+
+/// What one prune pass did (or, for a dry run, would do)
+#[derive(Debug, Default)]
+pub struct Report {
+    pub removed: Vec<String>,
+    pub stopped: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Clean up after dead hubs; with the hub running, only its expired
+/// leftovers (rotated logs) are touched
+pub fn prune(hub_config: &HubConfig, log_retention: Duration, dry_run: bool) -> Result<Report> {
+    let mut report = Report::default();
+    let data_dir = crate::config::data_dir();
+    let remove = |path: &Path, what: &str, report: &mut Report| {
+        if dry_run || std::fs::remove_file(path).is_ok() {
+            report.removed.push(format!("{} {}", what, path.display()));
+        }
+    };
+
+    let hub_pid = std::fs::read_to_string(data_dir.join("hub.pid"))
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok());
+    match hub_pid {
+        Some(pid) if crate::platform::is_alive(pid) => {
+            report.skipped.push(format!("hub is running (PID {}); leaving its state alone", pid));
+        }
+        _ => {
+            if hub_pid.is_some() {
+                remove(&data_dir.join("hub.pid"), "stale PID file", &mut report);
+            }
+            if data_dir.join("status.json").exists() {
+                remove(&data_dir.join("status.json"), "stale status file", &mut report);
+            }
+            for socket in [PathBuf::from(&hub_config.socket_path), crate::control::socket_path()] {
+                if is_dead_socket(&socket) {
+                    remove(&socket, "dead socket", &mut report);
+                }
+            }
+            prune_orphans(dry_run, &mut report);
+            if data_dir.join("servers.pid.json").exists() {
+                remove(&data_dir.join("servers.pid.json"), "server PID file", &mut report);
+            }
+            prune_sessions(hub_config, &mut report, &remove);
+        }
+    }
+
+    for dir in [data_dir.clone(), data_dir.join("logs")] {
+        for path in rotated_logs(&dir, log_retention) {
+            remove(&path, "old log", &mut report);
+        }
+    }
+    Ok(report)
+}
+
+/// How long orphaned servers get to exit before they're killed
+const ORPHAN_GRACE: Duration = Duration::from_secs(2);
+
+/// Stop the server processes a crashed hub left running, before this hub
+/// starts fresh ones that would contend with them for ports and locks;
+/// records of a hub that is still running are left alone
+pub async fn reap_orphans() -> Report {
+    let mut report = Report::default();
+    let servers = super::read_server_pids();
+    if servers.is_empty() {
+        return report;
+    }
+    if let Some(live) = servers.iter().find(|s| !s.hub_gone()) {
+        report.skipped.push(format!("servers of the running hub (PID {})", live.hub.unwrap_or_default()));
+        return report;
+    }
+
+    let mut stopping = stop_orphans(&servers, false, &mut report);
+    let deadline = tokio::time::Instant::now() + ORPHAN_GRACE;
+    loop {
+        stopping.retain(|s| crate::platform::is_alive(s.pid));
+        if stopping.is_empty() || tokio::time::Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    for server in stopping {
+        if let Err(e) = crate::platform::kill_tree(server.pid) {
+            warn!("Failed to kill orphaned server {} (PID {}): {:#}", server.name, server.pid, e);
+        }
+    }
+    let _ = super::remove_server_pids();
+    report
+}
+
+/// Stop server processes recorded by a hub that is gone
+fn prune_orphans(dry_run: bool, report: &mut Report) {
+    stop_orphans(&super::read_server_pids(), dry_run, report);
+}
+
+/// Ask recorded server processes that still run the recorded command (PIDs
+/// get reused) to stop, returning those asked
+fn stop_orphans<'a>(servers: &'a [RecordedServer], dry_run: bool, report: &mut Report) -> Vec<&'a RecordedServer> {
+    let mut stopping = Vec::new();
+    for server in servers {
+        if !crate::platform::is_alive(server.pid) {
+            continue;
+        }
+        let expected = server
+            .command
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map(|s| s.to_string_lossy().into_owned());
+        let actual = crate::platform::command_line(server.pid).unwrap_or_default();
+        match expected {
+            Some(expected) if actual.contains(&expected) => {
+                let what = format!("orphaned server {} (PID {})", server.name, server.pid);
+                if dry_run {
+                    report.stopped.push(what);
+                } else {
+                    match crate::platform::terminate_tree(server.pid) {
+                        Ok(()) => {
+                            report.stopped.push(what);
+                            stopping.push(server);
+                        }
+                        Err(e) => report.skipped.push(format!("{}: {:#}", what, e)),
+                    }
+                }
+            }
+            _ => report.skipped.push(format!(
+                "PID {} (was server {}) now runs something else; not stopping it",
+                server.pid, server.name
+            )),
+        }
+    }
+    stopping
+}
+
+/// Remove saved HTTP sessions that would all have expired by now, and
+/// half-written state files
+fn prune_sessions(hub_config: &HubConfig, report: &mut Report, remove: &impl Fn(&Path, &str, &mut Report)) {
+    let state_dir = crate::transport::durable::state_dir();
+    let timeout = Duration::from_secs(hub_config.http.clone().unwrap_or_default().session_timeout_secs);
+    let sessions = state_dir.join("sessions.json");
+    if age(&sessions).is_some_and(|age| age > timeout) {
+        remove(&sessions, "expired session store", report);
+    }
+    for entry in std::fs::read_dir(&state_dir).into_iter().flatten().flatten() {
+        if entry.path().extension().is_some_and(|e| e == "tmp") {
+            remove(&entry.path(), "partial state file", report);
+        }
+    }
+}
+
+/// `hub.log.1`, `hub.log.2026-01-01`, ... older than `retention`
+fn rotated_logs(dir: &Path, retention: Duration) -> Vec<PathBuf> {
+    let mut logs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.contains(".log.")))
+        .filter(|p| age(p).is_some_and(|age| age > retention))
+        .collect();
+    logs.sort();
+    logs
+}
+
+fn age(path: &Path) -> Option<Duration> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+/// A socket file nobody is listening on
+fn is_dead_socket(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        let is_socket = std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket());
+        is_socket
+            && std::os::unix::net::UnixStream::connect(path)
+                .is_err_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotated_logs() {
+        let dir = std::env::temp_dir().join(format!("citadel-prune-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["hub.log", "hub.log.1", "hub.log.2026-01-01", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        assert!(rotated_logs(&dir, Duration::from_secs(3600)).is_empty());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            rotated_logs(&dir, Duration::from_millis(10)),
+            vec![dir.join("hub.log.1"), dir.join("hub.log.2026-01-01")]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+//! Tool schema export
+//! Renders the aggregated tool catalog in the formats agent frameworks expect
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::router::catalog;
+
+/// Output format for `export-tools`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ToolFormat {
+    /// OpenAI function-calling tools
+    Openai,
+    /// Anthropic Messages API tools
+    Anthropic,
+    /// JSON Schema documents keyed by tool name
+    JsonSchema,
+}
+
+/// Tools per server, in server order
+pub type ToolCatalog = Vec<(String, Vec<Value>)>;
+
+/// Fetch every server's tools through the running hub's Unix socket
+///
+/// The hub pins a connection to the first server it sees, so each server gets
+/// its own connection.
+pub async fn live_catalog(socket_path: &str, servers: &[String]) -> Result<ToolCatalog> {
+    let mut catalog = Vec::new();
+    for server in servers {
+        catalog.push((server.clone(), live_tools(socket_path, server).await?));
+    }
+    Ok(catalog)
+}
+
+async fn live_tools(socket_path: &str, server: &str) -> Result<Vec<Value>> {
+    let stream = UnixStream::connect(socket_path)
+        .await
+        .context(format!("Failed to connect to hub at {}", socket_path))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut tools = Vec::new();
+    let mut cursor: Option<Value> = None;
+    for id in 1.. {
+        let mut params = serde_json::json!({ "server": server });
+        if let Some(cursor) = &cursor {
+            params["cursor"] = cursor.clone();
+        }
+        let request = serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": "tools/list", "params": params });
+        writer.write_all(format!("{}\n", request).as_bytes()).await?;
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let mut response: Value =
+            serde_json::from_str(&line).context(format!("Invalid tools/list response from {}", server))?;
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("tools/list failed on {}: {}", server, error);
+        }
+
+        let result = response.get_mut("result").map(Value::take).unwrap_or_default();
+        if let Some(page) = result.get("tools").and_then(|t| t.as_array()) {
+            tools.extend(page.iter().cloned());
+        }
+        match result.get("nextCursor") {
+            Some(next) if !next.is_null() => cursor = Some(next.clone()),
+            _ => break,
+        }
+    }
+    Ok(tools)
+}
+
+/// Tools from the on-disk catalog cache (used when the hub isn't running)
+pub fn cached_catalog() -> ToolCatalog {
+    catalog::read_all(&catalog::catalog_dir())
+        .into_iter()
+        .map(|(server, entry)| {
+            let tools = entry
+                .lists
+                .get("tools/list")
+                .and_then(|r| r.get("tools"))
+                .and_then(|t| t.as_array())
+                .cloned()
+                .unwrap_or_default();
+            (server, tools)
+        })
+        .collect()
+}
+
+/// Render the catalog in `format`
+///
+/// Tools are named `{server}__{tool}`, restricted to `[A-Za-z0-9_-]` and 64
+/// characters so the names are valid function names for both APIs.
+pub fn render(catalog: &ToolCatalog, format: ToolFormat) -> Value {
+    let tools = catalog.iter().flat_map(|(server, tools)| {
+        tools.iter().filter_map(move |tool| {
+            let name = tool.get("name")?.as_str()?;
+            let description = tool.get("description").and_then(|d| d.as_str()).unwrap_or("");
+            let schema = tool
+                .get("inputSchema")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "type": "object", "properties": {} }));
+            Some((qualified_name(server, name), description, schema))
+        })
+    });
+
+    match format {
+        ToolFormat::Openai => tools
+            .map(|(name, description, schema)| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": { "name": name, "description": description, "parameters": schema }
+                })
+            })
+            .collect(),
+        ToolFormat::Anthropic => tools
+            .map(|(name, description, schema)| {
+                serde_json::json!({ "name": name, "description": description, "input_schema": schema })
+            })
+            .collect(),
+        ToolFormat::JsonSchema => Value::Object(
+            tools
+                .map(|(name, description, mut schema)| {
+                    if let Some(schema) = schema.as_object_mut() {
+                        schema.insert("$schema".into(), "https://json-schema.org/draft/2020-12/schema".into());
+                        schema.insert("title".into(), name.clone().into());
+                        schema.insert("description".into(), description.into());
+                    }
+                    (name, schema)
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn qualified_name(server: &str, tool: &str) -> String {
+    format!("{}__{}", server, tool)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_formats() {
+        let catalog = vec![(
+            "git.hub".to_string(),
+            vec![serde_json::json!({
+                "name": "search",
+                "description": "Search repos",
+                "inputSchema": { "type": "object", "properties": { "q": { "type": "string" } } }
+            })],
+        )];
+
+        let openai = render(&catalog, ToolFormat::Openai);
+        assert_eq!(openai[0]["function"]["name"], "git_hub__search");
+        assert_eq!(openai[0]["function"]["parameters"]["properties"]["q"]["type"], "string");
+
+        let anthropic = render(&catalog, ToolFormat::Anthropic);
+        assert_eq!(anthropic[0]["input_schema"]["type"], "object");
+
+        let schemas = render(&catalog, ToolFormat::JsonSchema);
+        assert_eq!(schemas["git_hub__search"]["title"], "git_hub__search");
+    }
+}
@@ -0,0 +1,22 @@
+//! MCP Citadel
+//! Central hub for routing multiple MCP servers
+
+pub mod bench;
+pub mod bundle;
+pub mod compose;
+pub mod config;
+pub mod connect;
+pub mod control;
+pub mod daemon;
+pub mod export;
+pub mod logging;
+pub mod metrics;
+pub mod platform;
+pub mod redact;
+pub mod registry;
+pub mod report;
+pub mod router;
+pub mod summary;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
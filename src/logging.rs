@@ -0,0 +1,250 @@
+//! Log levels and log files
+//! The hub's log filter comes from `RUST_LOG` if set, else `log_level` in the
+//! hub config: a level (`info`), per-module directives
+//! (`info,router=debug,transport::http=trace`), or both. Modules of the hub
+//! can be named without the `mcp_citadel::` prefix. `mcp-citadel log-level`,
+//! the control method `log.level`, and `PUT /admin/log-level` change the filter
+//! of a running hub until it restarts or reloads its config.
+//!
+//! The hub's `--log-file` and the servers' stderr logs are rotated as
+//! `[logging]` says: each day and past `max_file_mb`, the file is renamed to
+//! `{name}.{date}` (`hub.log.2026-10-16`, then `.1`, `.2`, ... within a day)
+//! and the oldest rotated files past `max_files` are removed.
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::config::LoggingConfig;
+
+/// Path of the HTTP endpoint
+pub const LOG_LEVEL_PATH: &str = "/admin/log-level";
+
+/// The crate's modules are targets under this
+const CRATE_TARGET: &str = "mcp_citadel";
+
+/// Top-level modules of the crate, which directives may name on their own
+const KNOWN_MODULES: &[&str] = &[
+    "bench", "bundle", "compose", "config", "connect", "control", "daemon", "export", "logging", "metrics", "platform", "redact", "registry",
+    "report", "router", "summary", "transport",
+];
+
+struct Filter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// The directives in effect, as given
+    current: Mutex<String>,
+    /// Set by `RUST_LOG`, which the config doesn't override
+    from_env: bool,
+}
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+/// The filter layer for the hub's subscriber, from `RUST_LOG` or else `default`;
+/// changed later with [`set`]
+pub fn layer(default: &str) -> reload::Layer<EnvFilter, Registry> {
+    let env = std::env::var("RUST_LOG").ok().filter(|directives| parse(directives).is_ok());
+    let directives = env.clone().unwrap_or_else(|| default.to_string());
+    let (layer, handle) = reload::Layer::new(parse(&directives).unwrap_or_else(|_| EnvFilter::new("info")));
+    let _ = FILTER.set(Filter { handle, current: Mutex::new(directives), from_env: env.is_some() });
+    layer
+}
+
+/// Apply `log_level` from the hub config, unless `RUST_LOG` set the filter
+pub fn configure(log_level: &str) -> Result<()> {
+    match FILTER.get() {
+        Some(filter) if !filter.from_env => set(log_level),
+        _ => Ok(()),
+    }
+}
+
+/// Replace the filter of the running process
+pub fn set(directives: &str) -> Result<()> {
+    let filter = FILTER.get().context("Logging isn't set up in this process")?;
+    filter.handle.reload(parse(directives)?).context("Failed to change the log filter")?;
+    *filter.current.lock().unwrap() = directives.to_string();
+    Ok(())
+}
+
+/// The directives in effect; None before logging is set up
+pub fn current() -> Option<String> {
+    FILTER.get().map(|filter| filter.current.lock().unwrap().clone())
+}
+
+/// An `EnvFilter` from directives that may name the hub's modules without the
+/// crate prefix
+pub fn parse(directives: &str) -> Result<EnvFilter> {
+    let expanded: Vec<String> = directives
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(expand)
+        .collect();
+    EnvFilter::builder()
+        .parse(expanded.join(","))
+        .context(format!("Invalid log level '{}' (e.g. info, or info,router=debug)", directives))
+}
+
+/// `router=debug` → `mcp_citadel::router=debug`; levels, targets of other
+/// crates, and span filters stay as they are
+fn expand(directive: &str) -> String {
+    let Some((target, level)) = directive.split_once('=') else {
+        return directive.to_string();
+    };
+    match KNOWN_MODULES.contains(&target.split("::").next().unwrap_or_default()) {
+        true => format!("{}::{}={}", CRATE_TARGET, target, level),
+        false => directive.to_string(),
+    }
+}
+
+/// How log files are rotated; the default until [`configure_rotation`]
+static ROTATION: RwLock<Option<LoggingConfig>> = RwLock::new(None);
+
+/// Rotate log files as `[logging]` says from now on
+pub fn configure_rotation(config: &LoggingConfig) {
+    *ROTATION.write().unwrap() = Some(config.clone());
+}
+
+fn rotation() -> LoggingConfig {
+    ROTATION.read().unwrap().clone().unwrap_or_default()
+}
+
+/// A log file appended to and rotated by day and size
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// Day the lines in the file are from
+    day: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        let day = metadata.modified().map(|modified| chrono::DateTime::<Local>::from(modified).date_naive()).unwrap_or_else(|_| today());
+        Ok(Self { path, file, size: metadata.len(), day })
+    }
+
+    /// Append `buf`, first rotating the file if `config` says it's due
+    fn append(&mut self, buf: &[u8], config: &LoggingConfig) -> std::io::Result<()> {
+        let too_big = config.max_file_mb > 0 && self.size + buf.len() as u64 > config.max_file_mb * 1024 * 1024;
+        let too_old = config.daily && self.day != today();
+        // A failed rotation is tried again with the next write, which goes to the old file meanwhile
+        if self.size > 0 && (too_big || too_old) && self.rotate(config.max_files).is_ok() {
+            self.day = today();
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, max_files: usize) -> std::io::Result<()> {
+        std::fs::rename(&self.path, rotated_path(&self.path, self.day))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        for old in rotated_files(&self.path).into_iter().rev().skip(max_files) {
+            let _ = std::fs::remove_file(old);
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.append(buf, &rotation())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// `{path}.{day}`, or `{path}.{day}.{n}` for the first `n` not taken yet
+fn rotated_path(path: &Path, day: NaiveDate) -> PathBuf {
+    let base = format!("{}.{}", path.display(), day.format("%Y-%m-%d"));
+    std::iter::once(PathBuf::from(&base))
+        .chain((1..).map(|n| PathBuf::from(format!("{}.{}", base, n))))
+        .find(|candidate| !candidate.exists())
+        .expect("some suffix is free")
+}
+
+/// Rotated files of the log at `path`, oldest first
+fn rotated_files(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let prefix = format!("{}.", name);
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_name().to_str().is_some_and(|n| n.starts_with(&prefix)))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    files.sort();
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_directives() {
+        assert_eq!(expand("router=debug"), "mcp_citadel::router=debug");
+        assert_eq!(expand("transport::http=trace"), "mcp_citadel::transport::http=trace");
+        assert_eq!(expand("mcp_citadel::router=debug"), "mcp_citadel::router=debug");
+        assert_eq!(expand("hyper=warn"), "hyper=warn");
+        assert_eq!(expand("info"), "info");
+        assert!(parse("info, router=debug").is_ok());
+        assert!(parse("router=loud").is_err());
+    }
+
+    #[test]
+    fn test_set_at_runtime() {
+        assert!(set("debug").is_err());
+        let _layer = layer("warn");
+        set("info,router=debug").unwrap();
+        assert_eq!(current().as_deref(), Some("info,router=debug"));
+        assert!(set("router=loud").is_err());
+        assert_eq!(current().as_deref(), Some("info,router=debug"));
+    }
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-rotate-{}", std::process::id()));
+        let path = dir.join("hub.log");
+        let config = LoggingConfig { daily: true, max_file_mb: 1, max_files: 2 };
+        let mut log = RotatingFile::open(&path).unwrap();
+        let line = vec![b'x'; 700 * 1024];
+        for _ in 0..4 {
+            log.append(&line, &config).unwrap();
+        }
+        let day = today().format("%Y-%m-%d");
+        assert!(!dir.join(format!("hub.log.{}", day)).exists());
+        assert!(dir.join(format!("hub.log.{}.1", day)).exists() && dir.join(format!("hub.log.{}.2", day)).exists());
+        assert_eq!(rotated_files(&path).len(), 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), line.len() as u64);
+
+        // A day later, the current file is rotated before the next line
+        log.day = log.day.pred_opt().unwrap();
+        log.append(b"tomorrow\n", &LoggingConfig { max_file_mb: 0, ..config }).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "tomorrow\n");
+        assert_eq!(rotated_files(&path).len(), 2);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,112 +1,244 @@
 mod cli;
-mod config;
-mod daemon;
-mod metrics;
-mod router;
-mod transport;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::signal;
-use tracing::{info, warn};
-use tracing_subscriber;
+use tracing::{debug, info, warn};
 
-use cli::{Cli, Commands};
-use config::{load_claude_config, load_hub_config};
-use router::{HubManager, HubRouter};
-use transport::HttpTransport;
+use cli::{ApprovalCommands, Cli, Commands, ConfigCommands, ExportCommands, MaintenanceCommands, ServerCommands, SessionCommands, StartArgs, StateCommands};
+use mcp_citadel::config::lockfile::{self, LockedPackage, Lockfile};
+use mcp_citadel::config::{self, history, load_claude_config, load_hub_config};
+use mcp_citadel::control::{self, ControlClient, ControlServer};
+use mcp_citadel::router::workspace::Workspaces;
+use mcp_citadel::router::{self, HubManager, HubRouter};
+use mcp_citadel::transport::HttpTransport;
+use mcp_citadel::redact::Redacting;
+use mcp_citadel::{bench, bundle, compose, connect, daemon, export, logging, metrics, registry, report, summary};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Set before any threads exist; the daemonized hub inherits them
+    if let Some(path) = &cli.config {
+        std::env::set_var("MCP_CITADEL_CONFIG", path);
+    }
+    if let Some(dir) = &cli.data_dir {
+        std::env::set_var("MCP_CITADEL_DATA_DIR", dir);
+    }
+    if matches!(&cli.command, Commands::Start(args) if args.cloud) {
+        config::enter_cloud_mode();
+    }
+
+    // Only the hub itself is tuned; other commands must work even with a broken config
+    let runtime_config = match &cli.command {
+        Commands::Start(args) if args.foreground || args.cloud => load_hub_config()?.runtime,
+        _ => config::RuntimeConfig::default(),
+    };
+
+    build_runtime(&runtime_config)?.block_on(run(cli))
+}
+
+/// Build the tokio runtime, applying any configured sizing
+fn build_runtime(runtime_config: &config::RuntimeConfig) -> Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(threads) = runtime_config.worker_threads {
+        builder.worker_threads(threads.max(1));
+    }
+    if let Some(threads) = runtime_config.max_blocking_threads {
+        builder.max_blocking_threads(threads.max(1));
+    }
+    Ok(builder.build()?)
+}
+
+async fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::Start { foreground, log_file, enable_http, http_port, http_host, message_buffer_size } => {
-            if foreground {
-                start_hub(foreground, log_file, enable_http, http_port, http_host, message_buffer_size).await?;
+        Commands::Start(args) => {
+            if args.foreground || args.cloud {
+                start_hub(args).await?;
             } else {
-                daemon::daemonize()?;
+                daemon::daemonize(args.read_only)?;
             }
         }
         Commands::Stop => {
             daemon::stop()?;
         }
-        Commands::Status => {
-            let status = daemon::status()?;
-            println!("{}", status);
+        Commands::Status { json } => {
+            show_status(json).await?;
         }
         Commands::Servers => {
-            list_servers()?;
+            list_servers().await?;
+        }
+        Commands::Server { action } => {
+            control_server(action).await?;
+        }
+        Commands::Reload => {
+            reload_servers().await?;
+        }
+        Commands::Watch { json } => {
+            watch_events(json).await?;
+        }
+        Commands::Events { follow, json } => {
+            show_events(follow, json).await?;
+        }
+        Commands::Usage { json } => {
+            show_usage(json).await?;
+        }
+        Commands::Clients { json } => {
+            list_clients(json).await?;
+        }
+        Commands::Summary { remote, key, json } => {
+            show_summary(remote.as_deref(), key.as_deref(), json).await?;
+        }
+        Commands::Session { command } => match command {
+            SessionCommands::List { json } => list_sessions(json).await?,
+            SessionCommands::Export { id, output, log_file } => export_session(&id, output, log_file).await?,
+        },
+        Commands::Doctor => {
+            doctor()?;
+        }
+        Commands::Prune { dry_run, log_days } => {
+            prune(dry_run, log_days)?;
+        }
+        Commands::State { command } => match command {
+            StateCommands::Compact { dry_run, max_age_days, max_size_mb } => compact_state(dry_run, max_age_days, max_size_mb)?,
+        },
+        Commands::Maintenance { command } => match command {
+            MaintenanceCommands::On { duration, reason } => begin_maintenance(&duration, reason).await?,
+            MaintenanceCommands::Off => end_maintenance().await?,
+            MaintenanceCommands::Status => maintenance_status().await?,
+        },
+        Commands::Approvals { command } => match command {
+            ApprovalCommands::List { json } => list_approvals(json).await?,
+            ApprovalCommands::Approve { id } => decide_approval(id, true, None).await?,
+            ApprovalCommands::Deny { id, reason } => decide_approval(id, false, reason).await?,
+        },
+        Commands::LogLevel { level } => {
+            let mut control = ControlClient::connect().await?;
+            let result = control.call("log.level", serde_json::json!({ "level": level })).await?;
+            let current = result["level"].as_str().unwrap_or("unknown");
+            match level {
+                Some(_) => println!("✓ Log level set to {}", current),
+                None => println!("{}", current),
+            }
+        }
+        Commands::History { server, tool, session, since, failed, limit, json } => {
+            let query = router::history::HistoryQuery { server, tool, session, since, failed, limit: Some(limit) };
+            show_history(&query, json)?;
+        }
+        Commands::Tools { server } => {
+            list_tools(server.as_deref())?;
+        }
+        Commands::Connect { client } => {
+            connect_client(client)?;
+        }
+        Commands::Disconnect { client } => {
+            disconnect_client(client)?;
+        }
+        Commands::ExportTools { format, output, server } => {
+            export_tools(format, output, server).await?;
         }
+        Commands::Export { target: ExportCommands::Compose { output, http_port } } => {
+            export_compose(output, http_port)?;
+        }
+        Commands::Install { name, server_name, env, registry, no_install, reload } => {
+            install_server(&name, server_name, &env, registry, no_install, reload).await?;
+        }
+        Commands::Import { bundle, server_name, settings, reload } => {
+            import_bundle(&bundle, server_name, &settings, reload).await?;
+        }
+        Commands::Update { servers, check } => {
+            update_servers(&servers, check).await?;
+        }
+        Commands::Bench { local, requests, concurrency, payload_size } => {
+            run_bench(local, requests, concurrency, payload_size).await?;
+        }
+        Commands::EchoServer => {
+            bench::run_echo_server().await?;
+        }
+        Commands::Config { action } => match action {
+            ConfigCommands::Edit => edit_config().await?,
+            ConfigCommands::History => config_history()?,
+            ConfigCommands::Rollback { n } => rollback_config(n).await?,
+        },
     }
 
     Ok(())
 }
 
-async fn start_hub(
-    _foreground: bool, 
-    log_file: Option<std::path::PathBuf>,
-    enable_http: bool,
-    http_port: u16,
-    http_host: String,
-    message_buffer_size: usize,
-) -> Result<()> {
-    // Check if already running
-    if daemon::is_running()? {
-        eprintln!("❌ MCP Citadel is already running!");
-        eprintln!("   Check status: mcp-citadel status");
-        eprintln!("   Stop it:      mcp-citadel stop");
-        std::process::exit(1);
+async fn start_hub(args: StartArgs) -> Result<()> {
+    let StartArgs { log_file, enable_http, http_port, http_host, message_buffer_size, cloud, drain_timeout, read_only, .. } = args;
+
+    // A container is the only hub in its PID namespace, and a PID file left on
+    // a persistent volume would otherwise block the next start
+    if !cloud {
+        // Check if already running
+        if daemon::is_running()? {
+            eprintln!("❌ MCP Citadel is already running!");
+            eprintln!("   Check status: mcp-citadel status");
+            eprintln!("   Stop it:      mcp-citadel stop");
+            std::process::exit(1);
+        }
+
+        // Write PID file immediately
+        daemon::write_pid(std::process::id())?;
     }
-    
-    // Write PID file immediately
-    daemon::write_pid(std::process::id())?;
-    
-    // Setup logging
-    if let Some(log_path) = log_file {
-        // Log to file
-        use std::sync::{Arc, Mutex};
-        use tracing_subscriber::fmt::writer::MakeWriter;
-        
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&log_path)?;
-        
-        let file_writer = Arc::new(Mutex::new(file));
-        
-        // Custom writer wrapper
-        struct FileWriter(Arc<Mutex<std::fs::File>>);
-        
-        impl<'a> MakeWriter<'a> for FileWriter {
-            type Writer = std::io::LineWriter<std::fs::File>;
-            
-            fn make_writer(&'a self) -> Self::Writer {
-                let file = self.0.lock().unwrap();
-                let fd = file.try_clone().unwrap();
-                std::io::LineWriter::new(fd)
-            }
+
+    // Cloud mode reports through structured log events instead of the console banner
+    let announce = |message: String| {
+        if cloud {
+            info!("{}", message.trim_start());
+        } else {
+            println!("{}", message);
         }
-        
-        tracing_subscriber::fmt()
+    };
+
+    // Setup logging, at the configured level once the config is loaded
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    let filter = logging::layer("info");
+    if cloud {
+        let json = tracing_subscriber::fmt::layer()
+            .json()
+            .with_target(false)
+            .with_current_span(false)
+            .with_writer(Redacting(std::io::stdout));
+        tracing_subscriber::registry().with(filter).with(json).init();
+    } else if let Some(log_path) = log_file {
+        // Log to file, rotated as [logging] says once the config is loaded
+        let file = logging::RotatingFile::open(&log_path)?;
+        let fmt = tracing_subscriber::fmt::layer()
             .with_target(false)
             .with_level(true)
-            .with_writer(FileWriter(file_writer))
-            .init();
+            .with_writer(Redacting(std::sync::Mutex::new(file)));
+        tracing_subscriber::registry().with(filter).with(fmt).init();
         
         println!("✓ Logging to: {:?}", log_path);
     } else {
         // Log to stdout
-        tracing_subscriber::fmt()
+        let fmt = tracing_subscriber::fmt::layer()
             .with_target(false)
             .with_level(true)
-            .init();
+            .with_writer(Redacting(std::io::stdout));
+        tracing_subscriber::registry().with(filter).with(fmt).init();
     }
 
     // Load configuration
     let mut hub_config = load_hub_config()?;
+    if let Err(e) = logging::configure(&hub_config.log_level) {
+        warn!("Logging at info: {:#}", e);
+    }
+    logging::configure_rotation(&hub_config.logging);
     
+    // Probes need the HTTP transport reachable from outside the pod
+    if cloud && !enable_http {
+        let http_config = hub_config.http.get_or_insert_with(Default::default);
+        http_config.enabled = true;
+        http_config.host = "0.0.0.0".to_string();
+    }
+
     // Override HTTP config from CLI flags
     if enable_http {
         if let Some(http_config) = &mut hub_config.http {
@@ -117,52 +249,128 @@ async fn start_hub(
         }
     }
     
-    let server_configs = load_claude_config(&hub_config.claude_config_path)?;
+    hub_config.router.read_only_servers |= read_only;
+
+    let server_configs = config::load_servers(&hub_config)?;
+    // What a reload replaces is snapshotted from this
+    if let Err(e) = history::record_applied(&hub_config) {
+        warn!("Failed to record the applied config: {:#}", e);
+    }
 
-    println!("🚀 Starting MCP Citadel...");
-    println!("   Loaded {} MCP servers from Claude config", server_configs.len());
-    println!("");
+    announce("🚀 Starting MCP Citadel...".to_string());
+    announce(format!("   Loaded {} MCP servers from Claude config", server_configs.len()));
+    if !cloud {
+        println!();
+    }
+
+    // Servers a crashed hub left running would contend with fresh ones; only
+    // the daemon reaps them, as other managers may share its data directory
+    let reaped = daemon::prune::reap_orphans().await;
+    for what in &reaped.stopped {
+        warn!("Stopped {} left by a previous run", what);
+    }
+    for what in &reaped.skipped {
+        debug!("Not reaping {}", what);
+    }
 
     // Create hub manager and start all servers
-    let manager = HubManager::new(server_configs).await?;
+    let manager = HubManager::new(server_configs, hub_config.router.clone())
+        .await?
+        .with_workspaces(Workspaces::new(hub_config.workspaces.clone()))
+        .with_webhooks(hub_config.webhooks.clone());
 
     let server_list = manager.list_servers().await;
-    println!("✓ Started {} servers:", server_list.len());
-    for server in &server_list {
-        println!("  • {}", server);
+    if cloud {
+        info!(servers = ?server_list, "Started {} servers", server_list.len());
+    } else {
+        println!("✓ Started {} servers:", server_list.len());
+        for server in &server_list {
+            println!("  • {}", server);
+        }
+        println!();
     }
-    println!("");
 
     // Wrap manager in Arc for sharing
     let manager = Arc::new(manager);
 
-    println!("✓ Router ready on {}", hub_config.socket_path);
-    println!("");
-    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    println!("  MCP Citadel is running!");
-    println!("  Press Ctrl+C to stop");
-    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    println!("");
+    announce(format!("✓ Router ready on {}", hub_config.socket_path));
+    if !cloud {
+        println!();
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        println!("  MCP Citadel is running!");
+        println!("  Press Ctrl+C to stop");
+        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+        println!();
+    }
+
+    // Record server PIDs so `prune` can find them if the hub dies
+    let pids_task = (!cloud).then(|| {
+        let manager = Arc::clone(&manager);
+        tokio::spawn(async move {
+            let mut events = manager.lifecycle_events();
+            loop {
+                record_server_pids(&manager).await;
+                if let Err(tokio::sync::broadcast::error::RecvError::Closed) = events.recv().await {
+                    break;
+                }
+            }
+        })
+    });
+
+    if hub_config.metrics.exporter == config::MetricsExporter::Statsd {
+        metrics::spawn_statsd(&hub_config.metrics).await;
+    }
 
     // Start health monitoring task
     let health_manager = Arc::clone(&manager);
+    let check_interval = hub_config.router.restarts.check_interval_secs.max(1);
+    let status_socket_path = hub_config.socket_path.clone();
     let health_task = tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(check_interval));
         loop {
             interval.tick().await;
             if let Err(e) = health_manager.health_check().await {
                 eprintln!("Health check error: {}", e);
             }
+            health_manager.run_restart_schedules().await;
+            health_manager.stop_idle_servers().await;
             
             // Write status file
             let uptime = health_manager.uptime();
-            let count = health_manager.server_count().await;
-            if let Err(e) = daemon::write_status(count, uptime) {
+            let servers = health_manager.server_info().await;
+            metrics::set_mcp_servers_up(servers.iter().filter(|s| s.running).count());
+            if let Err(e) = daemon::write_status(&status_socket_path, uptime, &servers) {
                 eprintln!("Failed to write status: {}", e);
             }
         }
     });
 
+    // Keep the data dir within the retention limits
+    let compact_task = (hub_config.state.compact_interval_secs > 0).then(|| {
+        let hub_config = hub_config.clone();
+        tokio::spawn(async move {
+            let period = std::time::Duration::from_secs(hub_config.state.compact_interval_secs);
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let hub_config = hub_config.clone();
+                let result = tokio::task::spawn_blocking(move || daemon::compact::compact(&hub_config, &hub_config.state, false)).await;
+                match result {
+                    Ok(Ok(report)) => {
+                        if !report.removed.is_empty() {
+                            info!("Compacted state: removed {} entries ({} bytes)", report.removed.len(), report.freed());
+                        }
+                        for line in &report.failed {
+                            warn!("State compaction failed for {}", line);
+                        }
+                    }
+                    Ok(Err(e)) => warn!("State compaction error: {:#}", e),
+                    Err(e) => warn!("State compaction panicked: {}", e),
+                }
+            }
+        })
+    });
+
     // Start Unix socket router in background
     let router_manager = Arc::clone(&manager);
     let socket_path_for_cleanup = hub_config.socket_path.clone();
@@ -171,13 +379,26 @@ async fn start_hub(
         router.start().await
     });
 
+    // Control plane for the CLI; the hub keeps running without it
+    let control_path = control::socket_path();
+    let control_task = tokio::spawn({
+        let server = ControlServer::new(control_path.clone(), Arc::clone(&manager));
+        async move {
+            if let Err(e) = server.start().await {
+                warn!("Control plane unavailable: {:#}", e);
+            }
+        }
+    });
+
     // Start HTTP transport if enabled
+    let drain = Arc::new(tokio::sync::Notify::new());
     let http_task = if let Some(http_config) = hub_config.http.clone() {
         if http_config.enabled {
             let http_manager = Arc::clone(&manager);
+            let drain = Arc::clone(&drain);
             Some(tokio::spawn(async move {
                 let transport = HttpTransport::new(http_config, http_manager);
-                transport.start().await
+                transport.start_with_shutdown(async move { drain.notified().await }).await
             }))
         } else {
             None
@@ -187,7 +408,8 @@ async fn start_hub(
     };
 
     // Wait for shutdown signal
-    if let Some(http) = http_task {
+    let mut draining_http = None;
+    if let Some(mut http) = http_task {
         tokio::select! {
             result = router_task => {
                 match result {
@@ -196,7 +418,7 @@ async fn start_hub(
                     Err(e) => warn!("Unix socket router panicked: {}", e),
                 }
             }
-            result = http => {
+            result = &mut http => {
                 match result {
                     Ok(Ok(())) => info!("HTTP transport completed"),
                     Ok(Err(e)) => warn!("HTTP transport error: {}", e),
@@ -205,6 +427,7 @@ async fn start_hub(
             }
             _ = shutdown_signal() => {
                 info!("Shutdown signal received");
+                draining_http = Some(http);
             }
         }
     } else {
@@ -222,36 +445,61 @@ async fn start_hub(
         }
     }
 
-    // Graceful shutdown
-    println!("");
-    println!("🛑 Shutting down MCP Citadel...");
-    
-    // Stop health monitoring
+    // Graceful shutdown: fail readiness, stop accepting HTTP connections, and
+    // give open requests until the drain timeout before stopping the servers
+    manager.begin_drain();
+    if let Some(http) = draining_http {
+        drain.notify_one();
+        info!("Draining HTTP connections (up to {}s)", drain_timeout);
+        if tokio::time::timeout(std::time::Duration::from_secs(drain_timeout), http).await.is_err() {
+            warn!("Drain timeout reached, closing remaining HTTP connections");
+        }
+    }
+
+    if !cloud {
+        println!();
+    }
+    announce("🛑 Shutting down MCP Citadel...".to_string());
+
+    // Stop health monitoring and the control plane
     health_task.abort();
+    if let Some(task) = pids_task {
+        task.abort();
+    }
+    if let Some(task) = compact_task {
+        task.abort();
+    }
+    control_task.abort();
+    let _ = std::fs::remove_file(&control_path);
     
     // Stop all servers
     if let Err(e) = manager.stop_all().await {
         warn!("Error stopping servers: {}", e);
     } else {
-        println!("✓ All MCP servers stopped");
+        announce("✓ All MCP servers stopped".to_string());
     }
-    
+
     // Remove socket file
     if let Err(e) = std::fs::remove_file(&socket_path_for_cleanup) {
         warn!("Failed to remove socket file: {}", e);
     } else {
-        println!("✓ Socket file removed");
+        announce("✓ Socket file removed".to_string());
     }
-    
+
     // Remove PID file
-    if let Err(e) = daemon::remove_pid() {
-        warn!("Failed to remove PID file: {}", e);
-    } else {
-        println!("✓ PID file removed");
+    if !cloud {
+        let _ = daemon::remove_server_pids();
+        if let Err(e) = daemon::remove_pid() {
+            warn!("Failed to remove PID file: {}", e);
+        } else {
+            println!("✓ PID file removed");
+        }
+    }
+
+    announce("✓ MCP Citadel stopped gracefully".to_string());
+    if !cloud {
+        println!();
     }
-    
-    println!("✓ MCP Citadel stopped gracefully");
-    println!("");
 
     Ok(())
 }
@@ -281,13 +529,133 @@ async fn shutdown_signal() {
     }
 }
 
-fn list_servers() -> Result<()> {
+/// Live status from the control plane, or the PID and status files when it is unreachable
+async fn show_status(json: bool) -> Result<()> {
+    // The live state when the hub answers, else what it last wrote
+    let status = match ControlClient::connect().await {
+        Ok(mut control) => {
+            let mut status = control.call("status", serde_json::json!({})).await?;
+            if let (Some(status), serde_json::Value::Object(stats)) =
+                (status.as_object_mut(), control.call("stats", serde_json::json!({})).await?)
+            {
+                status.extend(stats);
+            }
+            status["servers"] = control.call("servers.list", serde_json::json!({})).await?;
+            Some(status)
+        }
+        Err(_) => daemon::status()?,
+    };
+
+    if json {
+        let status = status.unwrap_or_else(|| serde_json::json!({ "running": false }));
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    let Some(status) = status else {
+        println!("Hub is not running");
+        return Ok(());
+    };
+
+    let uptime = status["uptime_secs"].as_u64().or(status["uptime_seconds"].as_u64());
+    println!();
+    println!(
+        "● MCP Citadel running (PID {}{})",
+        status["pid"],
+        uptime.map_or(String::new(), |secs| format!(", up {}", format_uptime(secs)))
+    );
+    if let Some(socket) = status["socket_path"].as_str() {
+        println!("  Socket: {}", socket);
+    }
+    let servers: Vec<router::ServerInfo> = serde_json::from_value(status["servers"].clone()).unwrap_or_default();
+    if servers.is_empty() {
+        println!();
+        return Ok(());
+    }
+    println!("  Servers: {}/{} running", servers.iter().filter(|s| s.running).count(), servers.len());
+    println!();
+    println!("  {:<20} {:<13} {:<8} {:<8} {:<9} {:<10} LAST ERROR", "SERVER", "STATE", "PID", "UPTIME", "RESTARTS", "IN FLIGHT");
+    for server in servers {
+        let state = serde_json::to_value(server.state)?;
+        println!(
+            "  {:<20} {:<13} {:<8} {:<8} {:<9} {:<10} {}",
+            server.name,
+            state.as_str().unwrap_or("unknown"),
+            server.pid.map_or("-".to_string(), |pid| pid.to_string()),
+            server.uptime_secs.map_or("-".to_string(), format_uptime),
+            server.restarts,
+            server.in_flight,
+            server.last_error.as_deref().map_or("-".to_string(), |e| one_line(e, 60))
+        );
+    }
+    println!();
+    Ok(())
+}
+
+async fn record_server_pids(manager: &HubManager) {
+    let servers: Vec<_> = manager
+        .server_info()
+        .await
+        .into_iter()
+        .filter_map(|s| Some(daemon::RecordedServer { pid: s.pid?, name: s.name, command: s.command_path, hub: Some(std::process::id()) }))
+        .collect();
+    if let Err(e) = daemon::write_server_pids(&servers) {
+        warn!("Failed to record server PIDs: {}", e);
+    }
+}
+
+async fn list_servers() -> Result<()> {
+    if let Ok(mut control) = ControlClient::connect().await {
+        let servers: Vec<router::ServerInfo> = serde_json::from_value(control.call("servers.list", serde_json::json!({})).await?)?;
+
+        println!();
+        println!("📋 MCP Servers:");
+        println!();
+        for server in servers {
+            if server.running {
+                let state = match server.state {
+                    router::ServerState::Unresponsive => "unresponsive",
+                    _ => "running",
+                };
+                println!(
+                    "  ● {:<20} {:<8} PID {:<7} up {:<8} {} requests, {} restarts",
+                    server.name,
+                    state,
+                    server.pid.map_or("-".to_string(), |pid| pid.to_string()),
+                    format_uptime(server.uptime_secs.unwrap_or(0)),
+                    server.requests,
+                    server.restarts
+                );
+                if let Some(path) = &server.command_path {
+                    println!("    {}", path.display());
+                }
+                if let Some(url) = &server.url {
+                    println!("    {}", url);
+                }
+            } else {
+                let state = match server.state {
+                    router::ServerState::Initializing => "initializing",
+                    router::ServerState::Idle => "idle (starts on first request)",
+                    router::ServerState::Crashed => "crashed",
+                    router::ServerState::Restarting => "restarting",
+                    router::ServerState::Disabled => "disabled (server start brings it back)",
+                    _ => "stopped",
+                };
+                println!("  ○ {:<20} {}", server.name, state);
+            }
+            if server.pinned > 0 {
+                println!("    {} session process{}", server.pinned, if server.pinned == 1 { "" } else { "es" });
+            }
+        }
+        println!();
+        return Ok(());
+    }
+
     let hub_config = load_hub_config()?;
-    let server_configs = load_claude_config(&hub_config.claude_config_path)?;
+    let server_configs = config::load_servers(&hub_config)?;
 
-    println!("");
-    println!("📋 Configured MCP Servers:");
-    println!("");
+    println!();
+    println!("📋 Configured MCP Servers (hub not running):");
+    println!();
 
     for config in server_configs {
         println!("  {} - {} {:?}", 
@@ -297,6 +665,1025 @@ fn list_servers() -> Result<()> {
         );
     }
 
-    println!("");
+    println!();
+    Ok(())
+}
+
+/// Report configuration problems, including server commands that can't be
+/// found on the hub's PATH or in the usual runtime install locations
+fn doctor() -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let server_configs = config::load_servers(&hub_config)?;
+    let resolver = config::resolve::Resolver::global();
+
+    println!();
+    println!("🩺 MCP Citadel doctor");
+    println!();
+    println!("  Claude config: {}", hub_config.claude_config_path.display());
+    println!();
+
+    let mut problems = 0;
+    for server in &server_configs {
+        if server.mock.is_some() {
+            println!("  ✓ {:<20} (mock)", server.name);
+            continue;
+        }
+        if let Some(remote) = &server.remote {
+            println!("  ✓ {:<20} {}", server.name, remote.url);
+            continue;
+        }
+        // A container's command is inside its image; the hub needs the runtime
+        let command = server.container.as_ref().map_or(&server.command, |c| &c.runtime);
+        match resolver.resolve(command, server.env.get("PATH").map(String::as_str)) {
+            Some(resolved) => println!("  ✓ {:<20} {}", server.name, resolved.path.display()),
+            None => {
+                problems += 1;
+                println!("  ✗ {:<20} command not found: {}", server.name, command);
+            }
+        }
+    }
+    println!();
+
+    if problems > 0 {
+        println!("Searched:");
+        for dir in resolver.dirs() {
+            println!("  {}", dir.display());
+        }
+        println!();
+        println!("Install the missing runtimes or set an absolute \"command\" in the Claude config.");
+        std::process::exit(1);
+    }
+
+    println!("✓ All {} server commands resolved", server_configs.len());
+    Ok(())
+}
+
+fn prune(dry_run: bool, log_days: u64) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let retention = std::time::Duration::from_secs(log_days * 24 * 3600);
+    let report = daemon::prune::prune(&hub_config, retention, dry_run)?;
+
+    println!();
+    for line in &report.skipped {
+        println!("  ⏭  {}", line);
+    }
+    for line in &report.stopped {
+        println!("  {} {}", if dry_run { "would stop" } else { "✓ stopped" }, line);
+    }
+    for line in &report.removed {
+        println!("  {} {}", if dry_run { "would remove" } else { "✓ removed" }, line);
+    }
+    if report.stopped.is_empty() && report.removed.is_empty() {
+        println!("✓ Nothing to clean up");
+    }
+    println!();
+    Ok(())
+}
+
+fn compact_state(dry_run: bool, max_age_days: Option<u64>, max_size_mb: Option<u64>) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let mut limits = hub_config.state.clone();
+    limits.max_age_days = max_age_days.unwrap_or(limits.max_age_days);
+    limits.max_size_mb = max_size_mb.unwrap_or(limits.max_size_mb);
+    let report = daemon::compact::compact(&hub_config, &limits, dry_run)?;
+
+    println!();
+    for item in &report.removed {
+        let verb = if dry_run { "would remove" } else { "✓ removed" };
+        println!("  {} {} {} ({})", verb, item.store, item.path.display(), format_bytes(item.bytes));
+    }
+    for line in &report.failed {
+        println!("  ⚠️  {}", line);
+    }
+    if report.removed.is_empty() {
+        println!("✓ Nothing past the retention limits");
+    } else {
+        println!();
+        println!("  {} {}", if dry_run { "Would free" } else { "Freed" }, format_bytes(report.freed()));
+    }
+    for (store, bytes) in &report.remaining {
+        println!("  {:<8} {}", store, format_bytes(*bytes));
+    }
+    println!();
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+fn list_tools(server: Option<&str>) -> Result<()> {
+    let entries = router::catalog::read_all(&router::catalog::catalog_dir());
+
+    println!();
+    println!("🧰 Cached MCP Tools:");
+    println!();
+
+    let mut shown = 0;
+    for (name, entry) in entries {
+        if server.is_some_and(|s| s != name) {
+            continue;
+        }
+        shown += 1;
+
+        let tools = entry
+            .lists
+            .get("tools/list")
+            .and_then(|r| r.get("tools"))
+            .and_then(|t| t.as_array());
+
+        match tools {
+            Some(tools) => {
+                println!("  {} ({} tools, cached {})", name, tools.len(), entry.updated_at);
+                for tool in tools {
+                    let tool_name = tool.get("name").and_then(|n| n.as_str()).unwrap_or("?");
+                    let description = tool
+                        .get("description")
+                        .and_then(|d| d.as_str())
+                        .and_then(|d| d.lines().next())
+                        .unwrap_or("");
+                    println!("    • {} - {}", tool_name, description);
+                }
+            }
+            None => println!("  {} (no cached tools)", name),
+        }
+    }
+
+    if shown == 0 {
+        println!("  No cached catalogs yet. Start the hub and connect a client first.");
+    }
+
+    println!();
+    Ok(())
+}
+
+async fn edit_config() -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let path = config::hub_config_path();
+
+    let snapshot = history::snapshot(&hub_config, "config edit")?;
+
+    if !path.exists() {
+        std::fs::create_dir_all(config::data_dir())?;
+        std::fs::write(&path, toml::to_string_pretty(&hub_config)?)?;
+    }
+
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new(&editor)
+        .arg(&path)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Failed to launch editor {}: {}", editor, e))?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with {}", status);
+    }
+
+    if let Err(e) = load_hub_config() {
+        eprintln!("❌ {:#}", e);
+        eprintln!("   Restore the previous config: mcp-citadel config rollback");
+        std::process::exit(1);
+    }
+
+    println!("✓ Saved {:?} (previous config: snapshot {})", path, snapshot.id);
+    reload_hub().await
+}
+
+fn config_history() -> Result<()> {
+    let snapshots = history::list()?;
+
+    println!();
+    println!("🕘 Configuration Snapshots:");
+    println!();
+
+    if snapshots.is_empty() {
+        println!("  No snapshots yet");
+    }
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        println!("  {:>3}. {}  {}", i + 1, snapshot.timestamp, snapshot.reason);
+    }
+
+    println!();
+    Ok(())
+}
+
+async fn rollback_config(n: usize) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let snapshot = history::rollback(&hub_config, n)?;
+    println!("✓ Restored configuration from {} ({})", snapshot.timestamp, snapshot.reason);
+    reload_hub().await
+}
+
+/// Start, stop, or restart one server through the control plane
+async fn control_server(action: ServerCommands) -> Result<()> {
+    let (method, name, done) = match &action {
+        ServerCommands::Start { name } => ("servers.start", name, "Started"),
+        ServerCommands::Stop { name } => ("servers.stop", name, "Stopped"),
+        ServerCommands::Restart { name } => ("servers.restart", name, "Restarted"),
+        ServerCommands::Debug { name, off } => return debug_transcript(name, !off).await,
+    };
+
+    let mut control = ControlClient::connect().await?;
+    control.call(method, serde_json::json!({ "server": name })).await?;
+    println!("✓ {} {}", done, name);
+    Ok(())
+}
+
+/// Turn a server's debug transcript on or off in the running hub
+async fn debug_transcript(name: &str, enabled: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control
+        .call("servers.debug", serde_json::json!({ "server": name, "enabled": enabled }))
+        .await?;
+    let path = result["path"].as_str().unwrap_or_default();
+    match enabled {
+        true => println!("✓ Recording {}'s messages to {}", name, path),
+        false => println!("✓ Stopped recording {}'s messages ({})", name, path),
+    }
+    Ok(())
+}
+
+/// Re-read the Claude config in the running hub
+async fn reload_servers() -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("reload", serde_json::json!({})).await?;
+
+    let names = |key: &str| -> Vec<String> {
+        serde_json::from_value(result[key].clone()).unwrap_or_default()
+    };
+    println!("✓ Reloaded servers");
+    for (label, key) in [("Added", "added"), ("Removed", "removed"), ("Restarted", "changed")] {
+        let names = names(key);
+        if !names.is_empty() {
+            println!("  {}: {}", label, names.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Print hub events as they happen
+async fn watch_events(json: bool) -> Result<()> {
+    let mut events = ControlClient::connect().await?.tail(true).await?;
+    if !json {
+        println!("👀 Watching MCP Citadel events (Ctrl+C to stop)");
+    }
+
+    while let Some(event) = events.next_event().await? {
+        print_event(&event, json);
+    }
+
+    println!("Hub closed the connection");
+    Ok(())
+}
+
+/// Print the hub's recent lifecycle events, then with `follow` new ones as
+/// they happen
+async fn show_events(follow: bool, json: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let recent = control.call("events.recent", serde_json::json!({})).await?;
+    let recent = recent.as_array().cloned().unwrap_or_default();
+    if recent.is_empty() && !follow && !json {
+        println!("No events since the hub started");
+    }
+    for event in &recent {
+        print_event(event, json);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    let mut events = control.tail(false).await?;
+    while let Some(event) = events.next_event().await? {
+        print_event(&event, json);
+    }
+    println!("Hub closed the connection");
+    Ok(())
+}
+
+/// One line per event: raw JSON, or its time, kind, and details
+fn print_event(event: &serde_json::Value, json: bool) {
+    if json {
+        println!("{}", event);
+        return;
+    }
+
+    let field = |key: &str| event.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    let time = chrono::DateTime::parse_from_rfc3339(field("timestamp"))
+        .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+        .unwrap_or_default();
+    let detail = match field("event") {
+        "notification" => format!("{} → {}", field("server"), field("method")),
+        "restarted" | "failed" => format!("{} ({})", field("server"), field("reason")),
+        "reloaded" => format!(
+            "+{} -{} ~{}",
+            event["added"].as_array().map_or(0, Vec::len),
+            event["removed"].as_array().map_or(0, Vec::len),
+            event["changed"].as_array().map_or(0, Vec::len)
+        ),
+        "lagged" => format!("{} events skipped", event["skipped"]),
+        "approval_requested" => format!("{}/{} (#{})", field("server"), field("tool"), event["id"]),
+        "session_created" => format!("{} ({})", &field("session")[..field("session").len().min(8)], field("transport")),
+        "session_expired" => field("session")[..field("session").len().min(8)].to_string(),
+        "request_failed" => format!("{} {}: {}", field("server"), field("method"), field("error")),
+        "maintenance" => match event["active"].as_bool() {
+            Some(true) => format!("until {}", field("until")),
+            _ => "ended".to_string(),
+        },
+        _ => field("server").to_string(),
+    };
+    println!("[{}] {:<12} {}", time, field("event"), detail);
+}
+
+/// Print sampling token usage from the running hub
+async fn show_usage(json: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("usage", serde_json::json!({})).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    let report: router::usage::UsageReport = serde_json::from_value(result)?;
+    if report.servers.is_empty() {
+        println!("No sampling requests since the hub started");
+        return Ok(());
+    }
+
+    for (title, rows) in [("🪙 Sampling usage by server:", &report.servers), ("Sessions:", &report.sessions)] {
+        if rows.is_empty() {
+            continue;
+        }
+        println!();
+        println!("{}", title);
+        println!();
+        println!("  {:<24} {:>9} {:>12} {:>12}", "", "requests", "input", "output");
+        for (name, usage) in rows {
+            println!(
+                "  {:<24} {:>9} {:>12} {:>12}",
+                name, usage.requests, usage.input_tokens, usage.output_tokens
+            );
+        }
+    }
+    println!();
+    Ok(())
+}
+
+/// Print past requests from the request history
+fn show_history(query: &router::history::HistoryQuery, json: bool) -> Result<()> {
+    let records = router::history::query(&router::history::path(), query, |_| true)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("No matching requests in {}", router::history::path().display());
+        println!("   (requests are only recorded with [router.audit] enabled = true)");
+        return Ok(());
+    }
+
+    println!();
+    println!("  {:<24} {:<10} {:<16} {:<18} {:<24} {:>10}  outcome", "at", "session", "server", "method", "tool", "ms");
+    for record in &records {
+        let session = record.session.as_deref().map(|s| &s[..s.len().min(8)]).unwrap_or("-");
+        let outcome = match (record.outcome, &record.error) {
+            (router::audit::Outcome::Ok, _) => "✓ ok".to_string(),
+            (_, Some(error)) => format!("✗ {}", error),
+            (_, None) => "✗".to_string(),
+        };
+        println!(
+            "  {:<24} {:<10} {:<16} {:<18} {:<24} {:>10.1}  {}",
+            record.at,
+            session,
+            record.server,
+            record.method,
+            record.tool.as_deref().unwrap_or("-"),
+            record.latency_ms,
+            outcome
+        );
+    }
+    println!();
+    Ok(())
+}
+
+/// Print the mcp-client adapters registered with the running hub
+async fn list_clients(json: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("clients.list", serde_json::json!({})).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    let clients: Vec<router::clients::ClientInfo> = serde_json::from_value(result)?;
+    if clients.is_empty() {
+        println!("No mcp-client adapters attached");
+        return Ok(());
+    }
+
+    println!();
+    println!("🔌 Attached clients:");
+    println!();
+    println!("  {:<20} {:<8} {:<20} UP", "CLIENT", "PID", "SERVER");
+    for client in clients {
+        let up = chrono::DateTime::parse_from_rfc3339(&client.started_at)
+            .map(|t| (chrono::Utc::now() - t.with_timezone(&chrono::Utc)).num_seconds().max(0) as u64)
+            .map_or("-".to_string(), format_uptime);
+        let server = match &client.workspace {
+            Some(workspace) => format!("{} ({})", client.server, workspace),
+            None => client.server,
+        };
+        println!("  {:<20} {:<8} {:<20} {}", client.client, client.pid, server, up);
+    }
+    println!();
+    Ok(())
+}
+
+async fn show_summary(remote: Option<&str>, key: Option<&str>, json: bool) -> Result<()> {
+    let summary = match remote {
+        Some(url) => summary::fetch(url, key).await?,
+        None => {
+            let mut control = ControlClient::connect().await?;
+            serde_json::from_value(control.call("summary", serde_json::json!({})).await?)?
+        }
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+
+    println!();
+    println!(
+        "🏰 MCP Citadel v{} ({}), up {}",
+        summary.version,
+        summary.status,
+        format_uptime(summary.uptime_secs)
+    );
+    println!();
+    if summary.servers.is_empty() {
+        println!("  No servers configured");
+    } else {
+        println!("  {:<20} {:<10} {:<10} {:>8} {:>10} {:>8} {:>7}", "SERVER", "HEALTH", "UP", "RESTARTS", "REQUESTS", "ERRORS", "RATE");
+        for server in &summary.servers {
+            println!(
+                "  {:<20} {:<10} {:<10} {:>8} {:>10} {:>8} {:>6.1}%",
+                server.name,
+                server.health,
+                server.uptime_secs.map_or("-".to_string(), format_uptime),
+                server.restarts,
+                server.requests,
+                server.errors,
+                server.error_rate * 100.0
+            );
+        }
+    }
+    let totals = &summary.totals;
+    println!();
+    println!(
+        "  {}/{} up, {} requests, {} errors ({:.1}%)",
+        totals.up,
+        totals.servers,
+        totals.requests,
+        totals.errors,
+        totals.error_rate * 100.0
+    );
+    for (kind, address) in &summary.endpoints {
+        println!("  {:<10} {}", kind, address);
+    }
+    println!();
+    Ok(())
+}
+
+async fn begin_maintenance(duration: &str, reason: Option<String>) -> Result<()> {
+    let duration = config::schedule::parse_duration(duration)?;
+    let mut control = ControlClient::connect().await?;
+    let window: router::maintenance::Window = serde_json::from_value(
+        control
+            .call("maintenance.begin", serde_json::json!({ "duration_secs": duration.as_secs(), "reason": reason }))
+            .await?,
+    )?;
+    println!("🚧 Maintenance until {}; servers keep running, client requests are refused", window.until);
+    Ok(())
+}
+
+async fn end_maintenance() -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let ended = control.call("maintenance.end", serde_json::json!({})).await?;
+    match ended.as_bool() {
+        Some(true) => println!("✅ Maintenance ended, client traffic resumed"),
+        _ => println!("No maintenance window active"),
+    }
+    Ok(())
+}
+
+async fn maintenance_status() -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let window: Option<router::maintenance::Window> =
+        serde_json::from_value(control.call("maintenance.status", serde_json::json!({})).await?)?;
+    match window {
+        Some(window) => println!(
+            "🚧 Maintenance since {} until {}{}",
+            window.started_at,
+            window.until,
+            window.reason.map(|r| format!(" ({})", r)).unwrap_or_default()
+        ),
+        None => println!("No maintenance window active"),
+    }
+    Ok(())
+}
+
+async fn list_approvals(json: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("approvals.list", serde_json::json!({})).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    let calls: Vec<router::approvals::PendingCall> = serde_json::from_value(result)?;
+    if calls.is_empty() {
+        println!("No tool calls waiting for approval");
+        return Ok(());
+    }
+
+    println!();
+    println!("✋ Tool calls waiting for approval:");
+    println!();
+    for call in calls {
+        println!("  #{:<4} {}/{}  since {}", call.id, call.server, call.tool, call.requested_at);
+        println!("        {}", call.arguments);
+    }
+    println!();
+    println!("Decide with `mcp-citadel approvals approve <id>` or `mcp-citadel approvals deny <id>`");
+    Ok(())
+}
+
+async fn decide_approval(id: u64, approve: bool, reason: Option<String>) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    match approve {
+        true => {
+            control.call("approvals.approve", serde_json::json!({ "id": id })).await?;
+            println!("✅ Approved call #{}", id);
+        }
+        false => {
+            control.call("approvals.deny", serde_json::json!({ "id": id, "reason": reason })).await?;
+            println!("🚫 Denied call #{}", id);
+        }
+    }
+    Ok(())
+}
+
+async fn list_sessions(json: bool) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("sessions.list", serde_json::json!({})).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+
+    let sessions: Vec<router::transcript::SessionSummary> = serde_json::from_value(result)?;
+    if sessions.is_empty() {
+        println!("No client sessions recorded");
+        return Ok(());
+    }
+
+    println!();
+    println!("🧾 Client sessions:");
+    println!();
+    println!("  {:<38} {:<8} {:<8} {:<24} LAST ACTIVE", "ID", "VIA", "MESSAGES", "SERVERS");
+    for session in sessions {
+        let servers: Vec<_> = session.servers.into_iter().collect();
+        let state = if session.closed_at.is_some() { " (closed)" } else { "" };
+        println!(
+            "  {:<38} {:<8} {:<8} {:<24} {}{}",
+            session.id,
+            session.transport,
+            session.messages,
+            servers.join(","),
+            session.last_active,
+            state
+        );
+    }
+    println!();
+    Ok(())
+}
+
+async fn export_session(id: &str, output: Option<std::path::PathBuf>, log_file: Option<std::path::PathBuf>) -> Result<()> {
+    let mut control = ControlClient::connect().await?;
+    let result = control.call("sessions.export", serde_json::json!({ "id": id })).await?;
+    let export: report::SessionExport = serde_json::from_value(result)?;
+
+    let output = output.unwrap_or_else(|| {
+        let short = &export.session.id[..export.session.id.len().min(8)];
+        std::path::PathBuf::from(format!("mcp-citadel-session-{}.zip", short))
+    });
+    let log_file = log_file.unwrap_or_else(report::default_log_file);
+    report::write_archive(&export, &load_hub_config()?, Some(&log_file), &output)?;
+
+    println!("✓ Exported session {} ({} messages) to {}", export.session.id, export.messages.len(), output.display());
+    if !log_file.exists() {
+        println!("  No hub log at {}; pass --log-file to include log lines", log_file.display());
+    }
+    Ok(())
+}
+
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d{}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// The first line of `text`, cut to `max` characters
+fn one_line(text: &str, max: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    match line.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Apply the saved config to the running hub, if there is one, without
+/// restarting it
+async fn reload_hub() -> Result<()> {
+    if daemon::is_running()? {
+        println!("↻ Reloading MCP Citadel...");
+        reload_servers().await?;
+    }
+    Ok(())
+}
+
+fn connect_client(client: connect::ClientApp) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let mut servers: Vec<String> = config::load_servers(&hub_config)?
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    servers.sort();
+
+    // mcp-client is installed next to mcp-citadel
+    let mcp_client = std::env::current_exe()?
+        .parent()
+        .map(|dir| dir.join("mcp-client"))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| "mcp-client".into());
+
+    let state = connect::connect(client, &servers, &mcp_client)?;
+
+    println!();
+    println!("✓ Connected {} servers in {:?} through {:?}:", servers.len(), state.path, mcp_client);
+    for server in &servers {
+        println!("  • {}", server);
+    }
+    if let Some(backup) = &state.backup {
+        println!();
+        println!("  Backup: {:?}", backup);
+    }
+    println!("  Revert with: mcp-citadel disconnect {}", client.slug());
+    println!();
+    Ok(())
+}
+
+fn disconnect_client(client: connect::ClientApp) -> Result<()> {
+    let state = connect::disconnect(client)?;
+    println!("✓ Restored {} server entries in {:?}", state.replaced.len(), state.path);
+    Ok(())
+}
+
+async fn export_tools(
+    format: export::ToolFormat,
+    output: Option<std::path::PathBuf>,
+    server: Option<String>,
+) -> Result<()> {
+    let hub_config = load_hub_config()?;
+
+    let mut catalog = if daemon::is_running()? {
+        let mut servers: Vec<String> = config::load_servers(&hub_config)?
+            .into_iter()
+            .map(|c| c.name)
+            .filter(|name| server.as_ref().is_none_or(|s| s == name))
+            .collect();
+        servers.sort();
+        export::live_catalog(&hub_config.socket_path, &servers).await?
+    } else {
+        eprintln!("⚠️  MCP Citadel is not running, exporting the cached catalogs");
+        export::cached_catalog()
+    };
+    catalog.retain(|(name, _)| server.as_ref().is_none_or(|s| s == name));
+
+    let rendered = serde_json::to_string_pretty(&export::render(&catalog, format))?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, rendered + "\n")?;
+            let count: usize = catalog.iter().map(|(_, tools)| tools.len()).sum();
+            eprintln!("✓ Exported {} tools to {:?}", count, path);
+        }
+        None => println!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn export_compose(output: Option<std::path::PathBuf>, http_port: u16) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let servers = config::load_servers(&hub_config)?;
+    let compose = compose::render(&servers, http_port);
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, &compose.yaml)?;
+            eprintln!("✓ Wrote {:?} with {} servers", path, compose.services.len());
+        }
+        None => print!("{}", compose.yaml),
+    }
+    if !compose.skipped.is_empty() {
+        eprintln!(
+            "⚠️  Skipped servers that don't run through npx/uvx: {}",
+            compose.skipped.join(", ")
+        );
+    }
+    eprintln!("   Build the hub image first: docker build -t mcp-citadel .");
+    Ok(())
+}
+
+async fn install_server(
+    query: &str,
+    server_name: Option<String>,
+    env: &[String],
+    registry_url: Option<String>,
+    no_install: bool,
+    reload: bool,
+) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let registry_url = registry_url.unwrap_or_else(|| hub_config.registry.url.clone());
+
+    let env = parse_key_values(env, "--env")?;
+
+    println!();
+    println!("🔎 Searching {} for '{}'...", registry_url, query);
+
+    let servers = registry::search(&registry_url, query).await?;
+    let server = registry::resolve(&servers, query)?;
+    let plan = registry::plan(server, server_name, env)?;
+
+    println!("   Found {} {}", server.name, server.version.as_deref().unwrap_or(""));
+    if !server.description.is_empty() {
+        println!("   {}", server.description);
+    }
+
+    if !plan.missing_env.is_empty() {
+        eprintln!();
+        eprintln!("❌ {} requires environment variables:", server.name);
+        for variable in &plan.missing_env {
+            eprintln!("   {} - {}", variable.name, variable.description.as_deref().unwrap_or(""));
+        }
+        eprintln!("   Pass them with --env KEY=VALUE");
+        std::process::exit(1);
+    }
+
+    if !no_install {
+        println!();
+        println!("📦 {}", plan.install_command().join(" "));
+        plan.installer.install(&plan.spec()).await?;
+    }
+
+    let snapshot = history::snapshot(&hub_config, &format!("install {}", plan.server_name))?;
+    let replaced = config::add_claude_server(&hub_config.claude_config_path, &plan.server_name, plan.entry())?;
+
+    let mut lockfile = Lockfile::load()?;
+    lockfile.insert(
+        &plan.server_name,
+        LockedPackage {
+            installer: plan.installer,
+            package: plan.package.clone(),
+            version: plan.version.clone(),
+        },
+    );
+    lockfile.save()?;
+
+    println!();
+    println!(
+        "✓ {} {} in {:?} (previous config: snapshot {})",
+        if replaced { "Updated" } else { "Added" },
+        plan.server_name,
+        hub_config.claude_config_path,
+        snapshot.id
+    );
+    println!("   {} {}", plan.command, plan.args.join(" "));
+
+    if reload {
+        reload_hub().await?;
+    } else if daemon::is_running()? {
+        println!("   Restart the hub to load it: mcp-citadel stop && mcp-citadel start");
+    }
+    println!();
+
+    Ok(())
+}
+
+async fn import_bundle(path: &std::path::Path, server_name: Option<String>, settings: &[String], reload: bool) -> Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let hub_config = load_hub_config()?;
+    let manifest = bundle::read_manifest(path)?;
+    let server_name = bundle::server_name(&manifest, server_name.as_deref())?;
+    let mut values = parse_key_values(settings, "--set")?;
+
+    println!();
+    println!(
+        "📦 {} {}",
+        manifest.display_name.as_deref().unwrap_or(&manifest.name),
+        manifest.version.as_deref().unwrap_or("")
+    );
+    if let Some(description) = &manifest.description {
+        println!("   {}", description);
+    }
+
+    let missing = bundle::missing_settings(&manifest, &values);
+    if !missing.is_empty() {
+        if !std::io::stdin().is_terminal() {
+            eprintln!();
+            eprintln!("❌ {} requires settings:", manifest.name);
+            for key in &missing {
+                eprintln!("   {}", key);
+            }
+            eprintln!("   Pass them with --set KEY=VALUE");
+            std::process::exit(1);
+        }
+
+        println!();
+        let mut lines = std::io::stdin().lock().lines();
+        for key in missing {
+            let option = &manifest.user_config[&key];
+            print!("   {}", option.title.as_deref().unwrap_or(&key));
+            if let Some(description) = &option.description {
+                print!(" ({})", description);
+            }
+            print!(": ");
+            std::io::stdout().flush()?;
+            let value = lines.next().context("No value entered")??;
+            values.insert(key, value.trim().to_string());
+        }
+    }
+
+    let dir = bundle::servers_dir().join(&server_name);
+    bundle::unpack(path, &dir)?;
+    let entry = bundle::server_entry(&manifest, &dir, &values);
+
+    let snapshot = history::snapshot(&hub_config, &format!("import {}", server_name))?;
+    let replaced = config::add_claude_server(&hub_config.claude_config_path, &server_name, entry)?;
+
+    println!();
+    println!(
+        "✓ {} {} from {:?} (unpacked to {:?}, previous config: snapshot {})",
+        if replaced { "Updated" } else { "Imported" },
+        server_name,
+        path,
+        dir,
+        snapshot.id
+    );
+
+    if reload {
+        reload_hub().await?;
+    } else if daemon::is_running()? {
+        println!("   Restart the hub to load it: mcp-citadel stop && mcp-citadel start");
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Parse repeated `KEY=VALUE` arguments
+fn parse_key_values(pairs: &[String], flag: &str) -> Result<HashMap<String, String>> {
+    pairs
+        .iter()
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid {} '{}', expected KEY=VALUE", flag, pair))
+        })
+        .collect()
+}
+
+async fn update_servers(names: &[String], check: bool) -> Result<()> {
+    let hub_config = load_hub_config()?;
+    let mut server_configs = load_claude_config(&hub_config.claude_config_path)?;
+    server_configs.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut lockfile = Lockfile::load()?;
+
+    println!();
+    println!("🔄 Checking for updates...");
+    println!();
+
+    let mut updates = Vec::new();
+    for config in server_configs {
+        if !names.is_empty() && !names.contains(&config.name) {
+            continue;
+        }
+        let Some(managed) = lockfile::managed_package(&config, &lockfile) else {
+            if !names.is_empty() {
+                println!("  {} - not an npm/PyPI server, skipped", config.name);
+            }
+            continue;
+        };
+
+        let package = &managed.package;
+        let latest = match registry::latest_version(package.installer, &package.package).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                println!("  {} - {:#}", config.name, e);
+                continue;
+            }
+        };
+
+        let current = package.version.as_deref().unwrap_or("unpinned");
+        if current == latest {
+            println!("  {} {} {} (up to date)", config.name, package.package, current);
+            continue;
+        }
+        println!("  {} {} {} → {}", config.name, package.package, current, latest);
+        updates.push((config, managed, latest));
+    }
+
+    if check || updates.is_empty() {
+        println!();
+        return Ok(());
+    }
+
+    let snapshot = history::snapshot(&hub_config, "update")?;
+    println!();
+
+    let mut updated = 0;
+    for (config, managed, latest) in updates {
+        let previous = managed.package;
+        let next = LockedPackage {
+            version: Some(latest),
+            ..previous.clone()
+        };
+
+        println!("📦 Updating {} to {}", config.name, next.spec());
+        if let Err(e) = next.installer.install(&next.spec()).await {
+            println!("   ❌ {:#}", e);
+            continue;
+        }
+
+        let mut next_config = config.clone();
+        if let Some(index) = managed.arg_index {
+            next_config.args[index] = next.spec();
+        }
+
+        match router::handshake(next_config.clone()).await {
+            Ok(()) => {
+                if managed.arg_index.is_some() {
+                    config::set_claude_server_args(&hub_config.claude_config_path, &config.name, &next_config.args)?;
+                }
+                lockfile.insert(&config.name, next);
+                lockfile.save()?;
+                updated += 1;
+                println!("   ✓ Handshake OK");
+            }
+            Err(e) => {
+                println!("   ❌ Handshake failed: {:#}", e);
+                // Packages run by npx/uvx are selected by their args, which were never changed
+                if managed.arg_index.is_none() && previous.version.is_some() {
+                    println!("   ↩ Reinstalling {}", previous.spec());
+                    previous.installer.install(&previous.spec()).await?;
+                }
+                println!("   Kept {}", previous.spec());
+            }
+        }
+    }
+
+    println!();
+    println!("✓ Updated {} server(s) (previous config: snapshot {})", updated, snapshot.id);
+    println!();
+
+    if updated > 0 {
+        reload_hub().await?;
+    }
+    Ok(())
+}
+
+async fn run_bench(local: bool, requests: usize, concurrency: usize, payload_size: usize) -> Result<()> {
+    if !local {
+        anyhow::bail!("Only local benchmarks are supported: mcp-citadel bench --local");
+    }
+
+    println!();
+    println!("⏱  Benchmarking {} requests ({} concurrent, {}B payload)...", requests, concurrency, payload_size);
+
+    let report = bench::run_local(requests, concurrency, payload_size).await?;
+
+    println!();
+    println!("  Requests:    {} ({} errors)", report.requests, report.errors);
+    println!("  Elapsed:     {:.2?}", report.elapsed);
+    println!("  Throughput:  {:.0} req/s", report.throughput());
+    println!("  Latency p50: {:.2?}", report.p50);
+    println!("  Latency p95: {:.2?}", report.p95);
+    println!("  Latency p99: {:.2?}", report.p99);
+    println!();
+
     Ok(())
 }
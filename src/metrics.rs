@@ -1,13 +1,20 @@
 //! Prometheus Metrics for MCP Citadel
 //!
 //! Tracks request count, latency, active sessions, errors, and MCP server health.
+//! With `metrics.exporter = "statsd"` the same metrics are also sent to a
+//! StatsD agent every `flush_interval_secs`, labels as DogStatsD tags.
 
 use lazy_static::lazy_static;
+use prometheus::proto::{MetricFamily, MetricType};
 use prometheus::{
     register_counter_vec, register_gauge, register_histogram_vec, CounterVec, Encoder, Gauge,
     HistogramVec, TextEncoder,
 };
-use std::time::Instant;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::MetricsConfig;
 
 lazy_static! {
     // HTTP request metrics
@@ -70,6 +77,28 @@ lazy_static! {
     )
     .unwrap();
 
+    pub static ref SERVER_RESTARTS_TOTAL: CounterVec = register_counter_vec!(
+        "mcp_citadel_server_restarts_total",
+        "MCP server restarts by cause (crashed, unresponsive, requested, scheduled)",
+        &["server", "reason"]
+    )
+    .unwrap();
+
+    // Sampling metrics
+    pub static ref SAMPLING_REQUESTS_TOTAL: CounterVec = register_counter_vec!(
+        "mcp_citadel_sampling_requests_total",
+        "sampling/createMessage exchanges answered by clients, by server",
+        &["server"]
+    )
+    .unwrap();
+
+    pub static ref SAMPLING_TOKENS_TOTAL: CounterVec = register_counter_vec!(
+        "mcp_citadel_sampling_tokens_total",
+        "LLM tokens reported by sampling responses, by server and kind (input, output)",
+        &["server", "kind"]
+    )
+    .unwrap();
+
     // Error metrics
     pub static ref ERRORS_TOTAL: CounterVec = register_counter_vec!(
         "mcp_citadel_errors_total",
@@ -99,6 +128,12 @@ lazy_static! {
     )
     .unwrap();
 
+    pub static ref CLIENT_ADAPTERS: Gauge = register_gauge!(
+        "mcp_citadel_client_adapters",
+        "Number of registered mcp-client adapters"
+    )
+    .unwrap();
+
     pub static ref WEBSOCKET_CONNECTIONS_TOTAL: CounterVec = register_counter_vec!(
         "mcp_citadel_websocket_connections_total",
         "Total WebSocket connections",
@@ -192,6 +227,24 @@ pub fn set_mcp_servers_up(count: usize) {
     MCP_SERVER_UP.set(count as f64);
 }
 
+/// Record an MCP server restart
+pub fn record_server_restart(server: &str, reason: &str) {
+    SERVER_RESTARTS_TOTAL
+        .with_label_values(&[server, reason])
+        .inc();
+}
+
+/// Record the token usage of a sampling exchange
+pub fn record_sampling(server: &str, input_tokens: u64, output_tokens: u64) {
+    SAMPLING_REQUESTS_TOTAL.with_label_values(&[server]).inc();
+    SAMPLING_TOKENS_TOTAL
+        .with_label_values(&[server, "input"])
+        .inc_by(input_tokens as f64);
+    SAMPLING_TOKENS_TOTAL
+        .with_label_values(&[server, "output"])
+        .inc_by(output_tokens as f64);
+}
+
 /// Update message buffer size
 pub fn set_message_buffer_size(size: usize) {
     MESSAGE_BUFFER_SIZE.set(size as f64);
@@ -222,3 +275,167 @@ pub fn record_websocket_connection(status: &str) {
         .with_label_values(&[status])
         .inc();
 }
+
+/// Longest datagram sent to StatsD, to stay under a typical MTU
+const STATSD_PACKET_BYTES: usize = 1432;
+
+/// Turns the registered metrics into StatsD lines: counters as their increase
+/// since the last flush, gauges as their value, and histograms as the count
+/// and sum of the observations since the last flush
+#[derive(Default)]
+pub struct StatsdExporter {
+    /// Tags added to every line
+    tags: Vec<String>,
+    /// Counter values at the last flush, by name and tags
+    sent: HashMap<String, f64>,
+}
+
+impl StatsdExporter {
+    pub fn new(tags: Vec<String>) -> Self {
+        Self { tags, sent: HashMap::new() }
+    }
+
+    /// Lines for `families`, counters that haven't changed left out
+    pub fn lines(&mut self, families: &[MetricFamily]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for family in families {
+            let name = statsd_name(family.get_name());
+            for metric in family.get_metric() {
+                let mut tags: Vec<String> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| format!("{}:{}", label.get_name(), statsd_tag(label.get_value())))
+                    .collect();
+                tags.extend(self.tags.iter().cloned());
+                let tags = if tags.is_empty() { String::new() } else { format!("|#{}", tags.join(",")) };
+
+                let mut counter = |lines: &mut Vec<String>, name: &str, value: f64| {
+                    let last = self.sent.insert(format!("{}{}", name, tags), value).unwrap_or(0.0);
+                    // A counter below its last value was reset
+                    let increase = if value < last { value } else { value - last };
+                    if increase > 0.0 {
+                        lines.push(format!("{}:{}|c{}", name, increase, tags));
+                    }
+                };
+                match family.get_field_type() {
+                    MetricType::COUNTER => counter(&mut lines, &name, metric.get_counter().get_value()),
+                    MetricType::GAUGE => lines.push(format!("{}:{}|g{}", name, metric.get_gauge().get_value(), tags)),
+                    MetricType::HISTOGRAM => {
+                        let histogram = metric.get_histogram();
+                        counter(&mut lines, &format!("{}.count", name), histogram.get_sample_count() as f64);
+                        counter(&mut lines, &format!("{}.sum", name), histogram.get_sample_sum());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// `mcp_citadel_http_requests_total` → `mcp_citadel.http_requests_total`
+fn statsd_name(name: &str) -> String {
+    match name.strip_prefix("mcp_citadel_") {
+        Some(rest) => format!("mcp_citadel.{}", rest),
+        None => name.to_string(),
+    }
+}
+
+/// A label value without the characters that delimit StatsD lines and tags
+fn statsd_tag(value: &str) -> String {
+    value.replace(['|', ',', '#', '\n', ':'], "_")
+}
+
+/// `lines` joined into datagrams of at most [`STATSD_PACKET_BYTES`]
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets: Vec<String> = Vec::new();
+    for line in lines {
+        match packets.last_mut() {
+            Some(packet) if packet.len() + 1 + line.len() <= STATSD_PACKET_BYTES => {
+                packet.push('\n');
+                packet.push_str(line);
+            }
+            _ => packets.push(line.clone()),
+        }
+    }
+    packets
+}
+
+/// Send the metrics to the StatsD agent of `config` until the hub shuts down
+pub async fn spawn_statsd(config: &MetricsConfig) {
+    let addr = match tokio::net::lookup_host(&config.statsd_addr).await.map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr,
+        Ok(None) | Err(_) => {
+            warn!("Not sending metrics to StatsD: can't resolve {}", config.statsd_addr);
+            return;
+        }
+    };
+    let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = match tokio::net::UdpSocket::bind(bind).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Not sending metrics to StatsD: {}", e);
+            return;
+        }
+    };
+    info!("Sending metrics to StatsD at {}", addr);
+
+    let mut exporter = StatsdExporter::new(config.tags.clone());
+    let period = Duration::from_secs(config.flush_interval_secs.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            for packet in packets(&exporter.lines(&prometheus::gather())) {
+                // Nothing listening is the agent's business; UDP may report it
+                if let Err(e) = socket.send_to(packet.as_bytes(), addr).await {
+                    debug!("Failed to send metrics to StatsD at {}: {}", addr, e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{Counter, HistogramOpts, Opts, Registry};
+
+    #[test]
+    fn test_statsd_lines() {
+        let registry = Registry::new();
+        let requests = CounterVec::new(Opts::new("mcp_citadel_requests_total", "requests"), &["server"]).unwrap();
+        let up = Gauge::new("mcp_citadel_up", "up").unwrap();
+        let latency = prometheus::Histogram::with_opts(HistogramOpts::new("latency_seconds", "latency")).unwrap();
+        let idle = Counter::new("idle_total", "idle").unwrap();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(up.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(idle)).unwrap();
+
+        let mut exporter = StatsdExporter::new(vec!["env:test".to_string()]);
+        requests.with_label_values(&["git|hub"]).inc_by(3.0);
+        up.set(2.0);
+        latency.observe(0.5);
+        let mut lines = exporter.lines(&registry.gather());
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "latency_seconds.count:1|c|#env:test",
+                "latency_seconds.sum:0.5|c|#env:test",
+                "mcp_citadel.requests_total:3|c|#server:git_hub,env:test",
+                "mcp_citadel.up:2|g|#env:test",
+            ]
+        );
+
+        // Counters are sent as their increase, and not at all without one
+        requests.with_label_values(&["git|hub"]).inc();
+        let lines = exporter.lines(&registry.gather());
+        assert_eq!(lines, ["mcp_citadel.requests_total:1|c|#server:git_hub,env:test", "mcp_citadel.up:2|g|#env:test"]);
+
+        let packed = packets(&vec!["x".repeat(1000); 3]);
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packets(&vec!["x".repeat(100); 3]).len(), 1);
+    }
+}
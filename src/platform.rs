@@ -0,0 +1,366 @@
+//! Platform-specific process handling
+//! Spawning, liveness checks, and termination for Unix (signals) and Windows
+//! (`taskkill`, `tasklist`, `.cmd` shims)
+
+use anyhow::{Context, Result};
+use tokio::process::{Child, Command};
+
+/// Command for a server executable
+///
+/// On Windows, package-manager launchers like `npx` and `uvx` are `.cmd`
+/// shims that `CreateProcess` can't start directly, so commands without an
+/// extension run through `cmd /C`.
+pub fn command(program: impl AsRef<std::ffi::OsStr>) -> Command {
+    let program = program.as_ref();
+    #[cfg(windows)]
+    if std::path::Path::new(program).extension().is_none() {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(program);
+        return cmd;
+    }
+    Command::new(program)
+}
+
+/// Start a command with this file mode creation mask (Unix; ignored on Windows)
+pub fn set_umask(cmd: &mut Command, mask: u32) -> &mut Command {
+    #[cfg(unix)]
+    {
+        use nix::sys::stat::{umask, Mode};
+        let mode = Mode::from_bits_truncate(mask as nix::libc::mode_t);
+        // Safe in the forked child: umask is async-signal-safe and allocates nothing
+        unsafe {
+            cmd.pre_exec(move || {
+                umask(mode);
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    let _ = mask;
+    cmd
+}
+
+/// Start a command with at most this many open files (Unix; ignored on Windows)
+pub fn set_open_files(cmd: &mut Command, max: u64) -> &mut Command {
+    #[cfg(unix)]
+    {
+        use nix::sys::resource::{setrlimit, Resource};
+        // Safe in the forked child: setrlimit is a plain syscall
+        unsafe {
+            cmd.pre_exec(move || {
+                setrlimit(Resource::RLIMIT_NOFILE, max, max).map_err(std::io::Error::from)
+            });
+        }
+    }
+    #[cfg(windows)]
+    let _ = max;
+    cmd
+}
+
+/// The user and group a server process runs as
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub uid: u32,
+    pub gid: u32,
+    /// Name and home directory, when a user was given
+    pub user: Option<(String, std::path::PathBuf)>,
+}
+
+/// Look up the account for a `user` and `group` (names or numeric ids); the
+/// group defaults to the user's primary group, and each to the hub's own
+#[cfg(unix)]
+pub fn account(user: Option<&str>, group: Option<&str>) -> Result<Account> {
+    use nix::unistd::{getgid, getuid, Gid, Group, Uid, User};
+    let user = match user {
+        Some(user) => {
+            let found = match user.parse::<u32>() {
+                Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+                Err(_) => User::from_name(user),
+            };
+            Some(found.context(format!("Failed to look up user {}", user))?.context(format!("No user {}", user))?)
+        }
+        None => None,
+    };
+    let gid = match group {
+        Some(group) => {
+            let found = match group.parse::<u32>() {
+                Ok(gid) => Group::from_gid(Gid::from_raw(gid)),
+                Err(_) => Group::from_name(group),
+            };
+            found.context(format!("Failed to look up group {}", group))?.context(format!("No group {}", group))?.gid
+        }
+        None => user.as_ref().map_or_else(getgid, |u| u.gid),
+    };
+    Ok(Account {
+        uid: user.as_ref().map_or_else(getuid, |u| u.uid).as_raw(),
+        gid: gid.as_raw(),
+        user: user.map(|u| (u.name, u.dir)),
+    })
+}
+
+#[cfg(windows)]
+pub fn account(_user: Option<&str>, _group: Option<&str>) -> Result<Account> {
+    anyhow::bail!("Running servers as another user isn't supported on Windows")
+}
+
+/// Start a command as `account` (the hub needs root, or CAP_SETUID and
+/// CAP_SETGID); supplementary groups are dropped
+pub fn run_as<'a>(cmd: &'a mut Command, account: &Account) -> &'a mut Command {
+    #[cfg(unix)]
+    cmd.uid(account.uid).gid(account.gid);
+    #[cfg(windows)]
+    let _ = account;
+    cmd
+}
+
+/// Detach a spawned hub from the console that started it (Windows; on Unix
+/// the null stdio is enough)
+pub fn detach(cmd: &mut std::process::Command) -> &mut std::process::Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    cmd
+}
+
+/// Whether a process with this PID exists
+pub fn is_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::kill;
+        use nix::unistd::Pid;
+        kill(Pid::from_raw(pid as i32), None).is_ok()
+    }
+
+    #[cfg(windows)]
+    {
+        let output = std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+            .output();
+        match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Ask a process to shut down (SIGTERM; on Windows `taskkill`, forced if the
+/// process has no window to close)
+#[cfg(unix)]
+pub fn terminate(pid: u32) -> Result<()> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    kill(Pid::from_raw(pid as i32), Signal::SIGTERM).context("Failed to send SIGTERM")
+}
+
+#[cfg(windows)]
+pub fn terminate(pid: u32) -> Result<()> {
+    let taskkill = |force: bool| {
+        let mut cmd = std::process::Command::new("taskkill");
+        cmd.args(["/PID", &pid.to_string(), "/T"]);
+        if force {
+            cmd.arg("/F");
+        }
+        cmd.output().context("Failed to run taskkill").map(|o| o.status.success())
+    };
+    if !taskkill(false)? && !taskkill(true)? {
+        anyhow::bail!("taskkill could not stop PID {}", pid);
+    }
+    Ok(())
+}
+
+/// Ask a server process and the processes it started to shut down
+///
+/// Servers run in their own process group on Unix, so the group is signalled.
+pub fn terminate_tree(pid: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+        kill(Pid::from_raw(-(pid as i32)), Signal::SIGTERM)
+            .or_else(|_| kill(Pid::from_raw(pid as i32), Signal::SIGTERM))
+            .context("Failed to send SIGTERM")
+    }
+
+    // taskkill /T already covers the tree
+    #[cfg(windows)]
+    terminate(pid)
+}
+
+/// Kill a process and its process group outright, for processes that ignored
+/// `terminate_tree`
+pub fn kill_tree(pid: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+        kill(Pid::from_raw(-(pid as i32)), Signal::SIGKILL)
+            .or_else(|_| kill(Pid::from_raw(pid as i32), Signal::SIGKILL))
+            .context("Failed to send SIGKILL")
+    }
+
+    // Forced if asking didn't work
+    #[cfg(windows)]
+    terminate(pid)
+}
+
+/// Command line of a process (on Windows, its image name)
+pub fn command_line(pid: u32) -> Option<String> {
+    #[cfg(unix)]
+    let output = std::process::Command::new("ps").args(["-o", "args=", "-p", &pid.to_string()]).output();
+    #[cfg(windows)]
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+        .output();
+
+    let output = output.ok().filter(|o| o.status.success())?;
+    let line = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!line.is_empty()).then_some(line)
+}
+
+/// Resident memory of a server process, in bytes
+///
+/// On Linux this also counts the processes in its process group, so an
+/// `npx` launcher's `node` is included.
+pub fn memory_usage(pid: u32) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let group = pid.to_string();
+        let mut total = None;
+        for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+            let dir = entry.path();
+            // Fields after the parenthesized command name: state, ppid, pgrp
+            let Ok(stat) = std::fs::read_to_string(dir.join("stat")) else {
+                continue;
+            };
+            let pgrp = stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().nth(2));
+            if pgrp != Some(group.as_str()) && entry.file_name() != group.as_str() {
+                continue;
+            }
+            let status = std::fs::read_to_string(dir.join("status")).unwrap_or_default();
+            let kb = status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|rss| rss.trim().trim_end_matches("kB").trim().parse::<u64>().ok());
+            if let Some(kb) = kb {
+                *total.get_or_insert(0) += kb * 1024;
+            }
+        }
+        total
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        let output = std::process::Command::new("ps").args(["-o", "rss=", "-p", &pid.to_string()]).output().ok()?;
+        let kb: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+        Some(kb * 1024)
+    }
+
+    // "node.exe","1234","Console","1","52,344 K"
+    #[cfg(windows)]
+    {
+        let output = std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+            .output()
+            .ok()?;
+        let line = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let memory = line.rsplit("\",\"").next()?;
+        let kb: u64 = memory.chars().filter(char::is_ascii_digit).collect::<String>().parse().ok()?;
+        Some(kb * 1024)
+    }
+}
+
+/// Kill a server process and, on Windows, the children its launcher started
+/// (`cmd` → `npx` → `node`), which would otherwise outlive it
+pub async fn kill(child: &mut Child) -> Result<()> {
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        let _ = Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .output()
+            .await;
+    }
+    child.kill().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+
+    #[tokio::test]
+    async fn test_spawn_and_kill() {
+        #[cfg(windows)]
+        let mut command = command("ping");
+        #[cfg(windows)]
+        command.args(["-n", "30", "127.0.0.1"]);
+        #[cfg(not(windows))]
+        let mut command = command("sleep");
+        #[cfg(not(windows))]
+        command.arg("30");
+
+        let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).spawn().unwrap();
+        let pid = child.id().unwrap();
+        assert!(is_alive(pid));
+
+        kill(&mut child).await.unwrap();
+        child.wait().await.unwrap();
+        assert!(!is_alive(pid));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_account() {
+        let root = account(Some("root"), None).unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+        assert_eq!(root.user.map(|(name, _)| name).as_deref(), Some("root"));
+        assert_eq!(account(Some("0"), Some("0")).unwrap().uid, 0);
+
+        let own = account(None, None).unwrap();
+        assert_eq!((own.uid, own.user), (nix::unistd::getuid().as_raw(), None));
+        assert!(account(Some("no-such-user-here"), None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_as() {
+        // Only root can switch users
+        if !nix::unistd::getuid().is_root() {
+            return;
+        }
+        let nobody = account(Some("nobody"), None).unwrap();
+        let mut command = command("id");
+        command.args(["-u"]);
+        let output = run_as(&mut command, &nobody).output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), nobody.uid.to_string());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_open_files_and_memory() {
+        let mut command = command("sh");
+        command.args(["-c", "ulimit -n; exec sleep 30"]).stdout(Stdio::piped()).process_group(0);
+        let mut child = set_open_files(&mut command, 64).spawn().unwrap();
+        let mut stdout = tokio::io::BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        tokio::io::AsyncBufReadExt::read_line(&mut stdout, &mut line).await.unwrap();
+        assert_eq!(line.trim(), "64");
+
+        assert!(memory_usage(child.id().unwrap()).is_some_and(|bytes| bytes > 0));
+        kill(&mut child).await.unwrap();
+        child.wait().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_set_umask() {
+        let mut command = command("sh");
+        command.args(["-c", "umask"]);
+        let output = set_umask(&mut command, 0o027).output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "0027");
+    }
+}
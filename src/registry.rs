@@ -0,0 +1,382 @@
+//! MCP server registry client
+//! Searches an MCP registry (official `/v0/servers` API) and turns a listing into
+//! an installed package plus a Claude config server entry
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Official MCP registry
+pub const DEFAULT_REGISTRY_URL: &str = "https://registry.modelcontextprotocol.io";
+
+/// Results requested per search
+const SEARCH_LIMIT: usize = 20;
+
+/// Server listing from the registry
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryServer {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub packages: Vec<Package>,
+}
+
+/// Installable package for a server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Package {
+    #[serde(alias = "registry_type", alias = "registry_name")]
+    pub registry_type: String,
+    #[serde(alias = "name")]
+    pub identifier: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub transport: Option<PackageTransport>,
+    #[serde(default, alias = "package_arguments")]
+    pub package_arguments: Vec<PackageArgument>,
+    #[serde(default, alias = "environment_variables")]
+    pub environment_variables: Vec<EnvironmentVariable>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackageTransport {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Argument passed to the server command
+#[derive(Debug, Clone, Deserialize)]
+pub struct PackageArgument {
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// Environment variable the server reads
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentVariable {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, alias = "is_required")]
+    pub is_required: bool,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ServerList {
+    #[serde(default)]
+    servers: Vec<ServerListEntry>,
+}
+
+/// Newer registry versions wrap each listing as `{"server": {...}, "_meta": {...}}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ServerListEntry {
+    Wrapped { server: RegistryServer },
+    Bare(RegistryServer),
+}
+
+/// Package manager used to install a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Installer {
+    Npm,
+    Uv,
+    Pip,
+}
+
+/// What `install` will run and write
+#[derive(Debug, Clone)]
+pub struct InstallPlan {
+    /// Name the server is registered under in the Claude config
+    pub server_name: String,
+    pub installer: Installer,
+    pub package: String,
+    /// Version the entry is pinned to
+    pub version: Option<String>,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    /// Required variables with no default that were not supplied
+    pub missing_env: Vec<EnvironmentVariable>,
+}
+
+/// Search the registry for servers matching `query`
+pub async fn search(registry_url: &str, query: &str) -> Result<Vec<RegistryServer>> {
+    let url = format!("{}/v0/servers", registry_url.trim_end_matches('/'));
+    let limit = SEARCH_LIMIT.to_string();
+
+    let response = reqwest::Client::new()
+        .get(&url)
+        .query(&[("search", query), ("limit", limit.as_str())])
+        .send()
+        .await
+        .context(format!("Failed to reach registry at {}", url))?
+        .error_for_status()
+        .context("Registry search failed")?;
+
+    let list: ServerList = response.json().await.context("Invalid registry response")?;
+
+    Ok(list
+        .servers
+        .into_iter()
+        .map(|entry| match entry {
+            ServerListEntry::Wrapped { server } | ServerListEntry::Bare(server) => server,
+        })
+        .collect())
+}
+
+/// Latest published version of a package on npm or PyPI
+pub async fn latest_version(installer: Installer, package: &str) -> Result<String> {
+    let (url, pointer) = match installer {
+        Installer::Npm => (
+            format!("https://registry.npmjs.org/{}/latest", package.replace('/', "%2F")),
+            "/version",
+        ),
+        Installer::Uv | Installer::Pip => (format!("https://pypi.org/pypi/{}/json", package), "/info/version"),
+    };
+
+    let metadata: serde_json::Value = reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await
+        .context(format!("Failed to reach {}", url))?
+        .error_for_status()
+        .context(format!("Failed to look up {}", package))?
+        .json()
+        .await
+        .context(format!("Invalid package metadata for {}", package))?;
+
+    metadata
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .context(format!("No version published for {}", package))
+}
+
+/// Pick the listing for `query`: an exact name match, a match on the last
+/// name segment (`filesystem` for `io.github.acme/filesystem`), or the only result
+pub fn resolve<'a>(servers: &'a [RegistryServer], query: &str) -> Result<&'a RegistryServer> {
+    if let Some(server) = servers.iter().find(|s| s.name == query) {
+        return Ok(server);
+    }
+
+    let by_segment: Vec<_> = servers.iter().filter(|s| short_name(&s.name) == query).collect();
+    match (by_segment.as_slice(), servers) {
+        ([server], _) => Ok(server),
+        ([], [server]) => Ok(server),
+        ([], []) => anyhow::bail!("No registry server matches '{}'", query),
+        _ => {
+            let candidates: Vec<_> = servers.iter().map(|s| format!("  {} - {}", s.name, s.description)).collect();
+            anyhow::bail!(
+                "'{}' matches several servers, re-run with a full name:\n{}",
+                query,
+                candidates.join("\n")
+            )
+        }
+    }
+}
+
+/// Plan the installation of `server` using the first stdio package we can install
+pub fn plan(
+    server: &RegistryServer,
+    server_name: Option<String>,
+    env_overrides: HashMap<String, String>,
+) -> Result<InstallPlan> {
+    let (package, installer) = server
+        .packages
+        .iter()
+        .filter(|p| p.transport.as_ref().is_none_or(|t| t.kind == "stdio"))
+        .find_map(|p| installer_for(&p.registry_type).map(|i| (p, i)))
+        .context(format!("{} has no npm or PyPI stdio package", server.name))?;
+
+    let version = package.version.clone().or_else(|| server.version.clone());
+    let spec = installer.spec(&package.identifier, version.as_deref());
+
+    let (command, mut args) = match installer {
+        Installer::Npm => ("npx".to_string(), vec!["-y".to_string(), spec.clone()]),
+        Installer::Uv => ("uvx".to_string(), vec![spec.clone()]),
+        // pip installs the package's console script onto PATH
+        Installer::Pip => (package.identifier.clone(), Vec::new()),
+    };
+
+    for argument in &package.package_arguments {
+        let Some(value) = argument.value.as_ref().or(argument.default.as_ref()) else {
+            continue;
+        };
+        match (argument.kind.as_str(), &argument.name) {
+            ("named", Some(name)) => {
+                args.push(name.clone());
+                args.push(value.clone());
+            }
+            _ => args.push(value.clone()),
+        }
+    }
+
+    let mut env = HashMap::new();
+    let mut missing_env = Vec::new();
+    for variable in &package.environment_variables {
+        match env_overrides.get(&variable.name).or(variable.default.as_ref()) {
+            Some(value) => {
+                env.insert(variable.name.clone(), value.clone());
+            }
+            None if variable.is_required => missing_env.push(variable.clone()),
+            None => {}
+        }
+    }
+    // Explicit overrides are kept even when the registry doesn't list them
+    env.extend(env_overrides);
+
+    Ok(InstallPlan {
+        server_name: server_name.unwrap_or_else(|| short_name(&server.name).to_string()),
+        installer,
+        package: package.identifier.clone(),
+        version,
+        command,
+        args,
+        env,
+        missing_env,
+    })
+}
+
+impl Installer {
+    /// Package spec, pinned when a version is given (`pkg@1.2.3` / `pkg==1.2.3`)
+    pub fn spec(&self, package: &str, version: Option<&str>) -> String {
+        match (self, version) {
+            (Installer::Npm, Some(v)) => format!("{}@{}", package, v),
+            (_, Some(v)) => format!("{}=={}", package, v),
+            (_, None) => package.to_string(),
+        }
+    }
+
+    /// Command line that installs `spec`
+    pub fn install_command(&self, spec: &str) -> Vec<String> {
+        let parts: &[&str] = match self {
+            Installer::Npm => &["npm", "install", "-g"],
+            Installer::Uv => &["uv", "tool", "install"],
+            Installer::Pip => &["python3", "-m", "pip", "install", "--user"],
+        };
+        parts
+            .iter()
+            .map(|p| p.to_string())
+            .chain(std::iter::once(spec.to_string()))
+            .collect()
+    }
+
+    /// Run the package manager to install `spec`
+    pub async fn install(&self, spec: &str) -> Result<()> {
+        let command = self.install_command(spec);
+        let status = tokio::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .await
+            .context(format!("Failed to run {}", command[0]))?;
+
+        if !status.success() {
+            anyhow::bail!("`{}` exited with {}", command.join(" "), status);
+        }
+        Ok(())
+    }
+}
+
+impl InstallPlan {
+    /// Package spec including the pinned version
+    pub fn spec(&self) -> String {
+        self.installer.spec(&self.package, self.version.as_deref())
+    }
+
+    /// Command line that installs the package
+    pub fn install_command(&self) -> Vec<String> {
+        self.installer.install_command(&self.spec())
+    }
+
+    /// Server entry for the Claude config `mcpServers` map
+    pub fn entry(&self) -> serde_json::Value {
+        let mut entry = serde_json::json!({
+            "command": self.command,
+            "args": self.args,
+        });
+        if !self.env.is_empty() {
+            entry["env"] = serde_json::json!(self.env);
+        }
+        entry
+    }
+}
+
+fn installer_for(registry_type: &str) -> Option<Installer> {
+    match registry_type {
+        "npm" => Some(Installer::Npm),
+        "pypi" if on_path("uv") => Some(Installer::Uv),
+        "pypi" => Some(Installer::Pip),
+        _ => None,
+    }
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Whether `program` is an executable file in a PATH directory
+pub fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| Path::new(&dir).join(program).is_file()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = r#"{"servers":[{"server":{
+        "name":"io.github.acme/filesystem",
+        "description":"Files",
+        "version":"1.2.0",
+        "packages":[
+            {"registryType":"oci","identifier":"acme/fs","transport":{"type":"stdio"}},
+            {"registryType":"npm","identifier":"@acme/server-filesystem","version":"1.2.0",
+             "transport":{"type":"stdio"},
+             "packageArguments":[{"type":"positional","valueHint":"dir","default":"/tmp"}],
+             "environmentVariables":[
+                {"name":"FS_TOKEN","isRequired":true},
+                {"name":"FS_MODE","default":"ro"}]}]},
+        "_meta":{}}]}"#;
+
+    #[test]
+    fn test_plan_npm_install() {
+        let list: ServerList = serde_json::from_str(LISTING).unwrap();
+        let servers: Vec<_> = list
+            .servers
+            .into_iter()
+            .map(|entry| match entry {
+                ServerListEntry::Wrapped { server } | ServerListEntry::Bare(server) => server,
+            })
+            .collect();
+
+        let server = resolve(&servers, "filesystem").unwrap();
+        let plan = plan(server, None, HashMap::new()).unwrap();
+        assert_eq!(plan.server_name, "filesystem");
+        assert_eq!(plan.install_command(), ["npm", "install", "-g", "@acme/server-filesystem@1.2.0"]);
+        assert_eq!(plan.args, ["-y", "@acme/server-filesystem@1.2.0", "/tmp"]);
+        assert_eq!(plan.env.get("FS_MODE").map(String::as_str), Some("ro"));
+        assert_eq!(plan.missing_env.len(), 1);
+
+        let env = HashMap::from([("FS_TOKEN".to_string(), "secret".to_string())]);
+        let plan = super::plan(server, Some("files".to_string()), env).unwrap();
+        assert!(plan.missing_env.is_empty());
+        assert_eq!(plan.entry()["env"]["FS_TOKEN"], "secret");
+        assert_eq!(plan.server_name, "files");
+    }
+}
@@ -0,0 +1,248 @@
+//! Session reports
+//! `mcp-citadel session export` bundles a client session's transcript, its
+//! servers' stderr tails, the hub log lines around it, and a snapshot of the
+//! configuration into a zip to attach to bug reports. Env values and
+//! secret-looking settings are redacted from the snapshot, and wherever else
+//! they appear in the archive.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, HubConfig};
+use crate::redact::{self, is_secret_key, REDACTED};
+use crate::router::transcript::{Entry, SessionSummary};
+
+/// Hub log lines kept, the latest ones
+const MAX_LOG_LINES: usize = 5000;
+
+/// Log lines this long before the session started or after it ended still count as related
+const LOG_MARGIN_SECS: i64 = 60;
+
+/// What the hub returns for `sessions.export`
+#[derive(Debug, Deserialize)]
+pub struct SessionExport {
+    pub session: SessionSummary,
+    pub messages: Vec<Entry>,
+    /// Per server; None when it isn't running or has no stderr (mocks)
+    pub stderr: BTreeMap<String, Option<Vec<String>>>,
+}
+
+/// Hub log used when none is given: the one `prune` rotates
+pub fn default_log_file() -> PathBuf {
+    config::data_dir().join("hub.log")
+}
+
+/// Write the archive for a session
+pub fn write_archive(export: &SessionExport, hub_config: &HubConfig, log_file: Option<&Path>, output: &Path) -> Result<()> {
+    let mut secrets = Vec::new();
+    let config = config_snapshot(hub_config, export, &mut secrets);
+    // Longer secrets first, so one containing another is replaced whole
+    secrets.retain(|s| s.len() >= 8);
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    secrets.dedup();
+    let scrub = |text: &str| redact::scrub(&secrets.iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), REDACTED))).into_owned();
+
+    let file = std::fs::File::create(output).context(format!("Failed to create {}", output.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let mut add = |name: &str, content: &str| -> Result<()> {
+        zip.start_file(name, zip::write::SimpleFileOptions::default())?;
+        zip.write_all(content.as_bytes())?;
+        Ok(())
+    };
+
+    let log = log_file.filter(|path| path.exists());
+    add(
+        "session.json",
+        &serde_json::to_string_pretty(&json!({
+            "session": export.session,
+            "exported_at": chrono::Utc::now().to_rfc3339(),
+            "hub_version": env!("CARGO_PKG_VERSION"),
+            "log_file": log,
+        }))?,
+    )?;
+
+    let mut transcript = String::new();
+    for entry in &export.messages {
+        transcript.push_str(&scrub(&serde_json::to_string(entry)?));
+        transcript.push('\n');
+    }
+    add("transcript.jsonl", &transcript)?;
+
+    for (server, lines) in &export.stderr {
+        if let Some(lines) = lines {
+            add(&format!("stderr/{}.log", server), &scrub(&lines.join("\n")))?;
+        }
+    }
+
+    if let Some(log) = log {
+        let content = std::fs::read_to_string(log).context(format!("Failed to read {}", log.display()))?;
+        add("hub.log", &scrub(&related_log_lines(&content, &export.session).join("\n")))?;
+    }
+
+    add("config.json", &scrub(&serde_json::to_string_pretty(&config)?))?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// The hub config and the session's servers' Claude config entries, redacted
+///
+/// Redacted values are added to `secrets`.
+fn config_snapshot(hub_config: &HubConfig, export: &SessionExport, secrets: &mut Vec<String>) -> Value {
+    let hub = std::fs::read_to_string(config::hub_config_path())
+        .ok()
+        .and_then(|content| toml::from_str::<toml::Value>(&content).ok())
+        .and_then(|config| serde_json::to_value(config).ok())
+        .unwrap_or(Value::Null);
+
+    let servers = std::fs::read_to_string(&hub_config.claude_config_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|mut config| config.get_mut("mcpServers").map(Value::take))
+        .and_then(|servers| match servers {
+            Value::Object(servers) => Some(servers),
+            _ => None,
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| export.session.servers.contains(name))
+        .collect();
+
+    let mut snapshot = json!({ "hub": hub, "servers": Value::Object(servers) });
+    redact(&mut snapshot, secrets);
+    snapshot
+}
+
+/// Replace env values, headers, and secret-looking settings
+fn redact(value: &mut Value, secrets: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    Value::Object(vars) if key == "env" || key == "headers" => vars.values_mut().for_each(|v| conceal(v, secrets)),
+                    _ if is_secret_key(key) => conceal(value, secrets),
+                    _ => redact(value, secrets),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact(v, secrets)),
+        _ => {}
+    }
+}
+
+fn conceal(value: &mut Value, secrets: &mut Vec<String>) {
+    match value {
+        Value::Null => {}
+        Value::Object(map) => map.values_mut().for_each(|v| conceal(v, secrets)),
+        Value::Array(items) => items.iter_mut().for_each(|v| conceal(v, secrets)),
+        Value::String(s) if s.is_empty() => {}
+        Value::String(s) => {
+            secrets.push(std::mem::take(s));
+            *value = json!(REDACTED);
+        }
+        _ => *value = json!(REDACTED),
+    }
+}
+
+/// Log lines from around the session that mention it or one of its servers
+fn related_log_lines<'a>(content: &'a str, session: &SessionSummary) -> Vec<&'a str> {
+    let parse = |at: &str| chrono::DateTime::parse_from_rfc3339(at).ok();
+    let margin = chrono::Duration::seconds(LOG_MARGIN_SECS);
+    let start = parse(&session.started_at).map(|t| t - margin);
+    let end = parse(session.closed_at.as_deref().unwrap_or(&session.last_active)).map(|t| t + margin);
+    // HTTP request logs show the first 8 characters of the session id
+    let short_id = &session.id[..session.id.len().min(8)];
+
+    let mut lines: Vec<&str> = content
+        .lines()
+        .filter(|line| {
+            let plain = strip_ansi(line);
+            let in_window = match log_timestamp(&plain) {
+                Some(at) => start.is_none_or(|start| at >= start) && end.is_none_or(|end| at <= end),
+                None => false,
+            };
+            in_window && (plain.contains(short_id) || session.servers.iter().any(|server| mentions(&plain, server)))
+        })
+        .collect();
+    if lines.len() > MAX_LOG_LINES {
+        lines.drain(..lines.len() - MAX_LOG_LINES);
+    }
+    lines
+}
+
+/// Whether a line names a server, and not just one whose name starts with it
+fn mentions(line: &str, name: &str) -> bool {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    line.match_indices(name).any(|(at, _)| {
+        !line[..at].ends_with(is_name_char) && !line[at + name.len()..].starts_with(is_name_char)
+    })
+}
+
+/// Timestamp of a text (`2026-10-15T09:00:00.123Z  INFO ...`) or JSON log line
+fn log_timestamp(line: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let at = match line.trim_start().starts_with('{') {
+        true => serde_json::from_str::<Value>(line).ok()?.get("timestamp")?.as_str()?.to_string(),
+        false => line.split_whitespace().next()?.to_string(),
+    };
+    chrono::DateTime::parse_from_rfc3339(&at).ok()
+}
+
+/// A log line without terminal color codes
+fn strip_ansi(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip `ESC [ ... <letter>`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_and_related_logs() {
+        let mut secrets = Vec::new();
+        let mut config = json!({
+            "servers": { "github": { "command": "npx", "env": { "GITHUB_TOKEN": "ghp_abcdef123456" } } },
+            "hub": { "webhooks": [{ "url": "https://example.com", "secret": "s3cret-value" }] },
+        });
+        redact(&mut config, &mut secrets);
+        assert_eq!(config["servers"]["github"]["env"]["GITHUB_TOKEN"], REDACTED);
+        assert_eq!(config["servers"]["github"]["command"], "npx");
+        assert_eq!(config["hub"]["webhooks"][0]["secret"], REDACTED);
+        assert_eq!(secrets, ["s3cret-value", "ghp_abcdef123456"]);
+
+        let session = SessionSummary {
+            id: "0123456789abcdef".to_string(),
+            transport: "http".to_string(),
+            started_at: "2026-10-15T09:00:00Z".to_string(),
+            last_active: "2026-10-15T09:05:00Z".to_string(),
+            closed_at: None,
+            servers: ["github".to_string()].into(),
+            messages: 2,
+        };
+        let log = "\
+\x1b[2m2026-10-15T09:01:00.000Z\x1b[0m  INFO POST /mcp method=tools/list server=github session=01234567
+2026-10-15T09:02:00.000Z  INFO Started MCP server: github-enterprise
+2026-10-15T10:00:00.000Z  WARN Server github crashed during startup
+{\"timestamp\":\"2026-10-15T09:03:00Z\",\"level\":\"WARN\",\"fields\":{\"message\":\"Server github is alive but unresponsive\"}}";
+        let lines = related_log_lines(log, &session);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("session=01234567"));
+        assert!(lines[1].contains("unresponsive"));
+    }
+}
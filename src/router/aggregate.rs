@@ -0,0 +1,179 @@
+//! Aggregated tools and prompts
+//! The virtual `citadel` server: a client that names no backend (or names
+//! `citadel`) gets every backend's tools from one `tools/list` and prompts
+//! from one `prompts/list`, each name prefixed with `{server}__`, and its
+//! `tools/call` or `prompts/get` on a prefixed name goes to that backend with
+//! the prefix stripped:
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"tools/list"}
+//! ← {"jsonrpc":"2.0","id":1,"result":{"tools":[{"name":"github__search_repositories",...}]}}
+//! → {"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"github__search_repositories","arguments":{}}}
+//! ```
+
+use bytes::Bytes;
+use futures::future::join_all;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use super::identity;
+use super::message::{self, Envelope};
+use super::workspace::Scope;
+use super::HubManager;
+
+/// Name of the virtual server
+pub const SERVER: &str = "citadel";
+
+/// Between the server and own name of an aggregated tool or prompt
+const SEPARATOR: &str = "__";
+
+/// What becomes of a message for the virtual server
+pub enum Routed {
+    /// The hub's answer
+    Answer(Bytes),
+    /// A tool call or prompt request for this backend, with the tool's or
+    /// prompt's own name
+    Call(String, Bytes),
+}
+
+/// Whether a message routed to `name` is for the virtual server: it names
+/// `citadel`, or names no server and `name` (from its method) isn't one
+pub async fn addressed(manager: &HubManager, scope: Option<&Scope>, name: &str, message: &[u8]) -> bool {
+    if name != SERVER && message::server_param(message).is_some() {
+        return false;
+    }
+    let target = scope.and_then(|s| s.target(name)).unwrap_or(name);
+    !manager.list_servers().await.iter().any(|s| s == target)
+}
+
+/// Answer a message for the virtual server, or resolve its tool call or
+/// prompt request
+pub async fn route(manager: &HubManager, scope: Option<&Scope>, message: Bytes) -> Routed {
+    let Some(envelope) = Envelope::parse(&message) else {
+        return Routed::Answer(reply(Value::Null, Err(json!({ "code": -32700, "message": "Parse error" }))));
+    };
+    // Notifications (`notifications/initialized`, cancellations) have no one to go to
+    let Some(id) = envelope.id.and_then(|id| serde_json::from_str::<Value>(id.get()).ok()) else {
+        return Routed::Answer(Bytes::new());
+    };
+    let params: Value = envelope
+        .params
+        .and_then(|p| serde_json::from_str(p.get()).ok())
+        .unwrap_or_default();
+
+    let outcome = match envelope.method.as_deref().unwrap_or_default() {
+        "initialize" => {
+            let mut result = json!({
+                "protocolVersion": params.get("protocolVersion").cloned().unwrap_or_else(|| json!("2025-06-18")),
+                "capabilities": { "tools": {}, "prompts": {} },
+                "serverInfo": { "name": "mcp-citadel", "version": env!("CARGO_PKG_VERSION") },
+            });
+            identity::advertise(&mut result);
+            Ok(result)
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": list(manager, scope, List::Tools).await })),
+        "prompts/list" => Ok(json!({ "prompts": list(manager, scope, List::Prompts).await })),
+        method @ ("tools/call" | "prompts/get") => {
+            let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+            match split(&servers(manager, scope).await, name) {
+                Some((server, own)) => {
+                    let mut call = serde_json::from_slice::<Value>(&message).unwrap_or_default();
+                    call["params"]["name"] = json!(own);
+                    let mut line = call.to_string().into_bytes();
+                    line.push(b'\n');
+                    return Routed::Call(server, Bytes::from(line));
+                }
+                None => {
+                    let kind = if method == "tools/call" { "tool" } else { "prompt" };
+                    Err(json!({ "code": -32602, "message": format!("Unknown {}: {}", kind, name) }))
+                }
+            }
+        }
+        method => Err(json!({ "code": -32601, "message": format!("Method not found: {}", method) })),
+    };
+    Routed::Answer(reply(id, outcome))
+}
+
+/// Client-facing names of the servers in the client's scope
+async fn servers(manager: &HubManager, scope: Option<&Scope>) -> Vec<String> {
+    let servers = manager.list_servers().await;
+    let mut servers = match scope {
+        Some(scope) => scope.filter(servers),
+        None => servers,
+    };
+    servers.sort();
+    servers
+}
+
+/// A list the virtual server aggregates
+#[derive(Clone, Copy)]
+enum List {
+    Tools,
+    Prompts,
+}
+
+/// Every server's tools or prompts, fetched concurrently, with prefixed
+/// names; servers that can't list them are left out
+async fn list(manager: &HubManager, scope: Option<&Scope>, kind: List) -> Vec<Value> {
+    let servers = servers(manager, scope).await;
+    let lists = join_all(servers.iter().map(|server| async move {
+        let target = scope.and_then(|s| s.target(server)).unwrap_or(server);
+        match kind {
+            List::Tools => manager.tools(target).await,
+            List::Prompts => manager.prompts(target).await,
+        }
+    }))
+    .await;
+
+    let mut items = Vec::new();
+    for (server, list) in servers.iter().zip(lists) {
+        match list {
+            Ok(list) => items.extend(list.into_iter().filter_map(|mut item| {
+                let name = item.get("name")?.as_str()?;
+                item["name"] = json!(format!("{}{}{}", server, SEPARATOR, name));
+                Some(item)
+            })),
+            // Plenty of servers have no prompts
+            Err(e) if matches!(kind, List::Prompts) => debug!("Leaving {} out of the aggregated prompts: {}", server, e),
+            Err(e) => warn!("Leaving {} out of the aggregated tools: {}", server, e),
+        }
+    }
+    items
+}
+
+/// Server and own name of a prefixed tool or prompt name, preferring the
+/// longest server name when several match
+fn split(servers: &[String], name: &str) -> Option<(String, String)> {
+    servers
+        .iter()
+        .filter_map(|server| {
+            let own = name.strip_prefix(server.as_str())?.strip_prefix(SEPARATOR)?;
+            Some((server.clone(), own.to_string()))
+        })
+        .max_by_key(|(server, _)| server.len())
+}
+
+fn reply(id: Value, outcome: Result<Value, Value>) -> Bytes {
+    let response = match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    };
+    let mut line = response.to_string().into_bytes();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let servers = vec!["git".to_string(), "git__hub".to_string(), "slack".to_string()];
+        assert_eq!(split(&servers, "git__hub__search"), Some(("git__hub".to_string(), "search".to_string())));
+        assert_eq!(split(&servers, "git__log"), Some(("git".to_string(), "log".to_string())));
+        assert_eq!(split(&servers, "slack__post__message"), Some(("slack".to_string(), "post__message".to_string())));
+        assert_eq!(split(&servers, "search"), None);
+    }
+}
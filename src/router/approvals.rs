@@ -0,0 +1,161 @@
+//! Tool call approvals
+//! Calls to tools a server's filter lists under `approve` are held by the hub
+//! until an operator decides on them with `mcp-citadel approvals approve|deny`
+//! (the `approvals.*` control methods). Approved calls go on to the backend;
+//! denied ones, and those left undecided for `router.approval_timeout_secs`,
+//! are answered with a policy error.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+use tracing::info;
+
+use super::message::Envelope;
+use super::policy;
+use super::HubEvent;
+
+/// A held tool call, as listed to operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingCall {
+    pub id: u64,
+    pub server: String,
+    pub tool: String,
+    pub arguments: Value,
+    /// Client session that made the call, if any
+    pub session: Option<String>,
+    pub requested_at: String,
+}
+
+/// What an operator decided, or that nobody did in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Denied(Option<String>),
+    TimedOut,
+}
+
+pub struct Approvals {
+    pending: Mutex<BTreeMap<u64, (PendingCall, oneshot::Sender<Decision>)>>,
+    next_id: AtomicU64,
+    lifecycle: broadcast::Sender<HubEvent>,
+}
+
+impl Approvals {
+    pub(super) fn new(lifecycle: broadcast::Sender<HubEvent>) -> Self {
+        Self {
+            pending: Mutex::default(),
+            next_id: AtomicU64::new(0),
+            lifecycle,
+        }
+    }
+
+    /// Calls waiting for a decision, oldest first
+    pub fn list(&self) -> Vec<PendingCall> {
+        self.pending.lock().unwrap().values().map(|(call, _)| call.clone()).collect()
+    }
+
+    /// Approve or deny a held call; false if no call with this id is waiting
+    pub fn decide(&self, id: u64, decision: Decision) -> bool {
+        match self.pending.lock().unwrap().remove(&id) {
+            Some((call, decider)) => {
+                info!("Call to tool {} of {} (#{}): {:?}", call.tool, call.server, id, decision);
+                decider.send(decision).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    /// Hold a `tools/call` until it's decided on or `timeout` passes; the
+    /// policy error answering it unless approved
+    pub(super) async fn hold(&self, server: &str, session: Option<&str>, message: &[u8], timeout: Duration) -> Option<Bytes> {
+        let envelope = Envelope::parse(message)?;
+        let params: Value = serde_json::from_str(envelope.params?.get()).ok()?;
+        let tool = params.get("name")?.as_str()?.to_string();
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (decider, decision) = oneshot::channel();
+        let call = PendingCall {
+            id,
+            server: server.to_string(),
+            tool: tool.clone(),
+            arguments: params.get("arguments").cloned().unwrap_or(Value::Null),
+            session: session.map(String::from),
+            requested_at: chrono::Utc::now().to_rfc3339(),
+        };
+        info!("Call to tool {} of {} is waiting for approval (#{})", tool, server, id);
+        self.pending.lock().unwrap().insert(id, (call, decider));
+        let _ = self.lifecycle.send(HubEvent::ApprovalRequested { id, server: server.to_string(), tool: tool.clone() });
+
+        // A client that gives up takes its call off the list
+        let _withdraw = Withdraw { approvals: self, id };
+        let decision = tokio::time::timeout(timeout, decision).await.ok().and_then(Result::ok).unwrap_or(Decision::TimedOut);
+        let message = match decision {
+            Decision::Approved => return None,
+            Decision::Denied(Some(reason)) => format!("Call to tool {} of {} was denied: {}", tool, server, reason),
+            Decision::Denied(None) => format!("Call to tool {} of {} was denied", tool, server),
+            Decision::TimedOut => {
+                info!("Call to tool {} of {} (#{}) wasn't approved within {}s", tool, server, id, timeout.as_secs());
+                format!("Call to tool {} of {} wasn't approved within {}s", tool, server, timeout.as_secs())
+            }
+        };
+        policy::rejection(&envelope, message, json!({ "policy": "approval", "server": server, "tool": tool, "approval": id }))
+    }
+}
+
+struct Withdraw<'a> {
+    approvals: &'a Approvals,
+    id: u64,
+}
+
+impl Drop for Withdraw<'_> {
+    fn drop(&mut self) {
+        self.approvals.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_hold_until_decided() {
+        let approvals = Arc::new(Approvals::new(broadcast::channel(8).0));
+        let call = br#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"delete_repo","arguments":{"repo":"x"}}}"#;
+
+        let held = tokio::spawn({
+            let approvals = Arc::clone(&approvals);
+            async move { approvals.hold("github", Some("s1"), call, Duration::from_secs(10)).await }
+        });
+        while approvals.list().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let pending = approvals.list();
+        assert_eq!((pending[0].tool.as_str(), &pending[0].arguments), ("delete_repo", &json!({ "repo": "x" })));
+        assert!(approvals.decide(pending[0].id, Decision::Approved));
+        assert!(held.await.unwrap().is_none());
+        assert!(approvals.list().is_empty());
+        assert!(!approvals.decide(pending[0].id, Decision::Approved));
+
+        let held = tokio::spawn({
+            let approvals = Arc::clone(&approvals);
+            async move { approvals.hold("github", None, call, Duration::from_secs(10)).await }
+        });
+        while approvals.list().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        approvals.decide(approvals.list()[0].id, Decision::Denied(Some("not today".to_string())));
+        let rejection: Value = serde_json::from_slice(&held.await.unwrap().unwrap()).unwrap();
+        assert_eq!((rejection["id"].as_i64(), rejection["error"]["code"].as_i64()), (Some(5), Some(policy::POLICY_ERROR as i64)));
+        assert!(rejection["error"]["message"].as_str().unwrap().ends_with("not today"));
+
+        let timed_out = approvals.hold("github", None, call, Duration::from_millis(10)).await.unwrap();
+        assert!(String::from_utf8_lossy(&timed_out).contains("wasn't approved"));
+        assert!(approvals.list().is_empty());
+    }
+}
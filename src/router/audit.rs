@@ -0,0 +1,241 @@
+//! Audit log
+//! With `[router.audit] enabled = true`, every request routed to a server is
+//! appended to `~/.mcp-citadel/audit/audit.jsonl` once it's answered: when,
+//! which client session sent it, the server, method and tool, how long it
+//! took, and whether it succeeded. `payloads = true` adds the request's params
+//! and the response. The log is rotated to `audit.<timestamp>.jsonl` at
+//! `max_file_mb`, keeping the newest `max_files`. Secrets are masked (see
+//! `crate::redact`).
+//!
+//! Each record's metadata also goes into the request history (see
+//! `super::history`).
+//!
+//! ```text
+//! {"at":"2026-10-16T09:00:00.131Z","session":"3f2a…","client":"claude-code 1.2.0","server":"github","method":"tools/call","tool":"create_issue","latency_ms":412.5,"outcome":"ok"}
+//! ```
+
+use anyhow::Result;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+use super::history::History;
+use super::message::Envelope;
+use crate::config::AuditConfig;
+
+/// Directory of the audit log and its rotations
+pub fn dir() -> PathBuf {
+    crate::config::data_dir().join("audit")
+}
+
+/// What came of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Answered with a result (or nothing, for a request the hub absorbed)
+    Ok,
+    /// Answered with a JSON-RPC error, by the server or the hub
+    Error,
+    /// Not answered: the server couldn't be reached or didn't respond
+    Failed,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// RFC 3339, when the answer came
+    pub at: String,
+    pub session: Option<String>,
+    /// What the client said it is in its `initialize`
+    pub client: Option<String>,
+    pub server: String,
+    pub method: String,
+    /// Called tool, for `tools/call`
+    pub tool: Option<String>,
+    pub latency_ms: f64,
+    pub outcome: Outcome,
+    /// Error message, unless `ok`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+}
+
+/// A request on its way, recorded once it's answered
+pub struct Pending {
+    record: Record,
+    started: Instant,
+}
+
+pub struct AuditLog {
+    dir: PathBuf,
+    config: AuditConfig,
+    /// The current log and its size, opened on the first record
+    file: Mutex<Option<(File, u64)>>,
+    /// The request history, opened on the first record
+    history: Mutex<Option<History>>,
+}
+
+impl AuditLog {
+    pub fn new(dir: PathBuf, config: AuditConfig) -> Self {
+        Self { dir, config, file: Mutex::new(None), history: Mutex::new(None) }
+    }
+
+    /// Start auditing a client message; None unless it's a request
+    pub fn begin(&self, session: Option<&str>, client: Option<String>, server: &str, message: &[u8]) -> Option<Pending> {
+        let envelope = Envelope::parse(message).filter(Envelope::is_request)?;
+        let method = super::backend_method(server, envelope.method.as_deref()?).to_string();
+        let params: Option<Value> = envelope.params.and_then(|p| serde_json::from_str(p.get()).ok());
+        let tool = match method.as_str() {
+            "tools/call" => params.as_ref().and_then(|p| p.get("name")?.as_str().map(String::from)),
+            _ => None,
+        };
+        Some(Pending {
+            record: Record {
+                at: String::new(),
+                session: session.map(String::from),
+                client,
+                server: server.to_string(),
+                method,
+                tool,
+                latency_ms: 0.0,
+                outcome: Outcome::Ok,
+                error: None,
+                params: params.filter(|_| self.config.payloads),
+                response: None,
+            },
+            started: Instant::now(),
+        })
+    }
+
+    /// Record what came of a request
+    pub fn finish(&self, pending: Pending, routed: &Result<Bytes>) {
+        let Pending { mut record, started } = pending;
+        record.latency_ms = (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0;
+        record.at = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+        match routed {
+            Ok(response) => {
+                let response: Option<Value> = serde_json::from_slice(response).ok();
+                if let Some(error) = response.as_ref().and_then(|r| r.get("error")) {
+                    record.outcome = Outcome::Error;
+                    record.error = Some(error.get("message").and_then(Value::as_str).unwrap_or("unknown error").to_string());
+                }
+                record.response = response.filter(|_| self.config.payloads);
+            }
+            Err(e) => {
+                record.outcome = Outcome::Failed;
+                record.error = Some(format!("{:#}", e));
+            }
+        }
+        record.error = record.error.map(|e| crate::redact::scrub(&e).into_owned());
+        record.params.iter_mut().chain(&mut record.response).for_each(crate::redact::scrub_value);
+        if let Err(e) = self.write(&record) {
+            warn!("Failed to write the audit log in {}: {:#}", self.dir.display(), e);
+        }
+        if let Err(e) = self.remember(&record) {
+            warn!("Failed to record the request history in {}: {:#}", self.dir.display(), e);
+        }
+    }
+
+    fn remember(&self, record: &Record) -> Result<()> {
+        let mut history = self.history.lock().unwrap();
+        if history.is_none() {
+            *history = Some(History::open(&self.dir.join("history.db"), self.config.history_days)?);
+        }
+        history.as_mut().expect("opened above").record(record)
+    }
+
+    fn write(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let max_bytes = self.config.max_file_mb.saturating_mul(1024 * 1024);
+
+        let mut file = self.file.lock().unwrap();
+        if file.as_ref().is_some_and(|(_, size)| *size > 0 && size + line.len() as u64 > max_bytes) {
+            *file = None;
+            rotate(&self.dir, self.config.max_files)?;
+        }
+        if file.is_none() {
+            std::fs::create_dir_all(&self.dir)?;
+            let opened = File::options().create(true).append(true).open(self.dir.join("audit.jsonl"))?;
+            let size = opened.metadata()?.len();
+            *file = Some((opened, size));
+        }
+        let (file, size) = file.as_mut().expect("opened above");
+        file.write_all(&line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn is_rotated(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("audit.") && n != "audit.jsonl")
+}
+
+/// Move the current log aside and remove the oldest rotations past `keep`
+fn rotate(dir: &Path, keep: usize) -> Result<()> {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f");
+    std::fs::rename(dir.join("audit.jsonl"), dir.join(format!("audit.{}.jsonl", stamp)))?;
+    let mut rotated: Vec<PathBuf> = std::fs::read_dir(dir)?.filter_map(|entry| Some(entry.ok()?.path())).filter(|path| is_rotated(path)).collect();
+    // Timestamps sort oldest first
+    rotated.sort();
+    for path in &rotated[..rotated.len().saturating_sub(keep)] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::history::{query, HistoryQuery};
+
+    #[test]
+    fn test_record_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-audit-{}", std::process::id()));
+        let config = AuditConfig { enabled: true, payloads: true, max_file_mb: 0, max_files: 2, history_days: 30 };
+        let audit = AuditLog::new(dir.clone(), config);
+
+        let call = br#"{"jsonrpc":"2.0","id":1,"method":"github/tools/call","params":{"name":"create_issue","arguments":{"title":"x"}}}"#;
+        let pending = audit.begin(Some("s1"), Some("claude-code 1.2.0".to_string()), "github", call).unwrap();
+        audit.finish(pending, &Ok(Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32001,"message":"Denied"}}"#)));
+        let records: Vec<Record> = std::fs::read_to_string(dir.join("audit.jsonl")).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!((records[0].method.as_str(), records[0].tool.as_deref()), ("tools/call", Some("create_issue")));
+        assert_eq!((records[0].outcome, records[0].error.as_deref()), (Outcome::Error, Some("Denied")));
+        assert_eq!(records[0].params.as_ref().unwrap()["arguments"]["title"], "x");
+        assert!(audit.begin(None, None, "github", br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).is_none());
+
+        // Every further record rotates the one before it out, past a limit of 0
+        for _ in 0..4 {
+            let pending = audit.begin(None, None, "github", br#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#).unwrap();
+            audit.finish(pending, &Err(anyhow::anyhow!("Server not found: github")));
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let files = std::fs::read_dir(&dir).unwrap().filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with("audit.")).count();
+        assert_eq!(files, 3);
+        let last: Record = serde_json::from_str(std::fs::read_to_string(dir.join("audit.jsonl")).unwrap().trim()).unwrap();
+        assert_eq!((last.outcome, last.params), (Outcome::Failed, None));
+
+        // The history keeps what was rotated out, without payloads
+        let all = query(&dir.join("history.db"), &HistoryQuery::default(), |_| true).unwrap();
+        assert_eq!(all.len(), 5);
+        assert_eq!((all[0].tool.as_deref(), all[0].session.as_deref(), &all[0].params), (Some("create_issue"), Some("s1"), &None));
+        let failed = HistoryQuery { server: Some("github".to_string()), failed: true, since: Some("1h".to_string()), limit: Some(2), ..Default::default() };
+        let failed = query(&dir.join("history.db"), &failed, |_| true).unwrap();
+        assert_eq!(failed.iter().map(|r| (r.method.as_str(), r.outcome)).collect::<Vec<_>>(), [("ping", Outcome::Failed); 2]);
+        let session = HistoryQuery { session: Some("s".to_string()), ..Default::default() };
+        assert_eq!(query(&dir.join("history.db"), &session, |_| true).unwrap().len(), 1);
+        assert!(query(&dir.join("history.db"), &HistoryQuery::default(), |server| server != "github").unwrap().is_empty());
+        assert!(query(&dir.join("history.db"), &HistoryQuery { tool: Some("search".to_string()), ..Default::default() }, |_| true).unwrap().is_empty());
+        assert!(query(&dir.join("history.db"), &HistoryQuery { since: Some("soon".to_string()), ..Default::default() }, |_| true).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
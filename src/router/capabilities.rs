@@ -0,0 +1,197 @@
+//! Client capabilities
+//! Each session's `initialize` tells the hub what its host can handle. The
+//! hub shapes what it forwards to match (`router.capability_shaping`):
+//!
+//! - Server-initiated requests for a capability the client didn't declare
+//!   (`sampling/createMessage`, `roots/list`, `elicitation/create`) aren't
+//!   forwarded to it (see `reverse`); if no session using the server can
+//!   take one, the hub answers the backend with an error instead of leaving
+//!   it waiting.
+//! - Tool results are downgraded to the client's protocol version: content
+//!   types it predates (`audio`, `resource_link`) become text, and
+//!   `structuredContent` is dropped for clients older than 2025-06-18.
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+use super::backend_method;
+use super::message::Envelope;
+
+/// What a client declared in its `initialize`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientCapabilities {
+    pub protocol_version: Option<String>,
+    /// Top-level keys of `capabilities` (`sampling`, `roots`, `elicitation`, ...)
+    pub declared: BTreeSet<String>,
+    /// `clientInfo` name and version, like `claude-code 1.2.0`
+    pub client: Option<String>,
+}
+
+impl ClientCapabilities {
+    pub fn from_initialize(params: &Value) -> Self {
+        Self {
+            protocol_version: params.get("protocolVersion").and_then(|v| v.as_str()).map(String::from),
+            declared: params
+                .get("capabilities")
+                .and_then(|c| c.as_object())
+                .map(|c| c.keys().cloned().collect())
+                .unwrap_or_default(),
+            client: params.get("clientInfo").and_then(|info| {
+                let name = info.get("name")?.as_str()?;
+                Some(match info.get("version").and_then(|v| v.as_str()) {
+                    Some(version) => format!("{} {}", name, version),
+                    None => name.to_string(),
+                })
+            }),
+        }
+    }
+
+    /// Whether the client can take a server-initiated request
+    pub fn accepts(&self, method: &str) -> bool {
+        required_capability(method).is_none_or(|capability| self.declared.contains(capability))
+    }
+
+    /// Whether the client's protocol version has a feature introduced in `version`
+    fn speaks(&self, version: &str) -> bool {
+        self.protocol_version.as_deref().is_none_or(|v| v >= version)
+    }
+}
+
+/// Capability a client must declare to be sent a server-initiated request
+pub fn required_capability(method: &str) -> Option<&'static str> {
+    match method {
+        "sampling/createMessage" => Some("sampling"),
+        "roots/list" => Some("roots"),
+        "elicitation/create" => Some("elicitation"),
+        _ => None,
+    }
+}
+
+/// Declared capabilities of live sessions, by session id
+#[derive(Default)]
+pub struct SessionCapabilities(DashMap<String, ClientCapabilities>);
+
+impl SessionCapabilities {
+    /// Note a client message; `initialize` sets the session's capabilities
+    pub fn observe(&self, session: &str, server: &str, message: &[u8]) {
+        let Some(envelope) = Envelope::parse(message) else {
+            return;
+        };
+        if envelope.method.as_deref().map(|m| backend_method(server, m)) != Some("initialize") {
+            return;
+        }
+        let params = envelope
+            .params
+            .and_then(|p| serde_json::from_str::<Value>(p.get()).ok())
+            .unwrap_or_default();
+        self.0.insert(session.to_string(), ClientCapabilities::from_initialize(&params));
+    }
+
+    /// None until the session initialized
+    pub fn get(&self, session: &str) -> Option<ClientCapabilities> {
+        self.0.get(session).map(|c| c.clone())
+    }
+
+    pub fn remove(&self, session: &str) {
+        self.0.remove(session);
+    }
+}
+
+/// Whether a client message is one whose response gets shaped
+pub fn shapes_response(server: &str, message: &[u8]) -> bool {
+    Envelope::parse(message)
+        .and_then(|e| e.method.map(|m| backend_method(server, &m) == "tools/call"))
+        .unwrap_or(false)
+}
+
+/// A tool result downgraded to what the client understands (unchanged if it
+/// needs nothing removed)
+pub fn shape_tool_result(capabilities: &ClientCapabilities, response: Bytes) -> Bytes {
+    if capabilities.speaks("2025-06-18") {
+        return response;
+    }
+    let Ok(mut message) = serde_json::from_slice::<Value>(&response) else {
+        return response;
+    };
+    let Some(result) = message.get_mut("result").and_then(|r| r.as_object_mut()) else {
+        return response;
+    };
+
+    let mut changed = result.remove("structuredContent").is_some();
+    if let Some(content) = result.get_mut("content").and_then(|c| c.as_array_mut()) {
+        for item in content.iter_mut() {
+            if let Some(text) = downgrade(capabilities, item) {
+                *item = json!({ "type": "text", "text": text });
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        return response;
+    }
+    let mut line = message.to_string().into_bytes();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Text standing in for a content item the client predates
+fn downgrade(capabilities: &ClientCapabilities, item: &Value) -> Option<String> {
+    let field = |key: &str| item.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    match field("type") {
+        "resource_link" => Some(match field("name") {
+            "" => field("uri").to_string(),
+            name => format!("{}: {}", name, field("uri")),
+        }),
+        "audio" if !capabilities.speaks("2025-03-26") => {
+            Some(format!("[{} audio omitted: not supported by this client]", field("mimeType")))
+        }
+        _ => None,
+    }
+}
+
+/// The error answering a server-initiated request no client can take, naming
+/// the capability (or method) it needed
+pub fn unsupported(message: &[u8], capability: &str) -> Option<Bytes> {
+    let envelope = Envelope::parse(message)?;
+    let id = serde_json::from_str::<Value>(envelope.id?.get()).ok()?;
+    let error = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32601,
+            "message": format!("No connected client supports {}", capability),
+        },
+    });
+    let mut line = error.to_string().into_bytes();
+    line.push(b'\n');
+    Some(Bytes::from(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_and_shaping() {
+        let sessions = SessionCapabilities::default();
+        let initialize = br#"{"jsonrpc":"2.0","id":1,"method":"github/initialize","params":{"protocolVersion":"2024-11-05","capabilities":{"roots":{}}}}"#;
+        sessions.observe("s1", "github", initialize);
+        let capabilities = sessions.get("s1").unwrap();
+        assert!(capabilities.accepts("roots/list"));
+        assert!(!capabilities.accepts("sampling/createMessage"));
+        assert!(capabilities.accepts("ping"));
+
+        let result = br#"{"jsonrpc":"2.0","id":2,"result":{"content":[{"type":"text","text":"hi"},{"type":"audio","data":"AA==","mimeType":"audio/wav"},{"type":"resource_link","uri":"file:///a.txt","name":"a.txt"}],"structuredContent":{"ok":true}}}"#;
+        let shaped: Value = serde_json::from_slice(&shape_tool_result(&capabilities, Bytes::from_static(result))).unwrap();
+        let content = &shaped["result"]["content"];
+        assert_eq!(content[0]["text"], "hi");
+        assert_eq!(content[1]["type"], "text");
+        assert_eq!(content[2]["text"], "a.txt: file:///a.txt");
+        assert!(shaped["result"].get("structuredContent").is_none());
+
+        let current = ClientCapabilities { protocol_version: Some("2025-06-18".to_string()), ..Default::default() };
+        assert_eq!(shape_tool_result(&current, Bytes::from_static(result)), Bytes::from_static(result));
+    }
+}
@@ -0,0 +1,273 @@
+//! Persistent catalog cache
+//! Keeps the last tools/resources/prompts lists seen from each backend on disk,
+//! answering for backends that aren't running, and, within the list's
+//! `list_cache_ttl_secs`, for running ones too. A `list_changed` notification
+//! or a restart of the backend makes its lists stale.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::config::ServerConfig;
+
+/// List methods whose results are cached
+pub const CATALOG_METHODS: [&str; 3] = ["tools/list", "resources/list", "prompts/list"];
+
+/// Catalog cache directory
+pub fn catalog_dir() -> PathBuf {
+    crate::config::data_dir().join("cache").join("catalog")
+}
+
+/// Identity of the backend a catalog was captured from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogKey {
+    pub command: String,
+    pub args: Vec<String>,
+    /// `serverInfo.version` reported by the backend's initialize response
+    pub version: Option<String>,
+}
+
+/// Cached catalog for one backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub key: CatalogKey,
+    /// List method -> JSON-RPC `result` object
+    pub lists: HashMap<String, Value>,
+    pub updated_at: String,
+}
+
+/// On-disk catalog cache, one JSON file per server
+pub struct CatalogCache {
+    dir: PathBuf,
+    entries: HashMap<String, CatalogEntry>,
+    /// When lists were last fetched from the running backend, by server and method
+    fetched: HashMap<(String, String), Instant>,
+    /// Hands cache files to the writer thread, so callers holding the cache
+    /// never wait on the disk
+    writer: mpsc::Sender<(PathBuf, String)>,
+}
+
+impl CatalogCache {
+    /// Load cached catalogs for the configured servers, dropping entries
+    /// whose command or args no longer match the configuration
+    pub fn load(dir: PathBuf, configs: &[ServerConfig]) -> Self {
+        let mut cache = Self { dir, entries: HashMap::new(), fetched: HashMap::new(), writer: spawn_writer() };
+        cache.configure(configs);
+        cache
+    }
+
+    /// Follow a new configuration: load catalogs for added servers and servers
+    /// whose command or args changed, and forget removed ones (their files
+    /// are left for state compaction)
+    pub fn configure(&mut self, configs: &[ServerConfig]) {
+        let mut entries = HashMap::new();
+        for config in configs {
+            let key = catalog_key(config);
+            let entry = match self.entries.remove(&config.name) {
+                Some(entry) if entry.key.command == key.command && entry.key.args == key.args => entry,
+                _ => {
+                    self.fetched.retain(|(name, _), _| *name != config.name);
+                    self.read(config, key)
+                }
+            };
+            entries.insert(config.name.clone(), entry);
+        }
+        for server in self.entries.keys() {
+            debug!("Dropping catalog of removed server {}", server);
+        }
+        self.fetched.retain(|(name, _), _| entries.contains_key(name));
+        self.entries = entries;
+    }
+
+    /// A server's catalog from disk, if it was captured from the same command
+    fn read(&self, config: &ServerConfig, key: CatalogKey) -> CatalogEntry {
+        match read_entry(&entry_path(&self.dir, &config.name)) {
+            // A mock's catalog is in its config, which the key doesn't cover
+            Some(_) if config.mock.is_some() => CatalogEntry::new(key),
+            Some(entry) if entry.key.command == key.command && entry.key.args == key.args => {
+                debug!("Loaded cached catalog for {}", config.name);
+                entry
+            }
+            Some(_) => {
+                debug!("Discarding stale catalog for {} (command changed)", config.name);
+                CatalogEntry::new(key)
+            }
+            None => CatalogEntry::new(key),
+        }
+    }
+
+    /// Get a cached list result
+    pub fn get(&self, server: &str, method: &str) -> Option<&Value> {
+        self.entries.get(server)?.lists.get(method)
+    }
+
+    /// Get a list result fetched from the running backend within `ttl`
+    pub fn fresh(&self, server: &str, method: &str, ttl: Duration) -> Option<&Value> {
+        let fetched = self.fetched.get(&(server.to_string(), method.to_string()))?;
+        (fetched.elapsed() < ttl).then(|| self.get(server, method)).flatten()
+    }
+
+    /// Store a list result, writing it out only if it changed
+    pub fn store(&mut self, server: &str, method: &str, result: Value) {
+        if let Some(entry) = self.entries.get_mut(server) {
+            self.fetched.insert((server.to_string(), method.to_string()), Instant::now());
+            if entry.lists.get(method) == Some(&result) {
+                return;
+            }
+            entry.lists.insert(method.to_string(), result);
+            entry.touch();
+            self.persist(server);
+        }
+    }
+
+    /// Record the backend version, invalidating the catalog if it changed
+    pub fn record_version(&mut self, server: &str, version: Option<String>) {
+        if let Some(entry) = self.entries.get_mut(server) {
+            if entry.key.version == version {
+                return;
+            }
+            if entry.key.version.is_some() && !entry.lists.is_empty() {
+                debug!("Backend {} version changed, invalidating catalog", server);
+                entry.lists.clear();
+                self.fetched.retain(|(name, _), _| name != server);
+            }
+            entry.key.version = version;
+            entry.touch();
+            self.persist(server);
+        }
+    }
+
+    /// Drop a cached list (e.g. after a list_changed notification)
+    pub fn invalidate(&mut self, server: &str, method: &str) {
+        self.fetched.remove(&(server.to_string(), method.to_string()));
+        if let Some(entry) = self.entries.get_mut(server) {
+            if entry.lists.remove(method).is_some() {
+                debug!("Invalidated cached {} for {}", method, server);
+                entry.touch();
+                self.persist(server);
+            }
+        }
+    }
+
+    /// Treat a server's lists as stale, keeping them for while it's down
+    /// (e.g. after it restarted)
+    pub fn expire(&mut self, server: &str) {
+        self.fetched.retain(|(name, _), _| name != server);
+    }
+
+    fn persist(&self, server: &str) {
+        let Some(entry) = self.entries.get(server) else {
+            return;
+        };
+        match serde_json::to_string_pretty(entry) {
+            Ok(content) => {
+                let _ = self.writer.send((entry_path(&self.dir, server), content));
+            }
+            Err(e) => warn!("Failed to persist catalog for {}: {}", server, e),
+        }
+    }
+}
+
+/// What a server's catalog is captured from
+fn catalog_key(config: &ServerConfig) -> CatalogKey {
+    // A remote server's endpoint and a container's image stand in for the command
+    let command = match (&config.remote, &config.container) {
+        (Some(remote), _) => remote.url.clone(),
+        (None, Some(container)) => format!("{} {}", container.image, config.command).trim_end().to_string(),
+        (None, None) => config.command.clone(),
+    };
+    CatalogKey {
+        command,
+        args: config.args.clone(),
+        version: None,
+    }
+}
+
+/// Write cache files in the order they're sent, only the latest of a burst
+/// to the same file; the thread ends with the cache
+fn spawn_writer() -> mpsc::Sender<(PathBuf, String)> {
+    let (writer, files) = mpsc::channel::<(PathBuf, String)>();
+    let spawned = std::thread::Builder::new().name("catalog-writer".to_string()).spawn(move || {
+        while let Ok(first) = files.recv() {
+            let mut pending = vec![first];
+            for (path, content) in files.try_iter() {
+                pending.retain(|(queued, _)| *queued != path);
+                pending.push((path, content));
+            }
+            for (path, content) in pending {
+                if let Err(e) = write_file(&path, &content) {
+                    warn!("Failed to persist catalog: {:#}", e);
+                }
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        warn!("Failed to start the catalog writer, catalogs won't be saved: {}", e);
+    }
+    writer
+}
+
+impl CatalogEntry {
+    fn new(key: CatalogKey) -> Self {
+        Self {
+            key,
+            lists: HashMap::new(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    fn touch(&mut self) {
+        self.updated_at = chrono::Utc::now().to_rfc3339();
+    }
+}
+
+/// Map a `notifications/*/list_changed` method to the list it invalidates
+pub fn invalidated_list(notification: &str) -> Option<&'static str> {
+    match notification {
+        "notifications/tools/list_changed" => Some("tools/list"),
+        "notifications/resources/list_changed" => Some("resources/list"),
+        "notifications/prompts/list_changed" => Some("prompts/list"),
+        _ => None,
+    }
+}
+
+/// Read every cached catalog in a directory (used by the CLI without a running hub)
+pub fn read_all(dir: &Path) -> Vec<(String, CatalogEntry)> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut entries: Vec<(String, CatalogEntry)> = read_dir
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some((name, read_entry(&path)?))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+/// A server's cache file
+pub fn entry_path(dir: &Path, server: &str) -> PathBuf {
+    dir.join(format!("{}.json", server.replace(['/', '\\'], "_")))
+}
+
+fn read_entry(path: &Path) -> Option<CatalogEntry> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content).context(format!("Failed to write {:?}", path))
+}
@@ -0,0 +1,95 @@
+//! Client adapter registry
+//! `mcp-client` processes register over the control socket and stay listed
+//! for as long as their registration connection is open, so
+//! `mcp-citadel clients` shows which editors are attached to which servers
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::metrics;
+
+/// What an adapter reports about itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    /// The editor or agent that launched the adapter
+    pub client: String,
+    pub pid: u32,
+    /// Server the adapter routes to
+    pub server: String,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// When the adapter started (RFC 3339)
+    pub started_at: String,
+}
+
+/// Connected adapters by registration id
+#[derive(Debug, Default)]
+pub struct ClientRegistry {
+    state: Mutex<(u64, BTreeMap<u64, ClientInfo>)>,
+}
+
+/// A listed adapter, removed from the registry on drop
+#[derive(Debug)]
+pub struct Registration {
+    registry: Arc<ClientRegistry>,
+    pub id: u64,
+}
+
+impl ClientRegistry {
+    pub fn register(self: &Arc<Self>, info: ClientInfo) -> Registration {
+        let mut state = self.state.lock().unwrap();
+        state.0 += 1;
+        let id = state.0;
+        state.1.insert(id, info);
+        metrics::CLIENT_ADAPTERS.set(state.1.len() as f64);
+        Registration { registry: Arc::clone(self), id }
+    }
+
+    /// Connected adapters, oldest registration first
+    pub fn list(&self) -> Vec<ClientInfo> {
+        self.state.lock().unwrap().1.values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().1.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut state = self.registry.state.lock().unwrap();
+        state.1.remove(&self.id);
+        metrics::CLIENT_ADAPTERS.set(state.1.len() as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_lasts_until_dropped() {
+        let registry = Arc::new(ClientRegistry::default());
+        let info = |client: &str| ClientInfo {
+            client: client.to_string(),
+            pid: 42,
+            server: "github".to_string(),
+            workspace: None,
+            started_at: "2026-10-15T09:00:00Z".to_string(),
+        };
+
+        let cursor = registry.register(info("cursor"));
+        let claude = registry.register(info("claude"));
+        assert_eq!(registry.list(), [info("cursor"), info("claude")]);
+
+        drop(cursor);
+        assert_eq!(registry.list(), [info("claude")]);
+        drop(claude);
+        assert!(registry.is_empty());
+    }
+}
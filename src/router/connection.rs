@@ -0,0 +1,473 @@
+//! Backend connection
+//! Pipelines JSON-RPC requests over a backend's stdio, correlating responses by id
+//! (messages framed per [`super::framing`])
+
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::framing::Framer;
+use super::message::{cancelled_request_span, splice, with_newline, Envelope};
+use super::priority::Gate;
+use super::size::{self, Line};
+use crate::config::{Framing, Priority};
+use crate::metrics;
+
+/// JSON-RPC error code of requests the backend didn't answer in time
+pub const TIMEOUT_ERROR: i32 = -32002;
+
+/// Consecutive read timeouts before a backend is considered unresponsive
+const MAX_CONSECUTIVE_TIMEOUTS: u32 = 3;
+
+/// Unsolicited message from a backend (notification or server-initiated request)
+#[derive(Debug, Clone)]
+pub struct BackendMessage {
+    pub server: String,
+    pub method: String,
+    /// The raw message line
+    pub message: Bytes,
+}
+
+impl BackendMessage {
+    /// Whether this is a notification rather than a request for the client
+    pub fn is_notification(&self) -> bool {
+        self.method.starts_with("notifications/")
+    }
+
+    /// Whether this is a notification for every client of the server, rather
+    /// than progress on one client's request
+    pub fn is_broadcast(&self) -> bool {
+        self.is_notification() && self.method != super::progress::METHOD
+    }
+}
+
+/// Backend response line and where its id sits within it
+struct PendingResponse {
+    line: Bytes,
+    id_span: Range<usize>,
+    is_error: bool,
+    /// An error the hub answered with because the backend's response couldn't be read
+    from_hub: bool,
+}
+
+/// What a message sent to a backend got back
+#[derive(Debug, Clone)]
+pub struct Reply {
+    /// The response, empty for notifications and client responses
+    pub line: Bytes,
+    /// Whether the hub answered for the backend: the request timed out, or
+    /// the backend's response couldn't be read
+    pub from_hub: bool,
+}
+
+/// A request waiting for its response, and whose request it is
+struct Waiter {
+    tx: oneshot::Sender<PendingResponse>,
+    session: Option<String>,
+    /// The client's own id, as raw JSON
+    original_id: Bytes,
+}
+
+type PendingMap = std::sync::Mutex<HashMap<u64, Waiter>>;
+
+/// A backend's input: a process's stdin, or the mock backend's end of a pipe
+type BackendInput = Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+
+/// Request/response channel to one backend process
+///
+/// Request ids are rewritten to hub-assigned ids on the way in and restored on
+/// the way out, so concurrent clients reusing the same ids never collide. A
+/// client's `notifications/cancelled` is readdressed the same way, to the hub
+/// id of that client's own request.
+pub struct BackendConnection {
+    name: String,
+    stdin: BackendInput,
+    framer: Arc<Framer>,
+    pending: Arc<PendingMap>,
+    next_id: AtomicU64,
+    /// Limits requests in flight to this backend at once
+    in_flight: Arc<Gate>,
+    /// How long to wait for the backend to answer a request
+    request_timeout: Duration,
+    /// Read timeouts since the last successful response
+    consecutive_timeouts: AtomicU32,
+    /// Requests that failed or were answered with an error
+    errors: AtomicU64,
+    /// When a message was last sent or a request last completed
+    last_used: std::sync::Mutex<Instant>,
+}
+
+/// How much a connection pipelines, buffers, and waits for
+pub struct Limits {
+    /// Requests in flight to the backend at once
+    pub max_in_flight: usize,
+    /// Backend stdout read buffer size in bytes
+    pub read_buffer_size: usize,
+    /// Longest line read from the backend (0: unlimited)
+    pub max_message_bytes: usize,
+    /// How long to wait for the backend to answer a request
+    pub request_timeout: Duration,
+    /// Framing of the backend's stdio (unset: detected)
+    pub framing: Option<Framing>,
+}
+
+impl BackendConnection {
+    /// Wrap a backend's stdio and spawn the stdout reader task
+    pub fn new(
+        name: String,
+        stdin: impl AsyncWrite + Send + Unpin + 'static,
+        stdout: impl AsyncRead + Send + Unpin + 'static,
+        limits: Limits,
+        events: broadcast::Sender<BackendMessage>,
+    ) -> (Arc<Self>, JoinHandle<()>) {
+        let Limits { max_in_flight, read_buffer_size, max_message_bytes, request_timeout, framing } = limits;
+        let pending = Arc::new(PendingMap::default());
+        let framer = Arc::new(Framer::new(&name, framing));
+        let connection = Arc::new(Self {
+            name: name.clone(),
+            stdin: Arc::new(Mutex::new(Box::new(stdin))),
+            framer: Arc::clone(&framer),
+            pending: Arc::clone(&pending),
+            next_id: AtomicU64::new(1),
+            in_flight: Gate::new(max_in_flight.max(1)),
+            request_timeout,
+            consecutive_timeouts: AtomicU32::new(0),
+            errors: AtomicU64::new(0),
+            last_used: std::sync::Mutex::new(Instant::now()),
+        });
+
+        let stdout = BufReader::with_capacity(read_buffer_size.max(1), stdout);
+        let reader = tokio::spawn(read_loop(name, stdout, framer, max_message_bytes, pending, events));
+        (connection, reader)
+    }
+
+    /// Send a message and receive response
+    ///
+    /// Notifications and client responses get no reply, so an empty response is
+    /// returned for them. Requests wait at most the connection's request timeout
+    /// for their answer, then get a `TIMEOUT_ERROR` response.
+    pub async fn send(&self, message: Bytes) -> Result<Bytes> {
+        self.send_with_priority(message, Priority::Normal, None).await
+    }
+
+    /// Send a session's message, queueing by `priority` while the backend is
+    /// at `max_in_flight`
+    pub async fn send_with_priority(&self, message: Bytes, priority: Priority, session: Option<&str>) -> Result<Bytes> {
+        Ok(self.exchange(message, priority, session).await?.line)
+    }
+
+    /// Like `send_with_priority`, telling the backend's responses from the
+    /// errors the hub answered with for it
+    pub async fn exchange(&self, message: Bytes, priority: Priority, session: Option<&str>) -> Result<Reply> {
+        let reply = |line: Bytes, from_hub: bool| Reply { line, from_hub };
+        if self.is_unresponsive() {
+            anyhow::bail!(
+                "Server {} is unresponsive ({} consecutive read timeouts)",
+                self.name,
+                self.consecutive_timeouts()
+            );
+        }
+
+        let envelope = Envelope::parse(&message)
+            .ok_or_else(|| anyhow::anyhow!("Invalid JSON-RPC message"))?;
+        self.touch();
+
+        let id_span = match envelope.id_span(&message) {
+            Some(span) if envelope.is_request() => span,
+            _ if envelope.method.as_deref() == Some("notifications/cancelled") => {
+                if let Some(cancel) = self.readdress_cancel(&message, session) {
+                    self.write(cancel).await?;
+                }
+                return Ok(reply(Bytes::new(), false));
+            }
+            _ => {
+                self.write(with_newline(message)).await?;
+                return Ok(reply(Bytes::new(), false));
+            }
+        };
+        let original_id = message.slice(id_span.clone());
+
+        let _permit = self.in_flight.acquire(priority).await;
+
+        let hub_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let outgoing = splice(&message, id_span, hub_id.to_string().as_bytes());
+
+        let (tx, rx) = oneshot::channel();
+        let waiter = Waiter { tx, session: session.map(String::from), original_id: original_id.clone() };
+        self.pending.lock().unwrap().insert(hub_id, waiter);
+        // Cancels the request at the backend if the caller goes away or
+        // gives up on it first
+        let mut cancel = CancelOnDrop {
+            hub_id,
+            pending: Arc::clone(&self.pending),
+            stdin: Arc::clone(&self.stdin),
+            framer: Arc::clone(&self.framer),
+            reason: "client went away",
+        };
+
+        if let Err(e) = self.write(outgoing).await {
+            self.pending.lock().unwrap().remove(&hub_id);
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            return Err(e);
+        }
+
+        let outcome = tokio::time::timeout(self.request_timeout, rx).await;
+        self.touch();
+        match outcome {
+            Ok(Ok(response)) => {
+                self.consecutive_timeouts.store(0, Ordering::Relaxed);
+                if response.is_error {
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(reply(splice(&response.line, response.id_span, &original_id), response.from_hub))
+            }
+            Ok(Err(_)) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                anyhow::bail!("Server {} closed its output (crashed?)", self.name);
+            }
+            Err(_) => {
+                // Still pending, so the guard cancels it once it drops
+                cancel.reason = "request timed out";
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                let count = self.consecutive_timeouts.fetch_add(1, Ordering::Relaxed) + 1;
+                metrics::record_error("timeout", Some(&self.name));
+                warn!(
+                    "Server {} did not respond within {:?} ({} consecutive timeouts)",
+                    self.name, self.request_timeout, count
+                );
+                Ok(reply(timeout_error(&self.name, self.request_timeout, &original_id), true))
+            }
+        }
+    }
+
+    /// A client's `notifications/cancelled` addressed to the hub id of the
+    /// session's request it names; None if no such request is in flight
+    fn readdress_cancel(&self, message: &[u8], session: Option<&str>) -> Option<Bytes> {
+        let span = cancelled_request_span(message)?;
+        let requested = &message[span.clone()];
+        let hub_id = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, waiter)| waiter.session.as_deref() == session && waiter.original_id == requested)
+            .map(|(hub_id, _)| *hub_id);
+        let Some(hub_id) = hub_id else {
+            debug!("Dropping a cancellation of no request in flight to {}", self.name);
+            return None;
+        };
+        Some(splice(message, span, hub_id.to_string().as_bytes()))
+    }
+
+    /// Whether repeated read timeouts suggest the backend is hung
+    pub fn is_unresponsive(&self) -> bool {
+        self.consecutive_timeouts() >= MAX_CONSECUTIVE_TIMEOUTS
+    }
+
+    /// Read timeouts since the last successful response
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.consecutive_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Requests waiting for a response
+    pub fn in_flight(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// How long nothing has been sent or in flight
+    pub fn idle_for(&self) -> Duration {
+        if self.in_flight() > 0 {
+            return Duration::ZERO;
+        }
+        self.last_used.lock().unwrap().elapsed()
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    /// Requests sent since the backend started
+    pub fn requests_sent(&self) -> u64 {
+        self.next_id.load(Ordering::Relaxed) - 1
+    }
+
+    /// Requests since the backend started that failed or got an error response
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    async fn write(&self, line: Bytes) -> Result<()> {
+        let message = self.framer.encode(line);
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(&message).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+}
+
+/// Sends `notifications/cancelled` for a request still pending when its
+/// caller is dropped (e.g. the client disconnected) or it timed out
+struct CancelOnDrop {
+    hub_id: u64,
+    pending: Arc<PendingMap>,
+    stdin: BackendInput,
+    framer: Arc<Framer>,
+    /// Why the request is cancelled, as told to the backend
+    reason: &'static str,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if self.pending.lock().unwrap().remove(&self.hub_id).is_none() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let cancel = format!(
+            "{{\"jsonrpc\":\"2.0\",\"method\":\"notifications/cancelled\",\"params\":{{\"requestId\":{},\"reason\":\"{}\"}}}}\n",
+            self.hub_id, self.reason
+        );
+        let cancel = self.framer.encode(Bytes::from(cancel));
+        let stdin = Arc::clone(&self.stdin);
+        debug!("Cancelling request {} ({})", self.hub_id, self.reason);
+        runtime.spawn(async move {
+            let mut stdin = stdin.lock().await;
+            let _ = stdin.write_all(&cancel).await;
+            let _ = stdin.flush().await;
+        });
+    }
+}
+
+/// Read backend stdout, completing pending requests and publishing everything else
+async fn read_loop(
+    name: String,
+    mut stdout: BufReader<impl AsyncRead + Unpin>,
+    framer: Arc<Framer>,
+    max_message_bytes: usize,
+    pending: Arc<PendingMap>,
+    events: broadcast::Sender<BackendMessage>,
+) {
+    loop {
+        let mut buf = Vec::new();
+        match framer.read(&mut stdout, &mut buf, max_message_bytes).await {
+            Ok(Line::Eof) => break,
+            Ok(Line::Complete) => {}
+            Ok(Line::Oversized { size, tail }) => {
+                reject_oversized(&name, &buf, &tail, size, max_message_bytes, &pending);
+                continue;
+            }
+            Err(e) => {
+                warn!("Error reading from server {}: {}", name, e);
+                break;
+            }
+        }
+        let line = Bytes::from(buf);
+
+        let Some(envelope) = Envelope::parse(&line) else {
+            match line.trim_ascii_start().first() {
+                Some(b'{') => reject_malformed(&name, &line, &pending),
+                _ => debug!("Ignoring non-JSON output from {}: {}", name, String::from_utf8_lossy(&line).trim()),
+            }
+            continue;
+        };
+
+        let Some(method) = envelope.method else {
+            let id_span = envelope.id_span(&line);
+            let is_error = envelope.error.is_some();
+            let waiter = envelope
+                .id
+                .and_then(|id| id.get().parse::<u64>().ok())
+                .and_then(|id| pending.lock().unwrap().remove(&id));
+            match (waiter, id_span) {
+                (Some(waiter), Some(id_span)) => {
+                    let _ = waiter.tx.send(PendingResponse { line, id_span, is_error, from_hub: false });
+                }
+                _ => debug!("Discarding stale response from {}", name),
+            }
+            continue;
+        };
+
+        // No subscribers is fine - the message is simply dropped
+        let method = method.into_owned();
+        let _ = events.send(BackendMessage {
+            server: name.clone(),
+            method,
+            message: line,
+        });
+    }
+
+    debug!("Server {} stdout closed", name);
+    // Dropping the waiters fails every in-flight request
+    pending.lock().unwrap().clear();
+}
+
+/// The response to a request the backend didn't answer in time, carrying the
+/// client's own id
+fn timeout_error(name: &str, timeout: Duration, original_id: &[u8]) -> Bytes {
+    let error = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "error": {
+            "code": TIMEOUT_ERROR,
+            "message": format!("Server {} did not respond within {}s", name, timeout.as_secs()),
+            "data": { "server": name, "timeout_secs": timeout.as_secs() },
+        },
+    });
+    let line = with_newline(Bytes::from(error.to_string()));
+    match Envelope::parse(&line).and_then(|e| e.id_span(&line)) {
+        Some(id_span) => splice(&line, id_span, original_id),
+        None => line,
+    }
+}
+
+/// Answer the request a skipped oversized message was for with an error
+fn reject_oversized(name: &str, head: &[u8], tail: &[u8], size: usize, limit: usize, pending: &PendingMap) {
+    let waiter = size::truncated_id(head, tail).and_then(|id| Some((id, pending.lock().unwrap().remove(&id)?)));
+    let Some((id, waiter)) = waiter else {
+        warn!("Discarded a {} byte message from {} (limit {})", size, name, limit);
+        return;
+    };
+    warn!("Discarded a {} byte response from {} (limit {})", size, name, limit);
+    let message = format!("Response from {} is {} bytes, over the hub's {} byte message limit", name, size, limit);
+    fail(waiter.tx, id, message, serde_json::json!({ "size": size, "limit": limit }));
+}
+
+/// Answer the request a message that isn't valid JSON-RPC was for with an
+/// error, rather than leaving it to time out
+fn reject_malformed(name: &str, line: &[u8], pending: &PendingMap) {
+    let error = match serde_json::from_slice::<serde::de::IgnoredAny>(line) {
+        Ok(_) => "not a JSON-RPC message".to_string(),
+        Err(e) => e.to_string(),
+    };
+    let tail = &line[line.len().saturating_sub(size::TAIL_BYTES)..];
+    let waiter = size::truncated_id(line, tail).and_then(|id| Some((id, pending.lock().unwrap().remove(&id)?)));
+    let Some((id, waiter)) = waiter else {
+        warn!("Discarded a malformed message from {}: {}", name, error);
+        return;
+    };
+    warn!("Discarded a malformed response from {}: {}", name, error);
+    let message = format!("Response from {} isn't valid JSON-RPC: {}", name, error);
+    fail(waiter.tx, id, message, serde_json::json!({ "error": error }));
+}
+
+/// Answer a pending request with a hub error
+fn fail(waiter: oneshot::Sender<PendingResponse>, id: u64, message: String, data: serde_json::Value) {
+    let error = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": -32000, "message": message, "data": data },
+    });
+    let line = with_newline(Bytes::from(error.to_string()));
+    if let Some(id_span) = Envelope::parse(&line).and_then(|e| e.id_span(&line)) {
+        let _ = waiter.send(PendingResponse { line, id_span, is_error: true, from_hub: true });
+    }
+}
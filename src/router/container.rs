@@ -0,0 +1,145 @@
+//! Container backends
+//! A server with `"runtime": "docker"` (or `"podman"`) runs in a container of
+//! its `image`, attached over stdio like a process: the hub pulls the image if
+//! it's missing, runs it with `run -i --rm` and the server's env, user and
+//! limits, and removes the container when it stops the server, since killing
+//! the CLI leaves a container running.
+
+use anyhow::{Context, Result};
+use std::process::Stdio;
+use tracing::{info, warn};
+
+use crate::config::{Container, ServerConfig};
+
+/// A server's container, named so the hub can remove it
+#[derive(Debug)]
+pub struct Running {
+    runtime: String,
+    pub name: String,
+}
+
+impl Running {
+    /// A unique name for a container of `server` (`mcp-citadel-github-1a2b3c4d`)
+    pub fn new(server: &str, container: &Container) -> Self {
+        let server: String = server
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "_.-".contains(c) { c } else { '-' })
+            .collect();
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            runtime: container.runtime.clone(),
+            name: format!("mcp-citadel-{}-{}", server, &id[..8]),
+        }
+    }
+
+    /// Remove the container, stopping it if it's still running
+    pub async fn remove(&self) {
+        let removed = crate::platform::command(&self.runtime)
+            .args(["rm", "-f", &self.name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        if let Err(e) = removed {
+            warn!("Failed to remove container {}: {}", self.name, e);
+        }
+    }
+}
+
+/// Arguments to the container CLI that run a server's container
+pub fn run_args(config: &ServerConfig, container: &Container, name: &str) -> Vec<String> {
+    let mut args: Vec<String> = ["run", "-i", "--rm", "--name", name].map(String::from).into();
+
+    // Values come from the CLI's environment, so they stay off its command line
+    let mut keys: Vec<&String> = config.env.keys().collect();
+    keys.sort();
+    for key in keys {
+        args.extend(["-e".to_string(), key.clone()]);
+    }
+    if let Some(user) = &config.user {
+        let user = match &config.group {
+            Some(group) => format!("{}:{}", user, group),
+            None => user.clone(),
+        };
+        args.extend(["--user".to_string(), user]);
+    }
+    if let Some(mb) = config.limits.memory_mb {
+        args.extend(["--memory".to_string(), format!("{}m", mb)]);
+    }
+    if let Some(percent) = config.limits.cpu_percent {
+        args.extend(["--cpus".to_string(), format!("{}", f64::from(percent) / 100.0)]);
+    }
+    if let Some(max) = config.limits.max_open_files {
+        args.extend(["--ulimit".to_string(), format!("nofile={0}:{0}", max)]);
+    }
+    args.extend(container.run_args.iter().cloned());
+
+    args.push(container.image.clone());
+    if !config.command.is_empty() {
+        args.push(config.command.clone());
+    }
+    args.extend(config.args.iter().cloned());
+    args
+}
+
+/// Pull a server's image unless it's already there, so a slow download
+/// doesn't count against the server's startup
+pub async fn pull(server: &str, container: &Container) -> Result<()> {
+    let present = crate::platform::command(&container.runtime)
+        .args(["image", "inspect", &container.image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .context(format!("Failed to run {} for server {}", container.runtime, server))?
+        .success();
+    if present {
+        return Ok(());
+    }
+
+    info!("Pulling image {} for server {}", container.image, server);
+    let output = crate::platform::command(&container.runtime)
+        .args(["pull", &container.image])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context(format!("Failed to run {} pull", container.runtime))?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to pull image {}: {}", container.image, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceLimits;
+
+    #[test]
+    fn test_run_args() {
+        let container = Container {
+            runtime: "docker".to_string(),
+            image: "mcp/github".to_string(),
+            run_args: vec!["--network".to_string(), "none".to_string()],
+        };
+        let config = ServerConfig {
+            name: "github".to_string(),
+            args: vec!["stdio".to_string()],
+            env: [("GITHUB_TOKEN".to_string(), "secret".to_string())].into(),
+            user: Some("1000".to_string()),
+            limits: ResourceLimits { memory_mb: Some(512), cpu_percent: Some(50), ..Default::default() },
+            container: Some(container.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            run_args(&config, &container, "mcp-citadel-github-1"),
+            [
+                "run", "-i", "--rm", "--name", "mcp-citadel-github-1", "-e", "GITHUB_TOKEN", "--user", "1000", "--memory", "512m",
+                "--cpus", "0.5", "--network", "none", "mcp/github", "stdio",
+            ]
+        );
+
+        let running = Running::new("my server", &container);
+        assert!(running.name.starts_with("mcp-citadel-my-server-"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Debug transcripts
+//! With `debugTranscript: true` on a server (or `mcp-citadel server debug
+//! <name>` at runtime), every line the hub writes to that backend or reads
+//! from it is appended to `~/.mcp-citadel/debug/<server>.log`, timestamped and
+//! marked `→` (to the server) or `←` (from it), without turning on debug
+//! logging for the whole hub.
+//!
+//! ```text
+//! 2026-10-15T09:00:00.123Z → {"jsonrpc":"2.0","id":1,"method":"tools/list"}
+//! 2026-10-15T09:00:00.131Z ← {"jsonrpc":"2.0","id":1,"result":{"tools":[]}}
+//! ```
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::warn;
+
+/// Transcript file of a server
+pub fn path(server: &str) -> PathBuf {
+    crate::config::data_dir().join("debug").join(format!("{}.log", server))
+}
+
+/// A server's transcript switch and file
+pub struct DebugTranscript {
+    server: String,
+    enabled: AtomicBool,
+    /// Opened on the first line recorded after enabling
+    file: Mutex<Option<File>>,
+}
+
+impl DebugTranscript {
+    pub fn new(server: &str, enabled: bool) -> Arc<Self> {
+        Arc::new(Self {
+            server: server.to_string(),
+            enabled: AtomicBool::new(enabled),
+            file: Mutex::new(None),
+        })
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.file.lock().unwrap().take();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, arrow: &str, line: &[u8]) {
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            let path = path(&self.server);
+            let opened = std::fs::create_dir_all(path.parent().unwrap_or(&path))
+                .and_then(|_| File::options().create(true).append(true).open(&path));
+            match opened {
+                Ok(opened) => *file = Some(opened),
+                Err(e) => {
+                    warn!("Turning off the debug transcript of {}: {}: {}", self.server, path.display(), e);
+                    self.enabled.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+        let text = String::from_utf8_lossy(line);
+        let text = crate::redact::scrub(&text);
+        let at = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+        if let Some(file) = file.as_mut() {
+            let _ = writeln!(file, "{} {} {}", at, arrow, text.trim_end());
+        }
+    }
+}
+
+/// Wrap a backend's stdio so whole lines are recorded while the transcript is on
+pub fn tap(
+    transcript: Arc<DebugTranscript>,
+    stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    stdout: Box<dyn AsyncRead + Send + Unpin>,
+) -> (Box<dyn AsyncWrite + Send + Unpin>, Box<dyn AsyncRead + Send + Unpin>) {
+    let stdin = Tap { inner: stdin, lines: Lines::new(Arc::clone(&transcript), "→") };
+    let stdout = Tap { inner: stdout, lines: Lines::new(transcript, "←") };
+    (Box::new(stdin), Box::new(stdout))
+}
+
+/// Splits one direction's bytes into lines for the transcript
+struct Lines {
+    transcript: Arc<DebugTranscript>,
+    arrow: &'static str,
+    partial: Vec<u8>,
+}
+
+impl Lines {
+    fn new(transcript: Arc<DebugTranscript>, arrow: &'static str) -> Self {
+        Self { transcript, arrow, partial: Vec::new() }
+    }
+
+    fn feed(&mut self, mut bytes: &[u8]) {
+        if !self.transcript.is_enabled() {
+            self.partial.clear();
+            return;
+        }
+        while let Some(end) = bytes.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&bytes[..end]);
+            self.transcript.record(self.arrow, &self.partial);
+            self.partial.clear();
+            bytes = &bytes[end + 1..];
+        }
+        self.partial.extend_from_slice(bytes);
+    }
+}
+
+struct Tap<T> {
+    inner: T,
+    lines: Lines,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tap<W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.lines.feed(&buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Tap<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let Self { lines, .. } = &mut *self;
+        lines.feed(&buf.filled()[before..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_split_across_writes() {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-test-debug-{}", std::process::id()));
+        let transcript = Arc::new(DebugTranscript {
+            server: "github".to_string(),
+            enabled: AtomicBool::new(true),
+            file: Mutex::new(Some(File::create(&dir).unwrap())),
+        });
+        let mut lines = Lines::new(Arc::clone(&transcript), "→");
+        lines.feed(b"{\"id\":1,");
+        lines.feed(b"\"method\":\"ping\"}\n{\"id\":2}\n{\"id\"");
+        transcript.set_enabled(false);
+        lines.feed(b":3}\n");
+
+        let content = std::fs::read_to_string(&dir).unwrap();
+        let _ = std::fs::remove_file(&dir);
+        let recorded: Vec<&str> = content.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+        assert_eq!(recorded, ["→ {\"id\":1,\"method\":\"ping\"}", "→ {\"id\":2}"]);
+    }
+}
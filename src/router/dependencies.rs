@@ -0,0 +1,121 @@
+//! Server dependencies
+//! A server's `dependsOn` names servers it needs up first, like a database
+//! proxy behind a query server. The hub starts servers in dependency order,
+//! waits for each dependency to answer a ping before starting what depends on
+//! it, and restarts the running dependents of a server it restarts.
+
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Duration;
+
+use super::connection::BackendConnection;
+use super::health;
+use crate::config::ServerConfig;
+
+/// Pause between pings of a dependency that isn't answering yet
+const READY_RETRY: Duration = Duration::from_millis(250);
+
+/// Servers in an order that puts every dependency before its dependents,
+/// by name otherwise; fails for unknown dependencies and cycles
+pub fn start_order(configs: &[ServerConfig]) -> Result<Vec<&ServerConfig>> {
+    let by_name: HashMap<&str, &ServerConfig> = configs.iter().map(|c| (c.name.as_str(), c)).collect();
+    let mut waiting: HashMap<&str, usize> = HashMap::new();
+    for config in configs {
+        for dependency in &config.depends_on {
+            if !by_name.contains_key(dependency.as_str()) {
+                anyhow::bail!("Server {} depends on unknown server {}", config.name, dependency);
+            }
+        }
+        waiting.insert(&config.name, config.depends_on.iter().collect::<HashSet<_>>().len());
+    }
+
+    let mut ready: BTreeSet<&str> = waiting.iter().filter(|(_, &n)| n == 0).map(|(&name, _)| name).collect();
+    let mut order = Vec::with_capacity(configs.len());
+    while let Some(name) = ready.pop_first() {
+        order.push(by_name[name]);
+        for dependent in configs.iter().filter(|c| c.depends_on.iter().any(|d| d == name)) {
+            let count = waiting.get_mut(dependent.name.as_str()).expect("every server is counted");
+            *count -= 1;
+            if *count == 0 {
+                ready.insert(&dependent.name);
+            }
+        }
+    }
+
+    if order.len() < configs.len() {
+        let mut cycle: Vec<&str> = waiting.into_iter().filter(|(_, n)| *n > 0).map(|(name, _)| name).collect();
+        cycle.sort_unstable();
+        anyhow::bail!("Servers {} depend on each other", cycle.join(", "));
+    }
+    Ok(order)
+}
+
+/// Servers that depend on `server`, directly or not, in start order
+pub fn dependents(configs: &[ServerConfig], server: &str) -> Vec<String> {
+    let Ok(order) = start_order(configs) else {
+        return Vec::new();
+    };
+    let mut affected: HashSet<&str> = HashSet::from([server]);
+    let mut dependents = Vec::new();
+    for config in order {
+        if config.depends_on.iter().any(|d| affected.contains(d.as_str())) {
+            affected.insert(&config.name);
+            dependents.push(config.name.clone());
+        }
+    }
+    dependents
+}
+
+/// Servers `server` depends on, directly or not, with the server itself
+pub fn with_dependencies<'a>(configs: &'a [ServerConfig], server: &'a str) -> HashSet<&'a str> {
+    let mut needed = HashSet::new();
+    let mut stack = vec![server];
+    while let Some(name) = stack.pop() {
+        if needed.insert(name) {
+            if let Some(config) = configs.iter().find(|c| c.name == name) {
+                stack.extend(config.depends_on.iter().map(String::as_str));
+            }
+        }
+    }
+    needed
+}
+
+/// Wait up to `timeout` for a started dependency to answer a ping
+pub async fn ready(name: &str, connection: &BackendConnection, timeout: Duration) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+        match health::ping(connection, left).await {
+            Ok(()) => return Ok(()),
+            Err(_) if left > READY_RETRY => tokio::time::sleep(READY_RETRY).await,
+            Err(e) => return Err(e.context(format!("Dependency {} isn't answering", name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(name: &str, depends_on: &[&str]) -> ServerConfig {
+        ServerConfig {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_start_order() {
+        let configs = [server("query", &["db", "cache"]), server("db", &[]), server("cache", &["db"]), server("docs", &[])];
+        let order: Vec<&str> = start_order(&configs).unwrap().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(order, ["db", "cache", "docs", "query"]);
+        assert_eq!(dependents(&configs, "db"), ["cache", "query"]);
+        assert!(dependents(&configs, "docs").is_empty());
+        assert_eq!(with_dependencies(&configs, "cache"), HashSet::from(["cache", "db"]));
+
+        let cycle = [server("a", &["b"]), server("b", &["a"]), server("c", &[])];
+        assert_eq!(start_order(&cycle).unwrap_err().to_string(), "Servers a, b depend on each other");
+        assert!(start_order(&[server("a", &["missing"])]).is_err());
+    }
+}
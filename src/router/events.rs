@@ -0,0 +1,105 @@
+//! Hub events
+//! Server lifecycle changes, new client sessions, and requests that got no
+//! answer go out on one broadcast channel (`HubManager::lifecycle_events`).
+//! The control plane streams them to `mcp-citadel watch` and `mcp-citadel
+//! events --follow`, the HTTP transport to `/events` as server-sent events,
+//! and the latest ones are kept for `mcp-citadel events`.
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use super::workspace::Scope;
+use super::HubEvent;
+
+/// Path of the SSE endpoint
+pub const EVENTS_PATH: &str = "/events";
+
+/// Events kept for `events.recent`
+const RECENT_EVENTS: usize = 200;
+
+/// An event as subscribers see it: its fields, `event` naming its kind, and
+/// `timestamp`
+pub fn stamp(event: &HubEvent) -> Value {
+    let mut value = serde_json::to_value(event).unwrap_or_else(|_| json!({}));
+    value["timestamp"] = json!(chrono::Utc::now().to_rfc3339());
+    value
+}
+
+/// Whether a client in `scope` may see a stamped event: a workspace only
+/// sees events of its own servers
+pub fn visible(event: &Value, scope: &Scope) -> bool {
+    !scope.is_restricted() || event["server"].as_str().is_some_and(|server| scope.allows(server))
+}
+
+/// The latest events, oldest first
+#[derive(Default)]
+pub struct RecentEvents(Mutex<VecDeque<Value>>);
+
+impl RecentEvents {
+    pub fn list(&self) -> Vec<Value> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, event: Value) {
+        let mut events = self.0.lock().unwrap();
+        if events.len() == RECENT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+/// Keep the latest events of `lifecycle` in `recent` until the hub shuts down
+pub fn spawn_recording(mut lifecycle: broadcast::Receiver<HubEvent>, recent: Arc<RecentEvents>) {
+    tokio::spawn(async move {
+        loop {
+            match lifecycle.recv().await {
+                Ok(event) => recent.push(stamp(&event)),
+                Err(broadcast::error::RecvError::Lagged(n)) => recent.push(json!({ "event": "lagged", "skipped": n, "timestamp": chrono::Utc::now().to_rfc3339() })),
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recent_events() {
+        let (lifecycle, _) = broadcast::channel(16);
+        let recent = Arc::new(RecentEvents::default());
+        spawn_recording(lifecycle.subscribe(), Arc::clone(&recent));
+        for n in 0..RECENT_EVENTS + 1 {
+            lifecycle.send(HubEvent::SessionCreated { session: n.to_string(), transport: "socket".to_string() }).unwrap();
+            tokio::task::yield_now().await;
+        }
+        while recent.list().last().map(|e| e["session"].clone()) != Some(json!(RECENT_EVENTS.to_string())) {
+            tokio::task::yield_now().await;
+        }
+
+        let events = recent.list();
+        assert_eq!(events.len(), RECENT_EVENTS);
+        assert_eq!((events[0]["event"].as_str(), events[0]["session"].as_str()), (Some("session_created"), Some("1")));
+        assert!(events[0]["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_visible_in_workspace() {
+        use crate::config::WorkspaceConfig;
+        use crate::router::workspace::{Identity, Workspaces};
+
+        let team = WorkspaceConfig { servers: vec!["github".to_string()], ..Default::default() };
+        let team = Workspaces::new([("default".to_string(), team)].into()).resolve(Identity::default()).unwrap();
+        let failed = |server: &str| stamp(&HubEvent::Failed { server: server.to_string(), reason: "exited".to_string() });
+        let session = stamp(&HubEvent::SessionCreated { session: "s1".to_string(), transport: "http".to_string() });
+
+        assert!(visible(&failed("github"), &team));
+        assert!(!visible(&failed("postgres"), &team));
+        assert!(!visible(&session, &team));
+        assert!(visible(&failed("postgres"), &Scope::default()) && visible(&session, &Scope::default()));
+    }
+}
@@ -0,0 +1,327 @@
+//! Framed routing protocol (v2)
+//! Negotiated with a hello line, after which every line is a frame naming its
+//! target server, so one connection can talk to several servers at once
+//!
+//! ```text
+//! → {"citadel":"hello","protocol":2,"workspace":"team","project":"/src/app/.mcp.json"}
+//! ← {"citadel":"hello","protocol":2,"hub_version":"0.5.0"}
+//! → {"server":"github","rid":1,"message":{"jsonrpc":"2.0","id":1,"method":"tools/list"}}
+//! ← {"server":"github","rid":1,"message":{"jsonrpc":"2.0","id":1,"result":{...}}}
+//! ← {"server":"github","message":{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}}
+//! ```
+//!
+//! `rid` is an opaque client value echoed on the reply; frames without one get
+//! no reply. Replies arrive as backends answer, not in request order, and
+//! backend notifications are interleaved for every server the connection has
+//! addressed, as are `citadel/server_restarted` notices (see [`super::notices`])
+//! and the backend requests the connection is to answer (see [`super::reverse`]),
+//! and progress on its own requests.
+//! Clients that don't start with a hello keep the v1 protocol.
+//!
+//! `{"citadel":"ping"}` lines work on both protocols; see [`super::keepalive`].
+
+use anyhow::Result;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use super::keepalive::{self, Control, Keepalive, Tick};
+use super::notices;
+use super::session::ClientSession;
+use super::workspace::{Identity, Scope};
+use super::{attach_project, route_client_message, HubManager};
+
+/// Newest routing protocol this hub speaks
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Protocol negotiation line, sent first by the client and echoed by the hub
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Hello {
+    /// Always "hello"
+    pub citadel: String,
+    /// Client: highest version it speaks. Hub: the version in use
+    pub protocol: u32,
+    /// Workspace the client asks for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Project config whose servers the client wants
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hub_version: Option<String>,
+    /// Why the hub refused the connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Client: wants keepalive pings. Hub: will send them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keepalive: bool,
+}
+
+impl Hello {
+    /// Client hello asking for the newest protocol
+    pub fn new(workspace: Option<String>, project: Option<PathBuf>) -> Self {
+        Self {
+            citadel: "hello".to_string(),
+            protocol: PROTOCOL_VERSION,
+            workspace,
+            project,
+            ..Default::default()
+        }
+    }
+
+    /// Parse a hello line; anything else is a v1 message
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        serde_json::from_slice::<Self>(line)
+            .ok()
+            .filter(|hello| hello.citadel == "hello")
+    }
+
+    /// Hub answer: the negotiated version, or why the client was refused
+    pub(super) fn reply(protocol: u32, error: Option<String>) -> Self {
+        Self {
+            citadel: "hello".to_string(),
+            protocol,
+            hub_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            error,
+            ..Default::default()
+        }
+    }
+}
+
+/// Client frame, borrowed from the raw line
+#[derive(Debug, Deserialize)]
+pub struct Frame<'a> {
+    #[serde(borrow)]
+    pub server: Cow<'a, str>,
+    #[serde(borrow, default)]
+    pub rid: Option<&'a RawValue>,
+    #[serde(borrow)]
+    pub message: &'a RawValue,
+}
+
+/// Frame carrying a backend message (`rid` is None for notifications)
+pub fn message_frame(server: &str, rid: Option<&[u8]>, message: &[u8]) -> Bytes {
+    let message = message.strip_suffix(b"\n").unwrap_or(message);
+    let mut out = BytesMut::with_capacity(message.len() + server.len() + 48);
+    out.put_slice(b"{\"server\":");
+    out.put_slice(&serde_json::to_vec(server).unwrap_or_default());
+    if let Some(rid) = rid {
+        out.put_slice(b",\"rid\":");
+        out.put_slice(rid);
+    }
+    out.put_slice(b",\"message\":");
+    out.put_slice(message);
+    out.put_slice(b"}\n");
+    out.freeze()
+}
+
+/// Frame reporting that a message could not be routed
+pub fn error_frame(server: &str, rid: Option<&[u8]>, code: i32, message: &str) -> Bytes {
+    let error = serde_json::json!({ "code": code, "message": message });
+    let mut out = BytesMut::new();
+    out.put_slice(b"{\"server\":");
+    out.put_slice(&serde_json::to_vec(server).unwrap_or_default());
+    if let Some(rid) = rid {
+        out.put_slice(b",\"rid\":");
+        out.put_slice(rid);
+    }
+    out.put_slice(b",\"error\":");
+    out.put_slice(error.to_string().as_bytes());
+    out.put_slice(b"}\n");
+    out.freeze()
+}
+
+/// Serve a client that opened with a hello
+pub(super) async fn serve(
+    hello: Hello,
+    mut reader: BufReader<OwnedReadHalf>,
+    mut writer: OwnedWriteHalf,
+    manager: &HubManager,
+    session: &ClientSession,
+    project: &mut Option<PathBuf>,
+) -> Result<()> {
+    let unix_user = session.unix_user.as_deref();
+    let identity = Identity {
+        requested: hello.workspace.as_deref(),
+        unix_user,
+        ..Default::default()
+    };
+    let scope = match manager.scope(identity) {
+        Ok(scope) => scope,
+        Err(e) => {
+            warn!("Rejected client (user {:?}): {}", unix_user, e);
+            write_json(&mut writer, &Hello::reply(PROTOCOL_VERSION, Some(e.to_string()))).await?;
+            return Ok(());
+        }
+    };
+    if let Some(path) = hello.project {
+        attach_project(manager, path, session.peer_uid, project).await;
+    }
+
+    let protocol = hello.protocol.min(PROTOCOL_VERSION);
+    let mut keepalive = Keepalive::new(manager.client_keepalive());
+    if hello.keepalive {
+        keepalive.enable();
+    }
+    write_json(
+        &mut writer,
+        &Hello {
+            keepalive: hello.keepalive,
+            ..Hello::reply(protocol, None)
+        },
+    )
+    .await?;
+    debug!("Client negotiated routing protocol v{}", protocol);
+
+    let project = project.clone();
+    let mut events = manager.backend_events();
+    let mut requests = manager.reverse().open(&session.id);
+    let mut restarts = manager.restart_notices();
+    let mut addressed = HashSet::new();
+    let mut in_flight = FuturesUnordered::new();
+    let mut buf = Vec::new();
+
+    loop {
+        tokio::select! {
+            read = reader.read_until(b'\n', &mut buf) => {
+                if read? == 0 {
+                    debug!("Client disconnected");
+                    break;
+                }
+                let line = Bytes::from(std::mem::take(&mut buf));
+                keepalive.seen();
+                match Control::parse(&line) {
+                    Some(Control::Ping) => {
+                        keepalive.enable();
+                        writer.write_all(keepalive::PONG).await?;
+                        continue;
+                    }
+                    Some(Control::Pong) => continue,
+                    None => {}
+                }
+                let frame = match serde_json::from_slice::<Frame>(&line) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        writer.write_all(&error_frame("", None, -32700, &format!("Invalid frame: {}", e))).await?;
+                        continue;
+                    }
+                };
+
+                let server = frame.server.into_owned();
+                let rid = frame.rid.map(|rid| line.slice_ref(rid.get().as_bytes()));
+                let message = line.slice_ref(frame.message.get().as_bytes());
+                addressed.insert(server.clone());
+                in_flight.push(route_frame(manager, session, project.as_deref(), &scope, server, rid, message));
+            }
+            Some(reply) = in_flight.next(), if !in_flight.is_empty() => {
+                if let Some(reply) = reply {
+                    writer.write_all(&reply).await?;
+                }
+            }
+            tick = keepalive.tick() => match tick {
+                Tick::Ping => writer.write_all(keepalive::PING).await?,
+                Tick::Expired => {
+                    info!("Closing framed client connection that stopped answering pings");
+                    break;
+                }
+            },
+            event = events.recv() => match event {
+                Ok(event) if event.is_broadcast() && addressed.contains(&event.server) => {
+                    if manager.client_accepts(&session.id, &event.method) {
+                        writer.write_all(&message_frame(&event.server, None, &event.message)).await?;
+                    } else {
+                        debug!("Not forwarding {} from {}: the client didn't declare it", event.method, event.server);
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Framed client lagged, {} backend messages skipped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            Some((server, request)) = requests.recv() => {
+                writer.write_all(&message_frame(&server, None, &request)).await?;
+            }
+            (restarted, reason) = restarts.recv() => {
+                // Under every name the connection used for it
+                for name in addressed.iter().filter(|name| scope.target(name) == Some(restarted.as_str())) {
+                    let notice = notices::server_restarted(name, &reason).to_string();
+                    writer.write_all(&message_frame(name, None, notice.as_bytes())).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Route one frame's message and build the reply frame, if the client expects one
+async fn route_frame(
+    manager: &HubManager,
+    session: &ClientSession,
+    project: Option<&Path>,
+    scope: &Scope,
+    server: String,
+    rid: Option<Bytes>,
+    message: Bytes,
+) -> Option<Bytes> {
+    match route_client_message(manager, session, project, Some(scope), &server, message).await {
+        // Notifications and client responses have nothing to answer
+        Ok(response) if response.is_empty() => None,
+        Ok(response) => rid.map(|rid| message_frame(&server, Some(&rid), &response)),
+        Err(e) => {
+            debug!("Routing error: {}", e);
+            Some(error_frame(&server, rid.as_deref(), -32603, &e.to_string()))
+        }
+    }
+}
+
+pub(super) async fn write_json(writer: &mut OwnedWriteHalf, value: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hello() {
+        let hello = Hello::parse(br#"{"citadel":"hello","protocol":3,"workspace":"team"}"#).unwrap();
+        assert_eq!(hello.protocol, 3);
+        assert_eq!(hello.workspace.as_deref(), Some("team"));
+
+        // v1 messages are not hellos
+        assert!(Hello::parse(br#"{"jsonrpc":"2.0","id":1,"method":"github/tools/list"}"#).is_none());
+        assert!(Hello::parse(br#"{"citadel":"bye","protocol":2}"#).is_none());
+    }
+
+    #[test]
+    fn test_frames() {
+        let line = br#"{"server":"github","rid":"a-1","message":{"jsonrpc":"2.0","id":1,"method":"tools/list"}}"#;
+        let frame: Frame = serde_json::from_slice(line).unwrap();
+        assert_eq!(frame.server, "github");
+        assert_eq!(frame.rid.unwrap().get(), "\"a-1\"");
+        assert_eq!(frame.message.get(), r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+
+        let reply = message_frame("github", Some(b"\"a-1\""), b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}\n");
+        assert_eq!(
+            &reply[..],
+            &b"{\"server\":\"github\",\"rid\":\"a-1\",\"message\":{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{}}}\n"[..]
+        );
+
+        let error: serde_json::Value = serde_json::from_slice(&error_frame("gh", Some(b"7"), -32603, "down")).unwrap();
+        assert_eq!(error["rid"], 7);
+        assert_eq!(error["error"]["message"], "down");
+    }
+}
@@ -0,0 +1,283 @@
+//! Backend stdio framing
+//! Most servers write one JSON message per line, though some pretty-print
+//! theirs across lines, which are read until the JSON value is complete.
+//! Others speak LSP-style framing: `Content-Length` (and optionally
+//! `Content-Type`) headers, a blank line, then exactly that many bytes of
+//! message. Either way, a message is passed on as one line.
+//! Output is read in either framing; a server's input is framed as its
+//! `framing` says, or, when that's unset, switches to `Content-Length` as soon
+//! as the server's output turns out to use it.
+
+use bytes::Bytes;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tracing::info;
+
+use super::size::{self, Line, TAIL_BYTES};
+use crate::config::Framing;
+
+/// Longest header line read; message size limits apply to messages only
+const MAX_HEADER_BYTES: usize = 1024;
+
+/// One backend's framing, as configured or detected
+pub struct Framer {
+    name: String,
+    content_length: AtomicBool,
+    /// Set in the config, so never switched by what the server writes
+    configured: bool,
+}
+
+impl Framer {
+    pub fn new(name: &str, framing: Option<Framing>) -> Self {
+        Self {
+            name: name.to_string(),
+            content_length: AtomicBool::new(framing == Some(Framing::ContentLength)),
+            configured: framing.is_some(),
+        }
+    }
+
+    /// Whether messages to the server get `Content-Length` headers
+    pub fn content_length(&self) -> bool {
+        self.content_length.load(Ordering::Relaxed)
+    }
+
+    /// Frame a message line for the server
+    pub fn encode(&self, line: Bytes) -> Bytes {
+        if !self.content_length() {
+            return line;
+        }
+        let body = line.trim_ascii_end();
+        let mut framed = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+        framed.extend_from_slice(body);
+        Bytes::from(framed)
+    }
+
+    /// Read the server's next message into `buf` as one line, buffering at
+    /// most `max_bytes` of it (0: unlimited)
+    pub async fn read<R: AsyncBufRead + Unpin>(&self, reader: &mut R, buf: &mut Vec<u8>, max_bytes: usize) -> std::io::Result<Line> {
+        // Messages start with `{` (or `[`, or whitespace), headers don't
+        if !matches!(reader.fill_buf().await?.first(), Some(b'C' | b'c')) {
+            return read_lines(reader, buf, max_bytes).await;
+        }
+        let line = size::read_line(reader, buf, MAX_HEADER_BYTES).await?;
+        let Some(length) = (line == Line::Complete).then(|| content_length(buf)).flatten() else {
+            return Ok(line);
+        };
+
+        // The other headers end at a blank line
+        loop {
+            buf.clear();
+            match size::read_line(reader, buf, MAX_HEADER_BYTES).await? {
+                Line::Eof => return Ok(Line::Eof),
+                _ if buf.trim_ascii().is_empty() => break,
+                _ => {}
+            }
+        }
+        if !self.configured && !self.content_length.swap(true, Ordering::Relaxed) {
+            info!("Server {} uses Content-Length framing", self.name);
+        }
+        buf.clear();
+        read_body(reader, buf, length, max_bytes).await
+    }
+}
+
+/// Where a JSON value being read is: within how many objects and arrays, and
+/// whether within a string
+#[derive(Default)]
+struct Nesting {
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl Nesting {
+    fn feed(&mut self, b: u8) {
+        if self.in_string {
+            match b {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+            return;
+        }
+        match b {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.depth > 0 || self.in_string
+    }
+}
+
+/// Read the next newline-delimited message into `buf` as one line: up to the
+/// next newline, or, for a JSON value left open there, the newline after it
+/// closes, buffering at most `max_bytes` (0: unlimited)
+async fn read_lines<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>, max_bytes: usize) -> std::io::Result<Line> {
+    let limit = if max_bytes == 0 { usize::MAX } else { max_bytes };
+    let (mut size, mut tail) = (0, Vec::new());
+    // Decided by the first byte that isn't whitespace, so log lines never
+    // have their braces counted
+    let mut json = None;
+    let mut nesting = Nesting::default();
+    let mut done = false;
+    while !done {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        let mut n = 0;
+        for &b in available {
+            n += 1;
+            if json.is_none() && !b.is_ascii_whitespace() {
+                json = Some(matches!(b, b'{' | b'['));
+            }
+            if json == Some(true) {
+                nesting.feed(b);
+            }
+            done = b == b'\n' && !nesting.is_open();
+            // Line breaks in JSON only ever separate tokens (strings escape
+            // theirs), so the message stays the same without them
+            let kept = done || (b != b'\n' && b != b'\r');
+            if size < limit {
+                if kept {
+                    buf.push(b);
+                }
+            } else {
+                tail.push(b);
+                if tail.len() >= 2 * TAIL_BYTES {
+                    tail.drain(..TAIL_BYTES);
+                }
+            }
+            size += 1;
+            if done {
+                break;
+            }
+        }
+        reader.consume(n);
+    }
+
+    tail.drain(..tail.len().saturating_sub(TAIL_BYTES));
+    Ok(match size {
+        0 => Line::Eof,
+        _ if size <= limit => Line::Complete,
+        _ => Line::Oversized { size, tail },
+    })
+}
+
+/// The length a `Content-Length` header line announces
+fn content_length(line: &[u8]) -> Option<usize> {
+    let (name, value) = std::str::from_utf8(line).ok()?.split_once(':')?;
+    name.trim().eq_ignore_ascii_case("content-length").then_some(())?;
+    value.trim().parse().ok()
+}
+
+/// Read a `length` byte message into `buf` as a line
+async fn read_body<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>, length: usize, max_bytes: usize) -> std::io::Result<Line> {
+    let limit = if max_bytes == 0 { usize::MAX } else { max_bytes };
+    let mut read = 0;
+    let mut tail = Vec::new();
+    while read < length {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(Line::Eof);
+        }
+        let chunk = &available[..available.len().min(length - read)];
+        // Line breaks in JSON only ever separate tokens (strings escape
+        // theirs), so dropping them leaves the same message on one line
+        let kept = chunk.len().min(limit.saturating_sub(read));
+        buf.extend(chunk[..kept].iter().filter(|&&b| b != b'\n' && b != b'\r'));
+        if length > limit {
+            tail.extend_from_slice(chunk);
+            tail.drain(..tail.len().saturating_sub(TAIL_BYTES));
+        }
+        let n = chunk.len();
+        read += n;
+        reader.consume(n);
+    }
+
+    if length > limit {
+        return Ok(Line::Oversized { size: length, tail });
+    }
+    buf.push(b'\n');
+    Ok(Line::Complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_content_length_messages() {
+        let framer = Framer::new("lsp", None);
+        assert!(!framer.content_length());
+        let output = b"{\"jsonrpc\":\"2.0\",\"method\":\"log\"}\nContent-Length: 53\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{\r\n  \"jsonrpc\": \"2.0\",\r\n  \"id\": 1,\r\n  \"result\": {}\r\n}";
+        let mut reader = &output[..];
+        let mut buf = Vec::new();
+
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Complete);
+        assert_eq!(buf, b"{\"jsonrpc\":\"2.0\",\"method\":\"log\"}\n");
+        buf.clear();
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Complete);
+        assert_eq!(String::from_utf8(buf.clone()).unwrap(), "{  \"jsonrpc\": \"2.0\",  \"id\": 1,  \"result\": {}}\n");
+        buf.clear();
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Eof);
+
+        // Detected from the output, so the server's input follows
+        assert!(framer.content_length());
+        let framed = framer.encode(Bytes::from_static(b"{\"id\":2}\n"));
+        assert_eq!(&framed[..], b"Content-Length: 8\r\n\r\n{\"id\":2}");
+        let newline = Framer::new("plain", Some(Framing::Newline));
+        assert_eq!(&newline.encode(Bytes::from_static(b"{}\n"))[..], b"{}\n");
+    }
+
+    #[tokio::test]
+    async fn test_multi_line_messages() {
+        let framer = Framer::new("pretty", None);
+        let output = b"starting {\n{\n  \"id\": 1,\n  \"result\": { \"text\": \"a }\\\"\\n{\" }\n}\r\n[{\"id\": 2}]\n";
+        let mut reader = &output[..];
+        let mut buf = Vec::new();
+
+        // Braces of lines that aren't JSON don't count
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Complete);
+        assert_eq!(buf, b"starting {\n");
+        buf.clear();
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Complete);
+        assert_eq!(String::from_utf8(buf.clone()).unwrap(), "{  \"id\": 1,  \"result\": { \"text\": \"a }\\\"\\n{\" }}\n");
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["result"]["text"], "a }\"\n{");
+        buf.clear();
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Complete);
+        assert_eq!(buf, b"[{\"id\": 2}]\n");
+        buf.clear();
+        assert_eq!(framer.read(&mut reader, &mut buf, 0).await.unwrap(), Line::Eof);
+
+        // Over the limit, the rest of the value is skipped
+        let mut reader = &b"{\n  \"id\": 3,\n  \"result\": \"aaaaaaaaaaaaaaaaaaaaaaaa\"\n}\n{}\n"[..];
+        let Line::Oversized { size, tail } = framer.read(&mut reader, &mut buf, 16).await.unwrap() else {
+            panic!("not oversized");
+        };
+        assert_eq!((size, buf.len()), (54, 14));
+        assert_eq!(size::truncated_id(&buf, &tail), Some(3));
+        assert_eq!(reader, b"{}\n");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_content_length_message() {
+        let framer = Framer::new("lsp", Some(Framing::ContentLength));
+        let output = b"Content-Length: 20\r\n\r\n{\"x\":\"aaaaa\",\"id\":7}{}";
+        let mut reader = &output[..];
+        let mut buf = Vec::new();
+        let Line::Oversized { size, tail } = framer.read(&mut reader, &mut buf, 8).await.unwrap() else {
+            panic!("not oversized");
+        };
+        assert_eq!((size, buf.len()), (20, 8));
+        assert_eq!(size::truncated_id(&buf, &tail), Some(7));
+        // The next message is still found
+        assert_eq!(reader, b"{}");
+    }
+}
@@ -0,0 +1,76 @@
+//! Custom health checks
+//! Per-server `healthCheck` probes for servers whose process stays alive while
+//! whatever they depend on is dead, and the `ping` every other server gets so
+//! a hung one is caught even when no client is waiting on it
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use serde_json::Value;
+use std::process::Stdio;
+use std::time::Duration;
+
+use super::connection::{BackendConnection, TIMEOUT_ERROR};
+use crate::config::{HealthCheck, ServerConfig};
+
+/// Timeout when the check doesn't set `timeoutSecs`
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Ping a server; any answer, even an error, shows it's responsive
+pub async fn ping(connection: &BackendConnection, timeout: Duration) -> Result<()> {
+    let request = Bytes::from_static(b"{\"jsonrpc\":\"2.0\",\"id\":0,\"method\":\"ping\"}\n");
+    let response = tokio::time::timeout(timeout, connection.send(request))
+        .await
+        .map_err(|_| anyhow::anyhow!("no answer to ping within {:?}", timeout))??;
+    let response: Value = serde_json::from_slice(&response).context("Invalid ping response")?;
+    if response.pointer("/error/code").and_then(Value::as_i64) == Some(TIMEOUT_ERROR as i64) {
+        anyhow::bail!("no answer to ping within the request timeout");
+    }
+    Ok(())
+}
+
+/// Run a server's health check
+pub async fn run(check: &HealthCheck, config: &ServerConfig, connection: &BackendConnection) -> Result<()> {
+    match check {
+        HealthCheck::Method { method, params, timeout_secs } => {
+            let timeout = timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+            let mut request = serde_json::to_vec(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": method,
+                "params": params,
+            }))?;
+            request.push(b'\n');
+            let response = tokio::time::timeout(timeout, connection.send(Bytes::from(request)))
+                .await
+                .map_err(|_| anyhow::anyhow!("{} did not answer within {:?}", method, timeout))??;
+
+            let response: Value = serde_json::from_slice(&response).context(format!("Invalid {} response", method))?;
+            if let Some(error) = response.get("error") {
+                anyhow::bail!("{} returned {}", method, error);
+            }
+            Ok(())
+        }
+        HealthCheck::Command { command, args, timeout_secs } => {
+            let timeout = timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+            let mut cmd = crate::platform::command(command);
+            cmd.args(args)
+                .envs(&config.env)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .kill_on_drop(true);
+            if let Some(dir) = &config.cwd {
+                cmd.current_dir(dir);
+            }
+
+            let status = tokio::time::timeout(timeout, cmd.status())
+                .await
+                .map_err(|_| anyhow::anyhow!("{} did not finish within {:?}", command, timeout))?
+                .context(format!("Failed to run {}", command))?;
+            if !status.success() {
+                anyhow::bail!("{} exited with {}", command, status);
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Request history
+//! Alongside the audit log, each request's metadata (no payloads) goes into
+//! an embedded SQLite database, `~/.mcp-citadel/audit/history.db`, kept for
+//! `history_days`. `mcp-citadel history` and `/admin/history` query it by
+//! server, tool, session, age, and outcome without reading the log files.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::audit::{Outcome, Record};
+
+/// Path of the HTTP endpoint
+pub const HISTORY_PATH: &str = "/admin/history";
+
+/// Most records a query returns unless told otherwise
+const DEFAULT_LIMIT: usize = 100;
+
+/// How often records past their retention are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS requests (
+        id INTEGER PRIMARY KEY,
+        at TEXT NOT NULL,
+        session TEXT,
+        client TEXT,
+        server TEXT NOT NULL,
+        method TEXT NOT NULL,
+        tool TEXT,
+        latency_ms REAL NOT NULL,
+        outcome TEXT NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS requests_at ON requests (at);
+    CREATE INDEX IF NOT EXISTS requests_server_at ON requests (server, at);
+";
+
+/// The history database
+pub fn path() -> PathBuf {
+    super::audit::dir().join("history.db")
+}
+
+/// Which records a query returns; every condition given must hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub server: Option<String>,
+    pub tool: Option<String>,
+    /// A client session id, or a prefix of one
+    pub session: Option<String>,
+    /// Only requests answered within this long, like `30m` or `1h`
+    pub since: Option<String>,
+    /// Only requests that errored or failed
+    pub failed: bool,
+    /// The latest this many (default 100)
+    pub limit: Option<usize>,
+}
+
+/// Where the hub records requests
+pub struct History {
+    connection: Connection,
+    /// Days records are kept (0: forever)
+    retention_days: u64,
+    last_pruned: Instant,
+}
+
+impl History {
+    /// Open (or create) the database at `path`, dropping expired records
+    pub fn open(path: &Path, retention_days: u64) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // Readers (the CLI) don't block the hub's writes
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        let history = Self { connection, retention_days, last_pruned: Instant::now() };
+        history.prune()?;
+        Ok(history)
+    }
+
+    /// Add a request's metadata
+    pub fn record(&mut self, record: &Record) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO requests (at, session, client, server, method, tool, latency_ms, outcome, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                record.at,
+                record.session,
+                record.client,
+                record.server,
+                record.method,
+                record.tool,
+                record.latency_ms,
+                outcome_name(record.outcome),
+                record.error,
+            ],
+        )?;
+        if self.last_pruned.elapsed() >= PRUNE_INTERVAL {
+            self.last_pruned = Instant::now();
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// Remove records older than the retention
+    fn prune(&self) -> Result<()> {
+        if self.retention_days == 0 {
+            return Ok(());
+        }
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(self.retention_days.min(36500) as i64);
+        self.connection.execute("DELETE FROM requests WHERE at < ?1", [timestamp(cutoff)])?;
+        Ok(())
+    }
+}
+
+/// The latest records in the database at `path` matching `query` of the
+/// servers `visible` lets through, oldest first
+pub fn query(path: &Path, query: &HistoryQuery, visible: impl Fn(&str) -> bool) -> Result<Vec<Record>> {
+    let since = match &query.since {
+        Some(spec) => Some(chrono::Utc::now() - chrono::Duration::from_std(crate::config::schedule::parse_duration(spec)?)?),
+        None => None,
+    };
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    // Conditions not given are NULL, and hold for every record
+    let mut statement = connection.prepare(
+        "SELECT at, session, client, server, method, tool, latency_ms, outcome, error FROM requests
+         WHERE (?1 IS NULL OR server = ?1)
+           AND (?2 IS NULL OR tool = ?2)
+           AND (?3 IS NULL OR substr(session, 1, length(?3)) = ?3)
+           AND (?4 IS NULL OR at >= ?4)
+           AND (NOT ?5 OR outcome != 'ok')
+         ORDER BY id DESC",
+    )?;
+    let rows = statement.query_map(
+        params![query.server, query.tool, query.session, since.map(timestamp), query.failed],
+        |row| {
+            Ok(Record {
+                at: row.get(0)?,
+                session: row.get(1)?,
+                client: row.get(2)?,
+                server: row.get(3)?,
+                method: row.get(4)?,
+                tool: row.get(5)?,
+                latency_ms: row.get(6)?,
+                outcome: parse_outcome(&row.get::<_, String>(7)?),
+                error: row.get(8)?,
+                params: None,
+                response: None,
+            })
+        },
+    )?;
+    // Newest first, so the rows past the limit are never read
+    let visible = rows.filter(|row| row.as_ref().map_or(true, |record| visible(&record.server)));
+    let mut records = visible.take(query.limit.unwrap_or(DEFAULT_LIMIT)).collect::<rusqlite::Result<Vec<_>>>()?;
+    records.reverse();
+    Ok(records)
+}
+
+/// `at` as recorded, so timestamps compare as strings
+fn timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn outcome_name(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Ok => "ok",
+        Outcome::Error => "error",
+        Outcome::Failed => "failed",
+    }
+}
+
+fn parse_outcome(name: &str) -> Outcome {
+    match name {
+        "ok" => Outcome::Ok,
+        "error" => Outcome::Error,
+        _ => Outcome::Failed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_past_retention_pruned() {
+        let path = std::env::temp_dir().join(format!("mcp-citadel-history-{}.db", std::process::id()));
+        let record = |at: chrono::DateTime<chrono::Utc>| Record {
+            at: timestamp(at),
+            session: None,
+            client: None,
+            server: "github".to_string(),
+            method: "ping".to_string(),
+            tool: None,
+            latency_ms: 1.5,
+            outcome: Outcome::Ok,
+            error: None,
+            params: None,
+            response: None,
+        };
+        let mut history = History::open(&path, 7).unwrap();
+        history.record(&record(chrono::Utc::now() - chrono::Duration::days(8))).unwrap();
+        history.record(&record(chrono::Utc::now())).unwrap();
+        assert_eq!(query(&path, &HistoryQuery::default(), |_| true).unwrap().len(), 2);
+        drop(history);
+
+        History::open(&path, 7).unwrap();
+        let kept = query(&path, &HistoryQuery::default(), |_| true).unwrap();
+        assert_eq!((kept.len(), kept[0].latency_ms), (1, 1.5));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
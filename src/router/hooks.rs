@@ -0,0 +1,142 @@
+//! Lifecycle hooks
+//! Per-server `hooks` commands run by the hub before a server starts, once it
+//! is up, before it stops, and after it crashes. Their output goes to the hub
+//! log, prefixed with the server and hook.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::{HookCommand, Hooks, ServerConfig};
+
+/// Timeout when the hook doesn't set `timeoutSecs`
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    PreStart,
+    PostStart,
+    PreStop,
+    OnCrash,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PreStart => "preStart",
+            Hook::PostStart => "postStart",
+            Hook::PreStop => "preStop",
+            Hook::OnCrash => "onCrash",
+        }
+    }
+}
+
+/// A server's hooks with what they run in
+#[derive(Debug)]
+pub struct HookRunner {
+    server: String,
+    env: HashMap<String, String>,
+    cwd: Option<PathBuf>,
+    hooks: Hooks,
+}
+
+impl HookRunner {
+    /// None when the server has no hooks
+    pub fn new(config: &ServerConfig) -> Option<Arc<Self>> {
+        (!config.hooks.is_empty()).then(|| {
+            Arc::new(Self {
+                server: config.name.clone(),
+                env: config.env.clone(),
+                cwd: config.cwd.clone(),
+                hooks: config.hooks.clone(),
+            })
+        })
+    }
+
+    /// Run a hook if configured; `vars` are added to its environment
+    pub async fn run(&self, hook: Hook, vars: &[(&str, String)]) -> Result<()> {
+        let command = match hook {
+            Hook::PreStart => &self.hooks.pre_start,
+            Hook::PostStart => &self.hooks.post_start,
+            Hook::PreStop => &self.hooks.pre_stop,
+            Hook::OnCrash => &self.hooks.on_crash,
+        };
+        let Some(HookCommand { command, args, timeout_secs }) = command else {
+            return Ok(());
+        };
+
+        info!("Running {} hook for {}: {} {:?}", hook.name(), self.server, command, args);
+        let timeout = timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+        let mut cmd = crate::platform::command(command);
+        cmd.args(args)
+            .envs(&self.env)
+            .env("MCP_CITADEL_SERVER", &self.server)
+            .env("MCP_CITADEL_HOOK", hook.name())
+            .envs(vars.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.cwd {
+            cmd.current_dir(dir);
+        }
+
+        let output = tokio::time::timeout(timeout, cmd.output())
+            .await
+            .map_err(|_| anyhow::anyhow!("{} hook did not finish within {:?}", hook.name(), timeout))?
+            .context(format!("Failed to run {} hook {}", hook.name(), command))?;
+        for line in String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stderr).lines())
+            .filter(|l| !l.trim().is_empty())
+        {
+            info!("[{} {}] {}", self.server, hook.name(), line);
+        }
+        if !output.status.success() {
+            anyhow::bail!("{} hook {} exited with {}", hook.name(), command, output.status);
+        }
+        Ok(())
+    }
+
+    /// Run a hook whose failure doesn't change what happens to the server
+    pub async fn run_logged(&self, hook: Hook, vars: &[(&str, String)]) {
+        if let Err(e) = self.run(hook, vars).await {
+            warn!("Server {}: {:#}", self.server, e);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn runner(hooks: &str) -> Arc<HookRunner> {
+        let config = ServerConfig {
+            name: "db".to_string(),
+            env: HashMap::from([("DSN".to_string(), "postgres://db".to_string())]),
+            hooks: serde_json::from_str(hooks).unwrap(),
+            ..Default::default()
+        };
+        HookRunner::new(&config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hook_env_and_failure() {
+        let runner = runner(
+            r#"{
+                "preStart": {"command": "sh", "args": ["-c", "test \"$MCP_CITADEL_SERVER $MCP_CITADEL_HOOK $DSN\" = 'db preStart postgres://db'"]},
+                "onCrash": {"command": "sh", "args": ["-c", "exit $MCP_CITADEL_EXIT_CODE"]},
+                "preStop": {"command": "sleep", "args": ["5"], "timeoutSecs": 0}
+            }"#,
+        );
+        runner.run(Hook::PreStart, &[]).await.unwrap();
+        runner.run(Hook::PostStart, &[]).await.unwrap();
+        runner.run(Hook::OnCrash, &[("MCP_CITADEL_EXIT_CODE", "0".to_string())]).await.unwrap();
+        assert!(runner.run(Hook::OnCrash, &[("MCP_CITADEL_EXIT_CODE", "3".to_string())]).await.is_err());
+        assert!(runner.run(Hook::PreStop, &[]).await.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Idempotent tool calls
+//! A `tools/call` whose `params._meta.idempotencyKey` is set runs once per key,
+//! server and client session: retries with the same key (say, after an SSE
+//! stream dropped mid-response) wait for or get the recorded response instead
+//! of running a side-effecting tool again. Keys are never shared between
+//! sessions, and only the backend's answer is recorded, so a call the hub
+//! failed (it timed out, say) runs again when retried.
+
+use bytes::Bytes;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use super::message::{self, Envelope};
+
+/// One key's response, shared by the call and its retries
+#[derive(Debug)]
+pub struct Entry {
+    created: Instant,
+    pub response: OnceCell<Bytes>,
+}
+
+/// Who sent a key: the client session, the server, and the key itself
+type Scope = (Option<String>, String, String);
+
+/// Responses by (session, server, idempotency key)
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    window: Duration,
+    entries: Mutex<HashMap<Scope, Arc<Entry>>>,
+}
+
+impl IdempotencyCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The entry for a key, dropping answered entries older than the window
+    ///
+    /// None when deduplication is disabled.
+    pub fn entry(&self, session: Option<&str>, server: &str, key: &str) -> Option<Arc<Entry>> {
+        if self.window.is_zero() {
+            return None;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.response.get().is_none() || entry.created.elapsed() < self.window);
+        let entry = entries
+            .entry((session.map(String::from), server.to_string(), key.to_string()))
+            .or_insert_with(|| {
+                Arc::new(Entry {
+                    created: Instant::now(),
+                    response: OnceCell::new(),
+                })
+            });
+        Some(Arc::clone(entry))
+    }
+
+    /// Drop an entry whose call got no response to record, so a retry runs it
+    pub fn forget(&self, entry: &Arc<Entry>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, kept| !Arc::ptr_eq(kept, entry) || kept.response.get().is_some());
+    }
+}
+
+/// The idempotency key of a request, if it has one
+pub fn key(envelope: &Envelope) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Params {
+        #[serde(rename = "_meta")]
+        meta: Option<Meta>,
+    }
+    #[derive(Deserialize)]
+    struct Meta {
+        #[serde(rename = "idempotencyKey")]
+        idempotency_key: Option<String>,
+    }
+
+    let params: Params = serde_json::from_str(envelope.params?.get()).ok()?;
+    params.meta?.idempotency_key.filter(|k| !k.is_empty())
+}
+
+/// A recorded response, answering the request with the given raw id
+pub fn readdress(response: &Bytes, id: Option<&str>) -> Bytes {
+    let span = Envelope::parse(response).and_then(|e| e.id_span(response));
+    match (span, id) {
+        (Some(span), Some(id)) => message::splice(response, span, id.as_bytes()),
+        _ => response.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_gets_recorded_response() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60));
+        let request = br#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"charge","_meta":{"idempotencyKey":"k1"}}}"#;
+        let key = key(&Envelope::parse(request).unwrap()).unwrap();
+        assert_eq!(key, "k1");
+
+        let mut runs = 0;
+        for _ in 0..2 {
+            let entry = cache.entry(Some("s1"), "billing", &key).unwrap();
+            entry
+                .response
+                .get_or_init(|| async {
+                    runs += 1;
+                    Bytes::from_static(b"{\"jsonrpc\":\"2.0\",\"id\":7,\"result\":{}}\n")
+                })
+                .await;
+        }
+        assert_eq!(runs, 1);
+
+        let entry = cache.entry(Some("s1"), "billing", &key).unwrap();
+        let retry = readdress(entry.response.get().unwrap(), Some("8"));
+        assert_eq!(&retry[..], b"{\"jsonrpc\":\"2.0\",\"id\":8,\"result\":{}}\n");
+
+        // Another session's key of the same name is its own
+        assert!(cache.entry(Some("s2"), "billing", &key).unwrap().response.get().is_none());
+
+        // As is one whose call failed
+        let failed = cache.entry(Some("s3"), "billing", &key).unwrap();
+        cache.forget(&failed);
+        assert!(!Arc::ptr_eq(&failed, &cache.entry(Some("s3"), "billing", &key).unwrap()));
+
+        assert!(IdempotencyCache::new(Duration::ZERO).entry(None, "billing", &key).is_none());
+    }
+}
@@ -0,0 +1,105 @@
+//! Hub identity
+//! Initialize results the hub hands out carry the backend's own `serverInfo`
+//! plus an experimental `citadel` capability naming the hub, its version, and
+//! the `citadel/*` methods it answers itself, so capable clients can
+//! introspect the hub over MCP:
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"citadel/listServers","params":{"server":"github"}}
+//! ← {"jsonrpc":"2.0","id":1,"result":{"servers":[{"name":"github","running":true,...}]}}
+//! ```
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use super::message::Envelope;
+use super::workspace::Scope;
+use super::HubManager;
+
+/// Prefix of the methods the hub answers
+const PREFIX: &str = "citadel/";
+
+/// Methods the hub answers
+const METHODS: &[&str] = &["citadel/hubInfo", "citadel/listServers"];
+
+/// Add the hub's identity to a backend's initialize result
+pub fn advertise(result: &mut Value) {
+    let Some(result) = result.as_object_mut() else {
+        return;
+    };
+    let capabilities = result.entry("capabilities").or_insert_with(|| json!({}));
+    if let Some(capabilities) = capabilities.as_object_mut() {
+        let experimental = capabilities.entry("experimental").or_insert_with(|| json!({}));
+        if let Some(experimental) = experimental.as_object_mut() {
+            experimental.insert(
+                "citadel".to_string(),
+                json!({
+                    "serverInfo": { "name": "mcp-citadel", "version": env!("CARGO_PKG_VERSION") },
+                    "methods": METHODS,
+                }),
+            );
+        }
+    }
+}
+
+/// The hub's answer to a `citadel/*` request, or None for any other message
+pub async fn answer(manager: &HubManager, scope: Option<&Scope>, message: &[u8]) -> Option<Bytes> {
+    let envelope = Envelope::parse(message)?;
+    let method = envelope.method.as_deref().filter(|m| m.starts_with(PREFIX))?;
+    let id = serde_json::from_str::<Value>(envelope.id?.get()).ok()?;
+
+    let outcome = match method {
+        "citadel/hubInfo" => Ok(json!({
+            "name": "mcp-citadel",
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptimeSecs": manager.uptime().as_secs(),
+            "draining": manager.is_draining(),
+            "maintenance": manager.maintenance().current(),
+        })),
+        "citadel/listServers" => {
+            let mut servers = manager.server_info().await;
+            if let Some(scope) = scope {
+                let names = scope.filter(servers.iter().map(|s| s.name.clone()).collect());
+                servers.retain(|s| names.contains(&s.name));
+            }
+            let servers: Vec<Value> = servers
+                .into_iter()
+                .map(|s| {
+                    json!({
+                        "name": s.name,
+                        "running": s.running,
+                        "state": s.state,
+                        "uptimeSecs": s.uptime_secs,
+                        "restarts": s.restarts,
+                        "requests": s.requests,
+                        "errors": s.errors,
+                    })
+                })
+                .collect();
+            Ok(json!({ "servers": servers }))
+        }
+        _ => Err(json!({ "code": -32601, "message": format!("Method not found: {}", method) })),
+    };
+    let response = match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    };
+    let mut line = response.to_string().into_bytes();
+    line.push(b'\n');
+    Some(Bytes::from(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertise() {
+        let mut result = json!({ "serverInfo": { "name": "github", "version": "1.0" }, "capabilities": { "tools": {} } });
+        advertise(&mut result);
+        assert_eq!(result["serverInfo"]["name"], "github");
+        assert!(result["capabilities"]["tools"].is_object());
+        assert_eq!(result["capabilities"]["experimental"]["citadel"]["serverInfo"]["name"], "mcp-citadel");
+        assert_eq!(result["capabilities"]["experimental"]["citadel"]["methods"][1], "citadel/listServers");
+    }
+}
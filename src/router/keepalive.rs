@@ -0,0 +1,126 @@
+//! Unix socket keepalive
+//! Clients opt in by sending `{"citadel":"ping"}` (or `"keepalive": true` in a
+//! framed hello). The hub answers every ping with `{"citadel":"pong"}`, and on
+//! connections that opted in it pings the client after
+//! `router.client_keepalive_secs` of silence and closes the connection when
+//! another interval passes without a line, so half-open connections from
+//! crashed editors don't pile up.
+
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const PING: &[u8] = b"{\"citadel\":\"ping\"}\n";
+pub const PONG: &[u8] = b"{\"citadel\":\"pong\"}\n";
+
+/// Keepalive line from the peer
+#[derive(Debug, PartialEq, Eq)]
+pub enum Control {
+    Ping,
+    Pong,
+}
+
+impl Control {
+    /// Recognize a ping or pong line; everything else is a message
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        // Cheap check first, so regular messages are never parsed twice
+        if !line.trim_ascii_start().starts_with(b"{\"citadel\"") {
+            return None;
+        }
+        #[derive(Deserialize)]
+        struct Line<'a> {
+            #[serde(borrow)]
+            citadel: Cow<'a, str>,
+        }
+        match serde_json::from_slice::<Line>(line).ok()?.citadel.as_ref() {
+            "ping" => Some(Self::Ping),
+            "pong" => Some(Self::Pong),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when a keepalive interval passes in silence
+#[derive(Debug, PartialEq, Eq)]
+pub enum Tick {
+    /// Ask the client whether it's still there
+    Ping,
+    /// The client never answered the last ping
+    Expired,
+}
+
+/// Keepalive state of one client connection
+#[derive(Debug)]
+pub struct Keepalive {
+    interval: Option<Duration>,
+    enabled: bool,
+    deadline: Instant,
+    awaiting_pong: bool,
+}
+
+impl Keepalive {
+    /// Keepalive pinging every `interval` of silence once enabled (zero never pings)
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: (!interval.is_zero()).then_some(interval),
+            enabled: false,
+            deadline: Instant::now() + interval,
+            awaiting_pong: false,
+        }
+    }
+
+    /// The client speaks the keepalive convention
+    pub fn enable(&mut self) {
+        if !self.enabled {
+            self.enabled = true;
+            self.seen();
+        }
+    }
+
+    /// A line arrived from the client
+    pub fn seen(&mut self) {
+        if let Some(interval) = self.interval {
+            self.deadline = Instant::now() + interval;
+        }
+        self.awaiting_pong = false;
+    }
+
+    /// Wait for the connection's next silent interval to pass; never completes
+    /// unless keepalive is enabled
+    pub async fn tick(&mut self) -> Tick {
+        let Some(interval) = self.interval.filter(|_| self.enabled) else {
+            return std::future::pending().await;
+        };
+        tokio::time::sleep_until(self.deadline).await;
+        if self.awaiting_pong {
+            return Tick::Expired;
+        }
+        self.awaiting_pong = true;
+        self.deadline = Instant::now() + interval;
+        Tick::Ping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_control() {
+        assert_eq!(Control::parse(PING), Some(Control::Ping));
+        assert_eq!(Control::parse(b" {\"citadel\": \"pong\"}\n"), Some(Control::Pong));
+        assert_eq!(Control::parse(br#"{"citadel":"hello","protocol":2}"#), None);
+        assert_eq!(Control::parse(br#"{"jsonrpc":"2.0","method":"ping","id":1}"#), None);
+    }
+
+    #[tokio::test]
+    async fn test_expires_without_pong() {
+        let mut keepalive = Keepalive::new(Duration::from_millis(5));
+        keepalive.enable();
+        assert_eq!(keepalive.tick().await, Tick::Ping);
+        keepalive.seen();
+        assert_eq!(keepalive.tick().await, Tick::Ping);
+        assert_eq!(keepalive.tick().await, Tick::Expired);
+    }
+}
@@ -0,0 +1,73 @@
+//! Concurrency limits
+//! Caps on client requests handled at once, hub-wide
+//! (`router.max_concurrent_requests`) and per server (`maxConcurrentRequests`).
+//! Requests over a cap wait up to `router.queue_timeout_ms` for a slot, by
+//! priority class, and are then rejected.
+
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::priority::{Gate, GatePermit};
+use crate::config::Priority;
+
+/// A cap on concurrent requests
+#[derive(Debug)]
+pub struct Limit {
+    /// What the limit applies to, for errors ("the hub", "server github")
+    scope: String,
+    max: usize,
+    slots: Arc<Gate>,
+}
+
+impl Limit {
+    pub fn new(scope: impl Into<String>, max: usize) -> Arc<Self> {
+        let max = max.max(1);
+        Arc::new(Self {
+            scope: scope.into(),
+            max,
+            slots: Gate::new(max),
+        })
+    }
+
+    /// Take a slot, waiting up to `queue_timeout` for one to free up
+    pub async fn acquire(&self, queue_timeout: Duration, priority: Priority) -> Result<GatePermit> {
+        if let Some(permit) = self.slots.try_acquire() {
+            return Ok(permit);
+        }
+        if !queue_timeout.is_zero() {
+            if let Ok(permit) = tokio::time::timeout(queue_timeout, self.slots.acquire(priority)).await {
+                return Ok(permit);
+            }
+        }
+        anyhow::bail!(
+            "Too many concurrent requests to {} (limit {}); try again later",
+            self.scope,
+            self.max
+        )
+    }
+
+    /// Requests currently holding a slot
+    pub fn active(&self) -> usize {
+        self.max - self.slots.available()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limit_rejects_when_full() {
+        let limit = Limit::new("server github", 2);
+        let first = limit.acquire(Duration::ZERO, Priority::Normal).await.unwrap();
+        let _second = limit.acquire(Duration::ZERO, Priority::Normal).await.unwrap();
+        assert_eq!(limit.active(), 2);
+
+        let error = limit.acquire(Duration::from_millis(10), Priority::Normal).await.unwrap_err();
+        assert!(error.to_string().contains("server github (limit 2)"));
+
+        drop(first);
+        assert!(limit.acquire(Duration::ZERO, Priority::Normal).await.is_ok());
+    }
+}
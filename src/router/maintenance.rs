@@ -0,0 +1,177 @@
+//! Maintenance mode
+//! `mcp-citadel maintenance on --duration 10m` holds client traffic for a
+//! fixed window while config surgery or a backend data migration happens:
+//! backends keep running, but the hub answers every new client request
+//! (except `ping`) with a maintenance error until the window ends or
+//! `maintenance off`. Starting and ending a window is a lifecycle event, which
+//! HTTP sessions receive as a `citadel/maintenance` notice on their stream.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::info;
+
+use super::message::Envelope;
+use super::HubEvent;
+
+/// JSON-RPC error code of requests refused during maintenance
+pub const MAINTENANCE_ERROR: i32 = -32004;
+
+/// Method of the notice sent to HTTP sessions
+pub const MAINTENANCE: &str = "citadel/maintenance";
+
+/// An active maintenance window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Window {
+    pub started_at: String,
+    pub until: String,
+    pub reason: Option<String>,
+}
+
+struct Active {
+    window: Window,
+    ends: Instant,
+    /// Tells a window's own expiry apart from a later window's
+    generation: u64,
+}
+
+pub struct Maintenance {
+    active: Arc<Mutex<Option<Active>>>,
+    generation: AtomicU64,
+    lifecycle: broadcast::Sender<HubEvent>,
+}
+
+impl Maintenance {
+    pub(super) fn new(lifecycle: broadcast::Sender<HubEvent>) -> Self {
+        Self {
+            active: Arc::new(Mutex::new(None)),
+            generation: AtomicU64::new(0),
+            lifecycle,
+        }
+    }
+
+    /// Start a window (replacing any current one); traffic resumes by itself
+    /// once `duration` has passed
+    pub fn begin(&self, duration: Duration, reason: Option<String>) -> Window {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = chrono::Utc::now();
+        let window = Window {
+            started_at: now.to_rfc3339(),
+            until: (now + chrono::Duration::from_std(duration).unwrap_or_default()).to_rfc3339(),
+            reason,
+        };
+        *self.active.lock().unwrap() = Some(Active {
+            window: window.clone(),
+            ends: Instant::now() + duration,
+            generation,
+        });
+        info!("Maintenance until {}{}", window.until, window.reason.as_deref().map(|r| format!(": {}", r)).unwrap_or_default());
+        let _ = self.lifecycle.send(HubEvent::Maintenance {
+            active: true,
+            until: Some(window.until.clone()),
+            reason: window.reason.clone(),
+        });
+
+        let active = Arc::clone(&self.active);
+        let lifecycle = self.lifecycle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            let mut active = active.lock().unwrap();
+            if active.as_ref().is_some_and(|a| a.generation == generation) {
+                *active = None;
+                info!("Maintenance window over, resuming client traffic");
+                let _ = lifecycle.send(HubEvent::Maintenance { active: false, until: None, reason: None });
+            }
+        });
+        window
+    }
+
+    /// End the current window early; false if there was none
+    pub fn end(&self) -> bool {
+        let ended = self.active.lock().unwrap().take().is_some();
+        if ended {
+            info!("Maintenance ended, resuming client traffic");
+            let _ = self.lifecycle.send(HubEvent::Maintenance { active: false, until: None, reason: None });
+        }
+        ended
+    }
+
+    /// The window in effect, if any
+    pub fn current(&self) -> Option<Window> {
+        let active = self.active.lock().unwrap();
+        active.as_ref().filter(|a| a.ends > Instant::now()).map(|a| a.window.clone())
+    }
+
+    /// The error answering `message` during maintenance, or None if it may
+    /// go through (no window, a notification or response, or a ping)
+    pub fn rejection(&self, message: &[u8]) -> Option<Bytes> {
+        let (window, remaining) = {
+            let active = self.active.lock().unwrap();
+            let active = active.as_ref()?;
+            (active.window.clone(), active.ends.checked_duration_since(Instant::now())?)
+        };
+        let envelope = Envelope::parse(message)?;
+        if !envelope.is_request() || envelope.method.as_deref().is_some_and(|m| m == "ping" || m.ends_with("/ping")) {
+            return None;
+        }
+        let id = envelope.id.and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+        let error = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": MAINTENANCE_ERROR,
+                "message": format!("Hub is under maintenance until {}", window.until),
+                "data": {
+                    "maintenance": true,
+                    "until": window.until,
+                    "retry_after_secs": remaining.as_secs() + 1,
+                    "reason": window.reason,
+                },
+            },
+        });
+        let mut line = serde_json::to_vec(&error).ok()?;
+        line.push(b'\n');
+        Some(Bytes::from(line))
+    }
+}
+
+/// The JSON-RPC notification for a window starting (`until`) or ending
+pub fn notice(active: bool, until: Option<&str>, reason: Option<&str>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": MAINTENANCE,
+        "params": { "active": active, "until": until, "reason": reason },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_window_rejects_requests_until_it_ends() {
+        let (lifecycle, mut events) = broadcast::channel(8);
+        let maintenance = Maintenance::new(lifecycle);
+        let call = br#"{"jsonrpc":"2.0","id":3,"method":"github/tools/call","params":{}}"#;
+        assert!(maintenance.rejection(call).is_none());
+
+        maintenance.begin(Duration::from_millis(50), Some("migration".to_string()));
+        let rejection: Value = serde_json::from_slice(&maintenance.rejection(call).unwrap()).unwrap();
+        assert_eq!(rejection["id"], 3);
+        assert_eq!(rejection["error"]["code"], MAINTENANCE_ERROR);
+        assert_eq!(rejection["error"]["data"]["reason"], "migration");
+        assert!(maintenance.rejection(br#"{"jsonrpc":"2.0","id":4,"method":"ping"}"#).is_none());
+        assert!(maintenance.rejection(br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).is_none());
+        assert!(matches!(events.recv().await, Ok(HubEvent::Maintenance { active: true, .. })));
+
+        // Resumes by itself
+        assert!(matches!(events.recv().await, Ok(HubEvent::Maintenance { active: false, .. })));
+        assert!(maintenance.current().is_none());
+        assert!(maintenance.rejection(call).is_none());
+        assert!(!maintenance.end());
+    }
+}
@@ -0,0 +1,243 @@
+//! JSON-RPC message inspection
+//! Borrowed views over raw message bytes, so routing can peek at fields without
+//! deserializing or re-encoding whole messages
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::de::{Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+/// The JSON-RPC fields the hub routes on, borrowed from the raw message
+#[derive(Debug, Deserialize)]
+pub struct Envelope<'a> {
+    #[serde(borrow, default)]
+    pub method: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    pub id: Option<&'a RawValue>,
+    #[serde(borrow, default)]
+    pub params: Option<&'a RawValue>,
+    /// Present on error responses (skipped, not materialized)
+    #[serde(default)]
+    pub error: Option<IgnoredAny>,
+}
+
+impl<'a> Envelope<'a> {
+    /// Parse the envelope of a JSON-RPC message
+    pub fn parse(message: &'a [u8]) -> Option<Self> {
+        serde_json::from_slice(message).ok()
+    }
+
+    /// A request expects a response: it has both a method and a non-null id
+    pub fn is_request(&self) -> bool {
+        self.method.is_some() && self.id.is_some()
+    }
+
+    /// Byte range of the id value within `message`
+    pub fn id_span(&self, message: &[u8]) -> Option<Range<usize>> {
+        self.id.map(|id| raw_span(message, id))
+    }
+}
+
+/// Extract the target server name from a JSON-RPC message
+///
+/// Uses `params.server` when present, otherwise the method prefix
+/// (e.g. `github/tools/list`). Only the `method` and `params.server` fields are
+/// materialized; everything else is skipped in a single pass.
+pub fn extract_server_name(message: &[u8]) -> Option<String> {
+    let fields: RoutingFields = serde_json::from_slice(message).ok()?;
+
+    if let Some(server) = fields.params.and_then(|p| p.0) {
+        return Some(server);
+    }
+
+    let method = fields.method?;
+    method.split('/').next().map(String::from)
+}
+
+/// The server a message names in `params.server`, if any
+pub fn server_param(message: &[u8]) -> Option<String> {
+    let fields: RoutingFields = serde_json::from_slice(message).ok()?;
+    fields.params.and_then(|p| p.0)
+}
+
+/// Fields used to pick the target server
+#[derive(Deserialize)]
+struct RoutingFields<'a> {
+    #[serde(borrow, default)]
+    method: Option<Cow<'a, str>>,
+    #[serde(default)]
+    params: Option<ServerParam>,
+}
+
+/// `params.server`, tolerating positional (array) params
+struct ServerParam(Option<String>);
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum ParamKey {
+    Server,
+    #[serde(other)]
+    Other,
+}
+
+impl<'de> Deserialize<'de> for ServerParam {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ServerParamVisitor;
+
+        impl<'de> Visitor<'de> for ServerParamVisitor {
+            type Value = ServerParam;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("JSON-RPC params")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut server = None;
+                while let Some(key) = map.next_key::<ParamKey>()? {
+                    match key {
+                        ParamKey::Server if server.is_none() => server = map.next_value()?,
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(ServerParam(server))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Ok(ServerParam(None))
+            }
+        }
+
+        deserializer.deserialize_any(ServerParamVisitor)
+    }
+}
+
+/// Byte range of the progress token within `message`: `params._meta.progressToken`
+/// of a request, or `params.progressToken` of a `notifications/progress`
+pub fn progress_token_span(message: &[u8]) -> Option<Range<usize>> {
+    let params = Envelope::parse(message)?.params.filter(|p| p.get().starts_with('{'))?;
+    let params: ProgressParams = serde_json::from_str(params.get()).ok()?;
+    let token = params.token.or(params.meta.and_then(|m| m.token))?;
+    Some(raw_span(message, token))
+}
+
+/// Byte range of the cancelled request's id within a `notifications/cancelled`:
+/// `params.requestId`
+pub fn cancelled_request_span(message: &[u8]) -> Option<Range<usize>> {
+    let params = Envelope::parse(message)?.params.filter(|p| p.get().starts_with('{'))?;
+    let params: CancelledParams = serde_json::from_str(params.get()).ok()?;
+    Some(raw_span(message, params.request_id?))
+}
+
+#[derive(Deserialize)]
+struct CancelledParams<'a> {
+    #[serde(borrow, default, rename = "requestId")]
+    request_id: Option<&'a RawValue>,
+}
+
+/// Where progress tokens sit in params
+#[derive(Deserialize)]
+struct ProgressParams<'a> {
+    #[serde(borrow, default, rename = "progressToken")]
+    token: Option<&'a RawValue>,
+    #[serde(borrow, default, rename = "_meta")]
+    meta: Option<ProgressMeta<'a>>,
+}
+
+#[derive(Deserialize)]
+struct ProgressMeta<'a> {
+    #[serde(borrow, default, rename = "progressToken")]
+    token: Option<&'a RawValue>,
+}
+
+/// Byte range of a borrowed raw value within the buffer it was parsed from
+fn raw_span(message: &[u8], raw: &RawValue) -> Range<usize> {
+    let start = raw.get().as_ptr() as usize - message.as_ptr() as usize;
+    start..start + raw.get().len()
+}
+
+/// Copy `message` with the bytes in `span` replaced, ensuring a trailing newline
+pub fn splice(message: &[u8], span: Range<usize>, replacement: &[u8]) -> Bytes {
+    let body = trim_newline(message);
+    let mut out = BytesMut::with_capacity(body.len() + replacement.len() + 1);
+    out.put_slice(&body[..span.start]);
+    out.put_slice(replacement);
+    out.put_slice(&body[span.end..]);
+    out.put_u8(b'\n');
+    out.freeze()
+}
+
+/// Return `message` newline-terminated, copying only if the newline is missing
+pub fn with_newline(message: Bytes) -> Bytes {
+    if message.ends_with(b"\n") {
+        return message;
+    }
+    let mut out = BytesMut::with_capacity(message.len() + 1);
+    out.put_slice(&message);
+    out.put_u8(b'\n');
+    out.freeze()
+}
+
+fn trim_newline(message: &[u8]) -> &[u8] {
+    let message = message.strip_suffix(b"\n").unwrap_or(message);
+    message.strip_suffix(b"\r").unwrap_or(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splice_id() {
+        let message = br#"{"jsonrpc":"2.0","id":"abc","method":"tools/list"}"#;
+        let envelope = Envelope::parse(message).unwrap();
+        assert!(envelope.is_request());
+        assert_eq!(envelope.method.as_deref(), Some("tools/list"));
+
+        let span = envelope.id_span(message).unwrap();
+        let spliced = splice(message, span, b"42");
+        assert_eq!(&spliced[..], &b"{\"jsonrpc\":\"2.0\",\"id\":42,\"method\":\"tools/list\"}\n"[..]);
+    }
+
+    #[test]
+    fn test_notification_is_not_request() {
+        let envelope = Envelope::parse(br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).unwrap();
+        assert!(!envelope.is_request());
+
+        let envelope = Envelope::parse(br#"{"jsonrpc":"2.0","id":null,"method":"x"}"#).unwrap();
+        assert!(!envelope.is_request());
+    }
+
+    #[test]
+    fn test_progress_token_span() {
+        let request = br#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"x","_meta":{"progressToken":"abc"}}}"#;
+        let span = progress_token_span(request).unwrap();
+        assert_eq!(&request[span], br#""abc""#);
+
+        let notification = br#"{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":7,"progress":1}}"#;
+        let span = progress_token_span(notification).unwrap();
+        assert_eq!(&notification[span], b"7");
+
+        assert_eq!(progress_token_span(br#"{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{}}"#), None);
+        assert_eq!(progress_token_span(br#"{"jsonrpc":"2.0","id":1,"method":"x","params":[1]}"#), None);
+    }
+
+    #[test]
+    fn test_extract_server_name() {
+        let message = br#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"x","arguments":{"server":"nested"},"server":"github"}}"#;
+        assert_eq!(extract_server_name(message).as_deref(), Some("github"));
+
+        let message = br#"{"jsonrpc":"2.0","id":1,"method":"github/tools/list"}"#;
+        assert_eq!(extract_server_name(message).as_deref(), Some("github"));
+
+        let message = br#"{"jsonrpc":"2.0","id":1,"method":"slack/post","params":[1,2]}"#;
+        assert_eq!(extract_server_name(message).as_deref(), Some("slack"));
+
+        assert_eq!(extract_server_name(b"not json"), None);
+    }
+}
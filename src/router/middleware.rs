@@ -0,0 +1,169 @@
+//! Middleware
+//! Interceptors every client message to a server goes through, in the order
+//! they were added to the hub (`HubManager::with_middleware`), and every
+//! response on its way back, in reverse order. A middleware can log, redact,
+//! or rewrite messages, or answer a request itself so it never reaches the
+//! backend, without changes to the router.
+//!
+//! ```ignore
+//! struct DenyDeletes;
+//!
+//! impl Middleware for DenyDeletes {
+//!     fn on_request(&self, context: &Context, message: Bytes) -> Flow {
+//!         if context.method(&message).as_deref() == Some("tools/call") && is_delete(&message) {
+//!             return Flow::Respond(middleware::error_response(&message, -32001, "Deleting is not allowed"));
+//!         }
+//!         Flow::Forward(message)
+//!     }
+//! }
+//! ```
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use super::message::Envelope;
+
+/// What a message is routed for
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    /// Client session the message came from, if any
+    pub session: Option<&'a str>,
+    /// Server the message is for
+    pub server: &'a str,
+}
+
+impl Context<'_> {
+    /// A message's method, without the `server/` prefix of aggregated names
+    pub fn method(&self, message: &[u8]) -> Option<String> {
+        let envelope = Envelope::parse(message)?;
+        Some(super::backend_method(self.server, envelope.method.as_deref()?).to_string())
+    }
+}
+
+/// What becomes of a client message
+#[derive(Debug)]
+pub enum Flow {
+    /// Pass the (possibly changed) message on
+    Forward(Bytes),
+    /// Answer the client with this instead (empty for no answer)
+    Respond(Bytes),
+}
+
+/// An interceptor of routed messages; both hooks pass messages through unless
+/// overridden
+pub trait Middleware: Send + Sync {
+    /// A client message on its way to `context.server`
+    fn on_request(&self, _context: &Context, message: Bytes) -> Flow {
+        Flow::Forward(message)
+    }
+
+    /// A response on its way back to the client, whether from the backend, the
+    /// hub, or a later middleware
+    fn on_response(&self, _context: &Context, response: Bytes) -> Bytes {
+        response
+    }
+}
+
+/// The hub's middleware, in order
+#[derive(Clone, Default)]
+pub struct Chain(Vec<Arc<dyn Middleware>>);
+
+impl Chain {
+    pub fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Run a client message through each middleware until one answers it;
+    /// an answer goes back through those that saw the message
+    pub fn request(&self, context: &Context, mut message: Bytes) -> Flow {
+        for (i, middleware) in self.0.iter().enumerate() {
+            match middleware.on_request(context, message) {
+                Flow::Forward(forwarded) => message = forwarded,
+                Flow::Respond(response) => return Flow::Respond(Self(self.0[..=i].to_vec()).response(context, response)),
+            }
+        }
+        Flow::Forward(message)
+    }
+
+    /// Run a response back through the middleware, last first
+    pub fn response(&self, context: &Context, mut response: Bytes) -> Bytes {
+        if response.is_empty() {
+            return response;
+        }
+        for middleware in self.0.iter().rev() {
+            response = middleware.on_response(context, response);
+        }
+        response
+    }
+}
+
+/// A JSON-RPC error answering `request`, for middleware refusing it
+pub fn error_response(request: &[u8], code: i32, message: &str) -> Bytes {
+    let Some(envelope) = Envelope::parse(request).filter(Envelope::is_request) else {
+        return Bytes::new();
+    };
+    let id = envelope.id.and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+    let mut line = json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }).to_string().into_bytes();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records what it sees under its name, refusing requests containing `refuse`
+    struct Recorder {
+        name: &'static str,
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for Recorder {
+        fn on_request(&self, _context: &Context, message: Bytes) -> Flow {
+            self.seen.lock().unwrap().push(format!("{} request", self.name));
+            if message.windows(6).any(|w| w == b"refuse") {
+                return Flow::Respond(error_response(&message, -32001, "refused"));
+            }
+            Flow::Forward(message)
+        }
+
+        fn on_response(&self, _context: &Context, response: Bytes) -> Bytes {
+            self.seen.lock().unwrap().push(format!("{} response", self.name));
+            response
+        }
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = Chain::default();
+        for name in ["outer", "inner"] {
+            chain.push(Arc::new(Recorder { name, seen: Arc::clone(&seen) }));
+        }
+        let context = Context { session: None, server: "github" };
+
+        let request = Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#);
+        let Flow::Forward(request) = chain.request(&context, request) else {
+            panic!("not forwarded");
+        };
+        assert_eq!(context.method(&request).as_deref(), Some("tools/list"));
+        chain.response(&context, Bytes::from_static(b"{}\n"));
+        assert_eq!(*seen.lock().unwrap(), ["outer request", "inner request", "inner response", "outer response"]);
+
+        seen.lock().unwrap().clear();
+        let refused = Bytes::from_static(br#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"refuse"}}"#);
+        let Flow::Respond(response) = chain.request(&context, refused) else {
+            panic!("not answered");
+        };
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!((response["id"].as_i64(), response["error"]["code"].as_i64()), (Some(2), Some(-32001)));
+        // Refused by the first, so the second never saw it
+        assert_eq!(*seen.lock().unwrap(), ["outer request", "outer response"]);
+    }
+}
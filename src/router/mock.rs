@@ -0,0 +1,238 @@
+//! Built-in mock backend
+//! A `"type": "mock"` server is answered by a task inside the hub from its
+//! configured catalog and canned or scripted replies, over an in-memory pipe
+//! that carries the same JSON lines a process's stdio would. No process is
+//! spawned, so tests of clients, middleware, and transports run hermetically
+//! and `bench --local` measures the hub alone.
+
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, WriteHalf};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::config::{MockConfig, MockReply};
+
+/// Bytes buffered in each direction of the pipe
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Start serving a mock; returns the hub's end of the pipe
+pub fn spawn(name: String, config: MockConfig) -> (DuplexStream, JoinHandle<()>) {
+    let (hub, backend) = tokio::io::duplex(PIPE_CAPACITY);
+    let task = tokio::spawn(serve(Mock::new(name, config), backend));
+    (hub, task)
+}
+
+async fn serve(mut mock: Mock, stream: DuplexStream) {
+    let (reader, writer) = tokio::io::split(stream);
+    let writer = Arc::new(Mutex::new(writer));
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+
+    while matches!(reader.read_until(b'\n', &mut line).await, Ok(n) if n > 0) {
+        let request: Value = serde_json::from_slice(&line).unwrap_or_default();
+        line.clear();
+        if let Some(journal) = &mock.config.journal {
+            journal.record(request.clone());
+        }
+        // Notifications and responses to the mock's (nonexistent) requests
+        let (Some(id), Some(method)) = (request.get("id"), request.get("method").and_then(Value::as_str)) else {
+            continue;
+        };
+        let (mut response, delay, outgoing) = mock.reply(method, request.get("params").unwrap_or(&Value::Null));
+        response["jsonrpc"] = json!("2.0");
+        response["id"] = id.clone();
+        let token = request.pointer("/params/_meta/progressToken");
+        for mut message in outgoing {
+            message["jsonrpc"] = json!("2.0");
+            // Progress reports on the request unless it names another token
+            if let (Some(token), Some("notifications/progress")) = (token, message.get("method").and_then(Value::as_str)) {
+                if message.pointer("/params/progressToken").is_none() {
+                    message["params"]["progressToken"] = token.clone();
+                }
+            }
+            if write(&writer, &message).await.is_err() {
+                break;
+            }
+        }
+
+        match delay {
+            // Delayed replies don't hold up later requests
+            Some(delay) => {
+                let writer = Arc::clone(&writer);
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = write(&writer, &response).await;
+                });
+            }
+            None => {
+                if write(&writer, &response).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Mock server {} closed", mock.name);
+}
+
+async fn write(writer: &Mutex<WriteHalf<DuplexStream>>, response: &Value) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(response)?;
+    line.push(b'\n');
+    writer.lock().await.write_all(&line).await
+}
+
+/// A mock's catalog and how far each scripted rule has got
+struct Mock {
+    name: String,
+    config: MockConfig,
+    /// Replies already given per rule
+    used: Vec<usize>,
+}
+
+impl Mock {
+    fn new(name: String, config: MockConfig) -> Self {
+        let used = vec![0; config.responses.len()];
+        Self { name, config, used }
+    }
+
+    /// The `result` or `error` (plus delay, and notifications and requests to
+    /// send first) for a request
+    fn reply(&mut self, method: &str, params: &Value) -> (Value, Option<Duration>, Vec<Value>) {
+        let subject = match method {
+            "resources/read" => params.get("uri"),
+            _ => params.get("name"),
+        }
+        .and_then(Value::as_str);
+
+        let rule = self.config.responses.iter().position(|rule| {
+            rule.method == method && rule.name.as_deref().is_none_or(|name| Some(name) == subject)
+        });
+        if let Some(i) = rule {
+            let replies = &self.config.responses[i].replies;
+            let used = &mut self.used[i];
+            let reply = replies.get(*used).or(replies.last()).cloned().unwrap_or_default();
+            *used += 1;
+            let MockReply { result, error, delay_ms, notifications, requests } = reply;
+            let response = match error {
+                Some(error) => json!({ "error": error }),
+                None => json!({ "result": result.unwrap_or_else(|| json!({})) }),
+            };
+            return (response, delay_ms.map(Duration::from_millis), notifications.into_iter().chain(requests).collect());
+        }
+
+        let response = match self.builtin(method, params, subject) {
+            Ok(result) => json!({ "result": result }),
+            Err((code, message)) => json!({ "error": { "code": code, "message": message } }),
+        };
+        (response, None, Vec::new())
+    }
+
+    /// Answers to requests no rule covers, from the catalog
+    fn builtin(&self, method: &str, params: &Value, subject: Option<&str>) -> Result<Value, (i64, String)> {
+        let find = |items: &[Value], key: &str| {
+            items.iter().find(|item| item.get(key).and_then(Value::as_str).is_some_and(|v| Some(v) == subject)).cloned()
+        };
+        let listed = |items: &[Value], hidden: &[&str]| {
+            let mut items = items.to_vec();
+            for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                item.retain(|key, _| !hidden.contains(&key.as_str()));
+            }
+            items
+        };
+
+        match method {
+            "initialize" => {
+                let mut capabilities = json!({});
+                for (kind, items) in [("tools", &self.config.tools), ("resources", &self.config.resources), ("prompts", &self.config.prompts)] {
+                    if !items.is_empty() {
+                        capabilities[kind] = json!({});
+                    }
+                }
+                Ok(json!({
+                    "protocolVersion": params.get("protocolVersion").cloned().unwrap_or_else(|| json!("2025-06-18")),
+                    "capabilities": capabilities,
+                    "serverInfo": { "name": self.name, "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.config.tools })),
+            "resources/list" => Ok(json!({ "resources": listed(&self.config.resources, &["text", "blob"]) })),
+            "prompts/list" => Ok(json!({ "prompts": listed(&self.config.prompts, &["messages"]) })),
+            // A listed tool answers with its arguments
+            "tools/call" if find(&self.config.tools, "name").is_some() => {
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                Ok(json!({ "content": [{ "type": "text", "text": arguments.to_string() }] }))
+            }
+            "tools/call" if !self.config.echo => Err((-32602, format!("Unknown tool: {}", subject.unwrap_or_default()))),
+            "resources/read" => match find(&self.config.resources, "uri") {
+                Some(mut resource) => {
+                    if let Some(resource) = resource.as_object_mut() {
+                        resource.retain(|key, _| ["uri", "mimeType", "text", "blob"].contains(&key.as_str()));
+                    }
+                    Ok(json!({ "contents": [resource] }))
+                }
+                None => Err((-32002, format!("Resource not found: {}", subject.unwrap_or_default()))),
+            },
+            "prompts/get" => match find(&self.config.prompts, "name") {
+                Some(prompt) => Ok(json!({
+                    "description": prompt.get("description"),
+                    "messages": prompt.get("messages").cloned().unwrap_or_else(|| json!([])),
+                })),
+                None => Err((-32602, format!("Unknown prompt: {}", subject.unwrap_or_default()))),
+            },
+            _ if self.config.echo => Ok(params.clone()),
+            _ => Err((-32601, format!("Method not found: {}", method))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_catalog_and_scripted_replies() {
+        let config: MockConfig = serde_json::from_value(json!({
+            "tools": [{ "name": "search", "inputSchema": { "type": "object" } }],
+            "resources": [{ "uri": "mock://readme", "name": "README", "text": "hello" }],
+            "responses": [{
+                "method": "tools/call",
+                "name": "flaky",
+                "replies": [{ "error": { "code": -32000, "message": "try again" } }, { "result": { "content": [] } }],
+            }],
+        }))
+        .unwrap();
+        let (hub, _task) = spawn("fake".to_string(), config);
+        let (reader, mut writer) = tokio::io::split(hub);
+        let mut reader = BufReader::new(reader);
+
+        let mut call = async |method: &str, params: Value| {
+            let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+            writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            serde_json::from_str::<Value>(&line).unwrap()
+        };
+
+        let init = call("initialize", json!({ "protocolVersion": "2025-03-26" })).await;
+        assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+        assert!(init["result"]["capabilities"]["resources"].is_object());
+        assert!(init["result"]["capabilities"]["prompts"].is_null());
+
+        let listed = call("resources/list", json!({})).await;
+        assert!(listed["result"]["resources"][0]["text"].is_null());
+        let read = call("resources/read", json!({ "uri": "mock://readme" })).await;
+        assert_eq!(read["result"]["contents"][0]["text"], "hello");
+
+        let search = call("tools/call", json!({ "name": "search", "arguments": { "q": "x" } })).await;
+        assert_eq!(search["result"]["content"][0]["text"], r#"{"q":"x"}"#);
+        assert_eq!(call("tools/call", json!({ "name": "flaky" })).await["error"]["message"], "try again");
+        for _ in 0..2 {
+            assert!(call("tools/call", json!({ "name": "flaky" })).await["result"]["content"].is_array());
+        }
+        assert_eq!(call("tools/call", json!({ "name": "nope" })).await["error"]["code"], -32602);
+        assert_eq!(call("bench/echo", json!({})).await["error"]["code"], -32601);
+    }
+}
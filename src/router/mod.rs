@@ -1,273 +1,2424 @@
 //! MCP Citadel Router
 //! Routes MCP messages from clients to backend MCP servers
 
+pub mod aggregate;
+pub mod approvals;
+pub mod audit;
+pub mod capabilities;
+pub mod catalog;
+pub mod clients;
+pub mod connection;
+pub mod container;
+pub mod debug;
+pub mod dependencies;
+pub mod events;
+pub mod framed;
+pub mod framing;
+pub mod health;
+pub mod history;
+pub mod hooks;
+pub mod identity;
+pub mod idempotency;
+pub mod keepalive;
+pub mod limits;
+pub mod maintenance;
+pub mod message;
+pub mod middleware;
+pub mod mock;
+pub mod notices;
+pub mod policy;
+pub mod priority;
+pub mod project;
+pub mod provision;
+pub mod rate;
+pub mod remote;
+pub mod restart;
+pub mod pinned;
+pub mod progress;
+pub mod resources;
+pub mod reverse;
+pub mod session;
+pub mod size;
+pub mod stderr;
+mod table;
+pub mod transcript;
+pub mod upstream;
+pub mod usage;
+pub mod webhooks;
+pub mod workspace;
+
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
-use tokio::sync::Mutex;
+use tokio::process::Child;
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 
-use crate::config::ServerConfig;
+use crate::config::rewrite::{self, RewriteRule};
+use crate::config::tools::{self, ToolFilter};
+use crate::config::resolve::Resolver;
+use crate::config::{HealthCheck, OversizedResponse, Priority, RouterConfig, ServerConfig, WarmUpRequest, WebhookConfig};
+use capabilities::SessionCapabilities;
+use catalog::CatalogCache;
+use connection::{BackendConnection, BackendMessage, Reply};
+use debug::DebugTranscript;
+use hooks::{Hook, HookRunner};
+use clients::ClientRegistry;
+use idempotency::IdempotencyCache;
+use keepalive::{Control, Keepalive, Tick};
+use limits::Limit;
+use middleware::{Flow, Middleware};
+use pinned::PinnedInstances;
+use rate::RateLimit;
+use reverse::ReverseRouter;
+use maintenance::Maintenance;
+use approvals::Approvals;
+use audit::AuditLog;
+use usage::UsageTracker;
+use webhooks::{Alert, AlertKind, Webhooks};
+use message::Envelope;
+use session::{BackendInit, ClientSession, Replay};
+use stderr::StderrTail;
+use table::ServerTable;
+use notices::RestartNotices;
+use transcript::{Origin, Transcripts};
+use workspace::{Identity, Scope, Workspaces};
+pub use message::extract_server_name;
+
+/// Read buffer size when none is configured (tokio's `BufReader` default)
+const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Request timeout of servers started without the router's defaults applied
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+
+/// Spawn timeout of lazy servers without the router's defaults applied
+const DEFAULT_SPAWN_TIMEOUT_SECS: u64 = 30;
+
+/// How long a replaced instance may finish its in-flight requests
+const STANDBY_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long releasing a closed client's subscriptions may take per request
+const CLEANUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long a due scheduled restart waits for a busy server to go idle
+const MAX_RESTART_DEFERRAL: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// What answers a server's messages
+enum Backend {
+    Process {
+        child: Box<Child>,
+        stderr: Arc<StderrTail>,
+        stderr_reader: JoinHandle<()>,
+        /// Enforces its CPU and memory limits, on Linux
+        _cgroup: Option<resources::Cgroup>,
+        /// Removed on stop, when the server runs in one
+        container: Option<container::Running>,
+    },
+    /// A built-in mock's serving task
+    Mock(JoinHandle<()>),
+    /// The bridge to a remote server
+    Remote(remote::Bridge),
+}
+
+impl Backend {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Backend::Process { child, .. } => child.id(),
+            Backend::Mock(_) | Backend::Remote(_) => None,
+        }
+    }
+
+    /// The last lines a process wrote to stderr
+    fn stderr(&self) -> Vec<String> {
+        match self {
+            Backend::Process { stderr, .. } => stderr.lines(),
+            Backend::Mock(_) | Backend::Remote(_) => Vec::new(),
+        }
+    }
+
+    /// The exit status once the process is gone; a mock or remote server
+    /// never exits
+    fn try_wait(&mut self) -> std::io::Result<Option<std::process::ExitStatus>> {
+        match self {
+            Backend::Process { child, .. } => child.try_wait(),
+            Backend::Mock(_) | Backend::Remote(_) => Ok(None),
+        }
+    }
+}
+
+/// Managed MCP server process
+pub struct MCPServerProcess {
+    name: String,
+    backend: Backend,
+    /// Absolute path the command resolved to
+    command_path: Option<std::path::PathBuf>,
+    start_time: std::time::Instant,
+    connection: Arc<BackendConnection>,
+    reader: JoinHandle<()>,
+    rewrite: Arc<[RewriteRule]>,
+    /// Tools clients may see and call
+    tools: Arc<ToolFilter>,
+    /// Refuse calls to destructive tools (unset: the hub's setting)
+    read_only: Option<bool>,
+    /// Cap on client requests to this server at once
+    limit: Option<Arc<Limit>>,
+    /// Cap on client requests to this server per second
+    rate: Option<Arc<RateLimit>>,
+    /// Size limit for tool results and what to do with larger ones
+    response_limit: Option<(usize, OversizedResponse)>,
+    init: Arc<BackendInit>,
+    hooks: Option<Arc<HookRunner>>,
+    debug: Arc<DebugTranscript>,
+}
+
+/// What routing a message to a server needs, cloned out of the servers map
+struct Route {
+    connection: Arc<BackendConnection>,
+    rewrite: Arc<[RewriteRule]>,
+    tools: Arc<ToolFilter>,
+    read_only: Option<bool>,
+    limit: Option<Arc<Limit>>,
+    rate: Option<Arc<RateLimit>>,
+    response_limit: Option<(usize, OversizedResponse)>,
+    init: Arc<BackendInit>,
+}
+
+impl MCPServerProcess {
+    fn route(&self) -> Route {
+        Route {
+            connection: Arc::clone(&self.connection),
+            rewrite: Arc::clone(&self.rewrite),
+            tools: Arc::clone(&self.tools),
+            read_only: self.read_only,
+            limit: self.limit.clone(),
+            rate: self.rate.clone(),
+            response_limit: self.response_limit,
+            init: Arc::clone(&self.init),
+        }
+    }
+
+    /// Start an MCP server process
+    pub async fn start(config: ServerConfig, events: broadcast::Sender<BackendMessage>) -> Result<Self> {
+        info!("Starting MCP server: {}", config.name);
+        debug!(
+            "Command: {} {:?}",
+            config.command,
+            config.args
+        );
+
+        let hooks = HookRunner::new(&config);
+        if let Some(hooks) = &hooks {
+            hooks
+                .run(Hook::PreStart, &[])
+                .await
+                .context(format!("Failed to start server: {}", config.name))?;
+        }
+
+        let (backend, command_path, stdin, stdout): (_, _, Box<dyn AsyncWrite + Send + Unpin>, Box<dyn AsyncRead + Send + Unpin>) =
+            match (&config.mock, &config.remote) {
+                // Answered by a task in the hub; nothing to spawn
+                (Some(catalog), _) => {
+                    let (pipe, task) = mock::spawn(config.name.clone(), catalog.clone());
+                    let (stdout, stdin) = tokio::io::split(pipe);
+                    (Backend::Mock(task), None, Box::new(stdin), Box::new(stdout))
+                }
+                (None, Some(remote)) => {
+                    let (pipe, bridge) = remote::spawn(config.name.clone(), remote)?;
+                    let (stdout, stdin) = tokio::io::split(pipe);
+                    (Backend::Remote(bridge), None, Box::new(stdin), Box::new(stdout))
+                }
+                (None, None) => {
+                    let container = match &config.container {
+                        Some(image) => {
+                            container::pull(&config.name, image).await?;
+                            Some(container::Running::new(&config.name, image))
+                        }
+                        None => None,
+                    };
+                    let (mut process, command_path) = Self::spawn(&config, container.as_ref())?;
+                    let stdin = process
+                        .stdin
+                        .take()
+                        .context("Failed to get stdin")?;
+
+                    let stdout = process
+                        .stdout
+                        .take()
+                        .context("Failed to get stdout")?;
+
+                    let stderr = process
+                        .stderr
+                        .take()
+                        .context("Failed to get stderr")?;
+
+                    let log_file = config.stderr_log.unwrap_or(false).then(|| stderr::log_path(&config.name));
+                    let (stderr, stderr_reader) = stderr::drain(&config.name, stderr, log_file);
+                    // A container's limits are the container runtime's
+                    let cgroup = match container {
+                        Some(_) => None,
+                        None => process.id().and_then(|pid| resources::confine(&config.name, pid, &config.limits)),
+                    };
+                    let backend = Backend::Process {
+                        child: Box::new(process),
+                        stderr,
+                        stderr_reader,
+                        _cgroup: cgroup,
+                        container,
+                    };
+                    (backend, command_path, Box::new(stdin), Box::new(stdout))
+                }
+            };
+
+        let debug = DebugTranscript::new(&config.name, config.debug_transcript);
+        let (stdin, stdout) = debug::tap(Arc::clone(&debug), stdin, stdout);
+        let (connection, reader) = BackendConnection::new(
+            config.name.clone(),
+            stdin,
+            stdout,
+            connection::Limits {
+                max_in_flight: config.max_in_flight.unwrap_or(1),
+                read_buffer_size: config.read_buffer_size.unwrap_or(DEFAULT_READ_BUFFER_SIZE),
+                max_message_bytes: config.max_message_bytes.unwrap_or(0),
+                request_timeout: std::time::Duration::from_secs(config.request_timeout_secs.unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS)),
+                framing: config.framing,
+            },
+            events,
+        );
+
+        info!("✓ Started MCP server: {} (PID: {:?})", config.name, backend.pid());
+        
+        let mut server = Self {
+            name: config.name.clone(),
+            backend,
+            command_path,
+            start_time: std::time::Instant::now(),
+            connection,
+            reader,
+            rewrite: config.rewrite.clone().into(),
+            tools: Arc::new(config.tools.clone()),
+            read_only: config.read_only,
+            limit: config
+                .max_concurrent_requests
+                .map(|max| Limit::new(format!("server {}", config.name), max)),
+            rate: config
+                .max_requests_per_second
+                .map(|rate| Arc::new(RateLimit::new(&config.name, rate))),
+            response_limit: config
+                .max_response_bytes
+                .map(|max| (max, config.oversized_response.unwrap_or_default())),
+            init: Arc::new(BackendInit::new()),
+            hooks,
+            debug,
+        };
+        
+        // Wait 100ms and check if it immediately crashed
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        
+        if let Ok(Some(status)) = server.backend.try_wait() {
+            // Read any error output
+            let mut error_msg = String::new();
+            if let Backend::Process { stderr, stderr_reader, .. } = &mut server.backend {
+                let _ = tokio::time::timeout(std::time::Duration::from_millis(100), stderr_reader).await;
+                error_msg = stderr.lines().into_iter().next().unwrap_or_default();
+            }
+            
+            warn!("Server {} crashed during startup: {:?}", config.name, status);
+            if !error_msg.is_empty() {
+                warn!("Error output: {}", error_msg.trim());
+            }
+            
+            return Err(anyhow::anyhow!(
+                "Server crashed immediately with status: {:?}. Error: {}",
+                status,
+                error_msg.trim()
+            ));
+        }
+
+        if let Some(hooks) = &server.hooks {
+            hooks.run_logged(Hook::PostStart, &server.pid_var()).await;
+        }
+        
+        Ok(server)
+    }
+
+    /// Spawn a server's command, or its container, with stdio piped
+    fn spawn(config: &ServerConfig, container: Option<&container::Running>) -> Result<(Child, Option<std::path::PathBuf>)> {
+        // Inherit parent environment and merge with config env
+        // This ensures servers have access to PATH, HOME, etc.
+        let mut merged_env: HashMap<String, String> = std::env::vars().collect();
+
+        // A container gets the server's user and limits as `run` options (see
+        // `container::run_args`); the process on the host is just the CLI
+        let (command, args) = match (&config.container, container) {
+            (Some(image), Some(running)) => (image.runtime.clone(), container::run_args(config, image, &running.name)),
+            _ => (config.command.clone(), config.args.clone()),
+        };
+        let on_host = container.is_none();
+
+        // A less privileged account gets its own home, unless the config says otherwise
+        let account = match (&config.user, &config.group) {
+            _ if !on_host => None,
+            (None, None) => None,
+            (user, group) => Some(
+                crate::platform::account(user.as_deref(), group.as_deref())
+                    .context(format!("Failed to find the account to run server {} as", config.name))?,
+            ),
+        };
+        if let Some((user, home)) = account.as_ref().and_then(|a| a.user.as_ref()) {
+            merged_env.insert("HOME".to_string(), home.to_string_lossy().into_owned());
+            merged_env.insert("USER".to_string(), user.clone());
+            merged_env.insert("LOGNAME".to_string(), user.clone());
+        }
+        merged_env.extend(config.env.clone());
+
+        // Find npx/uvx even when the hub runs with launchd's or systemd's PATH
+        let resolved = Resolver::global().resolve(&command, config.env.get("PATH").map(String::as_str));
+        let mut cmd = match &resolved {
+            Some(resolved) => {
+                debug!("Resolved {} to {}", command, resolved.path.display());
+                merged_env.insert("PATH".to_string(), resolved.env_path.to_string_lossy().into_owned());
+                crate::platform::command(&resolved.path)
+            }
+            None => crate::platform::command(&command),
+        };
+
+        if let Some(dir) = &config.cwd {
+            if !dir.is_dir() {
+                anyhow::bail!("Working directory {} of server {} doesn't exist", dir.display(), config.name);
+            }
+            cmd.current_dir(dir);
+        }
+        if let Some(mask) = config.umask.filter(|_| on_host) {
+            crate::platform::set_umask(&mut cmd, mask);
+        }
+        if let Some(max) = config.limits.max_open_files.filter(|_| on_host) {
+            crate::platform::set_open_files(&mut cmd, max);
+        }
+        if let Some(account) = &account {
+            crate::platform::run_as(&mut cmd, account);
+        }
+        // Own process group, so `prune` can stop the server with everything it
+        // started if the hub dies
+        #[cfg(unix)]
+        cmd.process_group(0);
+        cmd.args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .env_clear()
+            .envs(&merged_env);
+
+        let process = cmd.spawn().context(match &resolved {
+            Some(_) => format!("Failed to spawn server: {}", config.name),
+            None => format!(
+                "Failed to spawn server: {} (command {} not found; run `mcp-citadel doctor`)",
+                config.name, command
+            ),
+        })?;
+
+        Ok((process, resolved.map(|r| r.path)))
+    }
+
+    /// Hook environment naming the server process
+    fn pid_var(&self) -> Vec<(&'static str, String)> {
+        self.backend.pid().map(|pid| ("MCP_CITADEL_PID", pid.to_string())).into_iter().collect()
+    }
+
+    /// Wall-clock time the process started
+    pub fn started_at(&self) -> chrono::DateTime<chrono::Local> {
+        chrono::Local::now() - self.start_time.elapsed()
+    }
+
+    /// Stop the server
+    pub async fn stop(&mut self) -> Result<()> {
+        info!("Stopping MCP server: {}", self.name);
+        if let Some(hooks) = &self.hooks {
+            hooks.run_logged(Hook::PreStop, &self.pid_var()).await;
+        }
+        self.reader.abort();
+        match &mut self.backend {
+            Backend::Process { child, stderr_reader, container, .. } => {
+                crate::platform::kill(child).await?;
+                child.wait().await?;
+                stderr_reader.abort();
+                if let Some(container) = container {
+                    container.remove().await;
+                }
+            }
+            Backend::Mock(task) => task.abort(),
+            Backend::Remote(bridge) => bridge.close().await,
+        }
+        Ok(())
+    }
+}
+
+/// Server lifecycle change, new session, or failed request, reported to
+/// event subscribers (see `events`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HubEvent {
+    Started { server: String },
+    Stopped { server: String },
+    Restarted { server: String, reason: String },
+    /// The server exited or hung (a restart follows unless it gave up)
+    Failed { server: String, reason: String },
+    Reloaded { added: Vec<String>, removed: Vec<String>, changed: Vec<String> },
+    /// A maintenance window began (until the given time) or ended
+    Maintenance { active: bool, until: Option<String>, reason: Option<String> },
+    /// A tool call is held until an operator approves or denies it
+    ApprovalRequested { id: u64, server: String, tool: String },
+    /// A client connected (`socket`, `http`, or `websocket`)
+    SessionCreated { session: String, transport: String },
+    /// An HTTP session went unused past its timeout and was dropped
+    SessionExpired { session: String },
+    /// A request got no answer: its server is down, timed out, or crashed
+    RequestFailed { server: String, method: Option<String>, session: Option<String>, error: String },
+}
+
+/// Where a configured server is in its lifecycle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerState {
+    Running,
+    /// A lazy server starting for its first request
+    Initializing,
+    /// A lazy server waiting for its first request
+    Idle,
+    /// Running, but missing pings or timing out requests
+    Unresponsive,
+    /// Exited or hung, and not being restarted
+    Crashed,
+    /// Failed, and waiting out its backoff before it's started again
+    Restarting,
+    /// Stopped with `mcp-citadel server stop`
+    Disabled,
+    #[default]
+    Stopped,
+}
+
+/// Live state of a configured server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub running: bool,
+    #[serde(default)]
+    pub state: ServerState,
+    pub pid: Option<u32>,
+    /// Absolute path of the running server's command
+    #[serde(default)]
+    pub command_path: Option<std::path::PathBuf>,
+    /// Where a remote server is
+    #[serde(default)]
+    pub url: Option<String>,
+    pub uptime_secs: Option<u64>,
+    pub restarts: u32,
+    pub in_flight: usize,
+    pub requests: u64,
+    /// Requests that failed or got an error response
+    #[serde(default)]
+    pub errors: u64,
+    pub consecutive_timeouts: u32,
+    /// When the restart schedule next restarts the server (RFC 3339)
+    #[serde(default)]
+    pub next_restart: Option<String>,
+    /// Client sessions with a process of their own (per-session servers)
+    #[serde(default)]
+    pub pinned: usize,
+    /// Why the server, or the last request to it that got no answer, failed last
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// MCP Citadel Server Manager
+pub struct HubManager {
+    /// Running processes, each behind its own lock
+    servers: Arc<ServerTable>,
+    /// Lazy servers starting for their first request
+    spawning: Arc<dashmap::DashSet<String>>,
+    /// Servers stopped for being idle, started again by their next request
+    parked: Arc<dashmap::DashSet<String>>,
+    /// Why servers that aren't running are down: crashed, restarting, or disabled
+    down: Arc<dashmap::DashMap<String, ServerState>>,
+    /// Latest failure of each server, or of a request to it
+    last_errors: dashmap::DashMap<String, String>,
+    /// Params of the client `initialize` each server last answered, replayed
+    /// by the hub to every process that replaces it
+    handshakes: Arc<dashmap::DashMap<String, Value>>,
+    configs: Mutex<Vec<ServerConfig>>,
+    start_time: std::time::Instant,
+    restart_counts: Arc<Mutex<HashMap<String, u32>>>,
+    catalog: Arc<Mutex<CatalogCache>>,
+    /// Unsolicited backend messages (notifications, server-initiated requests)
+    events: broadcast::Sender<BackendMessage>,
+    router_config: RouterConfig,
+    /// Set once shutdown begins so readiness probes take the hub out of rotation
+    draining: AtomicBool,
+    workspaces: Workspaces,
+    /// Interceptors every routed message and its response pass through
+    middleware: middleware::Chain,
+    /// Servers from project configs, started for the clients inside each project
+    projects: project::Projects,
+    /// Server lifecycle changes
+    lifecycle: broadcast::Sender<HubEvent>,
+    /// Cap on client requests across all servers at once
+    limit: Option<Arc<Limit>>,
+    /// Token usage of sampling exchanges
+    usage: Arc<UsageTracker>,
+    /// Recorded responses of tool calls with idempotency keys
+    idempotency: IdempotencyCache,
+    /// `mcp-client` adapters registered over the control socket
+    clients: Arc<ClientRegistry>,
+    /// Latest messages of each client session
+    transcripts: Transcripts,
+    /// Where the transports listen, by kind (`socket`, `http`, ...)
+    endpoints: std::sync::Mutex<BTreeMap<String, String>>,
+    /// Time-boxed window in which client requests are refused
+    maintenance: Maintenance,
+    /// Tool calls held for an operator's decision
+    approvals: Approvals,
+    /// Where answered requests are recorded, with `[router.audit]` enabled
+    audit: Option<AuditLog>,
+    /// What each session's client declared in its `initialize`
+    capabilities: Arc<SessionCapabilities>,
+    /// Which client sessions get the requests backends send, and the progress
+    /// of their own requests
+    reverse: Arc<ReverseRouter>,
+    /// Per-session servers' processes for each client session
+    pinned: Arc<PinnedInstances>,
+    /// Pings in a row each server's process (by generation) missed
+    missed_pings: dashmap::DashMap<String, (u64, u32)>,
+    /// Latest lifecycle events, for `events.recent`
+    recent_events: Arc<events::RecentEvents>,
+    /// Alerted when servers crash, hang, or run out of restarts
+    webhooks: Webhooks,
+}
+
+impl HubManager {
+    /// Create a new hub manager
+    pub async fn new(mut configs: Vec<ServerConfig>, router_config: RouterConfig) -> Result<Self> {
+        let mut servers = HashMap::new();
+        let catalog = Arc::new(Mutex::new(CatalogCache::load(catalog::catalog_dir(), &configs)));
+        let (events, _) = broadcast::channel(router_config.event_channel_capacity.max(1));
+        let (lifecycle, _) = broadcast::channel(router_config.event_channel_capacity.max(1));
+
+        // Apply hub-wide defaults
+        for config in &mut configs {
+            router_config.apply_defaults(config);
+        }
+        crate::redact::configure(&router_config.redaction, &configs);
+
+        spawn_catalog_invalidation(events.subscribe(), lifecycle.subscribe(), Arc::clone(&catalog));
+        let usage = Arc::new(UsageTracker::default());
+        usage::spawn_tracking(events.subscribe(), Arc::clone(&usage));
+        let recent_events = Arc::new(events::RecentEvents::default());
+        events::spawn_recording(lifecycle.subscribe(), Arc::clone(&recent_events));
+
+        // Lazy servers start with their first request, unless a server that
+        // doesn't needs them; dependencies start, and answer, first
+        let order = dependencies::start_order(&configs)?;
+        let needed: HashSet<&str> = order
+            .iter()
+            .filter(|c| !c.starts_on_demand())
+            .flat_map(|c| dependencies::with_dependencies(&configs, &c.name))
+            .collect();
+        for config in order.into_iter().filter(|c| needed.contains(c.name.as_str())) {
+            if let Err(e) = await_dependencies(config, &configs, &servers).await {
+                error!("Not starting server {}: {:#}", config.name, e);
+                continue;
+            }
+            match start_server(config, &events, router_config.auto_provision).await {
+                Ok(server) => {
+                    servers.insert(config.name.clone(), server);
+                }
+                Err(e) => {
+                    error!("Failed to start server {}: {}", config.name, e);
+                }
+            }
+        }
+
+        let servers = Arc::new(ServerTable::new(servers));
+        let capabilities = Arc::new(SessionCapabilities::default());
+        let reverse = Arc::new(ReverseRouter::default());
+        let shaping = router_config.capability_shaping;
+        let pinned = Arc::new(PinnedInstances::default());
+        pinned.configure(&configs);
+        spawn_reverse_routing(events.subscribe(), Arc::clone(&servers), Arc::clone(&capabilities), Arc::clone(&reverse), shaping);
+
+        Ok(Self {
+            servers,
+            spawning: Arc::default(),
+            parked: Arc::default(),
+            down: Arc::default(),
+            last_errors: dashmap::DashMap::new(),
+            handshakes: Arc::default(),
+            configs: Mutex::new(configs),
+            start_time: std::time::Instant::now(),
+            restart_counts: Arc::new(Mutex::new(HashMap::new())),
+            catalog,
+            events,
+            draining: AtomicBool::new(false),
+            workspaces: Workspaces::default(),
+            middleware: middleware::Chain::default(),
+            projects: project::Projects::default(),
+            maintenance: Maintenance::new(lifecycle.clone()),
+            approvals: Approvals::new(lifecycle.clone()),
+            audit: router_config
+                .audit
+                .enabled
+                .then(|| AuditLog::new(audit::dir(), router_config.audit.clone())),
+            capabilities,
+            reverse,
+            pinned,
+            missed_pings: dashmap::DashMap::new(),
+            recent_events,
+            webhooks: Webhooks::default(),
+            lifecycle,
+            limit: router_config
+                .max_concurrent_requests
+                .map(|max| Limit::new("the hub", max)),
+            idempotency: IdempotencyCache::new(std::time::Duration::from_secs(router_config.idempotency_window_secs)),
+            transcripts: Transcripts::new(router_config.session_transcript_messages),
+            router_config,
+            usage,
+            clients: Arc::default(),
+            endpoints: Default::default(),
+        })
+    }
+
+    /// Restrict clients to the servers of their workspace
+    pub fn with_workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = workspaces;
+        self
+    }
+
+    /// Alert `webhooks` when servers crash, hang, or run out of restarts
+    pub fn with_webhooks(mut self, webhooks: Vec<WebhookConfig>) -> Self {
+        self.webhooks = Webhooks::new(webhooks);
+        self
+    }
+
+    /// Run every routed message through `middleware`, after any added before it
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Map a client to the servers it may reach
+    pub fn scope(&self, identity: Identity) -> Result<Scope> {
+        let mut scope = self.workspaces.resolve(identity)?;
+        scope.read_only |= self.router_config.read_only;
+        Ok(scope)
+    }
+
+    /// Route a message to a specific server
+    pub async fn route_message(&self, server_name: &str, message: Bytes) -> Result<Bytes> {
+        self.route_session_message(None, Priority::Normal, server_name, message).await
+    }
+
+    /// Route a message from a client session, attributing its sampling usage
+    ///
+    /// `priority` is the client's queue priority; `router.method_priorities`
+    /// overrides it by method.
+    pub async fn route_session_message(
+        &self,
+        session: Option<&str>,
+        priority: Priority,
+        server_name: &str,
+        message: Bytes,
+    ) -> Result<Bytes> {
+        let request = message.clone();
+        let routed = match &self.audit {
+            None => self.route_through_middleware(session, priority, server_name, message).await,
+            Some(audit) => {
+                if let Some(id) = session {
+                    self.capabilities.observe(id, server_name, &message);
+                }
+                let client = session.and_then(|id| self.capabilities.get(id)).and_then(|c| c.client);
+                let pending = audit.begin(session, client, server_name, &message);
+                let routed = self.route_through_middleware(session, priority, server_name, message).await;
+                if let Some(pending) = pending {
+                    audit.finish(pending, &routed);
+                }
+                routed
+            }
+        };
+
+        // Errors reach clients, logs, and event subscribers, where secrets mustn't
+        let routed = routed.map_err(crate::redact::scrub_error);
+        if let Err(e) = &routed {
+            let method = Envelope::parse(&request).and_then(|e| e.method).map(|m| backend_method(server_name, &m).to_string());
+            self.emit(HubEvent::RequestFailed {
+                server: server_name.to_string(),
+                method,
+                session: session.map(String::from),
+                error: format!("{:#}", e),
+            });
+        }
+        routed
+    }
+
+    async fn route_through_middleware(
+        &self,
+        session: Option<&str>,
+        priority: Priority,
+        server_name: &str,
+        message: Bytes,
+    ) -> Result<Bytes> {
+        if self.middleware.is_empty() {
+            return self.route_client_message(session, priority, server_name, message).await;
+        }
+        let context = middleware::Context { session, server: server_name };
+        let message = match self.middleware.request(&context, message) {
+            Flow::Forward(message) => message,
+            Flow::Respond(response) => return Ok(response),
+        };
+        let routed = self.route_client_message(session, priority, server_name, message).await;
+        routed.map(|response| self.middleware.response(&context, response))
+    }
+
+    async fn route_client_message(
+        &self,
+        session: Option<&str>,
+        priority: Priority,
+        server_name: &str,
+        message: Bytes,
+    ) -> Result<Bytes> {
+        let Some(id) = session else {
+            return self.deliver(None, priority, server_name, message).await;
+        };
+        if Envelope::parse(&message).is_some_and(|e| e.is_request()) {
+            self.reverse.used(server_name, id);
+        }
+        let shape = match self.router_config.capability_shaping {
+            true => {
+                self.capabilities.observe(id, server_name, &message);
+                self.capabilities.get(id).filter(|_| capabilities::shapes_response(server_name, &message))
+            }
+            false => None,
+        };
+        self.transcripts.record(id, server_name, Origin::Client, &message);
+        let (message, progress) = self.reverse.progress().track(id, server_name, message);
+        let routed = self.deliver(session, priority, server_name, message).await;
+        if let Some(token) = progress {
+            self.reverse.progress().release(&token);
+        }
+        let routed = match shape {
+            Some(client) => routed.map(|response| capabilities::shape_tool_result(&client, response)),
+            None => routed,
+        };
+        self.record_outcome(id, server_name, &routed);
+        routed
+    }
+
+    /// Add what came of a session's message to its transcript
+    fn record_outcome(&self, session: &str, server_name: &str, routed: &Result<Bytes>) {
+        match routed {
+            Ok(response) if response.is_empty() => {}
+            Ok(response) => self.transcripts.record(session, server_name, Origin::Server, response),
+            Err(e) => self.transcripts.record(session, server_name, Origin::Hub, format!("{:#}", e).as_bytes()),
+        }
+    }
+
+    async fn deliver(
+        &self,
+        session: Option<&str>,
+        priority: Priority,
+        server_name: &str,
+        message: Bytes,
+    ) -> Result<Bytes> {
+        self.usage.observe_client_message(server_name, session, &message);
+        let envelope = Envelope::parse(&message);
+        let observed = envelope
+            .as_ref()
+            .and_then(|e| catalog_method(server_name, e));
+
+        // Only hold the server's slot long enough to grab the connection, so
+        // requests to the same or other backends can be in flight concurrently
+        let mut route = match session {
+            Some(session) if self.pinned.is_pinned(server_name) && !self.is_disabled(server_name) => {
+                Some(self.pinned_route(session, server_name).await?)
+            }
+            _ => self.servers.with(server_name, MCPServerProcess::route).await,
+        };
+        let mut spawn_error = None;
+        if route.is_none() {
+            match self.spawn_on_demand(server_name).await {
+                Ok(spawned) => route = spawned,
+                Err(e) => spawn_error = Some(e),
+            }
+        }
+
+        let Some(route) = route else {
+            // Backend not (yet) running - answer list requests from the catalog cache
+            if let (Some(envelope), Some(method)) = (&envelope, observed) {
+                if let Some(response) = self.cached_list_response(server_name, method, envelope, false).await {
+                    debug!("Serving cached {} for {}", method, server_name);
+                    let tools = self.config(server_name).await.map(|c| c.tools).unwrap_or_default();
+                    return Ok(policy::filter_tools(&tools, response));
+                }
+            }
+            return Err(spawn_error.unwrap_or_else(|| anyhow::anyhow!("Server not found: {}", server_name)));
+        };
+
+        // A list fetched within its TTL spares the running backend
+        if let (Some(envelope), Some(method)) = (&envelope, observed) {
+            if let Some(response) = self.cached_list_response(server_name, method, envelope, true).await {
+                debug!("Serving fresh cached {} for {}", method, server_name);
+                return Ok(policy::filter_tools(&route.tools, response));
+            }
+        }
+
+        // A backend process is initialized once; later clients get its answer
+        let method = envelope
+            .as_ref()
+            .and_then(|e| e.method.as_deref())
+            .map(|m| backend_method(server_name, m));
+        if method == Some("notifications/initialized") && route.init.notified.swap(true, Ordering::AcqRel) {
+            return Ok(Bytes::new());
+        }
+        let mut init = None;
+        if method == Some("initialize") {
+            let result = route.init.result.lock().await;
+            if let Some(result) = result.as_ref() {
+                let id = envelope
+                    .as_ref()
+                    .and_then(|e| e.id)
+                    .and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+                debug!("Replaying initialize result of {}", server_name);
+                let mut response = serde_json::to_vec(&serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+                response.push(b'\n');
+                return Ok(Bytes::from(response));
+            }
+            init = Some(result);
+        }
+        // Replacement processes get the same handshake from the hub
+        let handshake: Option<Value> = init
+            .as_ref()
+            .and(envelope.as_ref())
+            .and_then(|e| e.params)
+            .and_then(|p| serde_json::from_str(p.get()).ok());
+
+        let is_request = envelope.as_ref().is_some_and(Envelope::is_request);
+        let is_tool_call = method == Some("tools/call");
+        let is_tool_list = method == Some("tools/list");
+        let priority = method
+            .and_then(|m| self.router_config.method_priorities.get(m))
+            .copied()
+            .unwrap_or(priority);
+
+        // Read-only servers refuse calls to destructive tools, before an
+        // operator is asked to approve one
+        if is_request && is_tool_call && route.read_only.unwrap_or(self.router_config.read_only_servers) {
+            if let Some(rejection) = self.write_rejection(&route, server_name, &message).await {
+                return Ok(rejection);
+            }
+        }
+
+        // Calls to tools that need approval wait for an operator's decision
+        // (calls to blocked tools are refused outright below)
+        let held = is_request && is_tool_call && !route.tools.approve.is_empty();
+        if held && policy::tool_name(server_name, &message).is_some_and(|tool| route.tools.allows(&tool) && route.tools.needs_approval(&tool)) {
+            let timeout = std::time::Duration::from_secs(self.router_config.approval_timeout_secs);
+            if let Some(rejection) = self.approvals.hold(server_name, session, &message, timeout).await {
+                return Ok(rejection);
+            }
+        }
+
+        if let Some(rejection) = policy::tool_rejection(server_name, &route.tools, &message) {
+            debug!("Call to a blocked tool of {}", server_name);
+            return Ok(rejection);
+        }
+        if let Some(rejection) = route.rate.as_ref().and_then(|rate| rate.rejection(&message)) {
+            debug!("Request to {} over its rate limit", server_name);
+            return Ok(rejection);
+        }
+
+        // Retries of a tool call with an idempotency key share its response
+        let context = middleware::Context { session, server: server_name };
+        let dedup = envelope
+            .as_ref()
+            .filter(|_| is_request && is_tool_call)
+            .and_then(|e| Some((idempotency::key(e)?, e.id.map(|id| id.get().to_string()))))
+            .and_then(|(key, id)| Some((self.idempotency.entry(session, server_name, &key)?, key, id)));
+
+        let response = match dedup {
+            Some((entry, key, id)) => {
+                let mut executed = false;
+                let recorded = entry
+                    .response
+                    .get_or_try_init(|| {
+                        executed = true;
+                        let forwarded = self.forward(&route, context, message, is_request, is_tool_call, priority);
+                        // Only the backend's answer is recorded; after the hub's own
+                        // error (a timeout, say) a retry runs the call again
+                        async move {
+                            let reply = forwarded.await.map_err(Err)?;
+                            if reply.from_hub {
+                                return Err(Ok(reply.line));
+                            }
+                            Ok(reply.line)
+                        }
+                    })
+                    .await;
+                match recorded {
+                    Ok(response) if executed => response.clone(),
+                    Ok(response) => {
+                        debug!("Answering a retry of idempotency key {} for {} with the recorded response", key, server_name);
+                        idempotency::readdress(response, id.as_deref())
+                    }
+                    Err(unrecorded) => {
+                        self.idempotency.forget(&entry);
+                        unrecorded?
+                    }
+                }
+            }
+            None => self.forward(&route, context, message, is_request, is_tool_call, priority).await?.line,
+        };
+
+        // Clients learn they're talking through the hub
+        let mut response = response;
+        if let Some(mut init) = init {
+            let mut message = serde_json::from_slice::<Value>(&response).ok();
+            *init = message.as_mut().and_then(|m| m.get_mut("result")).map(|result| {
+                identity::advertise(result);
+                result.clone()
+            });
+            if let (Some(message), Some(_)) = (message, init.as_ref()) {
+                let mut line = serde_json::to_vec(&message)?;
+                line.push(b'\n');
+                response = Bytes::from(line);
+                if let Some(handshake) = handshake {
+                    self.handshakes.insert(server_name.to_string(), handshake);
+                }
+            }
+        }
+
+        if let Some(method) = observed {
+            self.observe_response(server_name, method, &response).await;
+        }
+        if is_tool_list {
+            response = policy::filter_tools(&route.tools, response);
+        }
+
+        Ok(response)
+    }
+
+    /// Send a message to a backend under the concurrency limits, applying its
+    /// rewrite rules (to the message and its response) and response size limit
+    async fn forward(
+        &self,
+        route: &Route,
+        context: middleware::Context<'_>,
+        message: Bytes,
+        is_request: bool,
+        is_tool_call: bool,
+        priority: Priority,
+    ) -> Result<Reply> {
+        let server_name = context.server;
+
+        // Only requests count against the concurrency limits; notifications and
+        // client responses to server requests always go through
+        let mut permits = Vec::new();
+        if is_request {
+            let queue_timeout = std::time::Duration::from_millis(self.router_config.queue_timeout_ms);
+            for limit in [&self.limit, &route.limit].into_iter().flatten() {
+                permits.push(limit.acquire(queue_timeout, priority).await?);
+            }
+        }
+
+        let response_rules = rewrite::response_rules(&route.rewrite, server_name, &message);
+        let message = rewrite::apply(&route.rewrite, server_name, message);
+        let Reply { line, from_hub } = route.connection.exchange(message, priority, context.session).await?;
+        drop(permits);
+        let mut response = rewrite::apply_response(&response_rules, line);
+
+        if let (true, Some((max_bytes, strategy))) = (is_tool_call, route.response_limit) {
+            response = size::enforce(server_name, response, max_bytes, strategy);
+        }
+        Ok(Reply { line: response, from_hub })
+    }
+
+    /// The error answering a call to a write tool of a read-only server: one
+    /// matching a write pattern, or annotated as destructive in the server's
+    /// tool list (fetched if the catalog doesn't have the tool)
+    async fn write_rejection(&self, route: &Route, server_name: &str, message: &[u8]) -> Option<Bytes> {
+        let tool = policy::tool_name(server_name, message)?;
+        if route.tools.writes(&tool) || tools::matches_any(&self.router_config.write_tools, &tool) {
+            return policy::write_rejection(server_name, &tool, message);
+        }
+        let named = |list: &Value| list.as_array()?.iter().find(|t| t.get("name").and_then(Value::as_str) == Some(tool.as_str())).cloned();
+        let cached = self.catalog.lock().await.get(server_name, "tools/list").and_then(|result| named(result.get("tools")?));
+        let definition = match cached {
+            Some(definition) => Some(definition),
+            None => Box::pin(self.tools(server_name)).await.ok().and_then(|tools| named(&Value::Array(tools))),
+        };
+        definition.filter(policy::is_destructive).and_then(|_| policy::write_rejection(server_name, &tool, message))
+    }
+
+    /// Start a lazy or idle-stopped server for a message that needs it,
+    /// waiting up to its spawn timeout; None if the server doesn't start on demand
+    ///
+    /// The start runs in its own task, so a request that gives up waiting
+    /// doesn't cancel it halfway and later requests find the server up.
+    async fn spawn_on_demand(&self, name: &str) -> Result<Option<Route>> {
+        let config = self.configs.lock().await.iter().find(|c| c.name == name).cloned();
+        let Some(config) = config.filter(|c| c.starts_on_demand() || self.parked.contains(&c.name)) else {
+            return Ok(None);
+        };
+        if self.is_disabled(name) {
+            anyhow::bail!("Server {} is stopped (start it with `mcp-citadel server start {}`)", name, name);
+        }
+        self.start_dependencies(&config).await.context(format!("Failed to start server {}", name))?;
+        let timeout = spawn_timeout(&config);
+        let (servers, spawning, parked, down) = (Arc::clone(&self.servers), Arc::clone(&self.spawning), Arc::clone(&self.parked), Arc::clone(&self.down));
+        let handshake = self.handshake(name);
+        let (events, lifecycle) = (self.events.clone(), self.lifecycle.clone());
+        let auto_provision = self.router_config.auto_provision;
+
+        let start = tokio::spawn(async move {
+            // Concurrent first requests wait here for the one that starts it
+            let mut slot = servers.lock(&config.name).await;
+            if slot.is_none() {
+                info!("Starting server {} on demand", config.name);
+                spawning.insert(config.name.clone());
+                let started = start_replacement(&config, &events, auto_provision, handshake.as_ref()).await;
+                spawning.remove(&config.name);
+                *slot = Some(started?);
+                parked.remove(&config.name);
+                down.remove(&config.name);
+                let _ = lifecycle.send(HubEvent::Started { server: config.name.clone() });
+            }
+            Ok::<_, anyhow::Error>(slot.as_ref().map(MCPServerProcess::route))
+        });
+        match tokio::time::timeout(timeout, start).await {
+            Ok(started) => started?.context(format!("Failed to start server {}", name)),
+            Err(_) => anyhow::bail!("Server {} is still starting (not up within {}s)", name, timeout.as_secs()),
+        }
+    }
+
+    /// Start the dependencies of a server that aren't running yet and wait
+    /// for all of them to answer
+    async fn start_dependencies(&self, config: &ServerConfig) -> Result<()> {
+        for dependency in &config.depends_on {
+            let mut route = self.servers.with(dependency, MCPServerProcess::route).await;
+            if route.is_none() {
+                route = Box::pin(self.spawn_on_demand(dependency)).await?;
+            }
+            let route = route.context(format!("its dependency {} isn't running", dependency))?;
+            dependencies::ready(dependency, &route.connection, spawn_timeout(&self.config(dependency).await?)).await?;
+        }
+        Ok(())
+    }
+
+    /// Restart the running servers that depend on `name`, directly or not,
+    /// each once its own dependencies answer again
+    async fn restart_dependents(&self, name: &str) {
+        let configs = self.configs.lock().await.clone();
+        for dependent in dependencies::dependents(&configs, name) {
+            if self.servers.with(&dependent, |_| ()).await.is_none() {
+                continue;
+            }
+            let Some(config) = configs.iter().find(|c| c.name == dependent) else {
+                continue;
+            };
+            if let Err(e) = self.start_dependencies(config).await {
+                error!("Not restarting server {} after {} restarted: {:#}", dependent, name, e);
+                continue;
+            }
+            info!("Restarting server {} since its dependency {} restarted", dependent, name);
+            if let Err(e) = self.restart_one(&dependent, "dependency").await {
+                error!("Failed to restart server {}: {:#}", dependent, e);
+            }
+        }
+    }
+
+    /// A session's own process of a per-session server, started for its first
+    /// message (or again, if it exited)
+    async fn pinned_route(&self, session: &str, server_name: &str) -> Result<Route> {
+        let instance = self.pinned.instance(session, server_name);
+        let mut instance = instance.lock().await;
+        let mut handshake = None;
+        if let Some(server) = instance.as_mut() {
+            if matches!(server.backend.try_wait(), Ok(None)) {
+                return Ok(server.route());
+            }
+            warn!("The {} process of session {} exited; starting another", server_name, session);
+            handshake = self.handshake(server_name);
+        }
+
+        // What the process sends on its own goes to this session only
+        let config = self.config(server_name).await?;
+        let (events, backend_events) = broadcast::channel(self.router_config.event_channel_capacity.max(1));
+        let server = start_replacement(&config, &events, self.router_config.auto_provision, handshake.as_ref())
+            .await
+            .context(format!("Failed to start a {} process for the session", server_name))?;
+        info!("Started a {} process for session {}", server_name, session);
+        spawn_pinned_routing(
+            backend_events,
+            session.to_string(),
+            Arc::clone(&server.connection),
+            Arc::clone(&self.capabilities),
+            Arc::clone(&self.reverse),
+            self.router_config.capability_shaping,
+        );
+        let route = server.route();
+        *instance = Some(server);
+        Ok(route)
+    }
+
+    /// The client handshake a new process of a server is initialized with
+    fn handshake(&self, name: &str) -> Option<Value> {
+        self.handshakes.get(name).map(|params| params.clone())
+    }
+
+    /// Generation of a server's running process
+    async fn backend_generation(&self, server_name: &str) -> Option<u64> {
+        self.servers.with(server_name, |s| s.init.generation).await
+    }
+
+    /// Generation of a server's process, starting it first if it starts on
+    /// demand, so a session's handshake is replayed to a respawned server
+    async fn generation_on_demand(&self, server_name: &str) -> Option<u64> {
+        match self.backend_generation(server_name).await {
+            Some(generation) => Some(generation),
+            None => Some(self.spawn_on_demand(server_name).await.ok()??.init.generation),
+        }
+    }
+
+    /// Bring a replaced backend process up to date with a session: its
+    /// handshake (answered from the process's own if another client already
+    /// initialized it) and its subscriptions
+    async fn replay_session(&self, server_name: &str, replay: Replay) -> Result<()> {
+        info!("Replaying a client session (handshake, {} subscriptions) to the new {} process", replay.subscriptions.len(), server_name);
+        self.request(server_name, "initialize", replay.initialize).await?;
+        self.route_message(
+            server_name,
+            Bytes::from_static(b"{\"jsonrpc\":\"2.0\",\"method\":\"notifications/initialized\"}\n"),
+        )
+        .await?;
+        for uri in replay.subscriptions {
+            self.request(server_name, "resources/subscribe", serde_json::json!({ "uri": uri })).await?;
+        }
+        Ok(())
+    }
+
+    /// Release what a closed client connection held at its backends
+    async fn close_session(&self, session: &ClientSession) {
+        for (server_name, generation, uris) in session.subscriptions() {
+            // The session's own process stops with it
+            if self.pinned.is_pinned(&server_name) {
+                continue;
+            }
+            // A replaced process never saw these subscriptions
+            if self.backend_generation(&server_name).await != Some(generation) {
+                continue;
+            }
+            for uri in uris {
+                let unsubscribe = self.request(&server_name, "resources/unsubscribe", serde_json::json!({ "uri": uri }));
+                if let Err(e) = tokio::time::timeout(CLEANUP_TIMEOUT, unsubscribe).await.unwrap_or_else(|_| Err(anyhow::anyhow!("timed out"))) {
+                    debug!("Failed to unsubscribe {} from {} for a closed client: {}", uri, server_name, e);
+                }
+            }
+        }
+    }
+
+    /// Forget an ended client session: what its client declared, the backend
+    /// requests waiting on it, and its own processes of per-session servers,
+    /// which stop
+    pub fn end_session(&self, session: &str) {
+        self.capabilities.remove(session);
+        self.reverse.close(session);
+        let (pinned, session) = (Arc::clone(&self.pinned), session.to_string());
+        tokio::spawn(async move { pinned.release(&session).await });
+    }
+
+    /// Send a session's response to a request a backend sent it back to that
+    /// backend; None if the message isn't one
+    pub async fn route_client_response(&self, session: &str, message: &[u8]) -> Option<Result<Bytes>> {
+        let (server_name, message) = self.reverse.response(session, message)?;
+        Some(self.route_session_message(Some(session), Priority::Normal, &server_name, message).await)
+    }
+
+    /// Send a hub-originated JSON-RPC request to a server and return its result
+    pub async fn request(&self, server_name: &str, method: &str, params: Value) -> Result<Value> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        });
+        let response = self
+            .route_message(server_name, Bytes::from(serde_json::to_vec(&request)?))
+            .await?;
+
+        let mut response: Value = serde_json::from_slice(&response)
+            .context(format!("Invalid {} response from {}", method, server_name))?;
+        if let Some(error) = response.get("error") {
+            anyhow::bail!(
+                "{} failed on {}: {}",
+                method,
+                server_name,
+                error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error")
+            );
+        }
+        response
+            .get_mut("result")
+            .map(Value::take)
+            .context(format!("{} response from {} has no result", method, server_name))
+    }
+
+    /// All tools a server offers, following `nextCursor` pagination
+    pub async fn tools(&self, server_name: &str) -> Result<Vec<Value>> {
+        self.list_all(server_name, "tools/list", "tools").await
+    }
+
+    /// All prompts a server offers, following `nextCursor` pagination
+    pub async fn prompts(&self, server_name: &str) -> Result<Vec<Value>> {
+        self.list_all(server_name, "prompts/list", "prompts").await
+    }
+
+    /// Every page of a list method's `field`
+    async fn list_all(&self, server_name: &str, method: &str, field: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<Value> = None;
+
+        loop {
+            let params = match &cursor {
+                Some(cursor) => serde_json::json!({ "cursor": cursor }),
+                None => serde_json::json!({}),
+            };
+            let mut result = self.request(server_name, method, params).await?;
+
+            if let Some(Value::Array(page)) = result.get_mut(field).map(Value::take) {
+                items.extend(page);
+            }
+            match result.get("nextCursor") {
+                Some(next) if !next.is_null() => cursor = Some(next.clone()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Build a response for a list request from the catalog cache
+    ///
+    /// `fresh` only takes a result fetched from the running backend within
+    /// the list's TTL.
+    async fn cached_list_response(&self, server_name: &str, method: &str, request: &Envelope<'_>, fresh: bool) -> Option<Bytes> {
+        if method == "initialize" {
+            return None;
+        }
+        let ttl = {
+            let configs = self.configs.lock().await;
+            let config = configs.iter().find(|c| c.name == server_name)?;
+            self.router_config.list_cache_ttl(config, method)
+        };
+        if fresh && ttl.is_zero() {
+            return None;
+        }
+
+        let id: Value = serde_json::from_str(request.id?.get()).ok()?;
+        let catalog = self.catalog.lock().await;
+        let result = match fresh {
+            true => catalog.fresh(server_name, method, ttl)?,
+            false => catalog.get(server_name, method)?,
+        };
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        });
+
+        let mut bytes = serde_json::to_vec(&response).ok()?;
+        bytes.push(b'\n');
+        Some(Bytes::from(bytes))
+    }
+
+    /// Update the catalog cache from a backend response
+    async fn observe_response(&self, server_name: &str, method: &str, response: &[u8]) {
+        let Ok(value) = serde_json::from_slice::<Value>(response) else {
+            return;
+        };
+
+        let Some(result) = value.get("result") else {
+            return;
+        };
+
+        if method == "initialize" {
+            let version = result
+                .get("serverInfo")
+                .and_then(|i| i.get("version"))
+                .and_then(|v| v.as_str())
+                .map(String::from);
+            self.catalog.lock().await.record_version(server_name, version);
+        } else {
+            self.catalog
+                .lock()
+                .await
+                .store(server_name, method, result.clone());
+        }
+    }
+
+    /// List all servers: the running ones and those that start on demand
+    pub async fn list_servers(&self) -> Vec<String> {
+        let configs = self.configs.lock().await.clone();
+        let running = self.servers.running().await;
+        configs
+            .into_iter()
+            .filter(|c| {
+                let on_demand = c.starts_on_demand() || self.parked.contains(&c.name);
+                (on_demand && !self.is_disabled(&c.name)) || running.contains(&c.name)
+            })
+            .map(|c| c.name)
+            .collect()
+    }
+
+    /// Whether a server was stopped with `mcp-citadel server stop`, so nothing
+    /// starts it until `server start`
+    fn is_disabled(&self, name: &str) -> bool {
+        self.down.get(name).is_some_and(|state| *state == ServerState::Disabled)
+    }
+
+    /// Stop all servers
+    pub async fn stop_all(&self) -> Result<()> {
+        for name in self.servers.names() {
+            if let Some(server) = self.servers.lock(&name).await.as_mut() {
+                if let Err(e) = server.stop().await {
+                    error!("Error stopping server: {}", e);
+                }
+            }
+        }
+        self.projects.stop_all().await;
+        Ok(())
+    }
+
+    /// Check health of all servers and restart crashed or hung ones, and ones
+    /// over their memory limit
+    pub async fn health_check(&self) -> Result<()> {
+        // Snapshot configs before taking any server's slot (reload takes them in that order)
+        let configs = self.configs.lock().await.clone();
+        let mut failed_checks = self.run_health_checks(&configs).await;
+        for config in configs.iter().filter(|c| c.limits.memory_mb.is_some() && c.container.is_none()) {
+            let Some(pid) = self.servers.with(&config.name, |s| s.backend.pid()).await.flatten() else {
+                continue;
+            };
+            if let Some(reason) = resources::over_memory(pid, &config.limits) {
+                failed_checks.entry(config.name.clone()).or_insert(reason);
+            }
+        }
+        // Side by side, so a server waiting out its backoff holds up no other
+        futures::future::join_all(configs.iter().map(|config| self.check_server(config, failed_checks.get(&config.name)))).await;
+        Ok(())
+    }
+
+    /// Check one server, restarting it as its restart policy says if it
+    /// exited, hung, or failed its health check
+    async fn check_server(&self, config: &ServerConfig, failed_check: Option<&String>) {
+        let Some(mut slot) = self.servers.lock_existing(&config.name).await else {
+            return;
+        };
+        let Some(server) = slot.as_mut() else {
+            return;
+        };
+        let restarts = &self.router_config.restarts;
+        let policy = config.restart_policy.unwrap_or(restarts.policy);
+        let max_restarts = config.max_restarts.unwrap_or(restarts.max_attempts);
+        let count = self.restart_counts.lock().await.get(&config.name).copied().unwrap_or(0);
+
+        // Check if process is still alive
+        let mut exit_status = None;
+        let reason = match server.backend.try_wait() {
+            Ok(Some(status)) => {
+                let uptime = server.start_time.elapsed();
+                
+                // Immediate crash detection (< 5 seconds)
+                let is_immediate_crash = uptime.as_secs() < 5;
+                let failure = format!("exited after {:.1}s with {}", uptime.as_secs_f32(), status);
+                self.alert(AlertKind::Crashed, server, &failure, Some(status.to_string()));
+                self.emit(HubEvent::Failed {
+                    server: config.name.clone(),
+                    reason: failure,
+                });
+                exit_status = Some(status.to_string());
+                if let Some(hooks) = server.hooks.clone() {
+                    let vars = [("MCP_CITADEL_EXIT_STATUS", status.to_string())];
+                    tokio::spawn(async move { hooks.run_logged(Hook::OnCrash, &vars).await });
+                }
+                
+                if is_immediate_crash {
+                    error!(
+                        "Server {} crashed immediately ({:.1}s uptime) with status: {:?}",
+                        config.name, uptime.as_secs_f32(), status
+                    );
+                    error!("This usually means:");
+                    error!("  • Wrong command or arguments in Claude config");
+                    error!("  • Missing dependencies (pin them with `mcp-citadel install`, or enable router.auto_provision)");
+                    error!("  • Incompatible CLI version");
+                    error!("Command: {} {:?}", config.command, config.args);
+                    
+                    // Don't retry immediate crashes - they're config errors
+                    *slot = None;
+                    return;
+                }
+                
+                warn!("Server {} exited after {:.1}s with status: {:?}", config.name, uptime.as_secs_f32(), status);
+                if !restart::restarts(policy, Some(&status)) {
+                    info!("Leaving server {} stopped (restart policy {:?})", config.name, policy);
+                    *slot = None;
+                    return;
+                }
+                "crashed"
+            }
+            Ok(None) if server.connection.is_unresponsive() || failed_check.is_some() => {
+                let reason = match failed_check {
+                    Some(failure) => {
+                        warn!("Server {} is alive but failed its health check: {}", config.name, failure);
+                        format!("health check failed: {}", failure)
+                    }
+                    None => {
+                        warn!(
+                            "Server {} is alive but unresponsive ({} consecutive read timeouts)",
+                            config.name, server.connection.consecutive_timeouts()
+                        );
+                        "unresponsive".to_string()
+                    }
+                };
+                self.alert(AlertKind::Unresponsive, server, &reason, None);
+                self.emit(HubEvent::Failed {
+                    server: config.name.clone(),
+                    reason,
+                });
+                if let Err(e) = server.stop().await {
+                    error!("Error stopping unresponsive server {}: {}", config.name, e);
+                }
+                if !restart::restarts(policy, None) {
+                    info!("Leaving server {} stopped (restart policy {:?})", config.name, policy);
+                    *slot = None;
+                    return;
+                }
+                if failed_check.is_some() { "health_check" } else { "unresponsive" }
+            }
+            Ok(None) => {
+                // Still running; once it has stayed up through the cool-down its attempts start over
+                if count > 0 && server.start_time.elapsed() >= std::time::Duration::from_secs(restarts.cooldown_secs) {
+                    self.restart_counts.lock().await.insert(config.name.clone(), 0);
+                }
+                return;
+            }
+            Err(e) => {
+                error!("Error checking server {}: {}", config.name, e);
+                return;
+            }
+        };
+
+        if count >= max_restarts {
+            error!(
+                "Server {} has failed {} times in a row. Giving up. Check your Claude config.",
+                config.name, count
+            );
+            if let Some(server) = slot.as_ref() {
+                self.alert(AlertKind::GaveUp, server, &format!("failed {} times in a row", count + 1), exit_status);
+            }
+            *slot = None;
+            return;
+        }
+        let attempt = count + 1;
+        self.restart_counts.lock().await.insert(config.name.clone(), attempt);
+
+        // Requests to the server fail fast instead of queueing while it waits out its backoff
+        *slot = None;
+        drop(slot);
+        let delay = restart::backoff(restarts, attempt);
+        info!("Restarting server: {} in {:.1}s (attempt {}/{})", config.name, delay.as_secs_f32(), attempt, max_restarts);
+        self.down.insert(config.name.clone(), ServerState::Restarting);
+        tokio::time::sleep(delay).await;
+
+        // Unless it was removed, or started by someone else, in the meantime
+        let Some(mut slot) = self.servers.lock_existing(&config.name).await else {
+            return;
+        };
+        if slot.is_some() {
+            return;
+        }
+        let handshake = self.handshake(&config.name);
+        let restarted = restart_server(&mut slot, config, &self.events, self.router_config.auto_provision, handshake.as_ref()).await;
+        drop(slot);
+        if restarted {
+            self.emit(HubEvent::Restarted { server: config.name.clone(), reason: reason.to_string() });
+            self.restart_dependents(&config.name).await;
+        } else {
+            self.down.insert(config.name.clone(), ServerState::Crashed);
+        }
+    }
+
+    /// Run the custom health checks and pings of running servers concurrently,
+    /// returning the failures by server; a missed ping only counts as one once
+    /// the server has missed `probe_failures` in a row
+    async fn run_health_checks(&self, configs: &[ServerConfig]) -> HashMap<String, String> {
+        let restarts = &self.router_config.restarts;
+        let probe_timeout = std::time::Duration::from_millis(restarts.probe_timeout_ms);
+        let mut checks = Vec::new();
+        for config in configs {
+            let probed = match &config.health_check {
+                Some(HealthCheck::Method { .. }) => false,
+                _ => restarts.probe_timeout_ms > 0,
+            };
+            if config.health_check.is_none() && !probed {
+                continue;
+            }
+            if let Some((connection, generation)) = self.servers.with(&config.name, |s| (Arc::clone(&s.connection), s.init.generation)).await {
+                checks.push((config, probed, connection, generation));
+            }
+        }
+
+        let results = futures::future::join_all(checks.into_iter().map(|(config, probed, connection, generation)| async move {
+            let checked = match &config.health_check {
+                Some(check) => health::run(check, config, &connection).await,
+                None => Ok(()),
+            };
+            let pinged = match probed {
+                true => Some(health::ping(&connection, probe_timeout).await),
+                false => None,
+            };
+            (config, generation, checked, pinged)
+        }))
+        .await;
+
+        let mut failures = HashMap::new();
+        for (config, generation, checked, pinged) in results {
+            if let Err(e) = checked {
+                failures.insert(config.name.clone(), format!("{:#}", e));
+            }
+            match pinged {
+                Some(Err(e)) => {
+                    let mut missed = self.missed_pings.entry(config.name.clone()).or_insert((generation, 0));
+                    if missed.0 != generation {
+                        *missed = (generation, 0);
+                    }
+                    missed.1 += 1;
+                    let count = missed.1;
+                    drop(missed);
+                    if count >= restarts.probe_failures.max(1) {
+                        self.missed_pings.remove(&config.name);
+                        failures.entry(config.name.clone()).or_insert(format!("{:#} ({} pings in a row)", e, count));
+                    } else {
+                        warn!("Server {} missed a ping ({}/{}): {:#}", config.name, count, restarts.probe_failures, e);
+                    }
+                }
+                Some(Ok(())) => {
+                    self.missed_pings.remove(&config.name);
+                }
+                None => {}
+            }
+        }
+        failures
+    }
+
+    /// Whether a server's process is missing pings or timing out requests
+    fn is_unresponsive(&self, server: &MCPServerProcess, name: &str) -> bool {
+        let missed = self.missed_pings.get(name).is_some_and(|m| m.0 == server.init.generation && m.1 > 0);
+        missed || server.connection.is_unresponsive()
+    }
+
+    /// Silence after which keepalive clients are pinged
+    fn client_keepalive(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.router_config.client_keepalive_secs)
+    }
+
+    /// Token usage of sampling exchanges so far
+    pub fn usage(&self) -> usage::UsageReport {
+        self.usage.report()
+    }
+
+    /// Connected `mcp-client` adapters
+    pub fn clients(&self) -> &Arc<ClientRegistry> {
+        &self.clients
+    }
+
+    /// Client session transcripts
+    pub fn transcripts(&self) -> &Transcripts {
+        &self.transcripts
+    }
+
+    /// The last lines a running server wrote to stderr
+    pub async fn server_stderr(&self, name: &str) -> Option<Vec<String>> {
+        self.servers
+            .with(name, |server| match &server.backend {
+                Backend::Process { stderr, .. } => Some(stderr.lines()),
+                Backend::Mock(_) | Backend::Remote(_) => None,
+            })
+            .await
+            .flatten()
+    }
+
+    /// Live state of every configured server
+    pub async fn server_info(&self) -> Vec<ServerInfo> {
+        let configs = self.configs.lock().await.clone();
+        // Copied so no slot is waited on while holding the counts
+        let restart_counts = self.restart_counts.lock().await.clone();
+
+        let mut infos = Vec::with_capacity(configs.len());
+        for config in &configs {
+            // A lazy server's slot is held while it starts
+            if self.spawning.contains(&config.name) {
+                infos.push(ServerInfo {
+                    name: config.name.clone(),
+                    running: false,
+                    state: ServerState::Initializing,
+                    pid: None,
+                    command_path: None,
+                    url: config.remote.as_ref().map(|r| r.url.clone()),
+                    uptime_secs: None,
+                    restarts: restart_counts.get(&config.name).copied().unwrap_or(0),
+                    in_flight: 0,
+                    requests: 0,
+                    errors: 0,
+                    consecutive_timeouts: 0,
+                    next_restart: None,
+                    pinned: 0,
+                    last_error: self.last_error(&config.name),
+                });
+                continue;
+            }
+            let mut slot = self.servers.lock_existing(&config.name).await;
+            let server = slot
+                .as_deref_mut()
+                .and_then(Option::as_mut)
+                .and_then(|s| matches!(s.backend.try_wait(), Ok(None)).then_some(&*s));
+            infos.push(ServerInfo {
+                name: config.name.clone(),
+                running: server.is_some(),
+                state: match server {
+                    Some(s) if self.is_unresponsive(s, &config.name) => ServerState::Unresponsive,
+                    Some(_) => ServerState::Running,
+                    None => match self.down.get(&config.name) {
+                        Some(down) => *down,
+                        None if config.starts_on_demand() || self.parked.contains(&config.name) => ServerState::Idle,
+                        None => ServerState::Stopped,
+                    },
+                },
+                pid: server.as_ref().and_then(|s| s.backend.pid()),
+                command_path: server.as_ref().and_then(|s| s.command_path.clone()),
+                url: config.remote.as_ref().map(|r| r.url.clone()),
+                uptime_secs: server.as_ref().map(|s| s.start_time.elapsed().as_secs()),
+                restarts: restart_counts.get(&config.name).copied().unwrap_or(0),
+                in_flight: server.as_ref().map_or(0, |s| s.connection.in_flight()),
+                requests: server.as_ref().map_or(0, |s| s.connection.requests_sent()),
+                errors: server.as_ref().map_or(0, |s| s.connection.errors()),
+                consecutive_timeouts: server.as_ref().map_or(0, |s| s.connection.consecutive_timeouts()),
+                next_restart: server
+                    .as_ref()
+                    .zip(config.restart_schedule.as_ref())
+                    .and_then(|(s, schedule)| schedule.next_restart(s.started_at()))
+                .map(|t| t.to_rfc3339()),
+                pinned: self.pinned.count(&config.name),
+                last_error: self.last_error(&config.name),
+            });
+        }
+        infos
+    }
+
+    /// Start a configured server that is not running
+    pub async fn start_server(&self, name: &str) -> Result<()> {
+        let config = self.config(name).await?;
+        let mut slot = self.servers.lock(name).await;
+        if let Some(server) = slot.as_mut() {
+            if matches!(server.backend.try_wait(), Ok(None)) {
+                anyhow::bail!("Server {} is already running", name);
+            }
+        }
+
+        *slot = Some(start_server(&config, &self.events, self.router_config.auto_provision).await?);
+        drop(slot);
+        self.parked.remove(name);
+        self.restart_counts.lock().await.remove(name);
+        self.emit(HubEvent::Started { server: name.to_string() });
+        Ok(())
+    }
+
+    /// Turn a server's debug transcript on or off, for its running instance
+    /// and the ones that replace it; returns the transcript file
+    pub async fn set_debug_transcript(&self, name: &str, enabled: bool) -> Result<PathBuf> {
+        let mut configs = self.configs.lock().await;
+        let config = configs
+            .iter_mut()
+            .find(|c| c.name == name)
+            .context(format!("Unknown server: {}", name))?;
+        config.debug_transcript = enabled;
+        self.servers.with(name, |server| server.debug.set_enabled(enabled)).await;
+        info!("Debug transcript of {} {}", name, if enabled { "on" } else { "off" });
+        Ok(debug::path(name))
+    }
+
+    /// Stop a server; neither the health check nor requests start it again
+    /// until it is started with `start_server`
+    pub async fn stop_server(&self, name: &str) -> Result<()> {
+        let config = self.config(name).await?;
+        let server = match self.servers.lock_existing(name).await {
+            Some(mut slot) => slot.take(),
+            None => None,
+        };
+        let Some(mut server) = server else {
+            // Stopped for idling, or not started yet: now it stays stopped
+            if self.parked.remove(name).is_some() || config.starts_on_demand() {
+                self.pinned.release_server(name).await;
+                self.down.insert(name.to_string(), ServerState::Disabled);
+                return Ok(());
+            }
+            anyhow::bail!("Server {} is not running", name);
+        };
+        server.stop().await?;
+        self.emit(HubEvent::Stopped { server: name.to_string() });
+        self.down.insert(name.to_string(), ServerState::Disabled);
+        Ok(())
+    }
+
+    /// Stop a server if it is running and start it again
+    pub async fn restart_server(&self, name: &str) -> Result<()> {
+        self.restart_for(name, "requested").await
+    }
+
+    /// Restart a server, and then the servers that depend on it
+    async fn restart_for(&self, name: &str, reason: &str) -> Result<()> {
+        self.restart_one(name, reason).await?;
+        self.restart_dependents(name).await;
+        Ok(())
+    }
+
+    async fn restart_one(&self, name: &str, reason: &str) -> Result<()> {
+        let config = self.config(name).await?;
+        if config.warm_restart == Some(true) {
+            self.warm_replace(&config).await?;
+        } else {
+            let mut slot = self.servers.lock(name).await;
+            if let Some(mut server) = slot.take() {
+                if let Err(e) = server.stop().await {
+                    warn!("Error stopping server {}: {}", name, e);
+                }
+            }
+
+            let handshake = self.handshake(name);
+            *slot = Some(start_replacement(&config, &self.events, self.router_config.auto_provision, handshake.as_ref()).await?);
+        }
+        self.parked.remove(name);
+        self.restart_counts.lock().await.remove(name);
+        self.emit(HubEvent::Restarted { server: name.to_string(), reason: reason.to_string() });
+        Ok(())
+    }
+
+    /// Start a standby instance, wait for its initialize handshake, switch
+    /// routing to it, and only then stop the old instance
+    async fn warm_replace(&self, config: &ServerConfig) -> Result<()> {
+        let mut standby = start_server(config, &self.events, self.router_config.auto_provision).await?;
+        match initialize_instance(&standby, self.handshake(&config.name).as_ref()).await {
+            Ok(()) => warm_up(&standby, &config.warm_up).await,
+            Err(e) => {
+                if let Err(e) = standby.stop().await {
+                    warn!("Error stopping standby for {}: {}", config.name, e);
+                }
+                return Err(e.context(format!("Standby for {} failed its handshake, keeping the running instance", config.name)));
+            }
+        }
+
+        let old = self.servers.lock(&config.name).await.replace(standby);
+        info!("Switched {} to its standby instance", config.name);
+
+        if let Some(mut old) = old {
+            // Requests already sent to the old instance still get their answers
+            let deadline = tokio::time::Instant::now() + STANDBY_DRAIN_TIMEOUT;
+            while old.connection.in_flight() > 0 && tokio::time::Instant::now() < deadline {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            if let Err(e) = old.stop().await {
+                warn!("Error stopping server {}: {}", config.name, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop servers nothing was sent to for their `idleTimeoutSecs`; each
+    /// starts again on its next request
+    pub async fn stop_idle_servers(&self) {
+        let configs = self.configs.lock().await.clone();
+        for config in &configs {
+            let Some(timeout) = config.idle_timeout_secs.filter(|&secs| secs > 0).map(std::time::Duration::from_secs) else {
+                continue;
+            };
+            let Some(mut slot) = self.servers.lock_existing(&config.name).await else {
+                continue;
+            };
+            let Some(mut server) = slot.take_if(|s| s.connection.idle_for() >= timeout) else {
+                continue;
+            };
+            // Parked before the slot frees up, so a request waiting on it starts the server again
+            self.parked.insert(config.name.clone());
+            info!("Stopping server {} after {}s idle", config.name, timeout.as_secs());
+            if let Err(e) = server.stop().await {
+                warn!("Error stopping idle server {}: {}", config.name, e);
+            }
+            self.emit(HubEvent::Stopped { server: config.name.clone() });
+        }
+    }
+
+    /// Restart servers whose `restartSchedule` is due
+    ///
+    /// A due server is restarted once it has no requests in flight, or anyway
+    /// after `MAX_RESTART_DEFERRAL` so a busy server can't put it off forever.
+    pub async fn run_restart_schedules(&self) {
+        let configs = self.configs.lock().await.clone();
+        let now = chrono::Local::now();
+
+        for config in &configs {
+            let Some(schedule) = &config.restart_schedule else {
+                continue;
+            };
+            let due = self
+                .servers
+                .with(&config.name, |server| match schedule.next_restart(server.started_at()) {
+                    Some(due) if due <= now => Some((now - due, server.connection.in_flight() == 0)),
+                    _ => None,
+                })
+                .await;
+            let Some((overdue, idle)) = due.flatten() else {
+                continue;
+            };
+
+            if !idle && overdue.to_std().unwrap_or_default() < MAX_RESTART_DEFERRAL {
+                debug!("Scheduled restart of {} waiting for in-flight requests", config.name);
+                continue;
+            }
+            info!("Scheduled restart of server {}", config.name);
+            if let Err(e) = self.restart_for(&config.name, "scheduled").await {
+                error!("Scheduled restart of {} failed: {}", config.name, e);
+            }
+        }
+    }
+
+    /// Apply a new set of server configs, starting added servers, stopping
+    /// removed ones, and restarting those whose config changed
+    pub async fn reload(&self, mut new_configs: Vec<ServerConfig>) -> Result<HubEvent> {
+        for config in &mut new_configs {
+            self.router_config.apply_defaults(config);
+        }
+        // Dependencies start before their dependents
+        let order: Vec<ServerConfig> = dependencies::start_order(&new_configs)?.into_iter().cloned().collect();
+
+        let mut configs = self.configs.lock().await;
+        let (mut added, mut removed, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+        let mut warm = Vec::new();
+
+        for old in configs.iter() {
+            if !new_configs.iter().any(|c| c.name == old.name) {
+                self.parked.remove(&old.name);
+                if let Some(mut server) = self.servers.remove(&old.name).await {
+                    if let Err(e) = server.stop().await {
+                        warn!("Error stopping server {}: {}", old.name, e);
+                    }
+                }
+                removed.push(old.name.clone());
+            }
+        }
+
+        for config in &order {
+            let mut slot = self.servers.lock(&config.name).await;
+            match configs.iter().find(|c| c.name == config.name) {
+                Some(old) if old == config => continue,
+                // Toggled without a restart
+                Some(old) if *config == (ServerConfig { debug_transcript: config.debug_transcript, ..old.clone() }) => {
+                    if let Some(server) = slot.as_ref() {
+                        server.debug.set_enabled(config.debug_transcript);
+                    }
+                    continue;
+                }
+                Some(_) if config.warm_restart == Some(true) && slot.is_some() => {
+                    changed.push(config.name.clone());
+                    warm.push(config.clone());
+                    continue;
+                }
+                Some(_) => {
+                    if let Some(mut server) = slot.take() {
+                        if let Err(e) = server.stop().await {
+                            warn!("Error stopping server {}: {}", config.name, e);
+                        }
+                    }
+                    changed.push(config.name.clone());
+                }
+                None => added.push(config.name.clone()),
+            }
+            if config.starts_on_demand() {
+                // Started (with the new config) by its next request
+                continue;
+            }
+            match start_replacement(config, &self.events, self.router_config.auto_provision, self.handshake(&config.name).as_ref()).await {
+                Ok(server) => {
+                    *slot = Some(server);
+                    self.parked.remove(&config.name);
+                }
+                Err(e) => error!("Failed to start server {}: {}", config.name, e),
+            }
+        }
+
+        *configs = new_configs;
+        self.pinned.configure(&configs);
+        self.catalog.lock().await.configure(&configs);
+        crate::redact::configure(&self.router_config.redaction, &configs);
+        drop(configs);
 
-/// Managed MCP server process
-pub struct MCPServerProcess {
-    name: String,
-    process: Child,
-    stdin: ChildStdin,
-    stdout: BufReader<ChildStdout>,
-    stderr: BufReader<ChildStderr>,
-    start_time: std::time::Instant,
-}
+        // Sessions start their own processes again, with the new config
+        for name in removed.iter().chain(&changed) {
+            self.pinned.release_server(name).await;
+        }
 
-impl MCPServerProcess {
-    /// Start an MCP server process
-    pub async fn start(config: ServerConfig) -> Result<Self> {
-        info!("Starting MCP server: {}", config.name);
-        debug!(
-            "Command: {} {:?}",
-            config.command,
-            config.args
+        // Keep routing to the old instances while their replacements start
+        for config in &warm {
+            if let Err(e) = self.warm_replace(config).await {
+                error!("Failed to restart server {}: {:#}", config.name, e);
+            }
+        }
+
+        info!(
+            "Reloaded servers: {} added, {} removed, {} changed",
+            added.len(),
+            removed.len(),
+            changed.len()
         );
+        let event = HubEvent::Reloaded { added, removed, changed };
+        self.emit(event.clone());
+        Ok(event)
+    }
 
-        let mut cmd = Command::new(&config.command);
-        
-        // Inherit parent environment and merge with config env
-        // This ensures servers have access to PATH, HOME, etc.
-        let mut merged_env: HashMap<String, String> = std::env::vars().collect();
-        merged_env.extend(config.env.clone());
-        
-        cmd.args(&config.args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .env_clear()
-            .envs(&merged_env);
+    /// Subscribe to server lifecycle changes, new sessions, and failed requests
+    pub fn lifecycle_events(&self) -> broadcast::Receiver<HubEvent> {
+        self.lifecycle.subscribe()
+    }
 
-        let mut process = cmd
-            .spawn()
-            .context(format!("Failed to spawn server: {}", config.name))?;
+    /// The latest lifecycle events, oldest first
+    pub fn recent_events(&self) -> Vec<Value> {
+        self.recent_events.list()
+    }
 
-        let stdin = process
-            .stdin
-            .take()
-            .context("Failed to get stdin")?;
+    /// Tell event subscribers a client connected over `transport`
+    pub fn session_created(&self, session: &str, transport: &str) {
+        let _ = self.lifecycle.send(HubEvent::SessionCreated { session: session.to_string(), transport: transport.to_string() });
+    }
 
-        let stdout = process
-            .stdout
-            .take()
-            .context("Failed to get stdout")?;
-        
-        let stderr = process
-            .stderr
-            .take()
-            .context("Failed to get stderr")?;
+    /// Tell event subscribers an HTTP session timed out
+    pub fn session_expired(&self, session: &str) {
+        let _ = self.lifecycle.send(HubEvent::SessionExpired { session: session.to_string() });
+    }
 
-        let stdout = BufReader::new(stdout);
-        let stderr = BufReader::new(stderr);
+    /// Record where a transport listens, for summaries
+    pub fn register_endpoint(&self, kind: &str, address: String) {
+        self.endpoints.lock().unwrap().insert(kind.to_string(), address);
+    }
 
-        info!("✓ Started MCP server: {} (PID: {:?})", config.name, process.id());
-        
-        let mut server = Self {
-            name: config.name.clone(),
-            process,
-            stdin,
-            stdout,
-            stderr,
-            start_time: std::time::Instant::now(),
-        };
-        
-        // Wait 100ms and check if it immediately crashed
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        
-        if let Ok(Some(status)) = server.process.try_wait() {
-            // Read any error output
-            let mut error_msg = String::new();
-            let _ = server.stderr.read_line(&mut error_msg).await;
-            
-            warn!("Server {} crashed during startup: {:?}", config.name, status);
-            if !error_msg.is_empty() {
-                warn!("Error output: {}", error_msg.trim());
+    /// Where the transports listen
+    pub fn endpoints(&self) -> BTreeMap<String, String> {
+        self.endpoints.lock().unwrap().clone()
+    }
+
+    /// Maintenance mode of the hub
+    pub fn maintenance(&self) -> &Maintenance {
+        &self.maintenance
+    }
+
+    /// Tool calls waiting for approval
+    pub fn approvals(&self) -> &Approvals {
+        &self.approvals
+    }
+
+    /// Declared capabilities of the client sessions
+    pub fn capabilities(&self) -> &SessionCapabilities {
+        &self.capabilities
+    }
+
+    /// Where requests from backends go back to client sessions
+    pub fn reverse(&self) -> &ReverseRouter {
+        &self.reverse
+    }
+
+    /// Whether a session's client can be sent a server-initiated request
+    pub fn client_accepts(&self, session: &str, method: &str) -> bool {
+        !self.router_config.capability_shaping || self.capabilities.get(session).is_none_or(|c| c.accepts(method))
+    }
+
+    /// Restarts to tell a client connection about
+    pub fn restart_notices(&self) -> RestartNotices {
+        RestartNotices::new(self.router_config.restart_notifications.then(|| self.lifecycle.subscribe()))
+    }
+
+    /// Subscribe to unsolicited backend messages
+    pub fn backend_events(&self) -> broadcast::Receiver<BackendMessage> {
+        self.events.subscribe()
+    }
+
+    /// Number of configured servers
+    pub async fn configured_count(&self) -> usize {
+        self.configs.lock().await.len()
+    }
+
+    async fn config(&self, name: &str) -> Result<ServerConfig> {
+        self.configs
+            .lock()
+            .await
+            .iter()
+            .find(|c| c.name == name)
+            .cloned()
+            .context(format!("Unknown server: {}", name))
+    }
+
+    /// Tell the webhooks what happened to `server`, with its last stderr lines
+    fn alert(&self, kind: AlertKind, server: &MCPServerProcess, reason: &str, exit_status: Option<String>) {
+        if !self.webhooks.is_empty() {
+            self.webhooks.send(Alert::new(kind, &server.name, reason, exit_status, server.backend.stderr()));
+        }
+    }
+
+    fn emit(&self, event: HubEvent) {
+        match &event {
+            HubEvent::Started { server } => {
+                self.down.remove(server);
             }
-            
-            return Err(anyhow::anyhow!(
-                "Server crashed immediately with status: {:?}. Error: {}",
-                status,
-                error_msg.trim()
-            ));
+            HubEvent::Restarted { server, reason } => {
+                self.down.remove(server);
+                crate::metrics::record_server_restart(server, reason);
+            }
+            HubEvent::Failed { server, reason } => {
+                self.down.insert(server.clone(), ServerState::Crashed);
+                self.last_errors.insert(server.clone(), reason.clone());
+            }
+            HubEvent::RequestFailed { server, error, .. } => {
+                self.last_errors.insert(server.clone(), error.clone());
+            }
+            _ => {}
         }
-        
-        Ok(server)
+        // No subscribers is fine
+        let _ = self.lifecycle.send(event);
     }
 
-    /// Send a message and receive response
-    pub async fn send_receive(&mut self, message: &[u8]) -> Result<Vec<u8>> {
-        // Write message
-        self.stdin.write_all(message).await?;
-        self.stdin.flush().await?;
+    fn last_error(&self, server: &str) -> Option<String> {
+        self.last_errors.get(server).map(|e| e.clone())
+    }
 
-        // Read response (one line)
-        let mut response = Vec::new();
-        self.stdout.read_until(b'\n', &mut response).await?;
+    /// Mark the hub as shutting down
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
 
-        Ok(response)
+    /// Whether shutdown has begun
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
     }
 
-    /// Stop the server
-    pub async fn stop(&mut self) -> Result<()> {
-        info!("Stopping MCP server: {}", self.name);
-        self.process.kill().await?;
-        self.process.wait().await?;
-        Ok(())
+    /// Get uptime
+    pub fn uptime(&self) -> std::time::Duration {
+        self.start_time.elapsed()
     }
-}
 
-/// MCP Citadel Server Manager
-pub struct HubManager {
-    servers: Arc<Mutex<HashMap<String, MCPServerProcess>>>,
-    configs: Vec<ServerConfig>,
-    start_time: std::time::Instant,
-    restart_counts: Arc<Mutex<HashMap<String, u32>>>,
+    /// Get server count
+    pub async fn server_count(&self) -> usize {
+        self.servers.running().await.len()
+    }
 }
 
-impl HubManager {
-    /// Create a new hub manager
-    pub async fn new(configs: Vec<ServerConfig>) -> Result<Self> {
-        let mut servers = HashMap::new();
+/// How long `handshake` waits for a server to answer `initialize`
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
-        for config in &configs {
-            match MCPServerProcess::start(config.clone()).await {
-                Ok(server) => {
-                    servers.insert(config.name.clone(), server);
-                }
-                Err(e) => {
-                    error!("Failed to start server {}: {}", config.name, e);
-                }
-            }
-        }
+/// Start a standalone instance of a server, check that it answers `initialize`,
+/// and stop it again
+pub async fn handshake(config: ServerConfig) -> Result<()> {
+    let (events, _) = broadcast::channel(16);
+    let mut server = MCPServerProcess::start(config, events).await?;
 
-        Ok(Self {
-            servers: Arc::new(Mutex::new(servers)),
-            configs,
-            start_time: std::time::Instant::now(),
-            restart_counts: Arc::new(Mutex::new(HashMap::new())),
-        })
+    let result = initialize(&server.connection, None).await.map(drop);
+    if let Err(e) = server.stop().await {
+        warn!("Error stopping handshake instance: {}", e);
     }
+    result
+}
 
-    /// Route a message to a specific server
-    pub async fn route_message(&self, server_name: &str, message: &[u8]) -> Result<Vec<u8>> {
-        let mut servers = self.servers.lock().await;
-        let server = servers
-            .get_mut(server_name)
-            .context(format!("Server not found: {}", server_name))?;
+/// Initialize a backend as the hub, with a client's handshake params or the
+/// hub's own, and return its initialize result
+async fn initialize(connection: &BackendConnection, params: Option<&Value>) -> Result<Value> {
+    let params = params.cloned().unwrap_or_else(|| {
+        serde_json::json!({
+            "protocolVersion": "2025-06-18",
+            "capabilities": {},
+            "clientInfo": { "name": "mcp-citadel", "version": env!("CARGO_PKG_VERSION") },
+        })
+    });
+    let request = serde_json::json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": params });
+    let request = Bytes::from(format!("{}\n", request));
+    let response = tokio::time::timeout(HANDSHAKE_TIMEOUT, connection.send(request))
+        .await
+        .map_err(|_| anyhow::anyhow!("No initialize response within {:?}", HANDSHAKE_TIMEOUT))??;
 
-        server.send_receive(message).await
+    let mut response: Value = serde_json::from_slice(&response).context("Invalid initialize response")?;
+    if let Some(error) = response.get("error") {
+        anyhow::bail!("initialize failed: {}", error);
     }
+    let Some(result) = response.get_mut("result").map(Value::take) else {
+        anyhow::bail!("initialize returned no result");
+    };
 
-    /// List all servers
-    pub async fn list_servers(&self) -> Vec<String> {
-        let servers = self.servers.lock().await;
-        servers.keys().cloned().collect()
+    connection
+        .send(Bytes::from_static(b"{\"jsonrpc\":\"2.0\",\"method\":\"notifications/initialized\"}\n"))
+        .await?;
+    Ok(result)
+}
+
+/// Initialize a fresh instance as the hub; clients that connect to it later
+/// get this handshake's answer
+async fn initialize_instance(server: &MCPServerProcess, params: Option<&Value>) -> Result<()> {
+    let mut result = initialize(&server.connection, params).await?;
+    identity::advertise(&mut result);
+    *server.init.result.lock().await = Some(result);
+    server.init.notified.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Send a server's `warmUp` requests to an initialized instance in order;
+/// failures are logged, since the instance can serve clients regardless
+async fn warm_up(server: &MCPServerProcess, requests: &[WarmUpRequest]) {
+    for (i, request) in requests.iter().enumerate() {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": format!("warm-up-{}", i),
+            "method": request.method,
+            "params": request.params.clone().unwrap_or_else(|| serde_json::json!({})),
+        });
+        let sent = tokio::time::timeout(HANDSHAKE_TIMEOUT, server.connection.send(Bytes::from(format!("{}\n", message)))).await;
+        let error = match sent {
+            Err(_) => Some(format!("no response within {:?}", HANDSHAKE_TIMEOUT)),
+            Ok(Err(e)) => Some(e.to_string()),
+            Ok(Ok(response)) => serde_json::from_slice::<Value>(&response)
+                .ok()
+                .and_then(|r| r.get("error").map(Value::to_string)),
+        };
+        match error {
+            Some(error) => warn!("Warm-up {} for {} failed: {}", request.method, server.name, error),
+            None => debug!("Warm-up {} for {} done", request.method, server.name),
+        }
     }
+}
 
-    /// Stop all servers
-    pub async fn stop_all(&self) -> Result<()> {
-        let mut servers = self.servers.lock().await;
-        for (_name, server) in servers.iter_mut() {
-            if let Err(e) = server.stop().await {
-                error!("Error stopping server: {}", e);
+/// Start a restarted server's new instance; it is initialized with the
+/// client handshake the server last answered (if any) and warmed up with its
+/// `warmUp` requests before it takes client traffic
+async fn start_replacement(
+    config: &ServerConfig,
+    events: &broadcast::Sender<BackendMessage>,
+    auto_provision: bool,
+    handshake: Option<&Value>,
+) -> Result<MCPServerProcess> {
+    let server = start_server(config, events, auto_provision).await?;
+    // Clients that initialized the previous process carry on with this one
+    if handshake.is_some() || !config.warm_up.is_empty() {
+        let started = std::time::Instant::now();
+        match initialize_instance(&server, handshake).await {
+            Ok(()) if config.warm_up.is_empty() => debug!("Replayed the initialize handshake to the new {} process", config.name),
+            Ok(()) => {
+                warm_up(&server, &config.warm_up).await;
+                info!("Warmed up {} in {:.1}s", config.name, started.elapsed().as_secs_f32());
             }
+            Err(e) => warn!("Failed to initialize the new {} process: {:#}", config.name, e),
         }
-        Ok(())
     }
+    Ok(server)
+}
 
-    /// Check health of all servers and restart crashed ones
-    pub async fn health_check(&self) -> Result<()> {
-        let mut servers = self.servers.lock().await;
-        let mut restart_counts = self.restart_counts.lock().await;
-        
-        const MAX_RESTARTS: u32 = 3;
-        
-        for config in &self.configs {
-            // Check if server exists
-            if let Some(server) = servers.get_mut(&config.name) {
-                // Check if process is still alive
-                match server.process.try_wait() {
-                    Ok(Some(status)) => {
-                        let uptime = server.start_time.elapsed();
-                        let count = restart_counts.entry(config.name.clone()).or_insert(0);
-                        
-                        // Immediate crash detection (< 5 seconds)
-                        let is_immediate_crash = uptime.as_secs() < 5;
-                        
-                        if is_immediate_crash {
-                            error!(
-                                "Server {} crashed immediately ({:.1}s uptime) with status: {:?}",
-                                config.name, uptime.as_secs_f32(), status
-                            );
-                            error!("This usually means:");
-                            error!("  • Wrong command or arguments in Claude config");
-                            error!("  • Missing dependencies (run: npm install -g {})", config.command);
-                            error!("  • Incompatible CLI version");
-                            error!("Command: {} {:?}", config.command, config.args);
-                            
-                            // Don't retry immediate crashes - they're config errors
-                            servers.remove(&config.name);
-                            continue;
-                        }
-                        
-                        if *count >= MAX_RESTARTS {
-                            error!(
-                                "Server {} has crashed {} times. Giving up. Check your Claude config.",
-                                config.name, count
-                            );
-                            servers.remove(&config.name);
-                            continue;
+/// Start a server, provisioning its missing dependencies and retrying once if enabled
+async fn start_server(
+    config: &ServerConfig,
+    events: &broadcast::Sender<BackendMessage>,
+    auto_provision: bool,
+) -> Result<MCPServerProcess> {
+    match MCPServerProcess::start(config.clone(), events.clone()).await {
+        Err(e) if auto_provision && provision::provision(config, &e).await => {
+            info!("Retrying server {} after provisioning", config.name);
+            MCPServerProcess::start(config.clone(), events.clone()).await
+        }
+        result => result,
+    }
+}
+
+/// How long to wait for a server to start and answer, e.g. a lazy server a
+/// request is waiting on or a dependency of a server about to start
+fn spawn_timeout(config: &ServerConfig) -> std::time::Duration {
+    std::time::Duration::from_secs(config.spawn_timeout_secs.unwrap_or(DEFAULT_SPAWN_TIMEOUT_SECS))
+}
+
+/// Wait for the dependencies of a server about to start at hub startup to answer
+async fn await_dependencies(config: &ServerConfig, configs: &[ServerConfig], servers: &HashMap<String, MCPServerProcess>) -> Result<()> {
+    for dependency in &config.depends_on {
+        let server = servers.get(dependency).context(format!("its dependency {} didn't start", dependency))?;
+        let timeout = configs.iter().find(|c| &c.name == dependency).map(spawn_timeout).unwrap_or_default();
+        dependencies::ready(dependency, &server.connection, timeout).await?;
+    }
+    Ok(())
+}
+
+/// Replace a server process with a freshly started one
+async fn restart_server(
+    slot: &mut Option<MCPServerProcess>,
+    config: &ServerConfig,
+    events: &broadcast::Sender<BackendMessage>,
+    auto_provision: bool,
+    handshake: Option<&Value>,
+) -> bool {
+    match start_replacement(config, events, auto_provision, handshake).await {
+        Ok(new_server) => {
+            *slot = Some(new_server);
+            info!("✓ Restarted server: {}", config.name);
+            true
+        }
+        Err(e) => {
+            error!("Failed to restart server {}: {}", config.name, e);
+            false
+        }
+    }
+}
+
+/// Drop cached catalogs when a backend announces a list change
+fn spawn_catalog_invalidation(
+    mut events: broadcast::Receiver<BackendMessage>,
+    mut lifecycle: broadcast::Receiver<HubEvent>,
+    catalog: Arc<Mutex<CatalogCache>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if let Some(list) = catalog::invalidated_list(&event.method) {
+                            catalog.lock().await.invalidate(&event.server, list);
                         }
-                        
-                        warn!("Server {} exited after {:.1}s with status: {:?}", config.name, uptime.as_secs_f32(), status);
-                        *count += 1;
-                        
-                        // Restart the server
-                        info!("Restarting server: {} (attempt {}/{})", config.name, count, MAX_RESTARTS);
-                        match MCPServerProcess::start(config.clone()).await {
-                            Ok(new_server) => {
-                                servers.insert(config.name.clone(), new_server);
-                                info!("✓ Restarted server: {}", config.name);
-                            }
-                            Err(e) => {
-                                error!("Failed to restart server {}: {}", config.name, e);
-                            }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Catalog invalidation lagged, {} backend messages skipped", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                // A new backend process may list something else
+                event = lifecycle.recv() => match event {
+                    Ok(HubEvent::Started { server } | HubEvent::Restarted { server, .. }) => catalog.lock().await.expire(&server),
+                    Ok(HubEvent::Reloaded { changed, .. }) => {
+                        let mut catalog = catalog.lock().await;
+                        for server in &changed {
+                            catalog.expire(server);
                         }
                     }
-                    Ok(None) => {
-                        // Still running, all good
-                        // Reset restart count on successful health check
-                        restart_counts.insert(config.name.clone(), 0);
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
+
+/// Send server-initiated requests to the client session that should answer
+/// them, and decline the ones no session can take so the backend isn't left
+/// waiting; progress goes to the session whose request it reports on
+fn spawn_reverse_routing(
+    mut events: broadcast::Receiver<BackendMessage>,
+    servers: Arc<ServerTable>,
+    sessions: Arc<SessionCapabilities>,
+    reverse: Arc<ReverseRouter>,
+    shaping: bool,
+) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let Some(error) = route_backend_message(&event, None, &sessions, &reverse, shaping) else {
+                        continue;
+                    };
+                    let connection = servers.with(&event.server, |s| Arc::clone(&s.connection)).await;
+                    if let Some(connection) = connection {
+                        if let Err(e) = connection.send(error).await {
+                            warn!("Failed to decline {} from {}: {}", event.method, event.server, e);
+                        }
                     }
-                    Err(e) => {
-                        error!("Error checking server {}: {}", config.name, e);
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Reverse routing lagged, {} backend messages skipped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Route what a session's own process sends on its own to that session, until
+/// the process is gone
+fn spawn_pinned_routing(
+    mut events: broadcast::Receiver<BackendMessage>,
+    session: String,
+    connection: Arc<BackendConnection>,
+    sessions: Arc<SessionCapabilities>,
+    reverse: Arc<ReverseRouter>,
+    shaping: bool,
+) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let Some(error) = route_backend_message(&event, Some(&session), &sessions, &reverse, shaping) else {
+                        continue;
+                    };
+                    if let Err(e) = connection.send(error).await {
+                        warn!("Failed to decline {} from {}: {}", event.method, event.server, e);
                     }
                 }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Session {} lagged, {} backend messages skipped", session, n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
             }
         }
-        
-        Ok(())
-    }
+    });
+}
 
-    /// Get uptime
-    pub fn uptime(&self) -> std::time::Duration {
-        self.start_time.elapsed()
+/// Pass a backend's request or progress on to the client session it's for,
+/// limited to `session` for a session's own process (which also gets its
+/// notifications this way); the error to answer the backend with when no
+/// session can take a request
+fn route_backend_message(
+    event: &BackendMessage,
+    session: Option<&str>,
+    sessions: &SessionCapabilities,
+    reverse: &ReverseRouter,
+    shaping: bool,
+) -> Option<Bytes> {
+    let accepts = |s: &str| session.is_none_or(|only| only == s) && (!shaping || sessions.get(s).is_none_or(|c| c.accepts(&event.method)));
+    if event.method == progress::METHOD {
+        if !reverse.dispatch_progress(event) {
+            debug!("Dropping progress from {} for no request in flight", event.server);
+        }
+        return None;
     }
-
-    /// Get server count
-    pub async fn server_count(&self) -> usize {
-        let servers = self.servers.lock().await;
-        servers.len()
+    if event.is_notification() {
+        // Shared processes' notifications go out through each transport
+        if let Some(session) = session.filter(|s| accepts(s)) {
+            reverse.deliver(session, (event.server.clone(), event.message.clone()));
+        }
+        return None;
     }
+    if reverse.dispatch(event, accepts) {
+        return None;
+    }
+    let capability = capabilities::required_capability(&event.method).unwrap_or(&event.method);
+    let error = capabilities::unsupported(&event.message, capability)?;
+    debug!("Declining {} from {}: no client session can take it", event.method, event.server);
+    Some(error)
 }
 
 /// MCP Citadel Router - Unix socket server
@@ -303,6 +2454,7 @@ impl HubRouter {
         }
 
         info!("🚀 MCP Citadel listening on {}", self.socket_path);
+        self.manager.register_endpoint("socket", self.socket_path.clone());
 
         loop {
             match listener.accept().await {
@@ -324,17 +2476,117 @@ impl HubRouter {
 
 /// Handle a client connection
 async fn handle_client(stream: UnixStream, manager: Arc<HubManager>) -> Result<()> {
+    let mut project = None;
+    let peer_uid = stream.peer_cred().ok().map(|c| c.uid());
+    let session = ClientSession::new(peer_uid, peer_uid.and_then(user_name));
+    debug!("Client session {} connected", session.id);
+    manager.transcripts.open(&session.id, "socket");
+    manager.session_created(&session.id, "socket");
+    let result = serve_client(stream, &manager, &session, &mut project).await;
+    manager.transcripts.close(&session.id);
+    manager.end_session(&session.id);
+
+    // Project servers stop once their last client is gone
+    if let Some(path) = project {
+        manager.projects.detach(&path).await;
+    }
+    manager.close_session(&session).await;
+    result
+}
+
+async fn serve_client(
+    stream: UnixStream,
+    manager: &HubManager,
+    session: &ClientSession,
+    project: &mut Option<PathBuf>,
+) -> Result<()> {
+    let (peer_uid, unix_user) = (session.peer_uid, session.unix_user.as_deref());
     let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    let mut reader = BufReader::with_capacity(manager.router_config.read_buffer_size.max(1), reader);
+
+    // A hello selects the framed protocol; anything else is the first v1 message
+    let mut first = Vec::new();
+    if reader.read_until(b'\n', &mut first).await? == 0 {
+        debug!("Client disconnected");
+        return Ok(());
+    }
+    let next = match framed::Hello::parse(&first) {
+        Some(hello) if hello.protocol >= 2 => {
+            return framed::serve(hello, reader, writer, manager, session, project).await;
+        }
+        Some(_) => {
+            framed::write_json(&mut writer, &framed::Hello::reply(1, None)).await?;
+            None
+        }
+        None => Some(first),
+    };
+
     let mut server_name: Option<String> = None;
+    let mut scope: Option<Scope> = None;
+    let mut keepalive = Keepalive::new(manager.client_keepalive());
+    let mut restarts = manager.restart_notices();
+    let mut events = manager.backend_events();
+    let mut requests = manager.reverse.open(&session.id);
+    let mut queued: VecDeque<Vec<u8>> = next.into_iter().collect();
+    let mut buf = Vec::new();
 
     loop {
-        let mut line = Vec::new();
-        let n = reader.read_until(b'\n', &mut line).await?;
+        let line = match queued.pop_front() {
+            Some(line) => line,
+            None => tokio::select! {
+                read = reader.read_until(b'\n', &mut buf) => {
+                    if read? == 0 {
+                        debug!("Client disconnected");
+                        break;
+                    }
+                    std::mem::take(&mut buf)
+                }
+                tick = keepalive.tick() => match tick {
+                    Tick::Ping => {
+                        writer.write_all(keepalive::PING).await?;
+                        continue;
+                    }
+                    Tick::Expired => {
+                        info!("Closing client connection that stopped answering pings");
+                        break;
+                    }
+                },
+                (restarted, reason) = restarts.recv() => {
+                    if let (Some(name), Some(scope)) = (&server_name, &scope) {
+                        if scope.target(name) == Some(restarted.as_str()) {
+                            writer.write_all(&notices::server_restarted_line(name, &reason)).await?;
+                        }
+                    }
+                    continue;
+                }
+                event = events.recv() => {
+                    match event {
+                        Ok(event) => {
+                            if let Some(notification) = client_notification(manager, session, server_name.as_deref(), scope.as_ref(), &event) {
+                                writer.write_all(&notification).await?;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => warn!("Client lagged, {} backend messages skipped", n),
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                    continue;
+                }
+                Some((_, request)) = requests.recv() => {
+                    writer.write_all(&request).await?;
+                    continue;
+                }
+            },
+        };
 
-        if n == 0 {
-            debug!("Client disconnected");
-            break;
+        keepalive.seen();
+        match Control::parse(&line) {
+            Some(Control::Ping) => {
+                keepalive.enable();
+                writer.write_all(keepalive::PONG).await?;
+                continue;
+            }
+            Some(Control::Pong) => continue,
+            None => {}
         }
 
         // Parse JSON to extract server name
@@ -342,10 +2594,73 @@ async fn handle_client(stream: UnixStream, manager: Arc<HubManager>) -> Result<(
             server_name = extract_server_name(&line);
         }
 
+        // Map the connection to a workspace and project on its first message
+        if scope.is_none() {
+            let (requested, project_path) = client_params(&line);
+            let identity = Identity {
+                requested: requested.as_deref(),
+                unix_user,
+                ..Default::default()
+            };
+            match manager.scope(identity) {
+                Ok(resolved) => scope = Some(resolved),
+                Err(e) => {
+                    warn!("Rejected client (user {:?}): {}", unix_user, e);
+                    let error_response = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": null,
+                        "error": { "code": -32001, "message": e.to_string() }
+                    });
+                    writer.write_all(format!("{}\n", error_response).as_bytes()).await?;
+                    continue;
+                }
+            }
+
+            if let Some(path) = project_path {
+                attach_project(manager, path, peer_uid, project).await;
+            }
+        }
+
         match &server_name {
             Some(name) => {
-                // Route to backend server
-                match manager.route_message(name, &line).await {
+                let routing = route_client_message(manager, session, project.as_deref(), scope.as_ref(), name, Bytes::from(line));
+                tokio::pin!(routing);
+
+                // While the request is in flight, the backend may need the client
+                // first (sampling, roots) or report progress: pass those on, take the
+                // client's answers and cancellations, and hold its other messages
+                // until the response
+                let routed = loop {
+                    tokio::select! {
+                        routed = &mut routing => break routed,
+                        Ok(event) = events.recv() => {
+                            if let Some(notification) = client_notification(manager, session, Some(name), scope.as_ref(), &event) {
+                                writer.write_all(&notification).await?;
+                            }
+                        }
+                        Some((_, request)) = requests.recv() => writer.write_all(&request).await?,
+                        read = reader.read_until(b'\n', &mut buf) => {
+                            if read? == 0 {
+                                debug!("Client disconnected with a request in flight");
+                                return Ok(());
+                            }
+                            let line = std::mem::take(&mut buf);
+                            if Envelope::parse(&line).and_then(|e| e.method).as_deref() == Some("notifications/cancelled") {
+                                if let Err(e) = route_client_message(manager, session, project.as_deref(), scope.as_ref(), name, Bytes::from(line)).await {
+                                    warn!("Failed to route a client cancellation: {}", e);
+                                }
+                                continue;
+                            }
+                            match manager.route_client_response(&session.id, &line).await {
+                                Some(Err(e)) => warn!("Failed to route a client response: {}", e),
+                                Some(Ok(_)) => {}
+                                None => queued.push_back(line),
+                            }
+                        }
+                    }
+                };
+
+                match routed {
                     Ok(response) => {
                         writer.write_all(&response).await?;
                     }
@@ -371,26 +2686,144 @@ async fn handle_client(stream: UnixStream, manager: Arc<HubManager>) -> Result<(
     Ok(())
 }
 
-/// Extract server name from MCP message
-fn extract_server_name(message: &[u8]) -> Option<String> {
-    let text = std::str::from_utf8(message).ok()?;
-    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+/// A notification from a socket connection's server to pass on to the client
+fn client_notification(
+    manager: &HubManager,
+    session: &ClientSession,
+    name: Option<&str>,
+    scope: Option<&Scope>,
+    event: &BackendMessage,
+) -> Option<Bytes> {
+    let addressed = name.zip(scope).and_then(|(name, scope)| scope.target(name));
+    let forward = event.is_broadcast() && addressed == Some(event.server.as_str()) && manager.client_accepts(&session.id, &event.method);
+    forward.then(|| message::with_newline(event.message.clone()))
+}
+
+/// Start a project's servers for a client, if it may use them
+async fn attach_project(manager: &HubManager, path: PathBuf, peer_uid: Option<u32>, project: &mut Option<PathBuf>) {
+    let attached = match project::authorize(&path, peer_uid) {
+        Ok(()) => manager.projects.attach(&path, &manager.events, &manager.router_config).await,
+        Err(e) => Err(e),
+    };
+    match attached {
+        Ok(servers) => {
+            debug!("Client joined project {:?} ({} servers)", path, servers.len());
+            *project = Some(path);
+        }
+        Err(e) => warn!("Ignoring project {:?}: {}", path, e),
+    }
+}
+
+/// Route a socket client's message to a project server or a hub server in its
+/// scope, resolving the workspace's aliases
+async fn route_client_message(
+    manager: &HubManager,
+    session: &ClientSession,
+    project: Option<&std::path::Path>,
+    scope: Option<&Scope>,
+    name: &str,
+    message: Bytes,
+) -> Result<Bytes> {
+    // Answers to requests backends sent the client go back where they came from
+    if let Some(routed) = manager.route_client_response(&session.id, &message).await {
+        return routed;
+    }
+
+    // The hub answers `citadel/*` itself, even during maintenance
+    if let Some(answer) = identity::answer(manager, scope, &message).await {
+        return Ok(answer);
+    }
+
+    // The virtual `citadel` server answers for every backend, handing tool calls on to theirs
+    let (name, message) = if aggregate::addressed(manager, scope, name, &message).await {
+        match aggregate::route(manager, scope, message).await {
+            aggregate::Routed::Answer(answer) => return Ok(answer),
+            aggregate::Routed::Call(server, call) => (server, call),
+        }
+    } else {
+        (name.to_string(), message)
+    };
+    let name = name.as_str();
 
-    // Try params.server
-    if let Some(params) = value.get("params") {
-        if let Some(server) = params.get("server") {
-            return server.as_str().map(String::from);
+    let project_connection = match project {
+        Some(path) => {
+            manager
+                .projects
+                .connection(path, name, &manager.events, &manager.router_config)
+                .await
         }
+        None => None,
+    };
+
+    let rejection = manager.maintenance.rejection(&message).or_else(|| scope.and_then(|s| s.reject(name, &message)));
+    if let Some(rejection) = rejection {
+        return Ok(rejection);
     }
 
-    // Try method prefix (e.g., "github/tools/list")
-    if let Some(method) = value.get("method") {
-        if let Some(method_str) = method.as_str() {
-            if let Some(server) = method_str.split('/').next() {
-                return Some(server.to_string());
+    // Project servers shadow hub servers (and aliases) of the same name
+    let priority = scope.map(|s| s.priority).unwrap_or_default();
+    match (project_connection, scope.and_then(|s| s.target(name))) {
+        (Some(connection), _) => {
+            manager.transcripts.record(&session.id, name, Origin::Client, &message);
+            let routed = connection.send_with_priority(message, priority, Some(&session.id)).await;
+            manager.record_outcome(&session.id, name, &routed);
+            routed
+        }
+        (None, Some(target)) => {
+            // A session's own process never changes under it
+            let generation = match manager.pinned.is_pinned(target) {
+                true => None,
+                false => manager.generation_on_demand(target).await,
+            };
+            if let Some(generation) = generation {
+                if let Some(replay) = session.track(target, &message, generation) {
+                    if let Err(e) = manager.replay_session(target, replay).await {
+                        warn!("Failed to replay session state to {}: {}", target, e);
+                    }
+                }
             }
+            manager.route_session_message(Some(&session.id), priority, target, message).await
         }
+        (None, None) => Err(anyhow::anyhow!("Server not found: {}", name)),
+    }
+}
+
+/// Name of a Unix user
+fn user_name(uid: u32) -> Option<String> {
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid)).ok()??;
+    Some(user.name)
+}
+
+/// Workspace (`params.workspace`) and project config (`params.project`) a socket client sends
+fn client_params(message: &[u8]) -> (Option<String>, Option<PathBuf>) {
+    let Ok(message) = serde_json::from_slice::<Value>(message) else {
+        return (None, None);
+    };
+    let param = |key: &str| message.get("params")?.get(key)?.as_str().map(String::from);
+    (param("workspace"), param("project").map(PathBuf::from))
+}
+
+/// Strip a `server/` routing prefix from a method name
+fn backend_method<'a>(server_name: &str, method: &'a str) -> &'a str {
+    method
+        .strip_prefix(server_name)
+        .and_then(|m| m.strip_prefix('/'))
+        .unwrap_or(method)
+}
+
+/// Catalog-relevant method of a request: `initialize` or the first page of a list
+fn catalog_method(server_name: &str, envelope: &Envelope) -> Option<&'static str> {
+    let method = backend_method(server_name, envelope.method.as_deref()?);
+    if method == "initialize" {
+        return Some("initialize");
     }
 
-    None
+    let list = catalog::CATALOG_METHODS.iter().find(|m| **m == method)?;
+    let has_cursor = envelope
+        .params
+        .and_then(|p| serde_json::from_str::<Value>(p.get()).ok())
+        .and_then(|p| p.get("cursor").cloned())
+        .is_some_and(|c| !c.is_null());
+
+    (!has_cursor).then_some(*list)
 }
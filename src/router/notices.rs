@@ -0,0 +1,104 @@
+//! Restart notices
+//! When the hub restarts a backend (crash, hang, schedule, request, or a
+//! reload that changed its config), clients that were talking to it get a
+//! `citadel/server_restarted` notification over their own transport, so they
+//! can re-list tools or retry instead of failing on lost state.
+//!
+//! ```text
+//! ← {"jsonrpc":"2.0","method":"citadel/server_restarted","params":{"server":"github","reason":"crashed"}}
+//! ```
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use super::HubEvent;
+
+/// Method of the notification
+pub const SERVER_RESTARTED: &str = "citadel/server_restarted";
+
+/// Reason given for servers restarted by a config reload
+const RELOADED: &str = "config changed";
+
+/// A connection's feed of backend restarts
+pub struct RestartNotices {
+    /// None when `router.restart_notifications` is off
+    events: Option<broadcast::Receiver<HubEvent>>,
+    /// Restarts from a reload, handed out one at a time
+    pending: VecDeque<(String, String)>,
+}
+
+impl RestartNotices {
+    pub(super) fn new(events: Option<broadcast::Receiver<HubEvent>>) -> Self {
+        Self {
+            events,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Next restarted server and why; never returns while notices are off
+    pub async fn recv(&mut self) -> (String, String) {
+        loop {
+            if let Some(restart) = self.pending.pop_front() {
+                return restart;
+            }
+            let Some(events) = &mut self.events else {
+                return std::future::pending().await;
+            };
+            match events.recv().await {
+                Ok(HubEvent::Restarted { server, reason }) => return (server, reason),
+                Ok(HubEvent::Reloaded { changed, .. }) => {
+                    self.pending.extend(changed.into_iter().map(|server| (server, RELOADED.to_string())));
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Client connection lagged, {} restart notices skipped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => self.events = None,
+            }
+        }
+    }
+}
+
+/// The JSON-RPC notification for a restart (`server` as the client names it)
+pub fn server_restarted(server: &str, reason: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": SERVER_RESTARTED,
+        "params": { "server": server, "reason": reason },
+    })
+}
+
+/// [`server_restarted`] as a newline-terminated line
+pub fn server_restarted_line(server: &str, reason: &str) -> Vec<u8> {
+    let mut line = server_restarted(server, reason).to_string().into_bytes();
+    line.push(b'\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notices_from_lifecycle_events() {
+        let (events, receiver) = broadcast::channel(8);
+        let mut notices = RestartNotices::new(Some(receiver));
+        events.send(HubEvent::Started { server: "slack".to_string() }).unwrap();
+        events
+            .send(HubEvent::Restarted { server: "github".to_string(), reason: "crashed".to_string() })
+            .unwrap();
+        events
+            .send(HubEvent::Reloaded { added: vec![], removed: vec![], changed: vec!["fs".to_string()] })
+            .unwrap();
+
+        assert_eq!(notices.recv().await, ("github".to_string(), "crashed".to_string()));
+        assert_eq!(notices.recv().await, ("fs".to_string(), RELOADED.to_string()));
+        assert_eq!(server_restarted("gh", "crashed")["params"]["server"], "gh");
+
+        let mut off = RestartNotices::new(None);
+        let waited = tokio::time::timeout(std::time::Duration::from_millis(10), off.recv()).await;
+        assert!(waited.is_err());
+    }
+}
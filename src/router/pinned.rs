@@ -0,0 +1,85 @@
+//! Session-pinned instances
+//! A server with `isolation: "per-session"` keeps state per conversation, so
+//! each client session (a socket connection, an HTTP session, a WebSocket)
+//! gets a process of its own: started by the session's first message to the
+//! server and stopped when the session ends. What a pinned process sends on
+//! its own goes to its session only. Requests the hub makes itself (health
+//! checks, aggregated lists) go to a shared process started on demand.
+
+use dashmap::DashMap;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::config::{Isolation, ServerConfig};
+
+use super::MCPServerProcess;
+
+/// A session's process of a server, None until it starts
+pub(super) type Instance = Arc<Mutex<Option<MCPServerProcess>>>;
+
+#[derive(Default)]
+pub(super) struct PinnedInstances {
+    /// Servers configured with per-session isolation
+    servers: RwLock<HashSet<String>>,
+    /// Processes by session and server
+    instances: DashMap<(String, String), Instance>,
+}
+
+impl PinnedInstances {
+    /// Take note of which servers are per-session
+    pub fn configure(&self, configs: &[ServerConfig]) {
+        *self.servers.write().unwrap() = configs
+            .iter()
+            .filter(|c| c.isolation == Isolation::PerSession)
+            .map(|c| c.name.clone())
+            .collect();
+    }
+
+    /// Whether a session's messages to a server go to a process of its own
+    pub fn is_pinned(&self, server: &str) -> bool {
+        self.servers.read().unwrap().contains(server)
+    }
+
+    /// A session's instance of a server, empty if it has none yet
+    pub fn instance(&self, session: &str, server: &str) -> Instance {
+        let key = (session.to_string(), server.to_string());
+        Arc::clone(&self.instances.entry(key).or_default())
+    }
+
+    /// Number of sessions with a process of a server, counting ones starting
+    pub fn count(&self, server: &str) -> usize {
+        self.instances
+            .iter()
+            .filter(|entry| entry.key().1 == server)
+            .filter(|entry| entry.value().try_lock().map_or(true, |instance| instance.is_some()))
+            .count()
+    }
+
+    /// Stop a session's processes
+    pub async fn release(&self, session: &str) {
+        self.stop(|(s, _)| s == session).await;
+    }
+
+    /// Stop every session's process of a server, so sessions start one with
+    /// its new config
+    pub async fn release_server(&self, server: &str) {
+        self.stop(|(_, s)| s == server).await;
+    }
+
+    async fn stop(&self, matches: impl Fn(&(String, String)) -> bool) {
+        let keys: Vec<_> = self.instances.iter().map(|entry| entry.key().clone()).filter(|key| matches(key)).collect();
+        for key in keys {
+            let Some((_, instance)) = self.instances.remove(&key) else {
+                continue;
+            };
+            let server = instance.lock().await.take();
+            if let Some(mut server) = server {
+                if let Err(e) = server.stop().await {
+                    warn!("Error stopping the {} process of session {}: {}", key.1, key.0, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Access policy
+//! A read-only client (`router.read_only`, or a workspace with `read_only`)
+//! may browse what the servers offer but not act through them: requests
+//! other than the listing, reading, and handshake methods below are answered
+//! by the hub with a policy error and never reach the backend.
+//!
+//! A server's tool filter (`tools: { allow, deny }`) likewise hides tools
+//! from its `tools/list` results and refuses calls to them. A read-only server
+//! (`readOnly`, or `router.read_only_servers`) lists every tool but refuses
+//! calls to those annotated as destructive or matching a write pattern.
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use super::message::Envelope;
+use crate::config::tools::ToolFilter;
+
+/// JSON-RPC error code of policy rejections (as for workspace refusals)
+pub const POLICY_ERROR: i32 = -32001;
+
+/// Requests a read-only client may send
+const READ_ONLY_METHODS: &[&str] = &[
+    "initialize",
+    "ping",
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "resources/subscribe",
+    "resources/unsubscribe",
+    "prompts/list",
+    "prompts/get",
+    "completion/complete",
+];
+
+/// The error response for a request a read-only client may not send, or None
+/// if it may (notifications and responses always pass)
+pub fn read_only_rejection(server: &str, message: &[u8]) -> Option<Bytes> {
+    let envelope = Envelope::parse(message)?;
+    if !envelope.is_request() {
+        return None;
+    }
+    let method = super::backend_method(server, envelope.method.as_deref()?);
+    if READ_ONLY_METHODS.contains(&method) {
+        return None;
+    }
+    rejection(&envelope, format!("Read-only access: {} is not allowed", method), json!({ "policy": "read_only", "method": method }))
+}
+
+/// The error response for a `tools/call` of a tool `filter` blocks, or None
+/// for every other message
+pub fn tool_rejection(server: &str, filter: &ToolFilter, message: &[u8]) -> Option<Bytes> {
+    if filter.is_empty() {
+        return None;
+    }
+    let tool = tool_name(server, message)?;
+    if filter.allows(&tool) {
+        return None;
+    }
+    let envelope = Envelope::parse(message)?;
+    rejection(&envelope, format!("Tool {} of {} is not allowed", tool, server), json!({ "policy": "tools", "server": server, "tool": tool }))
+}
+
+/// Whether a tool's annotations mark it destructive
+pub fn is_destructive(tool: &Value) -> bool {
+    let hint = |name: &str| tool.get("annotations").and_then(|a| a.get(name)).and_then(Value::as_bool);
+    hint("destructiveHint") == Some(true) && hint("readOnlyHint") != Some(true)
+}
+
+/// The error response for a call to a read-only server's write tool
+pub fn write_rejection(server: &str, tool: &str, message: &[u8]) -> Option<Bytes> {
+    let envelope = Envelope::parse(message)?;
+    rejection(
+        &envelope,
+        format!("Read-only server: {} of {} is a destructive tool", tool, server),
+        json!({ "policy": "read_only_server", "server": server, "tool": tool }),
+    )
+}
+
+/// The tool a `tools/call` request calls
+pub fn tool_name(server: &str, message: &[u8]) -> Option<String> {
+    let envelope = Envelope::parse(message)?;
+    if !envelope.is_request() || super::backend_method(server, envelope.method.as_deref()?) != "tools/call" {
+        return None;
+    }
+    let params: Value = serde_json::from_str(envelope.params?.get()).ok()?;
+    params.get("name")?.as_str().map(String::from)
+}
+
+/// A `tools/list` response without the tools `filter` blocks
+pub fn filter_tools(filter: &ToolFilter, response: Bytes) -> Bytes {
+    if filter.is_empty() {
+        return response;
+    }
+    let Ok(mut message) = serde_json::from_slice::<Value>(&response) else {
+        return response;
+    };
+    let Some(Value::Array(tools)) = message.get_mut("result").and_then(|r| r.get_mut("tools")) else {
+        return response;
+    };
+    tools.retain(|tool| tool.get("name").and_then(Value::as_str).is_some_and(|name| filter.allows(name)));
+    let mut line = message.to_string().into_bytes();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// A policy error answering the request of `envelope`
+pub(super) fn rejection(envelope: &Envelope, message: String, data: Value) -> Option<Bytes> {
+    let id = envelope.id.and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+    let error = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": POLICY_ERROR, "message": message, "data": data },
+    });
+    let mut line = serde_json::to_vec(&error).ok()?;
+    line.push(b'\n');
+    Some(Bytes::from(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_rejection() {
+        let call = br#"{"jsonrpc":"2.0","id":7,"method":"github/tools/call","params":{"name":"create_issue"}}"#;
+        let rejection: serde_json::Value = serde_json::from_slice(&read_only_rejection("github", call).unwrap()).unwrap();
+        assert_eq!(rejection["id"], 7);
+        assert_eq!(rejection["error"]["code"], POLICY_ERROR);
+        assert_eq!(rejection["error"]["data"]["method"], "tools/call");
+
+        assert!(read_only_rejection("github", br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#).is_none());
+        assert!(read_only_rejection("github", br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).is_none());
+        assert!(read_only_rejection("github", br#"{"jsonrpc":"2.0","id":2,"method":"logging/setLevel"}"#).is_some());
+    }
+
+    #[test]
+    fn test_tool_filter() {
+        let filter = ToolFilter { allow: vec!["get_*".to_string()], deny: vec!["get_secret".to_string()], ..Default::default() };
+        let call = |tool: &str| format!(r#"{{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{{"name":"{}"}}}}"#, tool);
+        assert!(tool_rejection("github", &filter, call("get_file").as_bytes()).is_none());
+        let rejection: Value = serde_json::from_slice(&tool_rejection("github", &filter, call("get_secret").as_bytes()).unwrap()).unwrap();
+        assert_eq!((rejection["id"].as_i64(), rejection["error"]["code"].as_i64()), (Some(3), Some(POLICY_ERROR as i64)));
+        assert_eq!(rejection["error"]["data"]["tool"], "get_secret");
+        assert!(tool_rejection("github", &filter, br#"{"jsonrpc":"2.0","id":4,"method":"tools/list"}"#).is_none());
+
+        let list = Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"result":{"tools":[{"name":"get_file"},{"name":"get_secret"},{"name":"delete_repo"}]}}"#);
+        let listed: Value = serde_json::from_slice(&filter_tools(&filter, list)).unwrap();
+        assert_eq!(listed["result"]["tools"], json!([{ "name": "get_file" }]));
+    }
+
+    #[test]
+    fn test_is_destructive() {
+        assert!(is_destructive(&json!({ "name": "drop", "annotations": { "destructiveHint": true } })));
+        assert!(!is_destructive(&json!({ "name": "read", "annotations": { "destructiveHint": true, "readOnlyHint": true } })));
+        assert!(!is_destructive(&json!({ "name": "search" })));
+    }
+}
@@ -0,0 +1,151 @@
+//! Prioritized queueing
+//! A counting gate like a semaphore, except that a freed slot goes to the
+//! longest-waiting request of the highest priority class, so interactive
+//! clients get ahead of a batch agent saturating the same backend
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+use crate::config::Priority;
+
+const CLASSES: usize = 3;
+
+/// Slots handed out by priority
+#[derive(Debug)]
+pub struct Gate {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    available: usize,
+    /// Waiters per class, indexed by `Priority as usize`
+    waiting: [VecDeque<oneshot::Sender<()>>; CLASSES],
+}
+
+/// A slot, given back on drop
+#[derive(Debug)]
+pub struct GatePermit {
+    gate: Arc<Gate>,
+}
+
+impl Gate {
+    pub fn new(slots: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                available: slots,
+                waiting: Default::default(),
+            }),
+        })
+    }
+
+    /// Take a slot if one is free
+    pub fn try_acquire(self: &Arc<Self>) -> Option<GatePermit> {
+        let mut state = self.state.lock().unwrap();
+        if state.available == 0 {
+            return None;
+        }
+        state.available -= 1;
+        Some(GatePermit { gate: Arc::clone(self) })
+    }
+
+    /// Take a slot, queueing behind waiters of the same or a higher class
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> GatePermit {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 {
+                state.available -= 1;
+                return GatePermit { gate: Arc::clone(self) };
+            }
+            let (tx, rx) = oneshot::channel();
+            state.waiting[priority as usize].push_back(tx);
+            rx
+        };
+
+        let mut waiter = Waiter { rx, gate: Arc::clone(self), granted: false };
+        // The sender is only dropped after a send, so this resolves to a slot
+        let _ = (&mut waiter.rx).await;
+        waiter.granted = true;
+        GatePermit { gate: Arc::clone(self) }
+    }
+
+    /// Free slots
+    pub fn available(&self) -> usize {
+        self.state.lock().unwrap().available
+    }
+
+    /// Hand a slot to the next waiter, or back to the pool
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        for queue in state.waiting.iter_mut().rev() {
+            while let Some(waiter) = queue.pop_front() {
+                if waiter.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.available += 1;
+    }
+}
+
+impl Drop for GatePermit {
+    fn drop(&mut self) {
+        self.gate.release();
+    }
+}
+
+/// A queued acquire; if it is abandoned after being handed a slot, the slot
+/// is passed on
+struct Waiter {
+    rx: oneshot::Receiver<()>,
+    gate: Arc<Gate>,
+    granted: bool,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if self.granted {
+            return;
+        }
+        self.rx.close();
+        if self.rx.try_recv().is_ok() {
+            self.gate.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_higher_priority_goes_first() {
+        let gate = Gate::new(1);
+        let held = gate.try_acquire().unwrap();
+        assert!(gate.try_acquire().is_none());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (name, priority) in [("batch", Priority::Background), ("normal", Priority::Normal), ("editor", Priority::Interactive)] {
+            let (gate, order) = (Arc::clone(&gate), Arc::clone(&order));
+            tasks.push(tokio::spawn(async move {
+                let _permit = gate.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // An abandoned waiter doesn't swallow a slot
+        let abandoned = tokio::time::timeout(Duration::from_millis(5), gate.acquire(Priority::Interactive)).await;
+        assert!(abandoned.is_err());
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["editor", "normal", "batch"]);
+        assert_eq!(gate.available(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+//! Progress passthrough
+//! A client asks for progress on a request with `params._meta.progressToken`;
+//! the backend's `notifications/progress` for it should reach that client
+//! only. Tokens are picked by clients, so two clients of one server may use
+//! the same one: each tracked request carries a token of the hub's instead,
+//! and the client's own is put back on the progress notifications it gets:
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"build","_meta":{"progressToken":"abc"}}}
+//! ⇢ {"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"build","_meta":{"progressToken":"citadel-progress-1"}}}
+//! ⇠ {"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"citadel-progress-1","progress":50,"total":100}}
+//! ← {"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":"abc","progress":50,"total":100}}
+//! ```
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::connection::BackendMessage;
+use super::message::{progress_token_span, splice};
+
+/// Method of progress notifications
+pub const METHOD: &str = "notifications/progress";
+
+/// Prefix of the tokens the hub gives tracked requests
+const TOKEN_PREFIX: &str = "citadel-progress-";
+
+/// A request whose progress goes to a session
+struct Tracked {
+    server: String,
+    session: String,
+    /// The client's own token, as raw JSON
+    token: Bytes,
+}
+
+#[derive(Default)]
+pub struct ProgressTracker {
+    /// Requests in flight by the hub's token
+    tokens: DashMap<String, Tracked>,
+    next_token: AtomicU64,
+}
+
+impl ProgressTracker {
+    /// Track a session's request to a server if it asks for progress: the
+    /// message to send, with the hub's token, and that token to release once
+    /// it's answered
+    pub fn track(&self, session: &str, server: &str, message: Bytes) -> (Bytes, Option<String>) {
+        let Some(span) = progress_token_span(&message) else {
+            return (message, None);
+        };
+        let token = format!("{}{}", TOKEN_PREFIX, self.next_token.fetch_add(1, Ordering::Relaxed) + 1);
+        let tracked = Tracked {
+            server: server.to_string(),
+            session: session.to_string(),
+            token: message.slice(span.clone()),
+        };
+        self.tokens.insert(token.clone(), tracked);
+        (splice(&message, span, format!("\"{}\"", token).as_bytes()), Some(token))
+    }
+
+    /// Stop tracking an answered request; later progress for it is dropped
+    pub fn release(&self, token: &str) {
+        self.tokens.remove(token);
+    }
+
+    /// Stop tracking a closed session's requests
+    pub fn close(&self, session: &str) {
+        self.tokens.retain(|_, tracked| tracked.session != session);
+    }
+
+    /// The session a backend's progress notification goes to, and the
+    /// notification with the client's token; None for untracked tokens
+    pub fn route(&self, event: &BackendMessage) -> Option<(String, Bytes)> {
+        let span = progress_token_span(&event.message)?;
+        let token: String = serde_json::from_slice(&event.message[span.clone()]).ok()?;
+        let tracked = self.tokens.get(&token).filter(|t| t.server == event.server)?;
+        Some((tracked.session.clone(), splice(&event.message, span, &tracked.token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(server: &str, token: &str) -> BackendMessage {
+        BackendMessage {
+            server: server.to_string(),
+            method: METHOD.to_string(),
+            message: Bytes::from(format!(r#"{{"jsonrpc":"2.0","method":"notifications/progress","params":{{"progressToken":"{}","progress":1}}}}"#, token)),
+        }
+    }
+
+    #[test]
+    fn test_track_and_route() {
+        let tracker = ProgressTracker::default();
+        let request = Bytes::from_static(br#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"_meta":{"progressToken":7}}}"#);
+        let (sent, token) = tracker.track("a", "github", request);
+        let token = token.unwrap();
+        assert_eq!(&sent[..], format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tools/call\",\"params\":{{\"_meta\":{{\"progressToken\":\"{}\"}}}}}}\n", token).as_bytes());
+
+        // Only the server the request went to reports on it, and the client sees its own token
+        let (session, message) = tracker.route(&progress("github", &token)).unwrap();
+        assert_eq!(session, "a");
+        assert_eq!(&message[..], &b"{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"progressToken\":7,\"progress\":1}}\n"[..]);
+        assert!(tracker.route(&progress("slack", &token)).is_none());
+        assert!(tracker.route(&progress("github", "7")).is_none());
+
+        tracker.release(&token);
+        assert!(tracker.route(&progress("github", &token)).is_none());
+
+        let untracked = Bytes::from_static(br#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+        assert_eq!(tracker.track("a", "github", untracked.clone()), (untracked, None));
+    }
+}
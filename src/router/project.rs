@@ -0,0 +1,226 @@
+//! Per-project servers
+//! Clients started inside a project with a `.mcp-citadel.toml` or `.mcp.json` get
+//! that project's servers, started on demand and visible only to clients of the
+//! same project
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{error, info, warn};
+
+use super::connection::{BackendConnection, BackendMessage};
+use super::{start_server, MCPServerProcess};
+use crate::config::{self, RouterConfig, ServerConfig};
+
+/// Project config files, in lookup order
+pub const PROJECT_FILES: [&str; 2] = [".mcp-citadel.toml", ".mcp.json"];
+
+/// Find the project config for `dir` or its nearest ancestor
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|dir| PROJECT_FILES.iter().map(move |file| dir.join(file)))
+        .find(|path| path.is_file())
+}
+
+/// `.mcp-citadel.toml`
+#[derive(Debug, Deserialize)]
+struct ProjectFile {
+    #[serde(default)]
+    servers: BTreeMap<String, ProjectServer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectServer {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+/// Load a project's servers; they run from the project directory
+pub fn load(path: &Path) -> Result<Vec<ServerConfig>> {
+    let dir = path.parent().context("Project config has no directory")?;
+
+    let mut servers = if path.extension().is_some_and(|e| e == "json") {
+        config::load_claude_config(path)?
+    } else {
+        let content = std::fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+        let file: ProjectFile = toml::from_str(&content).context(format!("Failed to parse {:?}", path))?;
+        file.servers
+            .into_iter()
+            .map(|(name, server)| ServerConfig {
+                name,
+                command: server.command,
+                args: server.args,
+                env: server.env,
+                ..Default::default()
+            })
+            .collect()
+    };
+
+    for server in &mut servers {
+        server.cwd.get_or_insert_with(|| dir.to_path_buf());
+    }
+    Ok(servers)
+}
+
+/// Check that a client may have the hub run a project's servers
+///
+/// The hub runs them as its own user, so the connecting client and the project
+/// file must both belong to that user.
+pub fn authorize(path: &Path, peer_uid: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let hub_uid = nix::unistd::getuid().as_raw();
+    if peer_uid != Some(hub_uid) {
+        anyhow::bail!("Project servers are only available to clients running as the hub's user");
+    }
+    if !path.is_absolute() || !path.file_name().is_some_and(|f| PROJECT_FILES.iter().any(|p| f == *p)) {
+        anyhow::bail!("Not a project config: {:?}", path);
+    }
+    let metadata = std::fs::metadata(path).context(format!("Failed to read {:?}", path))?;
+    if metadata.uid() != hub_uid {
+        anyhow::bail!("{:?} is not owned by the hub's user", path);
+    }
+    Ok(())
+}
+
+struct Project {
+    clients: usize,
+    configs: Vec<ServerConfig>,
+    servers: HashMap<String, MCPServerProcess>,
+}
+
+/// Running project servers, keyed by project config path
+#[derive(Default)]
+pub struct Projects {
+    projects: Mutex<HashMap<PathBuf, Project>>,
+}
+
+impl Projects {
+    /// Register a client of the project at `path`, starting its servers for the first one
+    pub async fn attach(
+        &self,
+        path: &Path,
+        events: &broadcast::Sender<BackendMessage>,
+        router_config: &RouterConfig,
+    ) -> Result<Vec<String>> {
+        let mut projects = self.projects.lock().await;
+        if let Some(project) = projects.get_mut(path) {
+            project.clients += 1;
+            return Ok(project.configs.iter().map(|c| c.name.clone()).collect());
+        }
+
+        let mut configs = load(path)?;
+        let mut servers = HashMap::new();
+        for config in &mut configs {
+            router_config.apply_defaults(config);
+            match start_server(config, events, router_config.auto_provision).await {
+                Ok(server) => {
+                    servers.insert(config.name.clone(), server);
+                }
+                Err(e) => error!("Failed to start project server {} from {:?}: {}", config.name, path, e),
+            }
+        }
+        info!("Started {} project servers from {:?}", servers.len(), path);
+
+        let names = configs.iter().map(|c| c.name.clone()).collect();
+        projects.insert(path.to_path_buf(), Project { clients: 1, configs, servers });
+        Ok(names)
+    }
+
+    /// Unregister a client, stopping the project's servers after the last one
+    pub async fn detach(&self, path: &Path) {
+        let mut projects = self.projects.lock().await;
+        let Some(project) = projects.get_mut(path) else {
+            return;
+        };
+        project.clients -= 1;
+        if project.clients > 0 {
+            return;
+        }
+
+        if let Some(mut project) = projects.remove(path) {
+            info!("Last client of {:?} disconnected, stopping its servers", path);
+            for server in project.servers.values_mut() {
+                if let Err(e) = server.stop().await {
+                    error!("Error stopping project server: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Connection to a project's server, restarting it if it exited
+    pub async fn connection(
+        &self,
+        path: &Path,
+        server: &str,
+        events: &broadcast::Sender<BackendMessage>,
+        router_config: &RouterConfig,
+    ) -> Option<Arc<BackendConnection>> {
+        let mut projects = self.projects.lock().await;
+        let project = projects.get_mut(path)?;
+        let config = project.configs.iter().find(|c| c.name == server)?;
+
+        let exited = project
+            .servers
+            .get_mut(server)
+            .is_none_or(|s| !matches!(s.backend.try_wait(), Ok(None)));
+        if exited {
+            warn!("Project server {} is not running, starting it", server);
+            match start_server(config, events, router_config.auto_provision).await {
+                Ok(process) => {
+                    project.servers.insert(server.to_string(), process);
+                }
+                Err(e) => {
+                    error!("Failed to start project server {}: {}", server, e);
+                    return None;
+                }
+            }
+        }
+
+        project.servers.get(server).map(|s| Arc::clone(&s.connection))
+    }
+
+    /// Stop every project's servers
+    pub async fn stop_all(&self) {
+        let mut projects = self.projects.lock().await;
+        for (_, mut project) in projects.drain() {
+            for server in project.servers.values_mut() {
+                if let Err(e) = server.stop().await {
+                    error!("Error stopping project server: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_and_load() {
+        let root = std::env::temp_dir().join(format!("mcp-citadel-project-{}", std::process::id()));
+        let nested = root.join("src").join("deep");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(
+            root.join(".mcp-citadel.toml"),
+            "[servers.docs]\ncommand = \"node\"\nargs = [\"docs.js\"]\n",
+        )
+        .unwrap();
+
+        let path = discover(&nested).unwrap();
+        assert_eq!(path, root.join(".mcp-citadel.toml"));
+
+        let servers = load(&path).unwrap();
+        assert_eq!(servers[0].name, "docs");
+        assert_eq!(servers[0].cwd.as_deref(), Some(root.as_path()));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+//! Dependency provisioning
+//! Installs a server's missing runtime or pinned package when its command is not found
+
+use tracing::{info, warn};
+
+use crate::config::lockfile::Lockfile;
+use crate::config::ServerConfig;
+use crate::registry::{self, Installer};
+
+/// Try to install whatever a failed spawn was missing
+///
+/// Only acts on "command not found" failures. Returns true if something was
+/// installed and starting the server again is worthwhile.
+pub async fn provision(config: &ServerConfig, error: &anyhow::Error) -> bool {
+    if !is_not_found(error) {
+        return false;
+    }
+
+    let mut installed = false;
+
+    match config.command.as_str() {
+        "uv" | "uvx" => {
+            info!("Installing missing runtime uv for server {}", config.name);
+            match Installer::Pip.install("uv").await {
+                Ok(()) if registry::on_path(&config.command) => installed = true,
+                Ok(()) => warn!("Installed uv, but {} is still not on PATH", config.command),
+                Err(e) => warn!("Failed to install uv: {}", e),
+            }
+        }
+        "npx" | "npm" | "node" => {
+            warn!("Server {} needs Node.js ({} not found); install it from https://nodejs.org", config.name, config.command);
+        }
+        _ => {}
+    }
+
+    let lockfile = Lockfile::load().unwrap_or_else(|e| {
+        warn!("Ignoring unreadable lockfile: {}", e);
+        Lockfile::default()
+    });
+
+    match lockfile.get(&config.name) {
+        Some(locked) => {
+            let spec = locked.spec();
+            info!("Installing pinned package {} for server {}", spec, config.name);
+            match locked.installer.install(&spec).await {
+                Ok(()) => installed = true,
+                Err(e) => warn!("Failed to install {}: {}", spec, e),
+            }
+        }
+        None if !installed => {
+            warn!(
+                "No pinned package for server {}; add it with `mcp-citadel install` so it can be provisioned",
+                config.name
+            );
+        }
+        None => {}
+    }
+
+    installed
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+    })
+}
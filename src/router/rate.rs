@@ -0,0 +1,111 @@
+//! Rate limits
+//! A server's `maxRequestsPerSecond` caps the client requests sent to it with
+//! a token bucket holding up to one second's worth, so short bursts pass and
+//! sustained floods don't. Requests over the rate are answered by the hub
+//! with a rate-limit error saying when to retry, and never reach the backend.
+
+use bytes::Bytes;
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::message::Envelope;
+
+/// JSON-RPC error code of requests over a server's rate limit
+pub const RATE_LIMITED_ERROR: i32 = -32005;
+
+/// A token bucket for one server
+#[derive(Debug)]
+pub struct RateLimit {
+    server: String,
+    per_second: f64,
+    /// Tokens left, as of when they were last counted
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimit {
+    pub fn new(server: &str, per_second: f64) -> Self {
+        let per_second = per_second.max(f64::MIN_POSITIVE);
+        Self {
+            server: server.to_string(),
+            per_second,
+            bucket: Mutex::new((per_second.max(1.0), Instant::now())),
+        }
+    }
+
+    /// Take a token, or learn how long until one is available
+    pub fn take(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, counted) = *bucket;
+        let now = Instant::now();
+        let tokens = (tokens + now.duration_since(counted).as_secs_f64() * self.per_second).min(self.per_second.max(1.0));
+        if tokens >= 1.0 {
+            *bucket = (tokens - 1.0, now);
+            return Ok(());
+        }
+        *bucket = (tokens, now);
+        Err(Duration::from_secs_f64((1.0 - tokens) / self.per_second))
+    }
+
+    /// The error answering `message` if it's a request over the rate, or
+    /// None if it may go through (notifications, responses and pings always do)
+    pub fn rejection(&self, message: &[u8]) -> Option<Bytes> {
+        let envelope = Envelope::parse(message)?;
+        if !envelope.is_request() || envelope.method.as_deref().is_some_and(|m| m == "ping" || m.ends_with("/ping")) {
+            return None;
+        }
+        let retry_after = self.take().err()?;
+        let retry_after_ms = retry_after.as_millis().max(1) as u64;
+        let id = envelope.id.and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+        let error = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {
+                "code": RATE_LIMITED_ERROR,
+                "message": format!("Too many requests to server {} (limit {}/s); retry in {}ms", self.server, self.per_second, retry_after_ms),
+                "data": {
+                    "server": self.server,
+                    "limit_per_second": self.per_second,
+                    "retry_after_ms": retry_after_ms,
+                },
+            },
+        });
+        let mut line = serde_json::to_vec(&error).ok()?;
+        line.push(b'\n');
+        Some(Bytes::from(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let limit = RateLimit::new("github", 2.0);
+        let request = br#"{"jsonrpc":"2.0","id":"a","method":"tools/call","params":{"name":"search"}}"#;
+        assert!(limit.rejection(request).is_none());
+        assert!(limit.rejection(request).is_none());
+
+        let rejection: Value = serde_json::from_slice(&limit.rejection(request).unwrap()).unwrap();
+        assert_eq!(rejection["id"], "a");
+        assert_eq!(rejection["error"]["code"], RATE_LIMITED_ERROR);
+        let retry_after_ms = rejection["error"]["data"]["retry_after_ms"].as_u64().unwrap();
+        assert!((1..=500).contains(&retry_after_ms));
+
+        // Pings and notifications aren't counted
+        assert!(limit.rejection(br#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#).is_none());
+        assert!(limit.rejection(br#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).is_none());
+
+        std::thread::sleep(Duration::from_millis(550));
+        assert!(limit.rejection(request).is_none());
+    }
+
+    #[test]
+    fn test_slow_rate() {
+        let limit = RateLimit::new("fragile", 0.5);
+        assert!(limit.take().is_ok());
+        let retry = limit.take().unwrap_err();
+        assert!(retry > Duration::from_millis(1900) && retry <= Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,613 @@
+//! Remote backends
+//! A server with a `url` is an MCP server elsewhere, spoken to over Streamable
+//! HTTP. A task bridges it to the same in-memory pipe of JSON lines a mock or
+//! a process's stdio gives the hub: each line the hub writes is POSTed, and the
+//! answer, a JSON body or an SSE stream of messages, is written back as lines.
+//! The bridge keeps the server's `Mcp-Session-Id` and protocol version for
+//! later requests, listens on a GET stream for what the server sends on its
+//! own, and ends the session (DELETE) when the server is stopped.
+//!
+//! Servers on the older HTTP+SSE transport (`"type": "sse"`) work the other
+//! way around: the bridge opens their event stream first, learns where to
+//! POST from its `endpoint` event, and every answer arrives on the stream.
+//! Their session lives as long as the stream, so when it drops the bridge
+//! closes the pipe, and the hub's ping probe restarts the server.
+//!
+//! `ws://` and `wss://` servers get one WebSocket, a message per frame, and
+//! TCP daemons one connection of JSON lines. The bridge reconnects with
+//! backoff when it drops, replaying the `initialize` exchange so the new
+//! connection is a working session; requests it was waiting on, or that come
+//! while it's down, fail right away.
+//!
+//! Its connections are pooled and kept alive, and requests (or WebSocket
+//! handshakes) it refuses with 401 are sent again with the token its
+//! `authCommand` prints (see [`super::upstream`]).
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, WriteHalf};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tracing::{debug, warn};
+
+use super::message::Envelope;
+use super::upstream::{self, Credentials};
+use crate::config::{RemoteServer, RemoteTransport};
+
+/// Bytes buffered in each direction of the pipe
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+const SESSION_HEADER: &str = "mcp-session-id";
+const PROTOCOL_HEADER: &str = "mcp-protocol-version";
+
+/// How long the GET stream waits before reconnecting, and a WebSocket at first
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between WebSocket reconnects
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+type Writer = Arc<Mutex<WriteHalf<DuplexStream>>>;
+
+/// A remote server's bridge task and HTTP session
+pub struct Bridge {
+    task: JoinHandle<()>,
+    client: Client,
+}
+
+impl Bridge {
+    /// Stop bridging, and end the session on the server if it has one
+    pub async fn close(&self) {
+        self.task.abort();
+        let Some(session) = self.client.session.read().unwrap().clone() else {
+            return;
+        };
+        let deleted = self.client.send(|c| c.http.delete(&c.url).headers(c.headers(false)).header(SESSION_HEADER, &session)).await;
+        if let Err(e) = deleted {
+            debug!("Failed to end the session of remote server {}: {}", self.client.server, e);
+        }
+    }
+}
+
+/// What every request to the server shares
+#[derive(Clone)]
+struct Client {
+    server: String,
+    url: String,
+    http: reqwest::Client,
+    /// Configured headers, like `Authorization`, and the token `authCommand` printed
+    credentials: Credentials,
+    /// For the WebSocket and TCP connections the bridge opens itself
+    tcp_keepalive: Option<Duration>,
+    session: Arc<RwLock<Option<String>>>,
+    protocol: Arc<RwLock<Option<String>>>,
+}
+
+impl Client {
+    fn headers(&self, post: bool) -> HeaderMap {
+        let mut headers = self.credentials.headers();
+        let accept = if post { "application/json, text/event-stream" } else { "text/event-stream" };
+        headers.insert(ACCEPT, HeaderValue::from_static(accept));
+        if post {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
+        let protocol = self.protocol.read().unwrap().clone();
+        if let Some(version) = protocol.and_then(|v| HeaderValue::from_str(&v).ok()) {
+            headers.insert(PROTOCOL_HEADER, version);
+        }
+        headers
+    }
+
+    /// Send the request `build` makes, once more with fresh credentials if
+    /// the server answers 401
+    async fn send(&self, build: impl Fn(&Self) -> reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        self.credentials.send(|| build(self)).await
+    }
+}
+
+/// Start bridging a remote server; returns the hub's end of the pipe
+pub fn spawn(name: String, remote: &RemoteServer) -> anyhow::Result<(DuplexStream, Bridge)> {
+    let mut extra = HeaderMap::new();
+    for (header, value) in &remote.headers {
+        let header = HeaderName::from_bytes(header.as_bytes()).map_err(|e| anyhow::anyhow!("Invalid header {} for server {}: {}", header, name, e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| anyhow::anyhow!("Invalid {} header for server {}: {}", header, name, e))?;
+        extra.insert(header, value);
+    }
+    let client = Client {
+        credentials: Credentials::new(&name, extra, &remote.upstream),
+        server: name,
+        url: remote.url.clone(),
+        http: upstream::http_client(&remote.upstream)?,
+        tcp_keepalive: upstream::tcp_keepalive(&remote.upstream),
+        session: Arc::default(),
+        protocol: Arc::default(),
+    };
+    let (hub, backend) = tokio::io::duplex(PIPE_CAPACITY);
+    let task = match remote.transport {
+        RemoteTransport::StreamableHttp => tokio::spawn(serve(client.clone(), backend)),
+        RemoteTransport::Sse => tokio::spawn(serve_legacy(client.clone(), backend)),
+        RemoteTransport::WebSocket | RemoteTransport::Tcp => tokio::spawn(serve_persistent(client.clone(), backend, remote.transport)),
+    };
+    Ok((hub, Bridge { task, client }))
+}
+
+async fn serve(client: Client, stream: DuplexStream) {
+    client.credentials.refresh().await;
+    let (reader, writer) = tokio::io::split(stream);
+    let writer: Writer = Arc::new(Mutex::new(writer));
+    let mut reader = BufReader::new(reader);
+    let mut listener: Option<JoinHandle<()>> = None;
+    let mut line = Vec::new();
+
+    while matches!(reader.read_until(b'\n', &mut line).await, Ok(n) if n > 0) {
+        let message = Bytes::from(std::mem::take(&mut line));
+        // Once the session is up, listen for what the server sends on its own
+        if listener.is_none() && message.windows(25).any(|w| w == b"notifications/initialized") {
+            listener = Some(tokio::spawn(listen(client.clone(), Arc::clone(&writer))));
+        }
+        // Requests side by side, as the hub pipelines them; notifications and
+        // responses in order, so `initialized` lands before what follows it
+        if Envelope::parse(&message).is_some_and(|e| e.is_request()) {
+            tokio::spawn(post(client.clone(), Arc::clone(&writer), message));
+        } else {
+            post(client.clone(), Arc::clone(&writer), message).await;
+        }
+    }
+    if let Some(listener) = listener {
+        listener.abort();
+    }
+}
+
+/// POST one message, writing back what the server answers
+async fn post(client: Client, writer: Writer, message: Bytes) {
+    let request: Value = serde_json::from_slice(&message).unwrap_or_default();
+    let id = request.get("method").and(request.get("id")).cloned();
+    let session = client.session.read().unwrap().clone();
+
+    let sent = client.send(|c| {
+        let sent = c.http.post(&c.url).headers(c.headers(true)).body(message.clone());
+        match &session {
+            Some(session) => sent.header(SESSION_HEADER, session),
+            None => sent,
+        }
+    });
+    let failure = match sent.await {
+        Ok(response) if response.status().is_success() => {
+            if let Some(new) = response.headers().get(SESSION_HEADER).and_then(|v| v.to_str().ok()) {
+                *client.session.write().unwrap() = Some(new.to_string());
+            }
+            match read_messages(&client, &writer, response).await {
+                Ok(()) => return,
+                Err(e) => e,
+            }
+        }
+        Ok(response) if response.status() == StatusCode::NOT_FOUND && session.is_some() => {
+            // The server forgot the session; the next initialize starts another
+            *client.session.write().unwrap() = None;
+            format!("remote session expired ({})", response.status())
+        }
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            format!("remote server answered {}: {}", status, body.trim())
+        }
+        Err(e) => format!("request to remote server failed: {}", e),
+    };
+
+    fail(&client, &writer, id, failure).await;
+}
+
+/// Answer a request the server didn't take with an error now, rather than
+/// leave it to time out
+async fn fail(client: &Client, writer: &Writer, id: Option<Value>, failure: String) {
+    warn!("Remote server {}: {}", client.server, failure);
+    if let Some(id) = id {
+        let error = json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32603, "message": failure } });
+        let _ = write(writer, error.to_string().as_bytes()).await;
+    }
+}
+
+/// Write back the messages of a response: a JSON body, an SSE stream, or
+/// nothing (202 Accepted)
+async fn read_messages(client: &Client, writer: &Writer, mut response: reqwest::Response) -> Result<(), String> {
+    let content_type = response.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+    if content_type.starts_with("text/event-stream") {
+        let mut events = EventStream::default();
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("stream from remote server broke: {}", e))? {
+            for event in events.push(&chunk) {
+                note_protocol(client, &event.data);
+                write(writer, &event.data).await.map_err(|e| e.to_string())?;
+            }
+        }
+        return Ok(());
+    }
+    let body = response.bytes().await.map_err(|e| format!("failed to read remote response: {}", e))?;
+    if body.trim_ascii().is_empty() {
+        return Ok(());
+    }
+    let body = single_line(body);
+    note_protocol(client, &body);
+    write(writer, &body).await.map_err(|e| e.to_string())
+}
+
+/// Keep a GET stream open for the server's own requests and notifications,
+/// unless the server doesn't offer one
+async fn listen(client: Client, writer: Writer) {
+    loop {
+        let Some(session) = client.session.read().unwrap().clone() else {
+            return;
+        };
+        let response = client.send(|c| c.http.get(&c.url).headers(c.headers(false)).header(SESSION_HEADER, &session)).await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                if let Err(e) = read_messages(&client, &writer, response).await {
+                    debug!("Remote server {}: {}", client.server, e);
+                }
+            }
+            Ok(response) if response.status() == StatusCode::METHOD_NOT_ALLOWED => return,
+            Ok(response) => debug!("Remote server {} refused a listening stream: {}", client.server, response.status()),
+            Err(e) => debug!("Remote server {} listening stream failed: {}", client.server, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn serve_legacy(client: Client, stream: DuplexStream) {
+    client.credentials.refresh().await;
+    let (reader, writer) = tokio::io::split(stream);
+    let writer: Writer = Arc::new(Mutex::new(writer));
+    let mut reader = BufReader::new(reader);
+    let (endpoint_tx, mut endpoint) = watch::channel(None);
+    let mut events = tokio::spawn(listen_legacy(client.clone(), Arc::clone(&writer), endpoint_tx));
+    let mut line = Vec::new();
+
+    loop {
+        tokio::select! {
+            read = reader.read_until(b'\n', &mut line) => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    break;
+                }
+                let message = Bytes::from(std::mem::take(&mut line));
+                // Nothing can be sent until the stream says where
+                let Ok(url) = endpoint.wait_for(Option::is_some).await.map(|url| url.clone().unwrap_or_default()) else {
+                    break;
+                };
+                post_legacy(&client, &writer, &url, message).await;
+            }
+            _ = &mut events => {
+                warn!("Remote server {} closed its event stream", client.server);
+                break;
+            }
+        }
+    }
+    events.abort();
+}
+
+/// Read a legacy server's event stream: where to POST, then its messages
+async fn listen_legacy(client: Client, writer: Writer, endpoint: watch::Sender<Option<String>>) {
+    let response = client.send(|c| c.http.get(&c.url).headers(c.headers(false))).await;
+    let mut response = match response {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            warn!("Remote server {} refused its event stream: {}", client.server, response.status());
+            return;
+        }
+        Err(e) => {
+            warn!("Remote server {} event stream failed: {}", client.server, e);
+            return;
+        }
+    };
+    let mut events = EventStream::default();
+    while let Ok(Some(chunk)) = response.chunk().await {
+        for event in events.push(&chunk) {
+            match event.name.as_deref() {
+                Some("endpoint") => {
+                    let path = String::from_utf8_lossy(&event.data);
+                    match reqwest::Url::parse(&client.url).and_then(|base| base.join(&path)) {
+                        Ok(url) => {
+                            endpoint.send_replace(Some(url.to_string()));
+                        }
+                        Err(e) => warn!("Remote server {} sent a bad endpoint {}: {}", client.server, path, e),
+                    }
+                }
+                _ => {
+                    if write(&writer, &event.data).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// POST a message to a legacy server's endpoint; answers come on its stream
+async fn post_legacy(client: &Client, writer: &Writer, url: &str, message: Bytes) {
+    let request: Value = serde_json::from_slice(&message).unwrap_or_default();
+    let id = request.get("method").and(request.get("id")).cloned();
+    let failure = match client.send(|c| c.http.post(url).headers(c.headers(true)).body(message.clone())).await {
+        Ok(response) if response.status().is_success() => return,
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            format!("remote server answered {}: {}", status, body.trim())
+        }
+        Err(e) => format!("request to remote server failed: {}", e),
+    };
+    fail(client, writer, id, failure).await;
+}
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A persistent connection to a WebSocket or TCP server
+enum Link {
+    WebSocket(Box<Socket>),
+    /// JSON lines, as over a process's stdio
+    Tcp {
+        reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+        writer: tokio::net::tcp::OwnedWriteHalf,
+        /// A line read so far, kept if a read is cancelled
+        line: Vec<u8>,
+    },
+}
+
+impl Link {
+    /// Connect, and if a WebSocket handshake is refused with 401, connect
+    /// once more with fresh credentials
+    async fn connect(client: &Client, transport: RemoteTransport) -> anyhow::Result<Self> {
+        match Self::open(client, transport).await {
+            Err(e) if is_unauthorized(&e) && client.credentials.refresh().await => Self::open(client, transport).await,
+            opened => opened,
+        }
+    }
+
+    async fn open(client: &Client, transport: RemoteTransport) -> anyhow::Result<Self> {
+        if transport == RemoteTransport::Tcp {
+            let address = client.url.trim_start_matches("tcp://");
+            let (reader, writer) = upstream::connect_tcp(address, client.tcp_keepalive).await?.into_split();
+            return Ok(Link::Tcp { reader: BufReader::new(reader), writer, line: Vec::new() });
+        }
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        let mut request = client.url.as_str().into_client_request()?;
+        request.headers_mut().extend(client.credentials.headers());
+        // Opened here rather than by tungstenite, for the keep-alive
+        let url = reqwest::Url::parse(&client.url)?;
+        let address = format!("{}:{}", url.host_str().unwrap_or_default(), url.port_or_known_default().unwrap_or(80));
+        let stream = upstream::connect_tcp(&address, client.tcp_keepalive).await?;
+        let (socket, _) = tokio_tungstenite::client_async_tls(request, stream).await?;
+        Ok(Link::WebSocket(Box::new(socket)))
+    }
+
+    async fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        let message = message.trim_ascii_end();
+        match self {
+            Link::WebSocket(socket) => socket.send(WsMessage::Text(String::from_utf8_lossy(message).into_owned())).await?,
+            Link::Tcp { writer, .. } => {
+                writer.write_all(message).await?;
+                writer.write_all(b"\n").await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The next message from the server; None once the connection is gone
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        match self {
+            Link::WebSocket(socket) => loop {
+                match socket.next().await? {
+                    Ok(WsMessage::Text(text)) => return Some(text.into_bytes()),
+                    Ok(WsMessage::Binary(data)) => return Some(data),
+                    Ok(WsMessage::Close(_)) | Err(_) => return None,
+                    Ok(_) => {}
+                }
+            },
+            Link::Tcp { reader, line, .. } => loop {
+                match reader.read_until(b'\n', line).await {
+                    Ok(n) if n > 0 && line.trim_ascii().is_empty() => line.clear(),
+                    Ok(n) if n > 0 => return Some(std::mem::take(line)),
+                    _ => return None,
+                }
+            },
+        }
+    }
+}
+
+/// Bridge a server the hub keeps one connection to
+async fn serve_persistent(client: Client, stream: DuplexStream, transport: RemoteTransport) {
+    client.credentials.refresh().await;
+    let (reader, writer) = tokio::io::split(stream);
+    let writer: Writer = Arc::new(Mutex::new(writer));
+    let mut reader = BufReader::new(reader);
+    let mut link: Option<Link> = None;
+    // The hub's `initialize` and `initialized`, replayed to each new connection
+    let mut handshake: Vec<Bytes> = Vec::new();
+    // Ids of requests sent and not answered, and of replayed `initialize`s
+    // whose answers the hub isn't waiting for
+    let mut pending: HashSet<String> = HashSet::new();
+    let mut replayed: HashSet<String> = HashSet::new();
+    let mut retry_at = tokio::time::Instant::now();
+    let mut delay = RECONNECT_DELAY;
+    let mut line = Vec::new();
+
+    loop {
+        if link.is_none() && tokio::time::Instant::now() >= retry_at {
+            match connect(&client, transport, &handshake).await {
+                Ok((connected, id)) => {
+                    debug!("Connected to remote server {}", client.server);
+                    replayed.extend(id);
+                    link = Some(connected);
+                    delay = RECONNECT_DELAY;
+                }
+                Err(e) => {
+                    warn!("Failed to connect to remote server {} (retrying in {:?}): {}", client.server, delay, e);
+                    retry_at = tokio::time::Instant::now() + delay;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+
+        let dropped = tokio::select! {
+            read = reader.read_until(b'\n', &mut line) => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    break;
+                }
+                let message = Bytes::from(std::mem::take(&mut line));
+                let envelope = Envelope::parse(&message);
+                let id = envelope.as_ref().filter(|e| e.is_request()).and_then(|e| e.id).map(|id| id.get().to_string());
+                match envelope.as_ref().and_then(|e| e.method.as_deref()) {
+                    Some("initialize") => handshake = vec![message.clone()],
+                    Some("notifications/initialized") => handshake.push(message.clone()),
+                    _ => {}
+                }
+                let sent = match &mut link {
+                    Some(link) => link.send(&message).await.map_err(|e| e.to_string()),
+                    None => Err("not connected".to_string()),
+                };
+                match sent {
+                    Ok(()) => {
+                        pending.extend(id);
+                        false
+                    }
+                    Err(e) => {
+                        let value = id.and_then(|id| serde_json::from_str(&id).ok());
+                        fail(&client, &writer, value, format!("remote server unavailable ({}); reconnecting", e)).await;
+                        link.is_some()
+                    }
+                }
+            }
+            incoming = async { link.as_mut().unwrap().recv().await }, if link.is_some() => match incoming {
+                Some(message) => receive(&writer, message, &mut pending, &mut replayed).await.is_err(),
+                None => true,
+            },
+            _ = tokio::time::sleep_until(retry_at), if link.is_none() => false,
+        };
+
+        if dropped {
+            warn!("Lost the connection to remote server {}", client.server);
+            link = None;
+            replayed.clear();
+            for id in pending.drain() {
+                let id = serde_json::from_str(&id).ok();
+                fail(&client, &writer, id, "connection to remote server lost".to_string()).await;
+            }
+        }
+    }
+}
+
+/// Connect to a server, replaying the handshake if the hub has made one;
+/// returns the id of the replayed `initialize`
+async fn connect(client: &Client, transport: RemoteTransport, handshake: &[Bytes]) -> anyhow::Result<(Link, Option<String>)> {
+    let mut link = Link::connect(client, transport).await?;
+    let mut initialize = None;
+    for message in handshake {
+        initialize = initialize.or_else(|| Envelope::parse(message).and_then(|e| e.id).map(|id| id.get().to_string()));
+        link.send(message).await?;
+    }
+    Ok((link, initialize))
+}
+
+/// Whether a WebSocket handshake failed on a 401
+fn is_unauthorized(error: &anyhow::Error) -> bool {
+    use tokio_tungstenite::tungstenite::Error;
+    matches!(error.downcast_ref::<Error>(), Some(Error::Http(response)) if response.status().as_u16() == StatusCode::UNAUTHORIZED.as_u16())
+}
+
+/// Pass on a message from a connected server, unless it answers a replayed
+/// `initialize`
+async fn receive(writer: &Writer, message: Vec<u8>, pending: &mut HashSet<String>, replayed: &mut HashSet<String>) -> std::io::Result<()> {
+    let message = single_line(Bytes::from(message));
+    if let Some(id) = Envelope::parse(&message).filter(|e| e.method.is_none()).and_then(|e| e.id).map(|id| id.get().to_string()) {
+        if replayed.remove(&id) {
+            return Ok(());
+        }
+        pending.remove(&id);
+    }
+    write(writer, &message).await
+}
+
+/// Take note of the protocol version the server settled on in `initialize`
+fn note_protocol(client: &Client, message: &[u8]) {
+    if !message.windows(15).any(|w| w == b"protocolVersion") {
+        return;
+    }
+    let Ok(message) = serde_json::from_slice::<Value>(message) else {
+        return;
+    };
+    if let Some(version) = message.pointer("/result/protocolVersion").and_then(Value::as_str) {
+        *client.protocol.write().unwrap() = Some(version.to_string());
+    }
+}
+
+/// A message on one line, as the hub reads them
+fn single_line(message: Bytes) -> Bytes {
+    let message = message.trim_ascii();
+    if !message.contains(&b'\n') {
+        return Bytes::copy_from_slice(message);
+    }
+    match serde_json::from_slice::<Value>(message) {
+        Ok(value) => Bytes::from(value.to_string()),
+        Err(_) => Bytes::from(message.iter().map(|&b| if b == b'\n' || b == b'\r' { b' ' } else { b }).collect::<Vec<_>>()),
+    }
+}
+
+async fn write(writer: &Writer, message: &[u8]) -> std::io::Result<()> {
+    let mut writer = writer.lock().await;
+    writer.write_all(message).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await
+}
+
+/// A server-sent event
+#[derive(Debug, PartialEq)]
+struct Event {
+    /// The `event:` field; unnamed events are messages
+    name: Option<String>,
+    data: Bytes,
+}
+
+/// Server-sent events split out of a byte stream
+#[derive(Default)]
+struct EventStream {
+    buffer: BytesMut,
+}
+
+impl EventStream {
+    fn push(&mut self, chunk: &[u8]) -> Vec<Event> {
+        // Lines may end in CRLF
+        self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r'));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event = self.buffer.split_to(end + 2);
+            let field = |line: &'_ [u8], name: &[u8]| {
+                let value = line.strip_prefix(name)?.strip_prefix(b":")?;
+                Some(value.strip_prefix(b" ").unwrap_or(value).to_vec())
+            };
+            let lines: Vec<&[u8]> = event.as_ref().split(|&b| b == b'\n').collect();
+            let data: Vec<Vec<u8>> = lines.iter().filter_map(|line| field(line, b"data")).collect();
+            let name = lines.iter().find_map(|line| field(line, b"event")).map(|name| String::from_utf8_lossy(&name).into_owned());
+            if !data.is_empty() {
+                events.push(Event { name, data: single_line(Bytes::from(data.join(&b'\n'))) });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_stream() {
+        let mut events = EventStream::default();
+        assert!(events.push(b"event: message\ndata: {\"id\":1,").is_empty());
+        let data = events.push(b"\"result\":{}}\n\n: keepalive\n\ndata: {\"a\":\ndata: 1}\r\n\r\n");
+        let message = |name: Option<&str>, data: &'static [u8]| Event { name: name.map(String::from), data: Bytes::from_static(data) };
+        assert_eq!(data, [message(Some("message"), b"{\"id\":1,\"result\":{}}"), message(None, b"{\"a\":1}")]);
+    }
+}
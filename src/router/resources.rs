@@ -0,0 +1,118 @@
+//! Resource limits
+//! A server's `limits` cap its process: open files through `RLIMIT_NOFILE`,
+//! set before it starts; CPU and memory through a cgroup of its own on Linux
+//! (cgroups v2, when the hub may create one under `/sys/fs/cgroup`), so the
+//! kernel throttles it past its CPU share and kills it past its memory.
+//! Memory isn't an `RLIMIT_AS`, as runtimes like V8 reserve far more address
+//! space than they use: without a cgroup the health check samples what the
+//! process group has resident and restarts a server over its ceiling.
+
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+use crate::config::ResourceLimits;
+
+/// Parent of the servers' cgroups
+#[cfg(target_os = "linux")]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/mcp-citadel";
+
+/// Period `cpu.max` quotas are measured in, in microseconds
+const CPU_PERIOD_US: u64 = 100_000;
+
+const MB: u64 = 1024 * 1024;
+
+/// A server process's cgroup, removed when dropped (after the process exits)
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir(&self.path) {
+            debug!("Failed to remove cgroup {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// `cpu.max` for a share of one core
+fn cpu_max(percent: u32) -> String {
+    format!("{} {}", u64::from(percent.max(1)) * CPU_PERIOD_US / 100, CPU_PERIOD_US)
+}
+
+/// Put a just-started server process in a cgroup enforcing its CPU and
+/// memory limits; None if it has neither, or the cgroup can't be made
+pub fn confine(server: &str, pid: u32, limits: &ResourceLimits) -> Option<Cgroup> {
+    if limits.cpu_percent.is_none() && limits.memory_mb.is_none() {
+        return None;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        match create(server, pid, limits) {
+            Ok(cgroup) => return Some(cgroup),
+            Err(e) => warn!("No cgroup for server {} ({:#}); only its memory limit is checked, by the health check", server, e),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        if limits.cpu_percent.is_some() {
+            warn!("The CPU limit of server {} needs Linux cgroups; not enforced", server);
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn create(server: &str, pid: u32, limits: &ResourceLimits) -> anyhow::Result<Cgroup> {
+    use anyhow::Context;
+    let root = PathBuf::from(CGROUP_ROOT);
+    let unified = root.parent().is_some_and(|parent| parent.join("cgroup.controllers").exists());
+    anyhow::ensure!(unified, "cgroups v2 isn't mounted at /sys/fs/cgroup");
+    std::fs::create_dir_all(&root).context(format!("Failed to create {}", root.display()))?;
+    // Controllers the servers' cgroups get; already enabled is fine
+    let _ = std::fs::write(root.join("cgroup.subtree_control"), "+cpu +memory");
+
+    let cgroup = Cgroup { path: root.join(format!("{}-{}", server, pid)) };
+    std::fs::create_dir(&cgroup.path).context(format!("Failed to create {}", cgroup.path.display()))?;
+    let write = |file: &str, value: String| {
+        std::fs::write(cgroup.path.join(file), value).context(format!("Failed to write {}", file))
+    };
+    if let Some(percent) = limits.cpu_percent {
+        write("cpu.max", cpu_max(percent))?;
+    }
+    if let Some(mb) = limits.memory_mb {
+        write("memory.max", (mb * MB).to_string())?;
+    }
+    write("cgroup.procs", pid.to_string())?;
+    Ok(cgroup)
+}
+
+/// Why a running server is over its memory limit, if it is
+pub fn over_memory(pid: u32, limits: &ResourceLimits) -> Option<String> {
+    let limit = limits.memory_mb?;
+    let used = crate::platform::memory_usage(pid)?;
+    (used > limit * MB).then(|| format!("using {} MB, over its {} MB memory limit", used / MB, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_max() {
+        assert_eq!(cpu_max(50), "50000 100000");
+        assert_eq!(cpu_max(200), "200000 100000");
+        assert_eq!(cpu_max(0), "1000 100000");
+    }
+
+    #[test]
+    fn test_over_memory() {
+        let limits = |memory_mb| ResourceLimits { memory_mb, ..Default::default() };
+        let pid = std::process::id();
+        assert!(over_memory(pid, &limits(None)).is_none());
+        assert!(over_memory(pid, &limits(Some(1 << 20))).is_none());
+        #[cfg(unix)]
+        assert!(over_memory(pid, &limits(Some(0))).is_some_and(|reason| reason.ends_with("over its 0 MB memory limit")));
+    }
+}
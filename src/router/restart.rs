@@ -0,0 +1,59 @@
+//! Restart policy
+//! What the health check does with a server that exited, hung, or failed its
+//! health check: the server's `restartPolicy` decides whether it comes back,
+//! each restart in a row waits twice as long as the one before (with jitter,
+//! up to `router.restarts.backoff_max_secs`), and once a server has stayed up
+//! for the cool-down window its attempts start over.
+
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::config::{RestartConfig, RestartPolicy};
+
+/// Whether a server is restarted after exiting with `exit`, or after hanging
+/// or failing its health check (`None`)
+pub fn restarts(policy: RestartPolicy, exit: Option<&ExitStatus>) -> bool {
+    match policy {
+        RestartPolicy::Always => true,
+        RestartPolicy::OnFailure => exit.is_none_or(|status| !status.success()),
+        RestartPolicy::Never => false,
+    }
+}
+
+/// Delay before restart `attempt` (from 1): doubled for each attempt up to
+/// the cap, less up to a fifth at random so servers that failed together
+/// don't come back in lockstep
+pub fn backoff(config: &RestartConfig, attempt: u32) -> Duration {
+    let initial = Duration::from_millis(config.backoff_initial_ms);
+    let delay = initial
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(Duration::from_secs(config.backoff_max_secs));
+    let random = uuid::Uuid::new_v4().as_u128() as u32;
+    delay.mul_f64(1.0 - 0.2 * f64::from(random) / f64::from(u32::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_policy_and_backoff() {
+        let (clean, failed) = (ExitStatus::from_raw(0), ExitStatus::from_raw(1 << 8));
+        assert!(restarts(RestartPolicy::Always, Some(&clean)));
+        assert!(!restarts(RestartPolicy::OnFailure, Some(&clean)));
+        assert!(restarts(RestartPolicy::OnFailure, Some(&failed)));
+        assert!(restarts(RestartPolicy::OnFailure, None));
+        assert!(!restarts(RestartPolicy::Never, Some(&failed)));
+
+        let config = RestartConfig { backoff_initial_ms: 1000, backoff_max_secs: 5, ..Default::default() };
+        let within = |attempt, secs: f64| {
+            let delay = backoff(&config, attempt).as_secs_f64();
+            delay <= secs && delay >= secs * 0.8
+        };
+        assert!(within(1, 1.0));
+        assert!(within(3, 4.0));
+        assert!(within(4, 5.0));
+        assert!(within(40, 5.0));
+    }
+}
@@ -0,0 +1,191 @@
+//! Reverse routing
+//! Requests a backend sends its client (`sampling/createMessage`,
+//! `roots/list`, `elicitation/create`) go to one client session: the last one
+//! to send that server a request, among those whose client can take the
+//! method. Each forwarded request gets an id of the hub's, so a client
+//! answering requests from several servers can't mix them up, and the
+//! backend's own id is put back on the client's response on its way back:
+//!
+//! ```text
+//! ← {"jsonrpc":"2.0","id":"citadel-1","method":"sampling/createMessage","params":{...}}
+//! → {"jsonrpc":"2.0","id":"citadel-1","result":{"model":"...","content":{...}}}
+//! ```
+//!
+//! The same channels carry each session's progress notifications (see
+//! `super::progress`).
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+use super::connection::BackendMessage;
+use super::message::{splice, Envelope};
+use super::progress::ProgressTracker;
+
+/// Prefix of the ids the hub gives forwarded requests
+const ID_PREFIX: &str = "citadel-";
+
+/// A request for a session: the server it came from, and the message with
+/// the hub's id
+pub type Forwarded = (String, Bytes);
+
+/// A forwarded request waiting for its client's response
+struct Outstanding {
+    server: String,
+    session: String,
+    /// The backend's own id, as raw JSON
+    id: Bytes,
+}
+
+#[derive(Default)]
+pub struct ReverseRouter {
+    /// Channel to each session that can take requests
+    sessions: DashMap<String, mpsc::UnboundedSender<Forwarded>>,
+    /// Sessions by server, the last to send it a request first
+    recent: Mutex<HashMap<String, Vec<String>>>,
+    /// Forwarded requests by the hub's id
+    outstanding: DashMap<String, Outstanding>,
+    next_id: AtomicU64,
+    /// Sessions' requests that asked for progress
+    progress: ProgressTracker,
+}
+
+impl ReverseRouter {
+    /// Take requests for a session, until it's closed
+    pub fn open(&self, session: &str) -> mpsc::UnboundedReceiver<Forwarded> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sessions.insert(session.to_string(), tx);
+        rx
+    }
+
+    /// Forget a session, along with the requests it never answered
+    pub fn close(&self, session: &str) {
+        self.sessions.remove(session);
+        for sessions in self.recent.lock().unwrap().values_mut() {
+            sessions.retain(|s| s != session);
+        }
+        self.outstanding.retain(|_, outstanding| outstanding.session != session);
+        self.progress.close(session);
+    }
+
+    /// Requests' progress tracking
+    pub fn progress(&self) -> &ProgressTracker {
+        &self.progress
+    }
+
+    /// Pass a backend's progress notification to the session whose request it
+    /// reports on; false if there's none
+    pub fn dispatch_progress(&self, event: &BackendMessage) -> bool {
+        let Some((session, message)) = self.progress.route(event) else {
+            return false;
+        };
+        self.deliver(&session, (event.server.clone(), message))
+    }
+
+    /// Send a session a message from a server; false if it's closed
+    pub fn deliver(&self, session: &str, forwarded: Forwarded) -> bool {
+        self.sessions.get(session).is_some_and(|tx| tx.send(forwarded).is_ok())
+    }
+
+    /// Note a session's request to a server
+    pub fn used(&self, server: &str, session: &str) {
+        if !self.sessions.contains_key(session) {
+            return;
+        }
+        let mut recent = self.recent.lock().unwrap();
+        let sessions = recent.entry(server.to_string()).or_default();
+        if sessions.first().map(String::as_str) != Some(session) {
+            sessions.retain(|s| s != session);
+            sessions.insert(0, session.to_string());
+        }
+    }
+
+    /// Forward a backend's request to the last session using its server that
+    /// `accepts` it; false if there's none
+    pub fn dispatch(&self, event: &BackendMessage, accepts: impl Fn(&str) -> bool) -> bool {
+        let Some(span) = Envelope::parse(&event.message).and_then(|e| e.id_span(&event.message)) else {
+            return false;
+        };
+        let candidates = self.recent.lock().unwrap().get(&event.server).cloned().unwrap_or_default();
+        for session in candidates.into_iter().filter(|s| accepts(s)) {
+            let Some(tx) = self.sessions.get(&session).map(|tx| tx.clone()) else {
+                continue;
+            };
+            let id = format!("{}{}", ID_PREFIX, self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+            let message = splice(&event.message, span.clone(), format!("\"{}\"", id).as_bytes());
+            // Before sending, so a quick answer finds it
+            let outstanding = Outstanding {
+                server: event.server.clone(),
+                session,
+                id: event.message.slice(span.clone()),
+            };
+            self.outstanding.insert(id.clone(), outstanding);
+            if tx.send((event.server.clone(), message)).is_ok() {
+                return true;
+            }
+            self.outstanding.remove(&id);
+        }
+        false
+    }
+
+    /// A session's response to a request forwarded to it: the server it goes
+    /// to, and the message with the backend's id; None for any other message
+    pub fn response(&self, session: &str, message: &[u8]) -> Option<Forwarded> {
+        let envelope = Envelope::parse(message)?;
+        if envelope.method.is_some() {
+            return None;
+        }
+        let span = envelope.id_span(message)?;
+        let id: String = serde_json::from_str(envelope.id?.get()).ok()?;
+        if !id.starts_with(ID_PREFIX) {
+            return None;
+        }
+        let (_, outstanding) = self.outstanding.remove_if(&id, |_, outstanding| outstanding.session == session)?;
+        Some((outstanding.server, splice(message, span, &outstanding.id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(server: &str, id: u64) -> BackendMessage {
+        BackendMessage {
+            server: server.to_string(),
+            method: "sampling/createMessage".to_string(),
+            message: Bytes::from(format!(r#"{{"jsonrpc":"2.0","id":{},"method":"sampling/createMessage","params":{{}}}}"#, id)),
+        }
+    }
+
+    #[test]
+    fn test_dispatch_and_response() {
+        let router = ReverseRouter::default();
+        let (mut a, mut b) = (router.open("a"), router.open("b"));
+        router.used("github", "a");
+        router.used("github", "b");
+        router.used("slack", "a");
+
+        // The last session to use the server gets it, unless it can't take it
+        assert!(router.dispatch(&request("github", 7), |_| true));
+        let (server, message) = b.try_recv().unwrap();
+        assert_eq!(server, "github");
+        assert!(message.starts_with(br#"{"jsonrpc":"2.0","id":"citadel-1","#));
+        assert!(router.dispatch(&request("github", 8), |s| s == "a"));
+        assert!(a.try_recv().is_ok());
+        assert!(!router.dispatch(&request("jira", 1), |_| true));
+
+        // Only the session it went to can answer, once, with the backend's id restored
+        let answer = br#"{"jsonrpc":"2.0","id":"citadel-1","result":{}}"#;
+        assert!(router.response("a", answer).is_none());
+        let (server, message) = router.response("b", answer).unwrap();
+        assert_eq!((server.as_str(), &message[..]), ("github", &b"{\"jsonrpc\":\"2.0\",\"id\":7,\"result\":{}}\n"[..]));
+        assert!(router.response("b", answer).is_none());
+
+        router.close("b");
+        assert!(router.dispatch(&request("github", 9), |_| true));
+        assert!(a.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,174 @@
+//! Client sessions
+//! Per-connection state for Unix socket clients, and the initialization state
+//! of each backend process they share.
+//!
+//! A backend process is initialized once: the first client `initialize` goes
+//! to it and later ones are answered with its recorded result, so clients can
+//! (re)connect without the backend seeing a second handshake. Each session
+//! remembers its own `initialize` params and resource subscriptions; when the
+//! backend is replaced (crash, restart), the session's next message first
+//! replays them to the new process. When the connection drops, its
+//! subscriptions are released.
+
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::backend_method;
+use super::message::Envelope;
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Initialization state of one backend process
+#[derive(Debug)]
+pub struct BackendInit {
+    /// Unique per started process, so sessions notice replacements
+    pub generation: u64,
+    /// The backend's `initialize` result, once a client initialized it
+    pub result: tokio::sync::Mutex<Option<Value>>,
+    /// `notifications/initialized` was forwarded
+    pub notified: AtomicBool,
+}
+
+impl BackendInit {
+    pub fn new() -> Self {
+        Self {
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+            result: tokio::sync::Mutex::new(None),
+            notified: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for BackendInit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a session replays to a replaced backend process
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub initialize: Value,
+    pub subscriptions: Vec<String>,
+}
+
+/// State of one client connection
+#[derive(Debug, Default)]
+pub struct ClientSession {
+    /// Identifies the session in transcripts and `mcp-citadel session`
+    pub id: String,
+    /// Peer credentials of the Unix socket connection
+    pub peer_uid: Option<u32>,
+    pub unix_user: Option<String>,
+    servers: Mutex<HashMap<String, ServerState>>,
+}
+
+#[derive(Debug, Default)]
+struct ServerState {
+    /// Params of the client's `initialize`
+    initialize: Option<Value>,
+    /// Backend process generation the session last initialized
+    generation: Option<u64>,
+    /// Resource URIs the client subscribed to
+    subscriptions: BTreeSet<String>,
+}
+
+impl ClientSession {
+    pub fn new(peer_uid: Option<u32>, unix_user: Option<String>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            peer_uid,
+            unix_user,
+            ..Default::default()
+        }
+    }
+
+    /// Record a client message to `server`, whose current process is
+    /// `generation`; returns what to replay first if that process never saw
+    /// this session's handshake
+    pub fn track(&self, server: &str, message: &[u8], generation: u64) -> Option<Replay> {
+        let envelope = Envelope::parse(message)?;
+        let method = envelope.method.as_deref().map(|m| backend_method(server, m));
+        let mut servers = self.servers.lock().unwrap();
+        let state = servers.entry(server.to_string()).or_default();
+
+        let uri = || {
+            let params: Value = serde_json::from_str(envelope.params?.get()).ok()?;
+            params.get("uri")?.as_str().map(String::from)
+        };
+        match method {
+            Some("initialize") => {
+                state.initialize = envelope.params.and_then(|p| serde_json::from_str(p.get()).ok());
+                state.generation = Some(generation);
+                return None;
+            }
+            Some("resources/subscribe") => {
+                state.subscriptions.extend(uri());
+            }
+            Some("resources/unsubscribe") => {
+                if let Some(uri) = uri() {
+                    state.subscriptions.remove(&uri);
+                }
+            }
+            _ => {}
+        }
+
+        let initialize = state.initialize.clone()?;
+        if state.generation == Some(generation) {
+            return None;
+        }
+        state.generation = Some(generation);
+        Some(Replay {
+            initialize,
+            subscriptions: state.subscriptions.iter().cloned().collect(),
+        })
+    }
+
+    /// Subscriptions to release when the connection closes, by server and the
+    /// process generation they were made on
+    pub fn subscriptions(&self) -> Vec<(String, u64, Vec<String>)> {
+        self.servers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| !state.subscriptions.is_empty())
+            .filter_map(|(server, state)| {
+                Some((server.clone(), state.generation?, state.subscriptions.iter().cloned().collect()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_after_backend_replaced() {
+        let session = ClientSession::default();
+        let initialize = br#"{"jsonrpc":"2.0","id":1,"method":"github/initialize","params":{"protocolVersion":"2025-06-18"}}"#;
+        assert_eq!(session.track("github", initialize, 1), None);
+
+        let subscribe = br#"{"jsonrpc":"2.0","id":2,"method":"resources/subscribe","params":{"uri":"repo://a"}}"#;
+        assert_eq!(session.track("github", subscribe, 1), None);
+
+        // The process was replaced: replay the handshake and subscriptions once
+        let list = br#"{"jsonrpc":"2.0","id":3,"method":"tools/list"}"#;
+        let replay = session.track("github", list, 2).unwrap();
+        assert_eq!(replay.initialize["protocolVersion"], "2025-06-18");
+        assert_eq!(replay.subscriptions, vec!["repo://a".to_string()]);
+        assert_eq!(session.track("github", list, 2), None);
+
+        assert_eq!(session.subscriptions(), vec![("github".to_string(), 2, vec!["repo://a".to_string()])]);
+    }
+
+    #[test]
+    fn test_no_replay_without_initialize() {
+        let session = ClientSession::default();
+        let list = br#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#;
+        assert_eq!(session.track("github", list, 1), None);
+        assert_eq!(session.track("github", list, 2), None);
+    }
+}
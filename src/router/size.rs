@@ -0,0 +1,266 @@
+//! Response size guard
+//! Keeps oversized `tools/call` results away from clients with small context
+//! windows: the result's content is cut down to the server's
+//! `maxResponseBytes` with an explicit marker, or the response is replaced by
+//! an error stating its actual size.
+//!
+//! Also bounds what a backend line may cost the hub: `read_line` stops
+//! buffering at `max_message_bytes` and skips the rest of the line, so a
+//! runaway payload neither exhausts memory nor desynchronizes the framing.
+
+use bytes::Bytes;
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tracing::warn;
+
+use super::message::Envelope;
+use crate::config::OversizedResponse;
+
+/// Room left for the JSON-RPC envelope around the truncated content
+const ENVELOPE_ALLOWANCE: usize = 256;
+
+/// Apply a size limit to a backend's `tools/call` response
+pub fn enforce(server: &str, response: Bytes, max_bytes: usize, strategy: OversizedResponse) -> Bytes {
+    if response.len() <= max_bytes {
+        return response;
+    }
+    let actual = response.len();
+
+    if strategy == OversizedResponse::Truncate {
+        if let Some(truncated) = truncate(&response, max_bytes) {
+            warn!("Truncated {} byte tools/call result from {} to {} bytes", actual, server, truncated.len());
+            return truncated;
+        }
+    }
+
+    warn!("Rejected {} byte tools/call result from {} (limit {})", actual, server, max_bytes);
+    let id = Envelope::parse(&response)
+        .and_then(|e| e.id)
+        .and_then(|id| serde_json::from_str::<Value>(id.get()).ok());
+    let error = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": -32000,
+            "message": format!("Response from {} is {} bytes, over the {} byte limit", server, actual, max_bytes),
+            "data": { "size": actual, "limit": max_bytes },
+        },
+    });
+    line(&error)
+}
+
+/// Cut a tool result's content down to fit, ending it with a marker;
+/// None if the response isn't a tool result or still doesn't fit
+fn truncate(response: &[u8], max_bytes: usize) -> Option<Bytes> {
+    let mut value: Value = serde_json::from_slice(response).ok()?;
+    let result = value.get_mut("result")?.as_object_mut()?;
+    // Structured output duplicates the content and can't be cut meaningfully
+    result.remove("structuredContent");
+    let content = result.get_mut("content")?.as_array_mut()?;
+
+    let marker = format!(
+        "[truncated by mcp-citadel: result was {} bytes, limit {}]",
+        response.len(),
+        max_bytes
+    );
+    let mut budget = max_bytes.checked_sub(marker.len() + ENVELOPE_ALLOWANCE)?;
+    let mut kept = Vec::new();
+    for mut item in content.drain(..) {
+        let size = serde_json::to_vec(&item).map_or(usize::MAX, |v| v.len());
+        if size <= budget {
+            budget -= size;
+            kept.push(item);
+            continue;
+        }
+        // Keep as much of the first text item that doesn't fit as possible
+        if let Some(Value::String(text)) = item.get_mut("text") {
+            let mut end = budget.saturating_sub(size - text.len()).min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end > 0 {
+                text.truncate(end);
+                kept.push(item);
+            }
+        }
+        break;
+    }
+    kept.push(serde_json::json!({ "type": "text", "text": marker }));
+    *content = kept;
+
+    let out = line(&value);
+    (out.len() <= max_bytes).then_some(out)
+}
+
+/// Outcome of reading one newline-delimited message
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    Eof,
+    /// The message is in the buffer
+    Complete,
+    /// The message was longer than the limit and was skipped; the buffer
+    /// holds its first `max_bytes` and `tail` its last bytes
+    Oversized { size: usize, tail: Vec<u8> },
+}
+
+/// Bytes kept from the end of an oversized message, enough for a trailing id
+pub const TAIL_BYTES: usize = 64;
+
+/// Read up to and including the next newline into `buf`, buffering at most
+/// `max_bytes` (0: unlimited)
+pub async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>, max_bytes: usize) -> std::io::Result<Line> {
+    let limit = if max_bytes == 0 { usize::MAX } else { max_bytes };
+    let mut size = 0;
+    let mut tail = Vec::new();
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        let (chunk, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..=i], true),
+            None => (available, false),
+        };
+        let n = chunk.len();
+        if size + n <= limit {
+            buf.extend_from_slice(chunk);
+        } else {
+            buf.extend_from_slice(&chunk[..limit.saturating_sub(size)]);
+            tail.extend_from_slice(&chunk[n.saturating_sub(TAIL_BYTES)..]);
+            tail.drain(..tail.len().saturating_sub(TAIL_BYTES));
+        }
+        size += n;
+        reader.consume(n);
+        if done {
+            break;
+        }
+    }
+
+    Ok(match size {
+        0 => Line::Eof,
+        _ if size <= limit => Line::Complete,
+        _ => Line::Oversized { size, tail },
+    })
+}
+
+/// The top-level numeric id of a message known only by its start and end,
+/// for failing the request an oversized response answers
+pub fn truncated_id(head: &[u8], tail: &[u8]) -> Option<u64> {
+    leading_id(head).or_else(|| trailing_id(tail))
+}
+
+/// `{"jsonrpc":"2.0","id":7,...`: an `id` key at depth 1 before the first value
+/// that is too long to scan
+fn leading_id(head: &[u8]) -> Option<u64> {
+    let (mut depth, mut in_string, mut escaped, mut key_start) = (0, false, false, None);
+    for (i, &b) in head.iter().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    in_string = false;
+                    let is_id = depth == 1 && key_start.is_some_and(|start| &head[start..i] == b"id");
+                    // A key, not a value that happens to be "id"
+                    if let Some(rest) = is_id.then(|| head[i + 1..].trim_ascii_start().strip_prefix(b":")).flatten() {
+                        return parse_digits(rest.trim_ascii_start().iter().take_while(|b| b.is_ascii_digit()).copied());
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => {
+                in_string = true;
+                key_start = Some(i + 1);
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `...,"id":7}`: an id right before the closing brace
+fn trailing_id(tail: &[u8]) -> Option<u64> {
+    let rest = tail.trim_ascii_end().strip_suffix(b"}")?.trim_ascii_end();
+    let digits = rest.iter().rev().take_while(|b| b.is_ascii_digit()).count();
+    let (rest, id) = rest.split_at(rest.len() - digits);
+    let rest = rest.trim_ascii_end().strip_suffix(b":")?.trim_ascii_end().strip_suffix(b"\"id\"")?;
+    matches!(rest.trim_ascii_end().last(), Some(b',' | b'{')).then_some(())?;
+    parse_digits(id.iter().copied())
+}
+
+fn parse_digits(digits: impl Iterator<Item = u8>) -> Option<u64> {
+    std::str::from_utf8(&digits.collect::<Vec<_>>()).ok()?.parse().ok()
+}
+
+fn line(value: &Value) -> Bytes {
+    let mut out = serde_json::to_vec(value).unwrap_or_default();
+    out.push(b'\n');
+    Bytes::from(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_result(text: &str) -> Bytes {
+        line(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "result": { "content": [{ "type": "text", "text": text }] },
+        }))
+    }
+
+    #[test]
+    fn test_truncates_tool_result() {
+        let response = tool_result(&"x".repeat(10_000));
+        let out = enforce("fs", response, 1024, OversizedResponse::Truncate);
+        assert!(out.len() <= 1024);
+
+        let value: Value = serde_json::from_slice(&out).unwrap();
+        let content = value["result"]["content"].as_array().unwrap();
+        assert!(content[0]["text"].as_str().unwrap().starts_with("xxx"));
+        assert!(content[1]["text"].as_str().unwrap().contains("result was"));
+
+        let small = tool_result("ok");
+        assert_eq!(enforce("fs", small.clone(), 1024, OversizedResponse::Truncate), small);
+    }
+
+    #[tokio::test]
+    async fn test_read_line_skips_oversized() {
+        let big = format!("{{\"jsonrpc\":\"2.0\",\"result\":{{\"id\":1,\"data\":\"{}\"}},\"id\":42}}\n", "A".repeat(100));
+        let input = format!("{{\"id\":1}}\n{}{{\"id\":2}}\n", big);
+        let mut reader = tokio::io::BufReader::with_capacity(16, input.as_bytes());
+
+        let mut buf = Vec::new();
+        assert_eq!(read_line(&mut reader, &mut buf, 64).await.unwrap(), Line::Complete);
+        assert_eq!(buf, b"{\"id\":1}\n");
+
+        buf.clear();
+        let Line::Oversized { size, tail } = read_line(&mut reader, &mut buf, 64).await.unwrap() else {
+            panic!("expected an oversized line");
+        };
+        assert_eq!((size, buf.len()), (big.len(), 64));
+        // The id inside the result isn't mistaken for the response's
+        assert_eq!(truncated_id(&buf, &tail), Some(42));
+        assert_eq!(truncated_id(b"{\"method\":\"id\",\"id\": 9,\"result\":{\"x", b""), Some(9));
+
+        buf.clear();
+        assert_eq!(read_line(&mut reader, &mut buf, 64).await.unwrap(), Line::Complete);
+        assert_eq!(buf, b"{\"id\":2}\n");
+        assert_eq!(read_line(&mut reader, &mut buf, 64).await.unwrap(), Line::Eof);
+    }
+
+    #[test]
+    fn test_rejects_with_size() {
+        let response = tool_result(&"x".repeat(10_000));
+        let size = response.len();
+        let value: Value = serde_json::from_slice(&enforce("fs", response, 1024, OversizedResponse::Reject)).unwrap();
+        assert_eq!(value["id"], 7);
+        assert_eq!(value["error"]["data"]["size"], size);
+    }
+}
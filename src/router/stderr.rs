@@ -0,0 +1,95 @@
+//! Backend stderr
+//! A task per server process reads its stderr as it comes, so a chatty server
+//! never blocks on a full pipe. Each line goes to the hub's log tagged with the
+//! server's name, to the server's own log file if it has one, and into the
+//! last lines kept for startup crash errors and session exports.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::ChildStderr;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::logging::RotatingFile;
+
+/// Lines kept per server
+const TAIL_LINES: usize = 200;
+
+/// The last lines a server wrote to stderr
+#[derive(Debug, Default)]
+pub struct StderrTail {
+    lines: Mutex<VecDeque<String>>,
+}
+
+impl StderrTail {
+    /// Oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == TAIL_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+/// Log file a server's stderr is appended to (`logs/{server}.log` in the data
+/// dir), rotated as `[logging]` says
+pub fn log_path(server: &str) -> PathBuf {
+    crate::config::data_dir().join("logs").join(format!("{}.log", server))
+}
+
+/// Read a server's stderr until it closes, appending it to `log_file` if given
+pub fn drain(server: &str, stderr: ChildStderr, log_file: Option<PathBuf>) -> (Arc<StderrTail>, JoinHandle<()>) {
+    let tail = Arc::new(StderrTail::default());
+    let reader = {
+        let (server, tail) = (server.to_string(), Arc::clone(&tail));
+        tokio::spawn(async move {
+            let mut file = log_file.and_then(|path| {
+                RotatingFile::open(&path).inspect_err(|e| warn!("Failed to open the {} log {}: {}", server, path.display(), e)).ok()
+            });
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                info!("[{}] {}", server, line);
+                // A line is one short write to a local file, done in place
+                if let Some(log) = &mut file {
+                    if let Err(e) = log.write_all(format!("{}\n", line).as_bytes()) {
+                        warn!("Failed to write the {} log, no longer writing it: {}", server, e);
+                        file = None;
+                    }
+                }
+                tail.push(line);
+            }
+        })
+    };
+    (tail, reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+
+    #[tokio::test]
+    async fn test_drain_to_log_file() {
+        let path = std::env::temp_dir().join(format!("mcp-citadel-stderr-{}", std::process::id())).join("echo.log");
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "echo starting >&2; echo ready >&2"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (tail, reader) = drain("echo", child.stderr.take().unwrap(), Some(path.clone()));
+        reader.await.unwrap();
+        child.wait().await.unwrap();
+
+        assert_eq!(tail.lines(), ["starting", "ready"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "starting\nready\n");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
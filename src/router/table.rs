@@ -0,0 +1,74 @@
+//! Server table
+//! The running server processes, each in a slot with its own lock. Starting,
+//! stopping, health-checking, or restarting a server holds only its slot, so
+//! messages to every other server keep flowing; routing takes a slot just
+//! long enough to clone out what it needs. The map of slots itself is only
+//! written when a server is added or removed, and never across an await.
+//!
+//! Lock order: `configs`, then a slot, then `restart_counts`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use super::MCPServerProcess;
+
+type Slot = Arc<Mutex<Option<MCPServerProcess>>>;
+
+/// A locked slot: the server's process, or None while it isn't running
+pub(super) type SlotGuard = OwnedMutexGuard<Option<MCPServerProcess>>;
+
+#[derive(Default)]
+pub(super) struct ServerTable {
+    slots: RwLock<HashMap<String, Slot>>,
+}
+
+impl ServerTable {
+    pub fn new(servers: HashMap<String, MCPServerProcess>) -> Self {
+        let slots = servers
+            .into_iter()
+            .map(|(name, server)| (name, Arc::new(Mutex::new(Some(server)))))
+            .collect();
+        Self { slots: RwLock::new(slots) }
+    }
+
+    /// Lock a server's slot, adding an empty one if it has none
+    pub async fn lock(&self, name: &str) -> SlotGuard {
+        let slot = Arc::clone(self.slots.write().unwrap().entry(name.to_string()).or_default());
+        slot.lock_owned().await
+    }
+
+    /// Lock a server's slot if it has one
+    pub async fn lock_existing(&self, name: &str) -> Option<SlotGuard> {
+        let slot = self.slots.read().unwrap().get(name).cloned()?;
+        Some(slot.lock_owned().await)
+    }
+
+    /// Remove a server's slot, returning its process once no one else holds it
+    pub async fn remove(&self, name: &str) -> Option<MCPServerProcess> {
+        let slot = self.slots.write().unwrap().remove(name)?;
+        let taken = slot.lock().await.take();
+        taken
+    }
+
+    /// Something from a running server, waiting only on its own slot
+    pub async fn with<T>(&self, name: &str, f: impl FnOnce(&MCPServerProcess) -> T) -> Option<T> {
+        self.lock_existing(name).await?.as_ref().map(f)
+    }
+
+    /// Names of the servers with a process
+    pub async fn running(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for name in self.names() {
+            if self.with(&name, |_| ()).await.is_some() {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Names of every slot, running or not
+    pub fn names(&self) -> Vec<String> {
+        self.slots.read().unwrap().keys().cloned().collect()
+    }
+}
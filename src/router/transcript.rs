@@ -0,0 +1,195 @@
+//! Session transcripts
+//! The hub keeps the latest messages of each client session, and of the most
+//! recently closed ones, in memory so `mcp-citadel session export` can bundle
+//! what a client and its servers said to each other into a bug report.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Closed sessions whose transcripts are kept for export
+const MAX_CLOSED: usize = 20;
+
+/// Longest message kept whole; longer ones are cut with a marker
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Who sent a transcript message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Client,
+    Server,
+    /// The hub answered for the server (routing errors)
+    Hub,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// RFC 3339
+    pub at: String,
+    pub server: String,
+    pub from: Origin,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub id: String,
+    /// `socket` or `http`
+    pub transport: String,
+    pub started_at: String,
+    pub last_active: String,
+    #[serde(default)]
+    pub closed_at: Option<String>,
+    pub servers: BTreeSet<String>,
+    /// Messages recorded, including ones since dropped from the transcript
+    pub messages: u64,
+}
+
+#[derive(Debug)]
+struct Transcript {
+    summary: SessionSummary,
+    entries: VecDeque<Entry>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    live: HashMap<String, Transcript>,
+    closed: VecDeque<Transcript>,
+}
+
+/// Recent messages per session
+#[derive(Debug)]
+pub struct Transcripts {
+    /// Messages kept per session (0: recording is off)
+    max_messages: usize,
+    state: Mutex<State>,
+}
+
+impl Transcripts {
+    pub fn new(max_messages: usize) -> Self {
+        Self {
+            max_messages,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Start recording a session, if it isn't already
+    pub fn open(&self, id: &str, transport: &str) {
+        if self.max_messages == 0 {
+            return;
+        }
+        let now = now();
+        self.state.lock().unwrap().live.entry(id.to_string()).or_insert_with(|| Transcript {
+            summary: SessionSummary {
+                id: id.to_string(),
+                transport: transport.to_string(),
+                started_at: now.clone(),
+                last_active: now,
+                closed_at: None,
+                servers: BTreeSet::new(),
+                messages: 0,
+            },
+            entries: VecDeque::new(),
+        });
+    }
+
+    /// Add a message to an open session's transcript
+    pub fn record(&self, id: &str, server: &str, from: Origin, message: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let Some(transcript) = state.live.get_mut(id) else {
+            return;
+        };
+        let mut text = String::from_utf8_lossy(&message[..message.len().min(MAX_MESSAGE_BYTES)]).trim_end().to_string();
+        if message.len() > MAX_MESSAGE_BYTES {
+            text.push_str(&format!("... [{} bytes]", message.len()));
+        }
+        let at = now();
+        transcript.summary.last_active = at.clone();
+        transcript.summary.messages += 1;
+        transcript.summary.servers.insert(server.to_string());
+        if transcript.entries.len() == self.max_messages {
+            transcript.entries.pop_front();
+        }
+        transcript.entries.push_back(Entry {
+            at,
+            server: server.to_string(),
+            from,
+            message: text,
+        });
+    }
+
+    /// Stop recording a session, keeping its transcript among the recently closed
+    pub fn close(&self, id: &str) {
+        let mut state = self.state.lock().unwrap();
+        let Some(mut transcript) = state.live.remove(id) else {
+            return;
+        };
+        // Sessions that never sent anything aren't worth a slot
+        if transcript.summary.messages == 0 {
+            return;
+        }
+        transcript.summary.closed_at = Some(now());
+        if state.closed.len() == MAX_CLOSED {
+            state.closed.pop_front();
+        }
+        state.closed.push_back(transcript);
+    }
+
+    /// Live sessions, then closed ones, each oldest first
+    pub fn list(&self) -> Vec<SessionSummary> {
+        let state = self.state.lock().unwrap();
+        let mut live: Vec<_> = state.live.values().map(|t| t.summary.clone()).collect();
+        live.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        live.extend(state.closed.iter().map(|t| t.summary.clone()));
+        live
+    }
+
+    /// A session's summary and transcript, by id or unique id prefix
+    pub fn get(&self, id: &str) -> Option<(SessionSummary, Vec<Entry>)> {
+        let state = self.state.lock().unwrap();
+        let mut matches = state
+            .live
+            .values()
+            .chain(state.closed.iter().rev())
+            .filter(|t| t.summary.id.starts_with(id));
+        let transcript = matches.next()?;
+        if transcript.summary.id != id && matches.any(|t| t.summary.id != transcript.summary.id) {
+            return None;
+        }
+        Some((transcript.summary.clone(), transcript.entries.iter().cloned().collect()))
+    }
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_keeps_latest_messages() {
+        let transcripts = Transcripts::new(2);
+        transcripts.open("abc123", "socket");
+        transcripts.open("abd456", "http");
+        for i in 0..3 {
+            transcripts.record("abc123", "github", Origin::Client, format!("{{\"id\":{}}}\n", i).as_bytes());
+        }
+        transcripts.record("unknown", "github", Origin::Client, b"{}");
+
+        let (summary, entries) = transcripts.get("abc").unwrap();
+        assert_eq!(summary.messages, 3);
+        assert_eq!(entries.iter().map(|e| e.message.as_str()).collect::<Vec<_>>(), ["{\"id\":1}", "{\"id\":2}"]);
+        assert!(transcripts.get("ab").is_none(), "ambiguous prefix");
+
+        transcripts.close("abc123");
+        transcripts.close("abd456");
+        let listed = transcripts.list();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].closed_at.is_some());
+        assert!(transcripts.get("abc123").is_some());
+        assert!(Transcripts::new(0).get("abc123").is_none());
+    }
+}
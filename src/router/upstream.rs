@@ -0,0 +1,236 @@
+//! Connections to upstream servers
+//! The hub keeps the connections it opens to a remote server for reuse rather
+//! than opening one per request: HTTP connections are pooled
+//! (`poolMaxIdlePerHost`, `poolIdleTimeoutSecs`), and every connection gets
+//! TCP keep-alive probes (`tcpKeepaliveSecs`) so dead peers and idle-killing
+//! middleboxes are noticed. A server with an `authCommand` gets the token it
+//! prints as `Authorization`, refreshed whenever the server answers 401, after
+//! which the refused request is sent once more.
+
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use crate::config::{AuthCommand, UpstreamConfig};
+
+/// How long connecting to a server may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval of TCP keep-alive probes unless `tcpKeepaliveSecs` says otherwise
+const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// How long an `authCommand` may run
+const AUTH_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keep-alive time of connections to a server; None when turned off
+pub fn tcp_keepalive(config: &UpstreamConfig) -> Option<Duration> {
+    match config.tcp_keepalive_secs {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(DEFAULT_TCP_KEEPALIVE),
+    }
+}
+
+/// An HTTP client pooling and keeping alive its connections to a server
+pub fn http_client(config: &UpstreamConfig) -> reqwest::Result<reqwest::Client> {
+    let mut http = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .tcp_keepalive(tcp_keepalive(config));
+    if let Some(max) = config.pool_max_idle_per_host {
+        http = http.pool_max_idle_per_host(max);
+    }
+    if let Some(secs) = config.pool_idle_timeout_secs {
+        http = http.pool_idle_timeout(Duration::from_secs(secs));
+    }
+    http.build()
+}
+
+/// A TCP connection to `address`, with keep-alive probes every `keepalive`
+pub async fn connect_tcp(address: &str, keepalive: Option<Duration>) -> std::io::Result<TcpStream> {
+    let stream = TcpStream::connect(address).await?;
+    if let Some(time) = keepalive {
+        socket2::SockRef::from(&stream).set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(time))?;
+    }
+    Ok(stream)
+}
+
+/// Headers sent with every request to a server, like `Authorization`, kept
+/// fresh by its `authCommand`
+#[derive(Clone)]
+pub struct Credentials {
+    server: String,
+    headers: Arc<RwLock<HeaderMap>>,
+    auth_command: Option<AuthCommand>,
+}
+
+impl Credentials {
+    pub fn new(server: &str, headers: HeaderMap, config: &UpstreamConfig) -> Self {
+        Self {
+            server: server.to_string(),
+            headers: Arc::new(RwLock::new(headers)),
+            auth_command: config.auth_command.clone(),
+        }
+    }
+
+    /// The headers as they are now
+    pub fn headers(&self) -> HeaderMap {
+        self.headers.read().unwrap().clone()
+    }
+
+    /// Send the request `build` makes; if the server answers 401 and
+    /// `authCommand` gives a fresh token, send it once more
+    pub async fn send(&self, build: impl Fn() -> reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        let response = build().send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED || !self.refresh().await {
+            return Ok(response);
+        }
+        build().send().await
+    }
+
+    /// Run `authCommand` and send what it prints as `Authorization` from now
+    /// on; false without one, or if it failed
+    pub async fn refresh(&self) -> bool {
+        let Some(AuthCommand { command, args, timeout_secs }) = &self.auth_command else {
+            return false;
+        };
+        let timeout = timeout_secs.map_or(AUTH_COMMAND_TIMEOUT, Duration::from_secs);
+        let output = crate::platform::command(command).args(args).kill_on_drop(true).output();
+        let token = match tokio::time::timeout(timeout, output).await {
+            Ok(Ok(output)) if output.status.success() => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            Ok(Ok(output)) => {
+                warn!("authCommand of server {} failed with {}", self.server, output.status);
+                return false;
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to run the authCommand of server {}: {}", self.server, e);
+                return false;
+            }
+            Err(_) => {
+                warn!("authCommand of server {} timed out after {:?}", self.server, timeout);
+                return false;
+            }
+        };
+        // A bare token is a bearer token; `Basic ...` and the like are sent as they are
+        let value = if token.contains(' ') { token } else { format!("Bearer {}", token) };
+        let Ok(mut value) = HeaderValue::from_str(&value) else {
+            warn!("authCommand of server {} printed an invalid token", self.server);
+            return false;
+        };
+        value.set_sensitive(true);
+        self.headers.write().unwrap().insert(AUTHORIZATION, value);
+        debug!("Refreshed the credentials of server {}", self.server);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// The client port and `Authorization` of each request a server got
+    type Seen = Arc<Mutex<Vec<(u16, Option<String>)>>>;
+
+    /// A server recording the client port (one per connection) and
+    /// `Authorization` of each request; requests without `accepted` get 401
+    async fn recording_server(accepted: Option<&'static str>) -> (String, Seen) {
+        use axum::extract::ConnectInfo;
+        use axum::http::{HeaderMap, StatusCode};
+
+        let seen: Seen = Arc::default();
+        let handler = {
+            let seen = Arc::clone(&seen);
+            move |ConnectInfo(peer): ConnectInfo<std::net::SocketAddr>, headers: HeaderMap| async move {
+                let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).map(String::from);
+                seen.lock().unwrap().push((peer.port(), auth.clone()));
+                match accepted {
+                    Some(token) if auth.as_deref() != Some(token) => StatusCode::UNAUTHORIZED,
+                    _ => StatusCode::OK,
+                }
+            }
+        };
+        let app = axum::Router::new().route("/", axum::routing::get(handler));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+                .await
+                .unwrap()
+        });
+        (url, seen)
+    }
+
+    #[tokio::test]
+    async fn test_connections_pooled() {
+        let distinct = |seen: &Seen| {
+            seen.lock().unwrap().iter().map(|(port, _)| *port).collect::<std::collections::HashSet<_>>().len()
+        };
+
+        // Reused across requests
+        let (url, seen) = recording_server(None).await;
+        let http = http_client(&UpstreamConfig::default()).unwrap();
+        for _ in 0..3 {
+            http.get(&url).send().await.unwrap();
+        }
+        assert_eq!(distinct(&seen), 1);
+
+        // A connection per request
+        let (url, seen) = recording_server(None).await;
+        let http = http_client(&UpstreamConfig { pool_max_idle_per_host: Some(0), ..Default::default() }).unwrap();
+        for _ in 0..3 {
+            http.get(&url).send().await.unwrap();
+        }
+        assert_eq!(distinct(&seen), 3);
+
+        // Reused until it idles out
+        let (url, seen) = recording_server(None).await;
+        let http = http_client(&UpstreamConfig { pool_idle_timeout_secs: Some(1), ..Default::default() }).unwrap();
+        http.get(&url).send().await.unwrap();
+        http.get(&url).send().await.unwrap();
+        assert_eq!(distinct(&seen), 1);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        http.get(&url).send().await.unwrap();
+        assert_eq!(distinct(&seen), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_keepalive() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let probed = connect_tcp(&address, Some(Duration::from_secs(30))).await.unwrap();
+        assert!(socket2::SockRef::from(&probed).keepalive().unwrap());
+        let plain = connect_tcp(&address, None).await.unwrap();
+        assert!(!socket2::SockRef::from(&plain).keepalive().unwrap());
+
+        let off = UpstreamConfig { tcp_keepalive_secs: Some(0), ..Default::default() };
+        assert_eq!(tcp_keepalive(&off), None);
+        assert_eq!(tcp_keepalive(&UpstreamConfig::default()), Some(DEFAULT_TCP_KEEPALIVE));
+    }
+
+    #[tokio::test]
+    async fn test_token_refreshed_on_401() {
+        let (url, seen) = recording_server(Some("Bearer fresh")).await;
+        let command = AuthCommand { command: "echo".to_string(), args: vec!["fresh".to_string()], timeout_secs: None };
+        let config = UpstreamConfig { auth_command: Some(command), ..Default::default() };
+        let mut stale = HeaderMap::new();
+        stale.insert(AUTHORIZATION, HeaderValue::from_static("Bearer stale"));
+        let credentials = Credentials::new("secured", stale, &config);
+        let http = http_client(&config).unwrap();
+
+        // Refused once, then sent with the new token, which later requests keep
+        let response = credentials.send(|| http.get(&url).headers(credentials.headers())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = credentials.send(|| http.get(&url).headers(credentials.headers())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let auths: Vec<_> = seen.lock().unwrap().iter().map(|(_, auth)| auth.clone().unwrap()).collect();
+        assert_eq!(auths, ["Bearer stale", "Bearer fresh", "Bearer fresh"]);
+
+        // Without an authCommand the 401 stands
+        let credentials = Credentials::new("secured", HeaderMap::new(), &UpstreamConfig::default());
+        let response = credentials.send(|| http.get(&url).headers(credentials.headers())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
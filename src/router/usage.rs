@@ -0,0 +1,181 @@
+//! Sampling usage accounting
+//! Tracks `sampling/createMessage` requests backends send to clients and
+//! tallies the token usage reported in the clients' responses, per server and
+//! per session, so it's visible which servers drive LLM cost
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+use super::connection::BackendMessage;
+use super::message::Envelope;
+use crate::metrics;
+
+const SAMPLING_METHOD: &str = "sampling/createMessage";
+
+/// Unanswered sampling requests remembered at most; clients that never answer
+/// shouldn't grow the set without bound
+const MAX_PENDING: usize = 4096;
+
+/// Token totals for a server or session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Sampling exchanges answered
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// Usage totals reported by `mcp-citadel usage`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub servers: BTreeMap<String, Usage>,
+    pub sessions: BTreeMap<String, Usage>,
+}
+
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    /// Sampling requests awaiting a client response, by server and raw id
+    pending: Mutex<HashSet<(String, String)>>,
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    /// Remember a backend's sampling request so its response can be attributed
+    pub fn observe_backend_message(&self, event: &BackendMessage) {
+        if event.method != SAMPLING_METHOD {
+            return;
+        }
+        let Some(id) = Envelope::parse(&event.message).and_then(|e| e.id.map(|id| id.get().to_string())) else {
+            return;
+        };
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING {
+            warn!("Too many unanswered sampling requests, forgetting them");
+            pending.clear();
+        }
+        pending.insert((event.server.clone(), id));
+    }
+
+    /// Account a client message to `server` if it answers a sampling request
+    pub fn observe_client_message(&self, server: &str, session: Option<&str>, message: &[u8]) {
+        let Some(envelope) = Envelope::parse(message) else {
+            return;
+        };
+        let (None, Some(id)) = (&envelope.method, envelope.id) else {
+            return;
+        };
+        if !self.pending.lock().unwrap().remove(&(server.to_string(), id.get().to_string())) {
+            return;
+        }
+
+        let usage = serde_json::from_slice::<Value>(message)
+            .ok()
+            .and_then(|response| response.get("result").map(parse_usage))
+            .unwrap_or_default();
+        let usage = Usage { requests: 1, ..usage };
+        debug!(
+            "Sampling for {}: {} input / {} output tokens",
+            server, usage.input_tokens, usage.output_tokens
+        );
+        metrics::record_sampling(server, usage.input_tokens, usage.output_tokens);
+
+        let mut report = self.report.lock().unwrap();
+        report.servers.entry(server.to_string()).or_default().add(&usage);
+        if let Some(session) = session {
+            report.sessions.entry(session.to_string()).or_default().add(&usage);
+        }
+    }
+
+    pub fn report(&self) -> UsageReport {
+        self.report.lock().unwrap().clone()
+    }
+}
+
+/// Token counts from a `CreateMessageResult`
+///
+/// MCP doesn't standardize usage reporting, so this accepts the shapes clients
+/// use in practice: `usage` or `_meta.usage`, with MCP-style camelCase,
+/// Anthropic-style, or OpenAI-style field names.
+pub fn parse_usage(result: &Value) -> Usage {
+    let Some(usage) = result
+        .get("usage")
+        .or_else(|| result.pointer("/_meta/usage"))
+    else {
+        return Usage::default();
+    };
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| usage.get(*name).and_then(Value::as_u64))
+            .unwrap_or(0)
+    };
+    Usage {
+        requests: 0,
+        input_tokens: field(&["inputTokens", "input_tokens", "prompt_tokens"]),
+        output_tokens: field(&["outputTokens", "output_tokens", "completion_tokens"]),
+    }
+}
+
+/// Feed backend sampling requests to the tracker
+pub fn spawn_tracking(mut events: broadcast::Receiver<BackendMessage>, tracker: Arc<UsageTracker>) {
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => tracker.observe_backend_message(&event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("Usage tracking lagged, {} backend messages skipped", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_parse_usage() {
+        let result = serde_json::json!({ "model": "m", "usage": { "inputTokens": 120, "outputTokens": 30 } });
+        assert_eq!(parse_usage(&result), Usage { requests: 0, input_tokens: 120, output_tokens: 30 });
+
+        let result = serde_json::json!({ "_meta": { "usage": { "prompt_tokens": 5, "completion_tokens": 7 } } });
+        assert_eq!(parse_usage(&result).output_tokens, 7);
+        assert_eq!(parse_usage(&serde_json::json!({})), Usage::default());
+    }
+
+    #[test]
+    fn test_attributes_sampling_responses() {
+        let tracker = UsageTracker::default();
+        tracker.observe_backend_message(&BackendMessage {
+            server: "research".to_string(),
+            method: SAMPLING_METHOD.to_string(),
+            message: Bytes::from_static(br#"{"jsonrpc":"2.0","id":"s-1","method":"sampling/createMessage","params":{}}"#),
+        });
+
+        let response = br#"{"jsonrpc":"2.0","id":"s-1","result":{"usage":{"input_tokens":10,"output_tokens":4}}}"#;
+        tracker.observe_client_message("other", Some("abc"), response);
+        tracker.observe_client_message("research", Some("abc"), response);
+        // Answered once; a repeat isn't counted again
+        tracker.observe_client_message("research", Some("abc"), response);
+
+        let report = tracker.report();
+        assert!(!report.servers.contains_key("other"));
+        assert_eq!(report.servers["research"], Usage { requests: 1, input_tokens: 10, output_tokens: 4 });
+        assert_eq!(report.sessions["abc"].input_tokens, 10);
+    }
+}
@@ -0,0 +1,163 @@
+//! Webhooks
+//! Each `[[webhooks]]` entry of the hub config gets a POST when a server
+//! crashes (`crashed`), stops responding or fails its health check
+//! (`unresponsive`), or fails more times in a row than it may be restarted
+//! (`gave_up`). The alert names the server, says what happened, and carries
+//! its exit status and last stderr lines, as JSON or as a Slack or Discord
+//! message.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{WebhookConfig, WebhookFormat};
+
+/// Stderr lines an alert carries, the latest ones
+const STDERR_LINES: usize = 20;
+
+/// How long a webhook has to accept an alert
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest Discord message
+const DISCORD_MAX_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Crashed,
+    Unresponsive,
+    GaveUp,
+}
+
+impl AlertKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Crashed => "crashed",
+            Self::Unresponsive => "unresponsive",
+            Self::GaveUp => "gave_up",
+        }
+    }
+
+    /// What happened to the server, for messages
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Crashed => "crashed",
+            Self::Unresponsive => "is unresponsive",
+            Self::GaveUp => "is out of restarts",
+        }
+    }
+}
+
+/// What the generic format posts
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub event: AlertKind,
+    pub server: String,
+    pub reason: String,
+    /// How the process exited, if it did
+    pub exit_status: Option<String>,
+    pub stderr: Vec<String>,
+    pub timestamp: String,
+}
+
+impl Alert {
+    /// An alert about `server`, with the last of its stderr and secrets masked
+    pub fn new(event: AlertKind, server: &str, reason: &str, exit_status: Option<String>, mut stderr: Vec<String>) -> Self {
+        stderr.drain(..stderr.len().saturating_sub(STDERR_LINES));
+        Self {
+            event,
+            server: server.to_string(),
+            reason: crate::redact::scrub(reason).into_owned(),
+            exit_status,
+            stderr: stderr.iter().map(|line| crate::redact::scrub(line).into_owned()).collect(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// The body to post in `format`
+    fn body(&self, format: WebhookFormat) -> Value {
+        if format == WebhookFormat::Generic {
+            return serde_json::to_value(self).unwrap_or_default();
+        }
+        let mut text = format!("🔴 MCP server **{}** {}: {}", self.server, self.event.describe(), self.reason);
+        if let Some(status) = &self.exit_status {
+            text.push_str(&format!(" ({})", status));
+        }
+        if !self.stderr.is_empty() {
+            text.push_str(&format!("\n```\n{}\n```", self.stderr.join("\n")));
+        }
+        match format {
+            // Slack's mrkdwn bolds with single asterisks
+            WebhookFormat::Slack => json!({ "text": text.replacen("**", "*", 2) }),
+            _ => json!({ "content": truncate(&text, DISCORD_MAX_CHARS) }),
+        }
+    }
+}
+
+/// `text` cut to at most `max` characters, keeping a code block closed
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let suffix = if text.contains("```") { "…\n```" } else { "…" };
+    let kept: String = text.chars().take(max - suffix.chars().count()).collect();
+    kept + suffix
+}
+
+/// The configured webhooks
+#[derive(Default)]
+pub struct Webhooks {
+    hooks: Vec<WebhookConfig>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<WebhookConfig>) -> Self {
+        Self { hooks, client: reqwest::Client::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Post `alert` to the webhooks that want it, in the background
+    pub fn send(&self, alert: Alert) {
+        let wanted = self.hooks.iter().filter(|hook| hook.events.is_empty() || hook.events.iter().any(|e| e == alert.event.name()));
+        for hook in wanted {
+            let mut request = self.client.post(&hook.url).timeout(POST_TIMEOUT).json(&alert.body(hook.format));
+            for (name, value) in &hook.headers {
+                request = request.header(name, value);
+            }
+            let (url, server) = (hook.url.clone(), alert.server.clone());
+            tokio::spawn(async move {
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => {}
+                    Err(e) => warn!("Webhook {} failed to take the alert about {}: {}", url, server, e.without_url()),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_bodies() {
+        let stderr = (1..=30).map(|n| format!("line {}", n)).collect();
+        let alert = Alert::new(AlertKind::Crashed, "github", "exited after 1.0s", Some("exit status: 1".to_string()), stderr);
+        assert_eq!((alert.stderr.len(), alert.stderr[0].as_str()), (STDERR_LINES, "line 11"));
+
+        let generic = alert.body(WebhookFormat::Generic);
+        assert_eq!((generic["event"].as_str(), generic["exit_status"].as_str()), (Some("crashed"), Some("exit status: 1")));
+        let slack = alert.body(WebhookFormat::Slack);
+        assert!(slack["text"].as_str().unwrap().starts_with("🔴 MCP server *github* crashed: exited after 1.0s (exit status: 1)\n```\nline 11"));
+
+        let long = Alert::new(AlertKind::GaveUp, "github", "failed 3 times", None, vec!["x".repeat(3000)]);
+        let content = long.body(WebhookFormat::Discord)["content"].as_str().unwrap().to_string();
+        assert_eq!(content.chars().count(), DISCORD_MAX_CHARS);
+        assert!(content.starts_with("🔴 MCP server **github** is out of restarts: failed 3 times") && content.ends_with("…\n```"));
+    }
+}
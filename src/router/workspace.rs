@@ -0,0 +1,209 @@
+//! Multi-workspace routing
+//! Each named workspace exposes a subset of the managed servers, and every client
+//! is mapped to one from its identity: an explicit workspace name, an API key, or
+//! the Unix user on the other end of the socket. A workspace can also alias
+//! server names, so the same client config reaches different backends per
+//! workspace
+
+use anyhow::Result;
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashSet};
+
+use super::policy;
+use crate::config::{Priority, WorkspaceConfig};
+
+/// Workspace used for clients that match no other workspace
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/// What a client presented about itself
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Identity<'a> {
+    /// Workspace asked for by name (header, query parameter, or `params.workspace`)
+    pub requested: Option<&'a str>,
+    pub api_key: Option<&'a str>,
+    pub unix_user: Option<&'a str>,
+}
+
+/// Servers a client may reach
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    /// Workspace name (None when no workspaces are configured)
+    pub workspace: Option<String>,
+    servers: Option<HashSet<String>>,
+    /// Client-facing server names and the servers they stand for
+    aliases: BTreeMap<String, String>,
+    /// Queue priority of the client's requests
+    pub priority: Priority,
+    /// The client may only list and read (see [`super::policy`])
+    pub read_only: bool,
+}
+
+impl Scope {
+    /// Whether only some servers are visible in this scope
+    pub fn is_restricted(&self) -> bool {
+        self.servers.is_some()
+    }
+
+    /// Whether `server` is visible in this scope
+    pub fn allows(&self, server: &str) -> bool {
+        self.servers.as_ref().is_none_or(|servers| servers.contains(server))
+    }
+
+    /// The server a client's name for it refers to, if it is reachable in
+    /// this scope: an alias's target, or the name itself
+    pub fn target<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        match self.aliases.get(name) {
+            Some(target) => Some(target),
+            None => self.allows(name).then_some(name),
+        }
+    }
+
+    /// The policy error answering `message`, if this scope may not send it
+    pub fn reject(&self, server: &str, message: &[u8]) -> Option<Bytes> {
+        self.read_only.then(|| policy::read_only_rejection(server, message)).flatten()
+    }
+
+    /// Client-facing names of the reachable servers: the visible ones and
+    /// the aliases of running ones
+    pub fn filter(&self, servers: Vec<String>) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|(_, target)| servers.contains(target))
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        names.extend(servers.into_iter().filter(|s| self.allows(s) && !self.aliases.contains_key(s)));
+        names
+    }
+}
+
+/// Configured workspaces
+#[derive(Debug, Clone, Default)]
+pub struct Workspaces {
+    workspaces: BTreeMap<String, WorkspaceConfig>,
+}
+
+impl Workspaces {
+    pub fn new(workspaces: BTreeMap<String, WorkspaceConfig>) -> Self {
+        Self { workspaces }
+    }
+
+    /// Map a client to its workspace
+    ///
+    /// A workspace asked for by name is granted only if it declares no API keys
+    /// or Unix users, or the client matches one of them. Otherwise the client's
+    /// API key, then its Unix user, picks the workspace, falling back to
+    /// `default`. With no workspaces configured every client sees every server.
+    pub fn resolve(&self, identity: Identity) -> Result<Scope> {
+        if self.workspaces.is_empty() {
+            return Ok(Scope::default());
+        }
+
+        let matches = |config: &WorkspaceConfig| {
+            identity.api_key.is_some_and(|key| config.api_keys.iter().any(|k| k == key))
+                || identity.unix_user.is_some_and(|user| config.unix_users.iter().any(|u| u == user))
+        };
+
+        let name = match identity.requested {
+            Some(name) => {
+                let Some(config) = self.workspaces.get(name) else {
+                    anyhow::bail!("Unknown workspace '{}'", name);
+                };
+                let restricted = !config.api_keys.is_empty() || !config.unix_users.is_empty();
+                if restricted && !matches(config) {
+                    anyhow::bail!("Not authorized for workspace '{}'", name);
+                }
+                name
+            }
+            None => {
+                let by_key = identity.api_key.and_then(|key| {
+                    self.workspaces
+                        .iter()
+                        .find(|(_, c)| c.api_keys.iter().any(|k| k == key))
+                        .map(|(name, _)| name.as_str())
+                });
+                let by_user = || {
+                    identity.unix_user.and_then(|user| {
+                        self.workspaces
+                            .iter()
+                            .find(|(_, c)| c.unix_users.iter().any(|u| u == user))
+                            .map(|(name, _)| name.as_str())
+                    })
+                };
+                match by_key.or_else(by_user) {
+                    Some(name) => name,
+                    None if self.workspaces.contains_key(DEFAULT_WORKSPACE) => DEFAULT_WORKSPACE,
+                    None => anyhow::bail!("No workspace matches this client"),
+                }
+            }
+        };
+
+        let config = &self.workspaces[name];
+        Ok(Scope {
+            workspace: Some(name.to_string()),
+            servers: Some(config.servers.iter().cloned().collect()),
+            aliases: config.aliases.clone(),
+            priority: config.priority.unwrap_or_default(),
+            read_only: config.read_only,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_workspace() {
+        let workspace = |servers: &[&str], api_keys: &[&str], unix_users: &[&str]| WorkspaceConfig {
+            servers: servers.iter().map(|s| s.to_string()).collect(),
+            api_keys: api_keys.iter().map(|s| s.to_string()).collect(),
+            unix_users: unix_users.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        let workspaces = Workspaces::new(BTreeMap::from([
+            ("web".to_string(), workspace(&["github", "figma"], &["key-web"], &[])),
+            ("data".to_string(), workspace(&["postgres"], &[], &["alice"])),
+            ("default".to_string(), workspace(&["time"], &[], &[])),
+        ]));
+
+        let scope = workspaces.resolve(Identity { api_key: Some("key-web"), ..Default::default() }).unwrap();
+        assert!(scope.allows("figma") && !scope.allows("postgres"));
+
+        let scope = workspaces.resolve(Identity { unix_user: Some("alice"), ..Default::default() }).unwrap();
+        assert_eq!(scope.workspace.as_deref(), Some("data"));
+
+        let scope = workspaces.resolve(Identity::default()).unwrap();
+        assert_eq!(scope.filter(vec!["time".into(), "github".into()]), ["time"]);
+        assert_eq!(scope.target("github"), None);
+
+        assert!(workspaces.resolve(Identity { requested: Some("web"), ..Default::default() }).is_err());
+        assert!(Workspaces::default().resolve(Identity::default()).unwrap().allows("anything"));
+    }
+
+    #[test]
+    fn test_aliases_per_workspace() {
+        let workspace = |key: &str, fs: &str| WorkspaceConfig {
+            servers: vec!["time".to_string()],
+            api_keys: vec![key.to_string()],
+            aliases: BTreeMap::from([("fs".to_string(), fs.to_string())]),
+            ..Default::default()
+        };
+        let workspaces = Workspaces::new(BTreeMap::from([
+            ("home".to_string(), workspace("key-home", "filesystem-home")),
+            ("project".to_string(), workspace("key-project", "filesystem-project")),
+        ]));
+
+        let home = workspaces.resolve(Identity { api_key: Some("key-home"), ..Default::default() }).unwrap();
+        let project = workspaces.resolve(Identity { api_key: Some("key-project"), ..Default::default() }).unwrap();
+        assert_eq!(home.target("fs"), Some("filesystem-home"));
+        assert_eq!(project.target("fs"), Some("filesystem-project"));
+        assert_eq!(home.target("time"), Some("time"));
+        // Targets are only reachable through their alias
+        assert_eq!(home.target("filesystem-home"), None);
+
+        let running = vec!["filesystem-home".to_string(), "time".to_string()];
+        assert_eq!(home.filter(running.clone()), ["fs", "time"]);
+        assert_eq!(project.filter(running), ["time"]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Fleet summary
+//! A compact health document for dashboards polling many hubs: version,
+//! uptime, each server's health, request and error counts, restarts, and
+//! where the transports listen. Served at `/admin/summary` and by the
+//! control plane, and printed by `mcp-citadel summary [--remote <url>]`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::router::workspace::Scope;
+use crate::router::{HubManager, ServerInfo, ServerState};
+
+/// Path of the HTTP endpoint
+pub const SUMMARY_PATH: &str = "/admin/summary";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub version: String,
+    /// `ok`, `degraded` (some servers down or timing out), `down`,
+    /// `maintenance`, or `draining`
+    pub status: String,
+    pub uptime_secs: u64,
+    pub generated_at: String,
+    pub servers: Vec<ServerSummary>,
+    pub totals: Totals,
+    /// Transport kind (`socket`, `http`, `websocket`, `metrics`) to address
+    pub endpoints: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSummary {
+    pub name: String,
+    /// `up`, `degraded` (recent read timeouts), `idle` (lazy, not started), or `down`
+    pub health: String,
+    pub uptime_secs: Option<u64>,
+    pub restarts: u32,
+    /// Since the running instance started
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Totals {
+    pub servers: usize,
+    pub up: usize,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+}
+
+/// Summarize the servers of a running hub visible in `scope`
+pub async fn build(manager: &HubManager, scope: &Scope) -> Summary {
+    let servers: Vec<ServerSummary> = manager.server_info().await.iter().filter(|info| scope.allows(&info.name)).map(server_summary).collect();
+    let mut totals = Totals {
+        servers: servers.len(),
+        ..Default::default()
+    };
+    for server in &servers {
+        totals.up += usize::from(server.health != "down");
+        totals.requests += server.requests;
+        totals.errors += server.errors;
+    }
+    totals.error_rate = rate(totals.errors, totals.requests);
+
+    let status = if manager.is_draining() {
+        "draining"
+    } else if manager.maintenance().current().is_some() {
+        "maintenance"
+    } else if totals.up == 0 && totals.servers > 0 {
+        "down"
+    } else if servers.iter().any(|s| s.health == "down" || s.health == "degraded") {
+        "degraded"
+    } else {
+        "ok"
+    };
+
+    Summary {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        status: status.to_string(),
+        uptime_secs: manager.uptime().as_secs(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        servers,
+        totals,
+        endpoints: manager.endpoints(),
+    }
+}
+
+fn server_summary(info: &ServerInfo) -> ServerSummary {
+    let health = match (info.running, info.consecutive_timeouts) {
+        // Lazy servers that haven't been needed yet are fine
+        (false, _) if matches!(info.state, ServerState::Idle | ServerState::Initializing) => "idle",
+        (false, _) => "down",
+        (true, 0) if info.state != ServerState::Unresponsive => "up",
+        (true, _) => "degraded",
+    };
+    ServerSummary {
+        name: info.name.clone(),
+        health: health.to_string(),
+        uptime_secs: info.uptime_secs,
+        restarts: info.restarts,
+        requests: info.requests,
+        errors: info.errors,
+        error_rate: rate(info.errors, info.requests),
+    }
+}
+
+/// Errors per request, rounded to 4 places
+fn rate(errors: u64, requests: u64) -> f64 {
+    if requests == 0 {
+        return 0.0;
+    }
+    (errors as f64 / requests as f64 * 10_000.0).round() / 10_000.0
+}
+
+/// Fetch the summary of the hub at `url` (its base URL, or the summary URL
+/// itself), with an admin or workspace API key if it needs one
+pub async fn fetch(url: &str, key: Option<&str>) -> Result<Summary> {
+    let url = url.trim_end_matches('/');
+    let url = match url.ends_with(SUMMARY_PATH) {
+        true => url.to_string(),
+        false => format!("{}{}", url, SUMMARY_PATH),
+    };
+    let mut request = reqwest::Client::new().get(&url).timeout(std::time::Duration::from_secs(10));
+    if let Some(key) = key {
+        request = request.bearer_auth(key);
+    }
+    let response = request
+        .send()
+        .await
+        .context(format!("Failed to reach {}", url))?
+        .error_for_status()?;
+    response.json().await.context(format!("Unexpected summary from {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_summary() {
+        let info = ServerInfo {
+            name: "github".to_string(),
+            running: true,
+            state: ServerState::Running,
+            pid: Some(42),
+            command_path: None,
+            url: None,
+            uptime_secs: Some(60),
+            restarts: 1,
+            in_flight: 0,
+            requests: 3,
+            errors: 1,
+            consecutive_timeouts: 1,
+            next_restart: None,
+            pinned: 0,
+            last_error: None,
+        };
+        let summary = server_summary(&info);
+        assert_eq!(summary.health, "degraded");
+        assert_eq!(summary.error_rate, 0.3333);
+        assert_eq!(server_summary(&ServerInfo { running: false, state: ServerState::Stopped, ..info.clone() }).health, "down");
+        assert_eq!(server_summary(&ServerInfo { running: false, state: ServerState::Idle, ..info }).health, "idle");
+        assert_eq!(rate(0, 0), 0.0);
+    }
+}
@@ -0,0 +1,370 @@
+//! Integration test harness (feature `testing`)
+//! Starts a HubManager with mock servers in-process, serves it on a Unix
+//! socket in a scratch directory (and HTTP on a free port if asked), and
+//! gives tests clients for both transports plus the requests each mock
+//! received, so end-to-end tests run hermetically and in parallel.
+//!
+//! ```ignore
+//! let hub = TestHub::builder().mock("github", MockConfig::default()).start().await?;
+//! let mut client = hub.connect().await?;
+//! let response = client.request("github", "tools/list", json!({})).await?;
+//! assert_eq!(hub.received("github")[0]["method"], "tools/list");
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::config::{HttpConfig, Journal, MockConfig, RouterConfig, ServerConfig, WebhookConfig};
+use crate::router::middleware::Middleware;
+use crate::router::workspace::Workspaces;
+use crate::router::{HubManager, HubRouter};
+use crate::transport::http::HttpTransport;
+
+/// How long a client waits for a reply before failing the test
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hub state (catalog cache, HTTP sessions) for every TestHub in the process
+/// goes under one scratch directory rather than ~/.mcp-citadel
+fn isolate_data_dir() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("mcp-citadel-test-{}", std::process::id()));
+        std::env::set_var("MCP_CITADEL_DATA_DIR", dir);
+    });
+}
+
+/// Configures a [`TestHub`]
+#[derive(Default)]
+pub struct TestHubBuilder {
+    servers: Vec<ServerConfig>,
+    journals: HashMap<String, Journal>,
+    router: RouterConfig,
+    workspaces: Option<Workspaces>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    webhooks: Vec<WebhookConfig>,
+    http: Option<HttpConfig>,
+}
+
+impl TestHubBuilder {
+    /// Add a mock server whose received messages [`TestHub::received`] returns
+    pub fn mock(mut self, name: &str, mut config: MockConfig) -> Self {
+        let journal = config.journal.get_or_insert_with(Journal::default).clone();
+        self.journals.insert(name.to_string(), journal);
+        self.servers.push(ServerConfig {
+            name: name.to_string(),
+            mock: Some(config),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add any server (a mock given this way records to its own journal, if any)
+    pub fn server(mut self, config: ServerConfig) -> Self {
+        self.servers.push(config);
+        self
+    }
+
+    pub fn router(mut self, config: RouterConfig) -> Self {
+        self.router = config;
+        self
+    }
+
+    pub fn workspaces(mut self, workspaces: Workspaces) -> Self {
+        self.workspaces = Some(workspaces);
+        self
+    }
+
+    /// Run routed messages through `middleware`, in the order added
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn webhooks(mut self, webhooks: Vec<WebhookConfig>) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    /// Also serve HTTP on a free local port (`host` and `port` are ignored)
+    pub fn http(mut self, config: HttpConfig) -> Self {
+        self.http = Some(config);
+        self
+    }
+
+    pub async fn start(self) -> Result<TestHub> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        isolate_data_dir();
+        let dir = crate::config::data_dir().join(format!("hub-{}", NEXT.fetch_add(1, Ordering::Relaxed)));
+        std::fs::create_dir_all(&dir)?;
+
+        let mut manager = HubManager::new(self.servers, self.router).await?;
+        if let Some(workspaces) = self.workspaces {
+            manager = manager.with_workspaces(workspaces);
+        }
+        manager = manager.with_webhooks(self.webhooks);
+        for middleware in self.middleware {
+            manager = manager.with_middleware(middleware);
+        }
+        let manager = Arc::new(manager);
+
+        let socket_path = dir.join("hub.sock");
+        let router = HubRouter::new(socket_path.to_string_lossy().into_owned(), Arc::clone(&manager));
+        let router = tokio::spawn(async move {
+            let _ = router.start().await;
+        });
+        // The router binds from its own task
+        while !socket_path.exists() {
+            if router.is_finished() {
+                bail!("Hub failed to listen on {}", socket_path.display());
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let mut http = None;
+        if let Some(config) = self.http {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let (shutdown, stopped) = oneshot::channel::<()>();
+            let transport = HttpTransport::new(config, Arc::clone(&manager));
+            tokio::spawn(async move {
+                let _ = transport
+                    .serve_with_shutdown(listener, async {
+                        let _ = stopped.await;
+                    })
+                    .await;
+            });
+            http = Some((addr, shutdown));
+        }
+
+        Ok(TestHub {
+            dir,
+            socket_path,
+            manager,
+            journals: self.journals,
+            router,
+            http,
+        })
+    }
+}
+
+/// A hub running inside the test, torn down on drop
+pub struct TestHub {
+    dir: PathBuf,
+    socket_path: PathBuf,
+    manager: Arc<HubManager>,
+    journals: HashMap<String, Journal>,
+    router: JoinHandle<()>,
+    /// HTTP address and the transport's shutdown trigger
+    http: Option<(SocketAddr, oneshot::Sender<()>)>,
+}
+
+impl TestHub {
+    pub fn builder() -> TestHubBuilder {
+        TestHubBuilder::default()
+    }
+
+    pub fn manager(&self) -> &Arc<HubManager> {
+        &self.manager
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// A new Unix socket connection to the hub
+    pub async fn connect(&self) -> Result<SocketClient> {
+        let stream = UnixStream::connect(&self.socket_path).await?;
+        let (reader, writer) = stream.into_split();
+        Ok(SocketClient {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 0,
+        })
+    }
+
+    /// Base URL of the HTTP transport, if the hub serves it
+    pub fn http_url(&self) -> Option<String> {
+        self.http.as_ref().map(|(addr, _)| format!("http://{}", addr))
+    }
+
+    /// A new HTTP client (without a session until it sends `initialize`)
+    pub fn http_client(&self) -> Result<HttpClient> {
+        let base = self.http_url().context("TestHub was started without HTTP")?;
+        Ok(HttpClient {
+            client: reqwest::Client::new(),
+            url: format!("{}/mcp", base),
+            session: None,
+            next_id: 0,
+        })
+    }
+
+    /// Messages a mock added with [`TestHubBuilder::mock`] has received
+    pub fn received(&self, server: &str) -> Vec<Value> {
+        self.journals.get(server).map(Journal::messages).unwrap_or_default()
+    }
+
+    /// Requests (not notifications) with the given method a mock has received
+    pub fn received_requests(&self, server: &str, method: &str) -> Vec<Value> {
+        self.received(server)
+            .into_iter()
+            .filter(|m| m.get("id").is_some() && m["method"] == method)
+            .collect()
+    }
+
+    /// Stop every server and the transports
+    pub async fn stop(self) -> Result<()> {
+        self.manager.stop_all().await
+    }
+}
+
+impl Drop for TestHub {
+    fn drop(&mut self) {
+        self.router.abort();
+        if let Some((_, shutdown)) = self.http.take() {
+            let _ = shutdown.send(());
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A v1 Unix socket client: one JSON line per message
+///
+/// The hub binds a v1 connection to the server of its first message; use a
+/// connection per server, or [`send`](Self::send) framed (v2) messages.
+pub struct SocketClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl SocketClient {
+    /// Send a request to a server and wait for its response, skipping any
+    /// notifications that arrive first
+    pub async fn request(&mut self, server: &str, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&message(Some(id), server, method, params)).await?;
+        loop {
+            let reply = self.recv().await?;
+            if reply["id"] == id {
+                return Ok(reply);
+            }
+        }
+    }
+
+    /// Send a notification to a server
+    pub async fn notify(&mut self, server: &str, method: &str, params: Value) -> Result<()> {
+        self.send(&message(None, server, method, params)).await
+    }
+
+    /// Send any line
+    pub async fn send(&mut self, message: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// The next line from the hub
+    pub async fn recv(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        let read = tokio::time::timeout(REPLY_TIMEOUT, self.reader.read_line(&mut line))
+            .await
+            .context("Timed out waiting for the hub")??;
+        if read == 0 {
+            bail!("Hub closed the connection");
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+/// A Streamable HTTP client for POST /mcp
+pub struct HttpClient {
+    client: reqwest::Client,
+    url: String,
+    session: Option<String>,
+    next_id: u64,
+}
+
+impl HttpClient {
+    /// The session `initialize` opened
+    pub fn session(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    /// Send a request and return its response, whether it comes back as JSON
+    /// or on an SSE stream (which `initialize` uses to hand out the session)
+    pub async fn request(&mut self, server: &str, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut request = self.client.post(&self.url).json(&message(Some(id), server, method, params));
+        if let Some(session) = &self.session {
+            request = request.header("mcp-session-id", session);
+        }
+        let mut response = tokio::time::timeout(REPLY_TIMEOUT, request.send())
+            .await
+            .context("Timed out waiting for the hub")??
+            .error_for_status()?;
+
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        if !is_sse {
+            return Ok(response.json().await?);
+        }
+
+        // The stream stays open for later events, so stop at the reply
+        let mut buffer = String::new();
+        loop {
+            while let Some(end) = buffer.find("\n\n") {
+                let event: String = buffer.drain(..end + 2).collect();
+                let data: String = event
+                    .lines()
+                    .filter_map(|l| l.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect();
+                let Ok(data) = serde_json::from_str::<Value>(&data) else {
+                    continue;
+                };
+                if let Some(session) = data.get("sessionId").and_then(Value::as_str) {
+                    self.session = Some(session.to_string());
+                } else if data["id"] == id {
+                    return Ok(data);
+                }
+            }
+            let chunk = tokio::time::timeout(REPLY_TIMEOUT, response.chunk())
+                .await
+                .context("Timed out waiting for the hub")??;
+            let Some(chunk) = chunk else {
+                bail!("SSE stream ended without a response");
+            };
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+        }
+    }
+}
+
+/// A JSON-RPC message addressed to a server through `params.server`
+fn message(id: Option<u64>, server: &str, method: &str, mut params: Value) -> Value {
+    if !params.is_object() {
+        params = json!({});
+    }
+    params["server"] = json!(server);
+    let mut message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+    if let Some(id) = id {
+        message["id"] = json!(id);
+    }
+    message
+}
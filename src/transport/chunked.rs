@@ -0,0 +1,72 @@
+//! Chunked delivery of large responses
+//! JSON bodies over `chunk_size_bytes` go out as a stream of slices of the
+//! routed response (chunked transfer encoding) rather than one buffer, and
+//! WebSocket clients that connect with `?chunked=true` get them as a series
+//! of `{"citadel":"chunk",...}` messages to concatenate
+
+use axum::body::Body;
+use bytes::Bytes;
+use serde_json::Value;
+use std::convert::Infallible;
+
+/// Zero-copy slices of at most `size` bytes
+pub fn slices(payload: &Bytes, size: usize) -> impl Iterator<Item = Bytes> + '_ {
+    let size = size.max(1);
+    (0..payload.len()).step_by(size).map(move |start| payload.slice(start..(start + size).min(payload.len())))
+}
+
+/// An HTTP body for a routed response, streamed when it's large
+pub fn body(payload: Bytes, chunk_size: usize) -> Body {
+    if payload.len() <= chunk_size {
+        return Body::from(payload);
+    }
+    let chunks: Vec<Bytes> = slices(&payload, chunk_size).collect();
+    Body::from_stream(futures::stream::iter(chunks.into_iter().map(Ok::<_, Infallible>)))
+}
+
+/// WebSocket messages carrying a response in pieces: `data` concatenated in
+/// `seq` order is the response, and `last` marks the final piece
+pub fn ws_messages(response: &str, id: Option<&Value>, chunk_size: usize) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut rest = response;
+    while !rest.is_empty() {
+        let mut end = chunk_size.max(4).min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (data, tail) = rest.split_at(end);
+        rest = tail;
+        messages.push(
+            serde_json::json!({
+                "citadel": "chunk",
+                "id": id,
+                "seq": messages.len(),
+                "last": rest.is_empty(),
+                "data": data,
+            })
+            .to_string(),
+        );
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_reassemble() {
+        let response = format!("{{\"jsonrpc\":\"2.0\",\"id\":3,\"result\":{{\"text\":\"{}\"}}}}", "é".repeat(50));
+        let bytes = Bytes::from(response.clone());
+        let joined: Vec<u8> = slices(&bytes, 16).flat_map(|b| b.to_vec()).collect();
+        assert_eq!(joined, response.as_bytes());
+
+        let messages = ws_messages(&response, Some(&serde_json::json!(3)), 16);
+        let chunks: Vec<Value> = messages.iter().map(|m| serde_json::from_str(m).unwrap()).collect();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c["id"] == 3));
+        assert_eq!(chunks.iter().filter(|c| c["last"] == true).count(), 1);
+        let data: String = chunks.iter().map(|c| c["data"].as_str().unwrap()).collect();
+        assert_eq!(data, response);
+    }
+}
@@ -0,0 +1,135 @@
+//! Durable HTTP session state
+//! SSE event ids come from a counter persisted in the data dir, so they keep
+//! increasing across hub restarts, and session replay buffers are saved on
+//! every cleanup pass and on shutdown, then restored on start, so
+//! `Last-Event-ID` resumption works across upgrades
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Event ids reserved per write of the counter file
+const RESERVE_BLOCK: u64 = 1000;
+
+/// HTTP session state directory
+pub fn state_dir() -> PathBuf {
+    crate::config::data_dir().join("http")
+}
+
+/// Hub-wide SSE event id source
+///
+/// Ids are handed out from a block reserved in the counter file; after a
+/// restart counting resumes past the reserved block, so an id is never reused.
+#[derive(Debug)]
+pub struct EventIds {
+    last: AtomicU64,
+    /// Highest id covered by the counter file
+    reserved: Mutex<u64>,
+    path: Option<PathBuf>,
+}
+
+impl EventIds {
+    /// Resume from the counter file at `path`
+    pub fn load(path: PathBuf) -> Self {
+        let start = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0);
+        Self {
+            last: AtomicU64::new(start),
+            reserved: Mutex::new(start),
+            path: Some(path),
+        }
+    }
+
+    /// Counter that isn't persisted
+    pub fn in_memory() -> Self {
+        Self {
+            last: AtomicU64::new(0),
+            reserved: Mutex::new(u64::MAX),
+            path: None,
+        }
+    }
+
+    pub fn next(&self) -> u64 {
+        let id = self.last.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut reserved = self.reserved.lock().unwrap();
+        if id > *reserved {
+            *reserved = id + RESERVE_BLOCK;
+            if let Some(path) = &self.path {
+                if let Err(e) = write(path, &reserved.to_string()) {
+                    warn!("Failed to persist SSE event id counter: {:#}", e);
+                }
+            }
+        }
+        id
+    }
+}
+
+/// A buffered SSE message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferedMessage {
+    pub event_id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    pub data: String,
+}
+
+/// What survives a restart of one session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub id: String,
+    #[serde(default)]
+    pub server_name: Option<String>,
+    pub correlation_id: String,
+    #[serde(default)]
+    pub messages: Vec<BufferedMessage>,
+}
+
+pub fn load_sessions(path: &Path) -> Vec<SavedSession> {
+    let Ok(data) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&data).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable session state {}: {}", path.display(), e);
+        Vec::new()
+    })
+}
+
+pub fn save_sessions(path: &Path, sessions: &[SavedSession]) -> Result<()> {
+    write(path, &serde_json::to_string(sessions)?)
+}
+
+/// Replace a file without leaving a half-written one behind
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents).context(format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_ids_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("citadel-event-ids-{}", std::process::id()));
+        let path = dir.join("event-id");
+
+        let ids = EventIds::load(path.clone());
+        assert_eq!(ids.next(), 1);
+        assert_eq!(ids.next(), 2);
+
+        // A restart continues past everything the first run could have issued
+        let ids = EventIds::load(path);
+        assert!(ids.next() > 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,44 +3,96 @@
 
 use anyhow::Result;
 use axum::{
-    extract::State,
-    http::{header, HeaderMap, StatusCode},
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode, Uri},
     response::{
         sse::{Event, KeepAlive, Sse},
-        Response,
+        IntoResponse, Response,
     },
     routing::post,
     Router,
 };
+use dashmap::DashMap;
 use headers::{HeaderMapExt, Origin};
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::convert::Infallible;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc;
 use tokio_stream::{wrappers::ReceiverStream, Stream};
-use futures::StreamExt;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use super::chunked;
+use super::durable::{self, BufferedMessage, EventIds, SavedSession};
 use crate::config::HttpConfig;
-use crate::metrics;
-use crate::router::HubManager;
+use crate::{logging, metrics};
+use crate::router::{events, history, identity, maintenance, notices};
+use crate::router::workspace::{Identity, Scope};
+use crate::router::{extract_server_name, HubEvent, HubManager, ServerInfo, ServerState};
 
 /// MCP Protocol version supported
 const MCP_PROTOCOL_VERSION: &str = "2025-06-18";
 
-/// Buffered message for replay
-#[derive(Debug, Clone)]
-struct BufferedMessage {
-    event_id: u64,
-    event_type: Option<String>,
-    data: String,
+/// Event IDs and recent messages of one session
+///
+/// Shared with the session's in-flight stream tasks, so buffering a response
+/// never touches the session map.
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    /// Hub-wide event IDs, so resumption works across sessions and restarts
+    event_ids: Arc<EventIds>,
+    /// Recent messages for replay (bounded by `message_buffer_size`)
+    messages: std::sync::Mutex<VecDeque<BufferedMessage>>,
+}
+
+impl ReplayBuffer {
+    pub fn new(event_ids: Arc<EventIds>, messages: Vec<BufferedMessage>) -> Self {
+        Self {
+            event_ids,
+            messages: std::sync::Mutex::new(messages.into()),
+        }
+    }
+
+    pub fn next_event_id(&self) -> u64 {
+        self.event_ids.next()
+    }
+
+    pub fn push(&self, event_id: u64, event_type: Option<String>, data: String, max_size: usize, max_bytes: usize) {
+        let mut messages = self.messages.lock().unwrap();
+        messages.push_back(BufferedMessage {
+            event_id,
+            event_type,
+            data,
+        });
+
+        // Keep buffer size limited, by count and by bytes (a single message
+        // over `max_bytes` isn't replayable)
+        let mut bytes: usize = messages.iter().map(|m| m.data.len()).sum();
+        while messages.len() > max_size || bytes > max_bytes {
+            let Some(dropped) = messages.pop_front() else { break };
+            bytes -= dropped.data.len();
+        }
+    }
+
+    fn messages_after(&self, last_event_id: u64) -> Vec<BufferedMessage> {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|msg| msg.event_id > last_event_id)
+            .cloned()
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.messages.lock().unwrap().len()
+    }
 }
 
 /// HTTP session state
-#[derive(Debug, Clone)]
-struct HttpSession {
+#[derive(Debug)]
+pub struct HttpSession {
     id: String,
     #[allow(dead_code)]
     created_at: Instant,
@@ -48,16 +100,14 @@ struct HttpSession {
     server_name: Option<String>,
     /// Channel for sending SSE events (bidirectional communication)
     event_tx: Option<mpsc::Sender<Result<Event, Infallible>>>,
-    /// Last event ID for resumability
-    last_event_id: u64,
-    /// Buffer of recent messages for replay (max 100 messages)
-    message_buffer: Vec<BufferedMessage>,
+    /// Event IDs and replay buffer
+    replay: Arc<ReplayBuffer>,
     /// Correlation ID for request tracing
     correlation_id: String,
 }
 
 impl HttpSession {
-    fn new() -> Self {
+    pub fn new(event_ids: Arc<EventIds>) -> Self {
         let session_id = Uuid::new_v4().to_string();
         Self {
             id: session_id.clone(),
@@ -65,53 +115,107 @@ impl HttpSession {
             last_activity: Instant::now(),
             server_name: None,
             event_tx: None,
-            last_event_id: 0,
-            message_buffer: Vec::new(),
+            replay: Arc::new(ReplayBuffer::new(event_ids, Vec::new())),
             correlation_id: format!("sess_{}", &session_id[..8]),
         }
     }
 
+    /// Bring back a session saved by a previous hub run; its idle timeout starts over
+    fn restore(saved: SavedSession, event_ids: Arc<EventIds>) -> Self {
+        Self {
+            id: saved.id,
+            created_at: Instant::now(),
+            last_activity: Instant::now(),
+            server_name: saved.server_name,
+            event_tx: None,
+            replay: Arc::new(ReplayBuffer::new(event_ids, saved.messages)),
+            correlation_id: saved.correlation_id,
+        }
+    }
+
+    fn save(&self) -> SavedSession {
+        SavedSession {
+            id: self.id.clone(),
+            server_name: self.server_name.clone(),
+            correlation_id: self.correlation_id.clone(),
+            messages: self.replay.messages.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+
     fn is_expired(&self, timeout: Duration) -> bool {
         self.last_activity.elapsed() > timeout
     }
 
-    fn touch(&mut self) {
-        self.last_activity = Instant::now();
+    /// Tell a client with an open stream that its session is gone and close
+    /// the stream, so it re-initializes instead of running into 404s
+    fn notify_expired(&mut self, timeout: Duration) {
+        let Some(tx) = self.event_tx.take() else {
+            return;
+        };
+        let data = serde_json::json!({
+            "session": self.id,
+            "reason": "expired",
+            "message": format!(
+                "Session expired after {}s of inactivity; send a new initialize request without Mcp-Session-Id",
+                timeout.as_secs()
+            ),
+        });
+        let event = Event::default()
+            .id(self.replay.next_event_id().to_string())
+            .event("session_expired")
+            .data(data.to_string());
+        // Don't wait on a stalled client; dropping the sender ends the stream either way
+        if tx.try_send(Ok(event)).is_err() {
+            debug!("Could not deliver session_expired to {}", self.id);
+        }
     }
 
-    fn next_event_id(&mut self) -> u64 {
-        self.last_event_id += 1;
-        self.last_event_id
+    /// Tell the client its server was restarted
+    fn notify_restarted(&self, reason: &str, config: &HttpConfig) {
+        if let Some(server) = &self.server_name {
+            self.notify(notices::server_restarted(server, reason), config);
+        }
     }
 
-    fn buffer_message(&mut self, event_id: u64, event_type: Option<String>, data: String, max_size: usize) {
-        self.message_buffer.push(BufferedMessage {
-            event_id,
-            event_type,
-            data,
-        });
-        
-        // Keep buffer size limited
-        if self.message_buffer.len() > max_size {
-            self.message_buffer.remove(0);
+    /// Send the client a hub notification (or a backend's message), on its
+    /// open stream and in the replay buffer for a client that reconnects with
+    /// `Last-Event-ID`
+    fn notify(&self, notification: serde_json::Value, config: &HttpConfig) {
+        let event_id = self.replay.next_event_id();
+        let data = notification.to_string();
+        self.replay.push(event_id, None, data.clone(), config.message_buffer_size, config.message_buffer_bytes);
+        if let Some(tx) = &self.event_tx {
+            if tx.try_send(Ok(Event::default().id(event_id.to_string()).data(data))).is_err() {
+                debug!("Could not deliver {} to {}", notification["method"], self.id);
+            }
         }
     }
 
-    fn get_messages_after(&self, last_event_id: u64) -> Vec<BufferedMessage> {
-        self.message_buffer
-            .iter()
-            .filter(|msg| msg.event_id > last_event_id)
-            .cloned()
-            .collect()
+    /// Session ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Replay buffer shared with this session's stream tasks
+    pub fn replay(&self) -> Arc<ReplayBuffer> {
+        Arc::clone(&self.replay)
+    }
+
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
     }
 }
 
+/// Concurrent session map, sharded so requests on different sessions don't contend
+pub type SessionMap = DashMap<String, HttpSession>;
+
 /// Shared application state
 #[derive(Clone)]
 pub(super) struct AppState {
     pub(super) manager: Arc<HubManager>,
-    pub(super) sessions: Arc<Mutex<HashMap<String, HttpSession>>>,
+    pub(super) sessions: Arc<SessionMap>,
     pub(super) config: HttpConfig,
+    pub(super) event_ids: Arc<EventIds>,
 }
 
 /// HTTP transport server
@@ -127,13 +231,50 @@ impl HttpTransport {
 
     /// Start the HTTP server
     pub async fn start(self) -> Result<()> {
+        self.start_with_shutdown(std::future::pending()).await
+    }
+
+    /// Start the HTTP server, stopping once `shutdown` completes
+    ///
+    /// After `shutdown` the listener closes and the call returns when the open
+    /// connections finish.
+    pub async fn start_with_shutdown(self, shutdown: impl std::future::Future<Output = ()> + Send + 'static) -> Result<()> {
         let addr = format!("{}:{}", self.config.host, self.config.port);
-        
+        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        self.serve_with_shutdown(listener, shutdown).await
+    }
+
+    /// Serve on an already bound listener (the configured host and port are
+    /// ignored), stopping once `shutdown` completes
+    pub async fn serve_with_shutdown(
+        self,
+        listener: tokio::net::TcpListener,
+        shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        let addr = listener.local_addr()?;
+
+        let state_dir = durable::state_dir();
+        let event_ids = Arc::new(EventIds::load(state_dir.join("event-id")));
+        let sessions_path = state_dir.join("sessions.json");
+        let sessions = SessionMap::new();
+        for saved in durable::load_sessions(&sessions_path) {
+            sessions.insert(saved.id.clone(), HttpSession::restore(saved, Arc::clone(&event_ids)));
+        }
+        if !sessions.is_empty() {
+            info!("Restored {} HTTP sessions", sessions.len());
+        }
+
         let state = AppState {
             manager: self.manager,
-            sessions: Arc::new(Mutex::new(HashMap::new())),
+            sessions: Arc::new(sessions),
             config: self.config.clone(),
+            event_ids,
         };
+        let sessions = Arc::clone(&state.sessions);
+        let manager = Arc::clone(&state.manager);
+        for session in sessions.iter() {
+            spawn_reverse_requests(&state, session.id.clone());
+        }
 
         // Start session cleanup task
         let cleanup_state = state.clone();
@@ -141,30 +282,79 @@ impl HttpTransport {
             session_cleanup_task(cleanup_state).await;
         });
 
-        let app = Router::new()
+        let notice_state = state.clone();
+        tokio::spawn(async move {
+            restart_notice_task(notice_state).await;
+        });
+
+        let maintenance_state = state.clone();
+        tokio::spawn(async move {
+            maintenance_notice_task(maintenance_state).await;
+        });
+
+        let notification_state = state.clone();
+        tokio::spawn(async move {
+            notification_task(notification_state).await;
+        });
+
+        let mut app = Router::new()
             .route("/mcp", post(handle_post))
             .route("/mcp", axum::routing::get(handle_get))
             .route("/ws", axum::routing::get(super::websocket::handle_websocket))
             .route("/metrics", axum::routing::get(handle_metrics))
             .route("/health", axum::routing::get(handle_health))
+            .route("/healthz", axum::routing::get(handle_healthz))
+            .route("/readyz", axum::routing::get(handle_readyz))
+            .route(crate::summary::SUMMARY_PATH, axum::routing::get(handle_summary))
+            .route(history::HISTORY_PATH, axum::routing::get(handle_history))
+            .route(events::EVENTS_PATH, axum::routing::get(handle_events))
+            .route(logging::LOG_LEVEL_PATH, axum::routing::get(handle_log_level).put(handle_set_log_level));
+
+        if self.config.inspector {
+            app = app
+                .route("/ui/inspector", axum::routing::get(super::inspector::handle_page))
+                .route("/ui/inspector/api/servers", axum::routing::get(super::inspector::handle_servers))
+                .route("/ui/inspector/api/:server", post(super::inspector::handle_request));
+            info!("🔍 Inspector UI at http://{}/ui/inspector", addr);
+        }
+
+        if self.config.rest_bridge {
+            app = app
+                .route("/api/openapi.json", axum::routing::get(super::rest::handle_openapi))
+                .route("/api/:server/:tool", post(super::rest::handle_call));
+            info!("🔌 REST bridge at http://{}/api (OpenAPI: /api/openapi.json)", addr);
+        }
+
+        let app = app
+            .layer(axum::extract::DefaultBodyLimit::max(self.config.max_request_bytes))
             .with_state(state);
 
         info!("🌐 HTTP transport listening on http://{}", addr);
-        
-        let listener = tokio::net::TcpListener::bind(&addr).await?;
-        axum::serve(listener, app).await?;
+        for (kind, address) in [
+            ("http", format!("http://{}/mcp", addr)),
+            ("websocket", format!("ws://{}/ws", addr)),
+            ("metrics", format!("http://{}/metrics", addr)),
+        ] {
+            manager.register_endpoint(kind, address);
+        }
+
+        axum::serve(listener, app).with_graceful_shutdown(shutdown).await?;
 
+        save_sessions(&sessions);
         Ok(())
     }
 }
 
+/// Boxed SSE event stream
+type EventStream = std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>;
+
 /// Response type for handle_post - either JSON or SSE
 enum PostResponse {
     Json(Response<axum::body::Body>),
-    Sse(Sse<std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>>>),
+    Sse(Sse<EventStream>),
 }
 
-impl axum::response::IntoResponse for PostResponse {
+impl IntoResponse for PostResponse {
     fn into_response(self) -> Response<axum::body::Body> {
         match self {
             PostResponse::Json(r) => r,
@@ -176,6 +366,25 @@ impl axum::response::IntoResponse for PostResponse {
 /// Handle POST /mcp - Client sends JSON-RPC message (smart response: JSON or SSE)
 async fn handle_post(
     State(state): State<AppState>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<PostResponse, StatusCode> {
+    let timer = metrics::RequestTimer::new("POST", "/mcp");
+    let result = process_post(state, uri, headers, body).await;
+    let status = match &result {
+        Ok(_) => StatusCode::OK,
+        Err(code) => *code,
+    };
+    metrics::record_http_request("POST", "/mcp", status.as_u16());
+    timer.observe_duration();
+    result
+}
+
+/// Process a POST /mcp request
+async fn process_post(
+    state: AppState,
+    uri: Uri,
     headers: HeaderMap,
     body: axum::body::Bytes,
 ) -> Result<PostResponse, StatusCode> {
@@ -205,34 +414,82 @@ async fn handle_post(
     let is_initialize = method == "initialize";
     let use_streaming = needs_streaming(method);
 
+    // Answers to requests backends sent the client go back where they came from
+    if let (None, Some(session_id)) = (json_value.get("method"), headers.get("mcp-session-id").and_then(|v| v.to_str().ok())) {
+        if let Some(routed) = state.manager.route_client_response(session_id, &body).await {
+            if let Err(e) = routed {
+                warn!("Failed to route a response from session {}: {}", session_id, e);
+            }
+            return Ok(PostResponse::Json(
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(axum::body::Body::empty())
+                    .unwrap()
+            ));
+        }
+    }
+
+    // Extract server name (only servers in the client's workspace are
+    // reachable, and its aliases stand for their targets)
+    let requested = extract_server_name(&body)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let scope = request_scope(&state, &headers, &uri)?;
+    if let Some(answer) = identity::answer(&state.manager, Some(&scope), &body).await {
+        return Ok(PostResponse::Json(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(answer))
+                .unwrap(),
+        ));
+    }
+    let server_name = scope.target(&requested).ok_or(StatusCode::NOT_FOUND)?.to_string();
+    let priority = scope.priority;
+
+    // Requests a read-only client may not send, and any during maintenance,
+    // never reach the backend
+    let rejection = state.manager.maintenance().rejection(&body).or_else(|| scope.reject(&requested, &body));
+    if let Some(rejection) = rejection {
+        info!("Rejected {} for {}", method, server_name);
+        return Ok(PostResponse::Json(
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(rejection))
+                .unwrap(),
+        ));
+    }
+
     // 4. Get or create session
     let session_id = headers
         .get("mcp-session-id")
         .and_then(|v| v.to_str().ok())
         .map(String::from);
 
-    let mut sessions = state.sessions.lock().await;
-    
-    let session = if is_initialize {
-        let new_session = HttpSession::new();
+    let session_id = if is_initialize {
+        let new_session = HttpSession::new(Arc::clone(&state.event_ids));
         let sid = new_session.id.clone();
-        sessions.insert(sid.clone(), new_session.clone());
-        new_session
+        state.sessions.insert(sid.clone(), new_session);
+        spawn_reverse_requests(&state, sid.clone());
+        metrics::record_session_created("http");
+        state.manager.session_created(&sid, "http");
+        sid
     } else if let Some(sid) = session_id {
-        sessions.get_mut(&sid)
-            .ok_or(StatusCode::NOT_FOUND)?
-            .clone()
+        sid
     } else {
         return Err(StatusCode::BAD_REQUEST);
     };
 
-    let session_id = session.id.clone();
-    let correlation_id = session.correlation_id.clone();
-    
-    // Extract server name
-    let server_name = extract_server_name(&body)
-        .ok_or(StatusCode::BAD_REQUEST)?;
-    
+    let (correlation_id, replay) = {
+        let mut session = state
+            .sessions
+            .get_mut(&session_id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        session.touch();
+        (session.correlation_id.clone(), session.replay())
+    };
+    state.manager.transcripts().open(&session_id, "http");
+
     // Log request with correlation ID
     info!(
         "[{}] POST /mcp method={} server={} session={}",
@@ -242,11 +499,18 @@ async fn handle_post(
     // 5. Smart response mode: JSON for simple ops, SSE for streaming
     if !use_streaming {
         // Direct JSON response for simple operations
-        drop(sessions);
-        
         let manager = state.manager.clone();
         let start = Instant::now();
-        match manager.route_message(&server_name, &body).await {
+        match manager.route_session_message(Some(&session_id), priority, &server_name, body.clone()).await {
+            Ok(response) if response.is_empty() => {
+                // Notification or client response - nothing to return
+                Ok(PostResponse::Json(
+                    Response::builder()
+                        .status(StatusCode::ACCEPTED)
+                        .body(axum::body::Body::empty())
+                        .unwrap()
+                ))
+            }
             Ok(response) => {
                 let duration_ms = start.elapsed().as_millis();
                 info!(
@@ -257,7 +521,7 @@ async fn handle_post(
                     Response::builder()
                         .status(StatusCode::OK)
                         .header(header::CONTENT_TYPE, "application/json")
-                        .body(axum::body::Body::from(response))
+                        .body(chunked::body(response, state.config.chunk_size_bytes))
                         .unwrap()
                 ))
             }
@@ -267,6 +531,7 @@ async fn handle_post(
                     "[{}] Error: method={} error={} duration={}ms",
                     correlation_id, method, e, duration_ms
                 );
+                metrics::record_error("routing_error", Some(&server_name));
                 
                 // Return JSON error response
                 let error_json = serde_json::json!({
@@ -293,31 +558,32 @@ async fn handle_post(
         }
     } else {
         // SSE streaming for long-running/bidirectional operations
-        let (tx, rx) = mpsc::channel(100);
+        let (tx, rx) = mpsc::channel(state.config.stream_channel_capacity.max(1));
         
         // Get next event ID for this session
-        let event_id = if let Some(session_mut) = sessions.get_mut(&session_id) {
-            session_mut.touch();
-            session_mut.server_name = Some(server_name.clone());
-            session_mut.event_tx = Some(tx.clone());
-            session_mut.next_event_id()
+        let event_id = replay.next_event_id();
+        if let Some(mut session) = state.sessions.get_mut(&session_id) {
+            session.server_name = Some(server_name.clone());
+            session.event_tx = Some(tx.clone());
         } else {
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        };
+        }
         
-        let sessions_arc = state.sessions.clone();
         let buffer_size = state.config.message_buffer_size;
-        drop(sessions);
+        let buffer_bytes = state.config.message_buffer_bytes;
 
         // 6. Spawn async task to handle backend communication
         let manager = state.manager.clone();
-        let body_clone = body.to_vec();
-        let session_id_clone = session_id.clone();
+        let body_clone = body.clone();
         let json_id = json_value.get("id").cloned();
+        let session = session_id.clone();
         
         tokio::spawn(async move {
             // Route message to backend (non-blocking for this HTTP handler)
-            match manager.route_message(&server_name, &body_clone).await {
+            match manager.route_session_message(Some(&session), priority, &server_name, body_clone).await {
+                Ok(response) if response.is_empty() => {
+                    // Notification - no event to send, the stream just ends
+                }
                 Ok(response) => {
                     // Parse response to extract event data
                     if let Ok(json) = std::str::from_utf8(&response) {
@@ -326,11 +592,7 @@ async fn handle_post(
                             .data(json.trim_end());
                         
                         // Buffer the message for replay
-                        let mut sessions = sessions_arc.lock().await;
-                        if let Some(session) = sessions.get_mut(&session_id_clone) {
-                            session.buffer_message(event_id, None, json.trim_end().to_string(), buffer_size);
-                        }
-                        drop(sessions);
+                        replay.push(event_id, None, json.trim_end().to_string(), buffer_size, buffer_bytes);
                         
                         // Send via SSE
                         let _ = tx.send(Ok(event)).await;
@@ -362,6 +624,8 @@ async fn handle_post(
                         (-32603, "internal_error")
                     };
                     
+                    metrics::record_error(error_type, Some(&server_name));
+
                     let error_json = serde_json::json!({
                         "jsonrpc": "2.0",
                         "id": json_id,
@@ -387,7 +651,7 @@ async fn handle_post(
         let base_stream = ReceiverStream::new(rx);
         
         // For initialize, prepend session event
-        let stream: std::pin::Pin<Box<dyn Stream<Item = Result<Event, Infallible>> + Send>> = if is_initialize {
+        let stream: EventStream = if is_initialize {
             // Include session ID in first event
             let init_event = Event::default()
                 .event("session")
@@ -418,8 +682,8 @@ async fn handle_get(
         .and_then(|v| v.to_str().ok())
         .ok_or(StatusCode::BAD_REQUEST)?;
 
-    let mut sessions = state.sessions.lock().await;
-    let session = sessions
+    let mut session = state
+        .sessions
         .get_mut(session_id)
         .ok_or(StatusCode::NOT_FOUND)?;
 
@@ -433,20 +697,21 @@ async fn handle_get(
 
     // Get buffered messages for replay
     let replay_messages = if let Some(last_id) = last_event_id {
-        let msgs = session.get_messages_after(last_id);
+        let msgs = session.replay.messages_after(last_id);
         info!("Client resuming from event {}: replaying {} messages", last_id, msgs.len());
+        metrics::record_message_replay(session_id, msgs.len());
         msgs
     } else {
         Vec::new()
     };
 
     // Create SSE stream
-    let (tx, rx) = mpsc::channel(100);
+    let (tx, rx) = mpsc::channel(state.config.stream_channel_capacity.max(1));
     
     // Store sender in session
     session.event_tx = Some(tx.clone());
     
-    drop(sessions);
+    drop(session);
 
     // Replay buffered messages if resuming
     if !replay_messages.is_empty() {
@@ -492,7 +757,7 @@ fn needs_streaming(method: &str) -> bool {
 }
 
 /// Validate Origin header to prevent DNS rebinding attacks
-fn validate_origin(headers: &HeaderMap) -> Result<(), StatusCode> {
+pub(super) fn validate_origin(headers: &HeaderMap) -> Result<(), StatusCode> {
     // In production, you should validate against allowed origins
     // For now, we require localhost origins only
     
@@ -515,28 +780,54 @@ fn validate_origin(headers: &HeaderMap) -> Result<(), StatusCode> {
     }
 }
 
-/// Extract server name from JSON-RPC message
-fn extract_server_name(message: &[u8]) -> Option<String> {
-    let text = std::str::from_utf8(message).ok()?;
-    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+/// Workspace scope of an HTTP client
+///
+/// The workspace can be named with `X-MCP-Workspace` or `?workspace=`; the API
+/// key comes from `Authorization: Bearer` or `X-API-Key`.
+pub(super) fn request_scope(state: &AppState, headers: &HeaderMap, uri: &Uri) -> Result<Scope, StatusCode> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let requested = header("x-mcp-workspace").or_else(|| {
+        uri.query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("workspace="))
+    });
+    let api_key = header("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"));
 
-    // Try params.server
-    if let Some(params) = value.get("params") {
-        if let Some(server) = params.get("server") {
-            return server.as_str().map(String::from);
-        }
-    }
+    state
+        .manager
+        .scope(Identity { requested, api_key, unix_user: None })
+        .map_err(|e| {
+            warn!("Rejected HTTP client: {}", e);
+            StatusCode::FORBIDDEN
+        })
+}
 
-    // Try method prefix (e.g., "github/tools/list")
-    if let Some(method) = value.get("method") {
-        if let Some(method_str) = method.as_str() {
-            if let Some(server) = method_str.split('/').next() {
-                return Some(server.to_string());
-            }
-        }
+/// Scope of a client of the `/admin/*` routes
+///
+/// An admin key sees every server. Otherwise the client must map to a
+/// workspace, and sees its servers, unless no admin keys are configured and
+/// the transport only listens on loopback.
+pub(super) fn admin_scope(state: &AppState, headers: &HeaderMap, uri: &Uri) -> Result<Scope, StatusCode> {
+    validate_origin(headers)?;
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let key = header("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"));
+    if key.is_some_and(|key| state.config.admin_keys.iter().any(|k| k == key)) {
+        return Ok(Scope::default());
+    }
+    let scope = request_scope(state, headers, uri)?;
+    if scope.workspace.is_some() || (state.config.admin_keys.is_empty() && is_loopback(&state.config.host)) {
+        return Ok(scope);
     }
+    warn!("Rejected admin request without an admin key");
+    Err(StatusCode::UNAUTHORIZED)
+}
 
-    None
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
 }
 
 /// Handle GET /metrics - Prometheus metrics endpoint
@@ -554,11 +845,32 @@ async fn handle_metrics() -> Result<Response<axum::body::Body>, StatusCode> {
     }
 }
 
+/// Handle GET /healthz - liveness probe (the process is serving requests)
+async fn handle_healthz() -> &'static str {
+    "ok"
+}
+
+/// Handle GET /readyz - readiness probe (servers are up, or waiting to start
+/// on demand, and the hub isn't draining)
+async fn handle_readyz(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    let available = |info: &ServerInfo| {
+        matches!(
+            info.state,
+            ServerState::Running | ServerState::Unresponsive | ServerState::Idle | ServerState::Initializing
+        )
+    };
+    if state.manager.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else if !state.manager.server_info().await.iter().any(available) {
+        (StatusCode::SERVICE_UNAVAILABLE, "no servers available")
+    } else {
+        (StatusCode::OK, "ready")
+    }
+}
+
 /// Handle GET /health - Health check endpoint
 async fn handle_health(State(state): State<AppState>) -> Result<Response<axum::body::Body>, StatusCode> {
-    let sessions = state.sessions.lock().await;
-    let session_count = sessions.len();
-    drop(sessions);
+    let session_count = state.sessions.len();
     
     let server_list = state.manager.list_servers().await;
     let server_count = server_list.len();
@@ -594,35 +906,221 @@ async fn handle_health(State(state): State<AppState>) -> Result<Response<axum::b
         .unwrap())
 }
 
+/// Handle GET /admin/summary - compact health document for fleet dashboards
+async fn handle_summary(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Result<axum::Json<crate::summary::Summary>, StatusCode> {
+    let scope = admin_scope(&state, &headers, &uri)?;
+    Ok(axum::Json(crate::summary::build(&state.manager, &scope).await))
+}
+
+/// Handle GET /admin/history - past requests from the request history
+async fn handle_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    Query(query): Query<history::HistoryQuery>,
+) -> Response<axum::body::Body> {
+    let scope = match admin_scope(&state, &headers, &uri) {
+        Ok(scope) => scope,
+        Err(status) => return status.into_response(),
+    };
+    match tokio::task::spawn_blocking(move || history::query(&history::path(), &query, |server| scope.allows(server))).await {
+        Ok(Ok(records)) => axum::Json(records).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// The log filter is hub-wide, so it's only for clients that see every server
+fn hub_admin(state: &AppState, headers: &HeaderMap, uri: &Uri) -> Result<(), StatusCode> {
+    match admin_scope(state, headers, uri)?.is_restricted() {
+        true => Err(StatusCode::FORBIDDEN),
+        false => Ok(()),
+    }
+}
+
+/// Handle GET /admin/log-level - the log filter in effect
+async fn handle_log_level(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+    hub_admin(&state, &headers, &uri)?;
+    Ok(axum::Json(serde_json::json!({ "level": logging::current() })))
+}
+
+/// Handle PUT /admin/log-level - change the log filter, e.g. `{"level": "info,router=debug"}`
+async fn handle_set_log_level(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+    axum::Json(body): axum::Json<serde_json::Value>,
+) -> Response<axum::body::Body> {
+    if let Err(status) = hub_admin(&state, &headers, &uri) {
+        return status.into_response();
+    }
+    let Some(level) = body.get("level").and_then(|l| l.as_str()) else {
+        return (StatusCode::BAD_REQUEST, "Expected {\"level\": \"...\"}").into_response();
+    };
+    match logging::set(level) {
+        Ok(()) => {
+            info!("Log level set to {}", level);
+            axum::Json(serde_json::json!({ "level": logging::current() })).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response(),
+    }
+}
+
+/// Handle GET /events - hub lifecycle events as server-sent events, each
+/// named by its kind
+async fn handle_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    validate_origin(&headers)?;
+    let scope = request_scope(&state, &headers, &uri)?;
+    let mut lifecycle = state.manager.lifecycle_events();
+    let (tx, rx) = mpsc::channel(state.config.stream_channel_capacity.max(1));
+    tokio::spawn(async move {
+        loop {
+            let event = match lifecycle.recv().await {
+                Ok(event) => events::stamp(&event),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => serde_json::json!({ "event": "lagged", "skipped": n }),
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            // A workspace only hears about its own servers
+            if event["event"] != "lagged" && !events::visible(&event, &scope) {
+                continue;
+            }
+            let kind = event["event"].as_str().unwrap_or("event").to_string();
+            // Stops once the client disconnects
+            if tx.send(Ok(Event::default().event(kind).data(event.to_string()))).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default()))
+}
+
 /// Background task to cleanup expired sessions
 async fn session_cleanup_task(state: AppState) {
-    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    let timeout = Duration::from_secs(state.config.session_timeout_secs);
+    // Every minute, or sooner for shorter timeouts
+    let mut interval = tokio::time::interval(timeout.clamp(Duration::from_secs(1), Duration::from_secs(60)));
     
     loop {
         interval.tick().await;
         
-        let timeout = Duration::from_secs(state.config.session_timeout_secs);
-        let mut sessions = state.sessions.lock().await;
-        
         // Update metrics
-        metrics::set_active_sessions(sessions.len());
+        metrics::set_active_sessions(state.sessions.len());
         
         // Calculate total buffer size
-        let total_buffer_size: usize = sessions
-            .values()
-            .map(|s| s.message_buffer.len())
+        let total_buffer_size: usize = state
+            .sessions
+            .iter()
+            .map(|s| s.replay.len())
             .sum();
         metrics::set_message_buffer_size(total_buffer_size);
         
-        let expired: Vec<String> = sessions
-            .iter()
-            .filter(|(_, session)| session.is_expired(timeout))
-            .map(|(id, _)| id.clone())
-            .collect();
-        
-        for id in expired {
-            info!("Cleaning up expired session: {}", id);
-            sessions.remove(&id);
+        state.sessions.retain(|id, session| {
+            let expired = session.is_expired(timeout);
+            if expired {
+                info!("Cleaning up expired session: {}", id);
+                session.notify_expired(timeout);
+                state.manager.transcripts().close(id);
+                state.manager.end_session(id);
+                state.manager.session_expired(id);
+            }
+            !expired
+        });
+
+        save_sessions(&state.sessions);
+    }
+}
+
+/// Forward backend restarts to the sessions using the restarted server
+async fn restart_notice_task(state: AppState) {
+    let mut restarts = state.manager.restart_notices();
+    loop {
+        let (server, reason) = restarts.recv().await;
+        for session in state.sessions.iter() {
+            if session.server_name.as_deref() == Some(server.as_str()) {
+                session.notify_restarted(&reason, &state.config);
+            }
         }
     }
 }
+
+/// Deliver the requests backends send a session's client on its SSE stream,
+/// until the session is gone
+fn spawn_reverse_requests(state: &AppState, session_id: String) {
+    let mut requests = state.manager.reverse().open(&session_id);
+    let state = state.clone();
+    tokio::spawn(async move {
+        while let Some((_, request)) = requests.recv().await {
+            let Ok(request) = serde_json::from_slice::<serde_json::Value>(&request) else {
+                continue;
+            };
+            match state.sessions.get(&session_id) {
+                Some(session) => session.notify(request, &state.config),
+                None => break,
+            }
+        }
+    });
+}
+
+/// Forward the notifications backends send on their own (logging, list
+/// changes, resource updates) to the sessions using them
+async fn notification_task(state: AppState) {
+    let mut events = state.manager.backend_events();
+    loop {
+        match events.recv().await {
+            Ok(event) if event.is_broadcast() => {
+                let Ok(notification) = serde_json::from_slice::<serde_json::Value>(&event.message) else {
+                    continue;
+                };
+                for session in state.sessions.iter() {
+                    if session.server_name.as_deref() == Some(event.server.as_str())
+                        && state.manager.client_accepts(&session.id, &event.method)
+                    {
+                        session.notify(notification.clone(), &state.config);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                warn!("HTTP sessions lagged, {} backend messages skipped", n);
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Tell every session when a maintenance window begins and ends
+async fn maintenance_notice_task(state: AppState) {
+    let mut events = state.manager.lifecycle_events();
+    loop {
+        match events.recv().await {
+            Ok(HubEvent::Maintenance { active, until, reason }) => {
+                let notice = maintenance::notice(active, until.as_deref(), reason.as_deref());
+                for session in state.sessions.iter() {
+                    session.notify(notice.clone(), &state.config);
+                }
+            }
+            Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Persist every session's replay buffer for the next hub run
+fn save_sessions(sessions: &SessionMap) {
+    let saved: Vec<_> = sessions.iter().map(|s| s.save()).collect();
+    if let Err(e) = durable::save_sessions(&durable::state_dir().join("sessions.json"), &saved) {
+        warn!("Failed to save HTTP sessions: {:#}", e);
+    }
+}
@@ -0,0 +1,71 @@
+//! Inspector UI for MCP Citadel
+//!
+//! Serves a browser UI at /ui/inspector for browsing a managed server's tools,
+//! resources, and prompts and invoking tools with forms built from their schemas.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use tracing::info;
+
+use super::http::{request_scope, validate_origin, AppState};
+
+const INSPECTOR_HTML: &str = include_str!("inspector.html");
+
+/// Handle GET /ui/inspector
+pub(super) async fn handle_page(
+    State(state): State<AppState>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Html<&'static str>, StatusCode> {
+    validate_origin(&headers)?;
+    request_scope(&state, &headers, &uri)?;
+    Ok(Html(INSPECTOR_HTML))
+}
+
+/// Handle GET /ui/inspector/api/servers - names of the servers in the client's workspace
+pub(super) async fn handle_servers(
+    State(state): State<AppState>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Vec<String>>, StatusCode> {
+    validate_origin(&headers)?;
+    let scope = request_scope(&state, &headers, &uri)?;
+    let mut servers = scope.filter(state.manager.list_servers().await);
+    servers.sort();
+    Ok(Json(servers))
+}
+
+/// Handle POST /ui/inspector/api/:server - send one JSON-RPC request to a server
+pub(super) async fn handle_request(
+    State(state): State<AppState>,
+    Path(server): Path<String>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    validate_origin(&headers)?;
+    let scope = request_scope(&state, &headers, &uri)?;
+    let target = scope.target(&server).ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(rejection) = state.manager.maintenance().rejection(&body).or_else(|| scope.reject(&server, &body)) {
+        return Ok(([(header::CONTENT_TYPE, "application/json")], rejection).into_response());
+    }
+
+    info!("[inspector] {} bytes to {}", body.len(), target);
+
+    match state.manager.route_message(target, body).await {
+        Ok(response) if response.is_empty() => Ok(StatusCode::ACCEPTED.into_response()),
+        Ok(response) => Ok(([(header::CONTENT_TYPE, "application/json")], response).into_response()),
+        Err(e) => {
+            let error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32603, "message": e.to_string() }
+            });
+            Ok((StatusCode::BAD_GATEWAY, Json(error)).into_response())
+        }
+    }
+}
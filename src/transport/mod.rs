@@ -1,6 +1,10 @@
 //! Transport layer implementations for MCP Citadel
 
+pub mod chunked;
+pub mod durable;
 pub mod http;
+pub mod inspector;
+pub mod rest;
 pub mod websocket;
 
 pub use http::HttpTransport;
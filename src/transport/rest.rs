@@ -0,0 +1,163 @@
+//! REST bridge for MCP Citadel
+//!
+//! Exposes every tool as `POST /api/{server}/{tool}` (JSON arguments in, tool
+//! result out) and describes them in an OpenAPI document at /api/openapi.json,
+//! so scripts and non-MCP services can call the same tools agents use.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use serde_json::Value;
+use tracing::{info, warn};
+
+use super::http::{request_scope, validate_origin, AppState};
+use crate::metrics;
+
+/// Handle POST /api/:server/:tool - call a tool with the request body as arguments
+pub(super) async fn handle_call(
+    State(state): State<AppState>,
+    Path((server, tool)): Path<(String, String)>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    validate_origin(&headers)?;
+
+    let arguments: Value = if body.is_empty() {
+        serde_json::json!({})
+    } else {
+        serde_json::from_slice(&body).map_err(|_| StatusCode::BAD_REQUEST)?
+    };
+    if !arguments.is_object() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let scope = request_scope(&state, &headers, &uri)?;
+    let target = match scope.target(&server) {
+        Some(target) if state.manager.list_servers().await.iter().any(|s| s == target) => target,
+        _ => return Err(StatusCode::NOT_FOUND),
+    };
+
+    if let Some(window) = state.manager.maintenance().current() {
+        let error = serde_json::json!({ "error": format!("Hub is under maintenance until {}", window.until), "maintenance": window });
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, Json(error)).into_response());
+    }
+
+    if scope.read_only {
+        let error = serde_json::json!({ "error": "Read-only access: tools/call is not allowed" });
+        return Ok((StatusCode::FORBIDDEN, Json(error)).into_response());
+    }
+
+    info!("[rest] {}/{}", server, tool);
+
+    let timer = metrics::MCPMessageTimer::new(target, "tools/call");
+    let params = serde_json::json!({ "name": tool, "arguments": arguments });
+    let (status, body) = match state.manager.request(target, "tools/call", params).await {
+        Ok(result) if result.get("isError").and_then(|e| e.as_bool()) == Some(true) => {
+            timer.observe_duration("error");
+            (StatusCode::UNPROCESSABLE_ENTITY, result)
+        }
+        Ok(result) => {
+            timer.observe_duration("success");
+            (StatusCode::OK, result)
+        }
+        Err(e) => {
+            timer.observe_duration("error");
+            (StatusCode::BAD_GATEWAY, serde_json::json!({ "error": e.to_string() }))
+        }
+    };
+
+    metrics::record_http_request("POST", "/api", status.as_u16());
+    Ok((status, Json(body)).into_response())
+}
+
+/// Handle GET /api/openapi.json - OpenAPI document for the tools in the client's workspace
+pub(super) async fn handle_openapi(
+    State(state): State<AppState>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Value>, StatusCode> {
+    validate_origin(&headers)?;
+    let scope = request_scope(&state, &headers, &uri)?;
+    let mut servers = scope.filter(state.manager.list_servers().await);
+    servers.sort();
+
+    let mut catalog = Vec::new();
+    for server in servers {
+        let Some(target) = scope.target(&server) else {
+            continue;
+        };
+        match state.manager.tools(target).await {
+            Ok(tools) => catalog.push((server, tools)),
+            Err(e) => warn!("[rest] Leaving {} out of the OpenAPI document: {}", server, e),
+        }
+    }
+
+    Ok(Json(openapi_document(&catalog)))
+}
+
+/// Build an OpenAPI 3.1 document with one POST operation per tool
+pub fn openapi_document(catalog: &[(String, Vec<Value>)]) -> Value {
+    let mut paths = serde_json::Map::new();
+
+    for (server, tools) in catalog {
+        for tool in tools {
+            let Some(name) = tool.get("name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            let description = tool.get("description").and_then(|d| d.as_str()).unwrap_or("");
+            let input_schema = tool
+                .get("inputSchema")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "type": "object" }));
+            let structured = tool
+                .get("outputSchema")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "type": "object" }));
+
+            paths.insert(
+                format!("/api/{}/{}", server, name),
+                serde_json::json!({
+                    "post": {
+                        "operationId": format!("{}__{}", server, name),
+                        "summary": description.lines().next().unwrap_or(""),
+                        "description": description,
+                        "tags": [server],
+                        "requestBody": {
+                            "required": true,
+                            "content": { "application/json": { "schema": input_schema } }
+                        },
+                        "responses": {
+                            "200": {
+                                "description": "Tool result",
+                                "content": { "application/json": { "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "content": { "type": "array", "items": { "type": "object" } },
+                                        "structuredContent": structured,
+                                        "isError": { "type": "boolean" }
+                                    }
+                                } } }
+                            },
+                            "422": { "description": "The tool reported an error" },
+                            "502": { "description": "The server failed or is unavailable" }
+                        }
+                    }
+                }),
+            );
+        }
+    }
+
+    serde_json::json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "MCP Citadel tools",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+    })
+}
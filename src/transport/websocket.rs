@@ -1,6 +1,8 @@
 //! WebSocket Transport for MCP Citadel
 //! 
 //! Provides bidirectional real-time communication as an alternative to SSE.
+//! Connect with `?chunked=true` to receive large responses in pieces (see
+//! `chunked`).
 
 use anyhow::Result;
 use axum::{
@@ -8,32 +10,46 @@ use axum::{
         ws::{Message, WebSocket},
         State, WebSocketUpgrade,
     },
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, Uri},
     response::Response,
 };
 use futures::{SinkExt, StreamExt};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::{HashSet, VecDeque};
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{error, info, warn};
 
-use super::http::AppState;
+use super::chunked;
+use super::http::{request_scope, AppState};
 use crate::metrics;
+use crate::router::{extract_server_name, identity, notices};
+use crate::router::workspace::Scope;
 
 /// Handle WebSocket upgrade at /ws endpoint
-pub async fn handle_websocket(
+pub(super) async fn handle_websocket(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
     info!("WebSocket connection requested");
     
     // Record WebSocket connection attempt
     metrics::record_websocket_connection("requested");
-    
-    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state)))
+
+    let scope = request_scope(&state, &headers, &uri)?;
+    let chunked = uri
+        .query()
+        .is_some_and(|q| q.split('&').any(|pair| pair == "chunked=true" || pair == "chunked=1"));
+    let chunk_size = chunked.then_some(state.config.chunk_size_bytes);
+
+    Ok(ws
+        .max_message_size(state.config.max_request_bytes)
+        .on_upgrade(move |socket| handle_socket(socket, state, scope, chunk_size)))
 }
 
-/// Handle an established WebSocket connection
-async fn handle_socket(socket: WebSocket, state: AppState) {
+/// Handle an established WebSocket connection; responses over `chunk_size`
+/// are split when the client asked for chunks
+async fn handle_socket(socket: WebSocket, state: AppState, scope: Scope, chunk_size: Option<usize>) {
     info!("WebSocket connection established");
     metrics::record_websocket_connection("established");
     metrics::set_active_connections(1); // Simplified - would track properly in production
@@ -42,12 +58,69 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
     let session_id = uuid::Uuid::new_v4().to_string();
     
     info!("[ws_{}] New WebSocket session", &session_id[..8]);
+    state.manager.session_created(&session_id, "websocket");
     
-    // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
+    let mut restarts = state.manager.restart_notices();
+    let mut events = state.manager.backend_events();
+    let mut requests = state.manager.reverse().open(&session_id);
+    let mut addressed = HashSet::new();
+    let mut queued = VecDeque::new();
+
+    // Handle incoming messages, and tell the client about restarts of and
+    // notifications from servers it used, and pass on requests they send it
+    loop {
+        let msg = match queued.pop_front() {
+            Some(msg) => msg,
+            None => tokio::select! {
+                msg = receiver.next() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                (restarted, reason) = restarts.recv() => {
+                    if addressed.contains(&restarted) {
+                        let notice = notices::server_restarted(&restarted, &reason).to_string();
+                        if let Err(e) = sender.send(Message::Text(notice)).await {
+                            error!("[ws_{}] Failed to send restart notice: {}", &session_id[..8], e);
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                event = events.recv() => {
+                    match event {
+                        Ok(event) if event.is_broadcast() && addressed.contains(&event.server) => {
+                            let notification = String::from_utf8_lossy(&event.message).trim_end().to_string();
+                            if let Err(e) = sender.send(Message::Text(notification)).await {
+                                error!("[ws_{}] Failed to forward {}: {}", &session_id[..8], event.method, e);
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(n)) => warn!("[ws_{}] Lagged, {} backend messages skipped", &session_id[..8], n),
+                        Err(RecvError::Closed) => break,
+                    }
+                    continue;
+                }
+                Some((_, request)) = requests.recv() => {
+                    if let Err(e) = sender.send(Message::Text(String::from_utf8_lossy(request.trim_ascii_end()).into_owned())).await {
+                        error!("[ws_{}] Failed to forward a backend request: {}", &session_id[..8], e);
+                        break;
+                    }
+                    continue;
+                }
+            },
+        };
         match msg {
             Ok(Message::Text(text)) => {
                 info!("[ws_{}] Received message: {} bytes", &session_id[..8], text.len());
+
+                // Answers to requests backends sent the client go back where they came from
+                if let Some(routed) = state.manager.route_client_response(&session_id, text.as_bytes()).await {
+                    if let Err(e) = routed {
+                        error!("[ws_{}] Failed to route a client response: {}", &session_id[..8], e);
+                    }
+                    continue;
+                }
                 
                 // Parse JSON-RPC message
                 match serde_json::from_str::<serde_json::Value>(&text) {
@@ -57,24 +130,84 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
                             .and_then(|m| m.as_str())
                             .unwrap_or("unknown");
                         
-                        // Extract server name (simplified)
-                        let server_name = json_value
-                            .get("params")
-                            .and_then(|p| p.get("server"))
-                            .and_then(|s| s.as_str())
-                            .unwrap_or("unknown");
+                        let requested = extract_server_name(text.as_bytes())
+                            .unwrap_or_else(|| "unknown".to_string());
+                        let target = scope.target(&requested).map(String::from);
+                        let rejection = state
+                            .manager
+                            .maintenance()
+                            .rejection(text.as_bytes())
+                            .or_else(|| scope.reject(&requested, text.as_bytes()));
+                        let server_name = target.clone().unwrap_or(requested);
                         
                         info!("[ws_{}] Routing: method={} server={}", &session_id[..8], method, server_name);
                         
                         // Route to MCP server
-                        let timer = metrics::MCPMessageTimer::new(server_name, method);
-                        match state.manager.route_message(server_name, text.as_bytes()).await {
+                        let timer = metrics::MCPMessageTimer::new(&server_name, method);
+                        let answer = identity::answer(&state.manager, Some(&scope), text.as_bytes()).await;
+                        let routed = match (answer, &target, rejection) {
+                            // The hub answers `citadel/*` itself
+                            (Some(answer), _, _) => Ok(answer),
+                            // Requests a read-only client may not send, and any during
+                            // maintenance, never reach the backend
+                            (None, Some(_), Some(rejection)) => Ok(rejection),
+                            (None, Some(target), None) => {
+                                addressed.insert(target.clone());
+                                let routing = state.manager.route_session_message(Some(&session_id), scope.priority, target, bytes::Bytes::from(text));
+                                tokio::pin!(routing);
+
+                                // The backend may need the client before it answers (sampling,
+                                // roots): pass its requests on and take the client's answers,
+                                // holding other messages until the response
+                                loop {
+                                    tokio::select! {
+                                        routed = &mut routing => break routed,
+                                        Some((_, request)) = requests.recv() => {
+                                            let request = String::from_utf8_lossy(request.trim_ascii_end()).into_owned();
+                                            if let Err(e) = sender.send(Message::Text(request)).await {
+                                                error!("[ws_{}] Failed to forward a backend request: {}", &session_id[..8], e);
+                                            }
+                                        }
+                                        Ok(event) = events.recv() => {
+                                            if event.is_broadcast() && addressed.contains(&event.server) {
+                                                let notification = String::from_utf8_lossy(event.message.trim_ascii_end()).into_owned();
+                                                let _ = sender.send(Message::Text(notification)).await;
+                                            }
+                                        }
+                                        Some(msg) = receiver.next() => {
+                                            if let Ok(Message::Text(answer)) = &msg {
+                                                if let Some(routed) = state.manager.route_client_response(&session_id, answer.as_bytes()).await {
+                                                    if let Err(e) = routed {
+                                                        error!("[ws_{}] Failed to route a client response: {}", &session_id[..8], e);
+                                                    }
+                                                    continue;
+                                                }
+                                            }
+                                            queued.push_back(msg);
+                                        }
+                                    }
+                                }
+                            }
+                            (None, None, _) => Err(anyhow::anyhow!("Server not found: {}", server_name)),
+                        };
+                        match routed {
+                            Ok(response) if response.is_empty() => {
+                                // Notification - nothing to send back
+                                timer.observe_duration("success");
+                            }
                             Ok(response) => {
                                 timer.observe_duration("success");
                                 
                                 // Send response back via WebSocket
-                                if let Ok(response_text) = String::from_utf8(response) {
-                                    if let Err(e) = sender.send(Message::Text(response_text)).await {
+                                if let Ok(response_text) = String::from_utf8(response.to_vec()) {
+                                    let messages = match chunk_size {
+                                        Some(size) if response_text.len() > size => {
+                                            chunked::ws_messages(response_text.trim_end(), json_value.get("id"), size)
+                                        }
+                                        _ => vec![response_text],
+                                    };
+                                    let mut messages = futures::stream::iter(messages.into_iter().map(|m| Ok(Message::Text(m))));
+                                    if let Err(e) = sender.send_all(&mut messages).await {
                                         error!("[ws_{}] Failed to send response: {}", &session_id[..8], e);
                                         break;
                                     }
@@ -132,6 +265,7 @@ async fn handle_socket(socket: WebSocket, state: AppState) {
         }
     }
     
+    state.manager.end_session(&session_id);
     info!("[ws_{}] WebSocket connection closed", &session_id[..8]);
     metrics::record_websocket_connection("closed");
     metrics::set_active_connections(0);
@@ -0,0 +1,1896 @@
+//! End-to-end routing through the Unix socket and HTTP transports
+
+use bytes::Bytes;
+use serde_json::json;
+use std::time::Duration;
+
+use mcp_citadel::config::tools::ToolFilter;
+use mcp_citadel::config::{AuditConfig, HealthCheck, HttpConfig, Isolation, Journal, MockConfig, RestartConfig, RestartPolicy, RouterConfig, ServerConfig, WarmUpRequest, WebhookConfig, WebhookFormat, WorkspaceConfig};
+use mcp_citadel::router::approvals::Decision;
+use mcp_citadel::router::{audit, history};
+use mcp_citadel::router::middleware::{self, Context, Flow, Middleware};
+use mcp_citadel::router::workspace::Workspaces;
+use mcp_citadel::router::{ServerInfo, ServerState};
+use mcp_citadel::testing::TestHub;
+
+fn github() -> MockConfig {
+    serde_json::from_value(json!({
+        "tools": [{ "name": "search_repositories", "inputSchema": { "type": "object" } }],
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_socket_request_reaches_backend() {
+    let hub = TestHub::builder().mock("github", github()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client
+        .request("github", "tools/call", json!({ "name": "search_repositories", "arguments": { "query": "mcp" } }))
+        .await
+        .unwrap();
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["content"][0]["text"], r#"{"query":"mcp"}"#);
+
+    let calls = hub.received_requests("github", "tools/call");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["params"]["arguments"]["query"], "mcp");
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_backend_initialized_once_across_clients() {
+    let hub = TestHub::builder().mock("github", github()).start().await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+
+    for _ in 0..2 {
+        let mut client = hub.connect().await.unwrap();
+        let response = client.request("github", "initialize", initialize.clone()).await.unwrap();
+        assert_eq!(response["result"]["serverInfo"]["name"], "github");
+    }
+    assert_eq!(hub.received_requests("github", "initialize").len(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_http_session() {
+    let hub = TestHub::builder()
+        .mock("github", github())
+        .http(HttpConfig::default())
+        .start()
+        .await
+        .unwrap();
+    let mut client = hub.http_client().unwrap();
+
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    let response = client.request("github", "initialize", initialize).await.unwrap();
+    assert_eq!(response["result"]["protocolVersion"], "2025-06-18");
+    assert!(client.session().is_some());
+
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    assert_eq!(hub.received_requests("github", "tools/list").len(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_fleet_summary() {
+    let hub = TestHub::builder()
+        .mock("github", github())
+        .http(HttpConfig::default())
+        .start()
+        .await
+        .unwrap();
+    let mut client = hub.http_client().unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    client.request("github", "initialize", initialize).await.unwrap();
+
+    let summary = mcp_citadel::summary::fetch(&hub.http_url().unwrap(), None).await.unwrap();
+    assert_eq!(summary.status, "ok");
+    assert_eq!(summary.servers[0].name, "github");
+    assert_eq!(summary.servers[0].health, "up");
+    assert_eq!(summary.totals.requests, 1);
+    assert_eq!(summary.totals.errors, 0);
+    assert!(summary.endpoints["http"].ends_with("/mcp"));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_routes_need_a_key_or_workspace() {
+    let team = WorkspaceConfig { servers: vec!["github".to_string()], api_keys: vec!["team-key".to_string()], ..Default::default() };
+    let workspaces = Workspaces::new(std::collections::BTreeMap::from([("team".to_string(), team)]));
+    let http = HttpConfig { admin_keys: vec!["admin-key".to_string()], ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).mock("time", github()).workspaces(workspaces).http(http).start().await.unwrap();
+    let url = hub.http_url().unwrap();
+    let names = |summary: mcp_citadel::summary::Summary| summary.servers.into_iter().map(|s| s.name).collect::<Vec<_>>();
+
+    assert!(mcp_citadel::summary::fetch(&url, None).await.is_err());
+    assert!(mcp_citadel::summary::fetch(&url, Some("wrong")).await.is_err());
+    assert_eq!(names(mcp_citadel::summary::fetch(&url, Some("admin-key")).await.unwrap()), ["github", "time"]);
+    assert_eq!(names(mcp_citadel::summary::fetch(&url, Some("team-key")).await.unwrap()), ["github"]);
+
+    let history = |key: &str| reqwest::Client::new().get(format!("{}{}", url, history::HISTORY_PATH)).bearer_auth(key.to_string()).send();
+    assert_eq!(history("wrong").await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+    let records: Vec<audit::Record> = history("team-key").await.unwrap().json().await.unwrap();
+    assert!(records.iter().all(|r| r.server == "github"));
+
+    let events = format!("{}{}", url, mcp_citadel::router::events::EVENTS_PATH);
+    assert_eq!(reqwest::get(&events).await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+    let response = reqwest::Client::new().get(&events).bearer_auth("team-key").header("origin", "https://evil.example").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    // The log filter is the whole hub's
+    let log_level = format!("{}{}", url, mcp_citadel::logging::LOG_LEVEL_PATH);
+    let set_level = |key: &str| reqwest::Client::new().put(&log_level).bearer_auth(key.to_string()).json(&json!({ "level": "info" })).send();
+    assert_eq!(set_level("team-key").await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+    // Past the gate (logging isn't set up in tests)
+    assert_eq!(set_level("admin-key").await.unwrap().status(), reqwest::StatusCode::BAD_REQUEST);
+    let response = reqwest::Client::new().get(&log_level).bearer_auth("admin-key").send().await.unwrap();
+    assert!(response.status().is_success());
+
+    // Browsers on other sites are turned away even with a key
+    let summary_url = format!("{}{}", url, mcp_citadel::summary::SUMMARY_PATH);
+    let response = reqwest::Client::new().get(summary_url).bearer_auth("admin-key").header("origin", "https://evil.example").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_events_stream_sessions_and_failures() {
+    let hub = TestHub::builder().mock("github", github()).http(HttpConfig::default()).start().await.unwrap();
+    let url = format!("{}{}", hub.http_url().unwrap(), mcp_citadel::router::events::EVENTS_PATH);
+    let mut stream = reqwest::get(url).await.unwrap();
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+
+    let mut client = hub.http_client().unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    client.request("github", "initialize", initialize).await.unwrap();
+    let response = client.request("missing", "tools/list", json!({})).await.unwrap();
+    assert!(response["error"].is_object());
+
+    let mut received = String::new();
+    while !received.contains("event: request_failed") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), stream.chunk()).await.unwrap().unwrap().unwrap();
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(received.contains("event: session_created"));
+    let failed = received.lines().find(|l| l.contains("\"request_failed\"")).unwrap();
+    let failed: serde_json::Value = serde_json::from_str(failed.trim_start_matches("data:").trim()).unwrap();
+    assert_eq!((failed["server"].as_str(), failed["method"].as_str()), (Some("missing"), Some("tools/list")));
+
+    let recent = hub.manager().recent_events();
+    assert!(recent.iter().any(|e| e["event"] == "session_created" && e["transport"] == "http"));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_restart_warms_up_before_routing() {
+    let journal = Journal::default();
+    let config = ServerConfig {
+        name: "github".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }),
+        warm_up: vec![
+            WarmUpRequest { method: "tools/list".to_string(), params: None },
+            WarmUpRequest {
+                method: "tools/call".to_string(),
+                params: Some(json!({ "name": "search_repositories", "arguments": {} })),
+            },
+        ],
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    hub.manager().restart_server("github").await.unwrap();
+
+    let methods: Vec<_> = journal.messages().iter().map(|m| m["method"].as_str().unwrap_or_default().to_string()).collect();
+    assert_eq!(methods, ["initialize", "notifications/initialized", "tools/list", "tools/call"]);
+
+    // The client's handshake is answered from the hub's
+    let mut client = hub.connect().await.unwrap();
+    let response = client.request("github", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "github");
+    assert_eq!(journal.messages().len(), 4);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_socket_session_transcript() {
+    let hub = TestHub::builder().mock("github", github()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    client.request("github", "tools/list", json!({})).await.unwrap();
+
+    let sessions = hub.manager().transcripts().list();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].transport, "socket");
+    assert!(sessions[0].servers.contains("github"));
+    let (_, messages) = hub.manager().transcripts().get(&sessions[0].id).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[1].message.contains("search_repositories"));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_clients_notified_of_restart() {
+    let hub = TestHub::builder()
+        .mock("github", github())
+        .mock("slack", MockConfig::default())
+        .start()
+        .await
+        .unwrap();
+    let mut github_client = hub.connect().await.unwrap();
+    github_client.request("github", "tools/list", json!({})).await.unwrap();
+    let mut slack_client = hub.connect().await.unwrap();
+    slack_client.request("slack", "tools/list", json!({})).await.unwrap();
+
+    hub.manager().restart_server("github").await.unwrap();
+    let notice = github_client.recv().await.unwrap();
+    assert_eq!(notice["method"], "citadel/server_restarted");
+    assert_eq!(notice["params"], json!({ "server": "github", "reason": "requested" }));
+
+    // Only clients of the restarted server hear about it
+    let nothing = tokio::time::timeout(Duration::from_millis(100), slack_client.recv()).await;
+    assert!(nothing.is_err());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_read_only_rejects_tool_calls() {
+    let router = RouterConfig { read_only: true, ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).router(router).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+
+    let response = client
+        .request("github", "tools/call", json!({ "name": "search_repositories", "arguments": {} }))
+        .await
+        .unwrap();
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["data"]["policy"], "read_only");
+    assert!(hub.received_requests("github", "tools/call").is_empty());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_maintenance_refuses_requests_then_resumes() {
+    let hub = TestHub::builder().mock("github", github()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    hub.manager().maintenance().begin(Duration::from_secs(60), Some("migration".to_string()));
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["error"]["data"]["maintenance"], true);
+    assert_eq!(response["error"]["data"]["reason"], "migration");
+    assert!(hub.received_requests("github", "tools/list").is_empty());
+
+    assert!(hub.manager().maintenance().end());
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_debug_transcript_toggled_at_runtime() {
+    let hub = TestHub::builder().mock("traced", github()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    client.request("traced", "tools/list", json!({})).await.unwrap();
+
+    let path = hub.manager().set_debug_transcript("traced", true).await.unwrap();
+    client.request("traced", "tools/call", json!({ "name": "search_repositories", "arguments": {} })).await.unwrap();
+    hub.manager().set_debug_transcript("traced", false).await.unwrap();
+    client.request("traced", "resources/list", json!({})).await.unwrap();
+
+    let transcript = std::fs::read_to_string(path).unwrap();
+    let lines: Vec<&str> = transcript.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(" → ") && lines[0].contains("tools/call"));
+    assert!(lines[1].contains(" ← ") && lines[1].contains("result"));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tool_results_shaped_for_older_clients() {
+    let mock: MockConfig = serde_json::from_value(json!({
+        "tools": [{ "name": "fetch", "inputSchema": { "type": "object" } }],
+        "responses": [{
+            "method": "tools/call",
+            "replies": [{ "result": {
+                "content": [{ "type": "resource_link", "uri": "file:///notes.md", "name": "notes.md" }],
+                "structuredContent": { "count": 1 },
+            } }],
+        }],
+    }))
+    .unwrap();
+    let hub = TestHub::builder().mock("files", mock).start().await.unwrap();
+
+    let mut old = hub.connect().await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": { "name": "old", "version": "1" } });
+    old.request("files", "initialize", initialize).await.unwrap();
+    let response = old.request("files", "tools/call", json!({ "name": "fetch", "arguments": {} })).await.unwrap();
+    assert_eq!(response["result"]["content"][0], json!({ "type": "text", "text": "notes.md: file:///notes.md" }));
+    assert!(response["result"].get("structuredContent").is_none());
+
+    let mut current = hub.connect().await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "new", "version": "1" } });
+    current.request("files", "initialize", initialize).await.unwrap();
+    let response = current.request("files", "tools/call", json!({ "name": "fetch", "arguments": {} })).await.unwrap();
+    assert_eq!(response["result"]["content"][0]["type"], "resource_link");
+    assert_eq!(response["result"]["structuredContent"]["count"], 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_hub_identity_and_methods() {
+    let hub = TestHub::builder().mock("github", github()).mock("slack", github()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    let response = client.request("github", "initialize", initialize).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "github");
+    let citadel = &response["result"]["capabilities"]["experimental"]["citadel"];
+    assert_eq!(citadel["serverInfo"]["version"], env!("CARGO_PKG_VERSION"));
+
+    let response = client.request("github", "citadel/listServers", json!({})).await.unwrap();
+    let servers = response["result"]["servers"].as_array().unwrap();
+    assert_eq!(servers.len(), 2);
+    assert!(servers.iter().all(|s| s["running"] == true));
+
+    let response = client.request("github", "citadel/unknown", json!({})).await.unwrap();
+    assert_eq!(response["error"]["code"], -32601);
+    assert!(hub.received_requests("github", "citadel/listServers").is_empty());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_restart_does_not_block_other_servers() {
+    let slow: MockConfig = serde_json::from_value(json!({
+        "responses": [{ "method": "tools/list", "replies": [{ "result": { "tools": [] }, "delayMs": 1500 }] }],
+    }))
+    .unwrap();
+    let config = ServerConfig {
+        name: "slow".to_string(),
+        mock: Some(slow),
+        warm_up: vec![WarmUpRequest { method: "tools/list".to_string(), params: None }],
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).mock("github", github()).start().await.unwrap();
+    let manager = hub.manager().clone();
+    let restart = tokio::spawn(async move { manager.restart_server("slow").await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The restart holds only its own server's slot
+    let mut client = hub.connect().await.unwrap();
+    let response = tokio::time::timeout(Duration::from_millis(500), client.request("github", "tools/list", json!({})))
+        .await
+        .expect("github blocked behind the restart of slow")
+        .unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    assert!(!restart.is_finished());
+
+    restart.await.unwrap().unwrap();
+    hub.stop().await.unwrap();
+}
+
+fn warm_mock(mock: serde_json::Value, journal: &Journal) -> ServerConfig {
+    let mock: MockConfig = serde_json::from_value(mock).unwrap();
+    ServerConfig {
+        name: "slow".to_string(),
+        warm_restart: Some(true),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..mock }),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_warm_restart_lets_in_flight_requests_finish() {
+    let journal = Journal::default();
+    let config = warm_mock(
+        json!({ "responses": [{ "method": "tools/call", "replies": [{ "result": { "content": [] }, "delayMs": 300 }] }] }),
+        &journal,
+    );
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let manager = std::sync::Arc::clone(hub.manager());
+
+    let call = tokio::spawn({
+        let manager = std::sync::Arc::clone(&manager);
+        async move { manager.request("slow", "tools/call", json!({ "name": "wait" })).await }
+    });
+    while !journal.messages().iter().any(|m| m["method"] == "tools/call") {
+        tokio::task::yield_now().await;
+    }
+
+    manager.restart_server("slow").await.unwrap();
+    assert!(call.await.unwrap().unwrap()["content"].is_array());
+    // The standby was initialized before it took over
+    let methods: Vec<_> = journal.messages().iter().filter_map(|m| m["method"].as_str().map(String::from)).collect();
+    let initialize = methods.iter().position(|m| m == "initialize").unwrap();
+    assert_eq!(methods[initialize + 1], "notifications/initialized");
+    assert!(manager.request("slow", "tools/list", json!({})).await.is_ok());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_failed_standby_keeps_running_instance() {
+    let journal = Journal::default();
+    let config = warm_mock(
+        json!({ "responses": [{ "method": "initialize", "replies": [{ "error": { "code": -32000, "message": "not today" } }] }] }),
+        &journal,
+    );
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let manager = hub.manager();
+    manager.request("slow", "tools/list", json!({})).await.unwrap();
+
+    let error = manager.restart_server("slow").await.unwrap_err();
+    assert!(format!("{:#}", error).contains("keeping the running instance"));
+    assert!(manager.server_info().await[0].running);
+    assert!(manager.request("slow", "tools/list", json!({})).await.is_ok());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_demultiplexed_by_id() {
+    let config: MockConfig = serde_json::from_value(json!({
+        "responses": [
+            { "method": "tools/call", "name": "slow", "replies": [{ "result": { "content": [{ "type": "text", "text": "slow" }] }, "delayMs": 400 }] },
+            { "method": "tools/call", "name": "fast", "replies": [{ "result": { "content": [{ "type": "text", "text": "fast" }] } }] },
+        ],
+    }))
+    .unwrap();
+    // Pipelining is opt-in
+    let router = RouterConfig { max_in_flight: 8, ..Default::default() };
+    let hub = TestHub::builder().mock("github", config).router(router).start().await.unwrap();
+    let (mut first, mut second) = (hub.connect().await.unwrap(), hub.connect().await.unwrap());
+
+    // Both clients use id 1; the fast answer overtakes the slow one
+    let started = std::time::Instant::now();
+    let slow = first.request("github", "tools/call", json!({ "name": "slow", "arguments": {} }));
+    let fast = async {
+        let response = second.request("github", "tools/call", json!({ "name": "fast", "arguments": {} })).await;
+        (response, started.elapsed())
+    };
+    let (slow, (fast, fast_elapsed)) = tokio::join!(slow, fast);
+    let (slow, fast) = (slow.unwrap(), fast.unwrap());
+    assert_eq!((&slow["id"], &fast["id"]), (&json!(1), &json!(1)));
+    assert_eq!(slow["result"]["content"][0]["text"], "slow");
+    assert_eq!(fast["result"]["content"][0]["text"], "fast");
+    assert!(fast_elapsed < Duration::from_millis(300), "fast request waited {:?}", fast_elapsed);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_request_timeout_answers_with_error() {
+    let journal = Journal::default();
+    let hung: MockConfig = serde_json::from_value(json!({
+        "responses": [{ "method": "tools/call", "replies": [{ "result": { "content": [] }, "delayMs": 5000 }] }],
+    }))
+    .unwrap();
+    let hung = MockConfig { journal: Some(journal.clone()), ..hung };
+    let config = ServerConfig { name: "hung".to_string(), mock: Some(hung), request_timeout_secs: Some(1), ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("hung", "tools/call", json!({ "name": "wait", "arguments": {} })).await.unwrap();
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["error"]["code"], -32002);
+    assert_eq!(response["error"]["data"]["timeout_secs"], 1);
+
+    // The backend is told to stop working on it
+    let messages = journal.messages();
+    let call = messages.iter().find(|m| m["method"] == "tools/call").unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let cancelled = loop {
+        if let Some(cancelled) = journal.messages().into_iter().find(|m| m["method"] == "notifications/cancelled") {
+            break cancelled;
+        }
+        assert!(std::time::Instant::now() < deadline, "no cancellation sent");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(cancelled["params"]["requestId"], call["id"]);
+    assert_eq!(cancelled["params"]["reason"], "request timed out");
+
+    // Other methods still answer promptly
+    let response = client.request("hung", "tools/list", json!({})).await.unwrap();
+    assert!(response["result"]["tools"].is_array());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_client_cancel_reaches_its_own_request() {
+    let journal = Journal::default();
+    let slow: MockConfig = serde_json::from_value(json!({
+        "responses": [{ "method": "tools/call", "replies": [{ "result": { "content": [] }, "delayMs": 1000 }] }],
+    }))
+    .unwrap();
+    let slow = MockConfig { journal: Some(journal.clone()), ..slow };
+    let router = RouterConfig { max_in_flight: 8, ..Default::default() };
+    let hub = TestHub::builder().mock("slow", slow).router(router).start().await.unwrap();
+    let (mut first, mut second) = (hub.connect().await.unwrap(), hub.connect().await.unwrap());
+
+    // Both clients use id 7 for their calls
+    let call = |who: &str| json!({ "jsonrpc": "2.0", "id": 7, "method": "tools/call", "params": { "server": "slow", "name": "work", "arguments": { "who": who } } });
+    first.send(&call("first")).await.unwrap();
+    second.send(&call("second")).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let calls = loop {
+        let calls: Vec<_> = journal.messages().into_iter().filter(|m| m["method"] == "tools/call").collect();
+        if calls.len() == 2 {
+            break calls;
+        }
+        assert!(std::time::Instant::now() < deadline, "calls not received");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    let hub_id = |who: &str| calls.iter().find(|c| c["params"]["arguments"]["who"] == who).unwrap()["id"].clone();
+
+    // The second client cancels its call, and names one it never made
+    second.notify("slow", "notifications/cancelled", json!({ "requestId": 7, "reason": "user stopped it" })).await.unwrap();
+    second.notify("slow", "notifications/cancelled", json!({ "requestId": 8 })).await.unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !journal.messages().iter().any(|m| m["method"] == "notifications/cancelled") {
+        assert!(std::time::Instant::now() < deadline, "no cancellation sent");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let cancelled: Vec<_> = journal.messages().into_iter().filter(|m| m["method"] == "notifications/cancelled").collect();
+    assert_eq!(cancelled.len(), 1, "{:?}", cancelled);
+    assert_eq!(cancelled[0]["params"]["requestId"], hub_id("second"));
+    assert_ne!(cancelled[0]["params"]["requestId"], hub_id("first"));
+    assert_eq!(cancelled[0]["params"]["reason"], "user stopped it");
+
+    // The first client's call is still answered
+    assert_eq!(first.recv().await.unwrap()["id"], 7);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rate_limited_requests_rejected_with_retry_hint() {
+    let config = ServerConfig { name: "github".to_string(), mock: Some(github()), max_requests_per_second: Some(1.0), ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert!(response["result"]["tools"].is_array());
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["error"]["code"], -32005);
+    assert!(response["error"]["data"]["retry_after_ms"].as_u64().unwrap() > 0);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rate_limited_keyed_call_runs_on_retry() {
+    let journal = Journal::default();
+    let mock = MockConfig { echo: true, journal: Some(journal.clone()), ..Default::default() };
+    let config = ServerConfig { name: "billing".to_string(), mock: Some(mock), max_requests_per_second: Some(1.0), ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    let call = json!({ "name": "charge", "_meta": { "idempotencyKey": "order-1" } });
+
+    client.request("billing", "tools/list", json!({})).await.unwrap();
+    let response = client.request("billing", "tools/call", call.clone()).await.unwrap();
+    assert_eq!(response["error"]["code"], -32005);
+
+    // The rejection isn't the key's response: once the bucket refills the call runs
+    let wait = response["error"]["data"]["retry_after_ms"].as_u64().unwrap();
+    tokio::time::sleep(Duration::from_millis(wait + 50)).await;
+    let response = client.request("billing", "tools/call", call).await.unwrap();
+    assert_eq!(response["result"]["name"], "charge", "{}", response);
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "tools/call").count(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_idempotency_keys_scoped_to_session() {
+    let journal = Journal::default();
+    let mock = MockConfig { echo: true, journal: Some(journal.clone()), ..Default::default() };
+    let hub = TestHub::builder().mock("billing", mock).start().await.unwrap();
+    let (mut first, mut second) = (hub.connect().await.unwrap(), hub.connect().await.unwrap());
+    let call = |who: &str| json!({ "name": "charge", "arguments": { "who": who }, "_meta": { "idempotencyKey": "order-1" } });
+
+    // Each session's call runs, and its retry gets that session's result
+    for _ in 0..2 {
+        let response = first.request("billing", "tools/call", call("first")).await.unwrap();
+        assert_eq!(response["result"]["arguments"]["who"], "first", "{}", response);
+        let response = second.request("billing", "tools/call", call("second")).await.unwrap();
+        assert_eq!(response["result"]["arguments"]["who"], "second", "{}", response);
+    }
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "tools/call").count(), 2);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_blocked_tools_hidden_and_refused() {
+    let journal = Journal::default();
+    let mut mock = MockConfig { journal: Some(journal.clone()), ..github() };
+    mock.tools.push(json!({ "name": "delete_repository", "inputSchema": { "type": "object" } }));
+    let tools = ToolFilter { deny: vec!["delete_*".to_string()], ..Default::default() };
+    let config = ServerConfig { name: "github".to_string(), mock: Some(mock), tools, ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    let names: Vec<&str> = response["result"]["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["search_repositories"]);
+
+    let response = client.request("github", "tools/call", json!({ "name": "delete_repository", "arguments": {} })).await.unwrap();
+    assert_eq!(response["error"]["code"], -32001);
+    assert_eq!(response["error"]["data"]["tool"], "delete_repository");
+    let response = client.request("github", "tools/call", json!({ "name": "search_repositories", "arguments": {} })).await.unwrap();
+    assert!(response["result"].is_object());
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "tools/call").count(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_read_only_servers_refuse_destructive_tools() {
+    let mock: MockConfig = serde_json::from_value(json!({
+        "tools": [
+            { "name": "search", "inputSchema": { "type": "object" } },
+            { "name": "drop_table", "inputSchema": { "type": "object" }, "annotations": { "destructiveHint": true } },
+            { "name": "create_issue", "inputSchema": { "type": "object" } },
+        ],
+    }))
+    .unwrap();
+    let tools = ToolFilter { write: vec!["create_*".to_string()], approve: vec!["drop_*".to_string()], ..Default::default() };
+    let github = ServerConfig { name: "github".to_string(), mock: Some(mock.clone()), tools, ..Default::default() };
+    let scratch = ServerConfig { name: "scratch".to_string(), mock: Some(mock), read_only: Some(false), ..Default::default() };
+    let router = RouterConfig { read_only_servers: true, ..Default::default() };
+    let hub = TestHub::builder().server(github).server(scratch).router(router).start().await.unwrap();
+    let call = |server: &'static str, tool: &'static str| {
+        let hub = &hub;
+        async move {
+            let mut client = hub.connect().await.unwrap();
+            client.request(server, "tools/call", json!({ "name": tool, "arguments": {} })).await.unwrap()
+        }
+    };
+
+    assert!(call("github", "search").await["result"].is_object());
+    for tool in ["drop_table", "create_issue"] {
+        let response = call("github", tool).await;
+        assert_eq!((response["error"]["code"].as_i64(), response["error"]["data"]["tool"].as_str()), (Some(-32001), Some(tool)));
+    }
+    // Refused without waiting for an approval that couldn't let it through
+    assert!(hub.manager().approvals().list().is_empty());
+    // Not read-only, despite the hub setting
+    assert!(call("scratch", "drop_table").await["result"].is_object());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_audit_log_records_requests() {
+    let audit = AuditConfig { enabled: true, payloads: true, ..Default::default() };
+    let router = RouterConfig { audit, ..Default::default() };
+    let hub = TestHub::builder().mock("audited", github()).router(router).http(HttpConfig::default()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    client.request("audited", "tools/call", json!({ "name": "search_repositories", "arguments": { "query": "mcp" } })).await.unwrap();
+    client.request("audited", "resources/read", json!({ "uri": "missing://x" })).await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    hub.http_client().unwrap().request("audited", "initialize", initialize).await.unwrap();
+
+    let url = format!("{}{}?server=audited&failed=true&since=1h", hub.http_url().unwrap(), history::HISTORY_PATH);
+    let failed: Vec<audit::Record> = reqwest::get(url).await.unwrap().json().await.unwrap();
+    assert_eq!(failed.iter().map(|r| r.method.as_str()).collect::<Vec<_>>(), ["resources/read"]);
+    hub.stop().await.unwrap();
+
+    // Every hub of the test run shares the data directory
+    let log = std::fs::read_to_string(audit::dir().join("audit.jsonl")).unwrap();
+    let records: Vec<audit::Record> = log.lines().map(|l| serde_json::from_str(l).unwrap()).filter(|r: &audit::Record| r.server == "audited").collect();
+    let call = records.iter().find(|r| r.method == "tools/call").unwrap();
+    assert_eq!((call.tool.as_deref(), call.outcome), (Some("search_repositories"), audit::Outcome::Ok));
+    assert_eq!(call.params.as_ref().unwrap()["arguments"]["query"], "mcp");
+    assert!(call.response.as_ref().unwrap()["result"].is_object());
+    assert!(call.session.is_some());
+    let read = records.iter().find(|r| r.method == "resources/read").unwrap();
+    assert_eq!(read.outcome, audit::Outcome::Error);
+    let initialize = records.iter().find(|r| r.method == "initialize").unwrap();
+    assert_eq!(initialize.client.as_deref(), Some("test 1"));
+}
+
+#[tokio::test]
+async fn test_secrets_masked_in_audit_log() {
+    let audit = AuditConfig { enabled: true, payloads: true, ..Default::default() };
+    let env = [("GITHUB_TOKEN".to_string(), "hunter2-but-longer".to_string())].into();
+    let config = ServerConfig { name: "masked".to_string(), mock: Some(github()), env, ..Default::default() };
+    let hub = TestHub::builder().server(config).router(RouterConfig { audit, ..Default::default() }).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    let arguments = json!({ "query": "hunter2-but-longer", "auth": "Bearer abcdefghijklmnopqrstuvwxyz", "password": "pw" });
+    client.request("masked", "tools/call", json!({ "name": "search_repositories", "arguments": arguments })).await.unwrap();
+    hub.stop().await.unwrap();
+
+    let log = std::fs::read_to_string(audit::dir().join("audit.jsonl")).unwrap();
+    let record: audit::Record = log.lines().map(|l| serde_json::from_str(l).unwrap()).find(|r: &audit::Record| r.server == "masked").unwrap();
+    let arguments = &record.params.unwrap()["arguments"];
+    assert_eq!((arguments["query"].as_str(), arguments["password"].as_str()), (Some("<redacted>"), Some("<redacted>")));
+    assert_eq!(arguments["auth"], "Bearer <redacted>");
+}
+
+#[tokio::test]
+async fn test_approval_gate_holds_calls_until_decided() {
+    let mut mock = github();
+    mock.tools.push(json!({ "name": "delete_repository", "inputSchema": { "type": "object" } }));
+    let tools = ToolFilter { approve: vec!["delete_*".to_string()], ..Default::default() };
+    let config = ServerConfig { name: "github".to_string(), mock: Some(mock), tools, ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let approvals = || hub.manager().approvals().list();
+
+    for approve in [true, false] {
+        let mut client = hub.connect().await.unwrap();
+        let call = tokio::spawn(async move { client.request("github", "tools/call", json!({ "name": "delete_repository", "arguments": { "repo": "x" } })).await });
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while approvals().is_empty() {
+            assert!(std::time::Instant::now() < deadline, "call not held");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let pending = approvals();
+        assert_eq!((pending.len(), pending[0].tool.as_str()), (1, "delete_repository"));
+        let decision = match approve {
+            true => Decision::Approved,
+            false => Decision::Denied(Some("not today".to_string())),
+        };
+        assert!(hub.manager().approvals().decide(pending[0].id, decision));
+
+        let response = call.await.unwrap().unwrap();
+        match approve {
+            true => assert_eq!(response["result"]["content"][0]["text"], r#"{"repo":"x"}"#),
+            false => assert_eq!((response["error"]["code"].as_i64(), response["error"]["data"]["policy"].as_str()), (Some(-32001), Some("approval"))),
+        }
+    }
+
+    // Other tools aren't held
+    let mut client = hub.connect().await.unwrap();
+    let response = client.request("github", "tools/call", json!({ "name": "search_repositories", "arguments": {} })).await.unwrap();
+    assert!(response["result"].is_object());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_rewrite_rules_patch_requests_and_responses() {
+    let rewrite = serde_json::from_value(json!([
+        { "method": "tools/call", "rename": "/params/arguments/q", "to": "/params/arguments/query" },
+        { "method": "tools/call", "set": "/params/arguments/per_page", "value": 10 },
+        { "method": "tools/call", "on": "response", "set": "/result/_meta/patched", "value": true },
+    ]))
+    .unwrap();
+    let config = ServerConfig { name: "github".to_string(), mock: Some(github()), rewrite, ..Default::default() };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client
+        .request("github", "tools/call", json!({ "name": "search_repositories", "arguments": { "q": "mcp" } }))
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], r#"{"per_page":10,"query":"mcp"}"#);
+    assert_eq!(response["result"]["_meta"]["patched"], true);
+
+    // Other methods' responses are left alone
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert!(response["result"].get("_meta").is_none());
+    hub.stop().await.unwrap();
+}
+
+/// Refuses calls to `delete_*` tools and masks "secret" in responses
+struct Guard;
+
+impl Middleware for Guard {
+    fn on_request(&self, context: &Context, message: Bytes) -> Flow {
+        let request: serde_json::Value = serde_json::from_slice(&message).unwrap();
+        let tool = request["params"]["name"].as_str().unwrap_or_default();
+        if context.method(&message).as_deref() == Some("tools/call") && tool.starts_with("delete_") {
+            return Flow::Respond(middleware::error_response(&message, -32001, "Deleting is not allowed"));
+        }
+        Flow::Forward(message)
+    }
+
+    fn on_response(&self, _context: &Context, response: Bytes) -> Bytes {
+        Bytes::from(String::from_utf8_lossy(&response).replace("secret", "[redacted]"))
+    }
+}
+
+#[tokio::test]
+async fn test_middleware_refuses_and_rewrites() {
+    let hub = TestHub::builder().mock("github", github()).middleware(Guard).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client
+        .request("github", "tools/call", json!({ "name": "search_repositories", "arguments": { "query": "secret" } }))
+        .await
+        .unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], r#"{"query":"[redacted]"}"#);
+
+    let response = client.request("github", "tools/call", json!({ "name": "delete_repository", "arguments": {} })).await.unwrap();
+    assert_eq!(response["error"]["code"], -32001);
+    // Only the search reached the backend
+    assert_eq!(hub.received_requests("github", "tools/call").len(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_multi_line_and_malformed_responses() {
+    // Answers its first request pretty-printed, and its second with broken JSON
+    let script = r#"read _; printf '{\n  "jsonrpc": "2.0",\n  "id": 1,\n  "result": {\n    "tools": []\n  }\n}\n'; read _; printf '{"jsonrpc":"2.0","id":2,"result":}\n'; read _"#;
+    let config = ServerConfig {
+        name: "pretty".to_string(),
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), script.to_string()],
+        request_timeout_secs: Some(5),
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("pretty", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"], json!([]));
+
+    // A parse error, not a timeout
+    let started = std::time::Instant::now();
+    let response = client.request("pretty", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["error"]["code"], -32000);
+    assert!(response["error"]["message"].as_str().unwrap().contains("isn't valid JSON-RPC"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    hub.stop().await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_recorded_servers_left_alone_by_new_manager() {
+    // Isolates the data directory
+    TestHub::builder().start().await.unwrap().stop().await.unwrap();
+
+    let mut orphan = std::process::Command::new("sleep").arg("300").spawn().unwrap();
+    let record = json!([{ "name": "github", "pid": orphan.id(), "command": "/bin/sleep", "hub": null }]);
+    std::fs::write(mcp_citadel::config::data_dir().join("servers.pid.json"), record.to_string()).unwrap();
+
+    // Other tests' managers share the data directory; only the daemon reaps
+    let hub = TestHub::builder().mock("github", github()).start().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(orphan.try_wait().unwrap().is_none());
+    hub.stop().await.unwrap();
+    orphan.kill().unwrap();
+    orphan.wait().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_orphans_of_crashed_hub_reaped_when_daemon_starts() {
+    let dir = std::env::temp_dir().join(format!("mcp-citadel-daemon-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let claude_config = dir.join("claude.json");
+    std::fs::write(&claude_config, r#"{"mcpServers": {}}"#).unwrap();
+    let config = format!("socket_path = {:?}\nclaude_config_path = {:?}\n\n[http]\nenabled = false\n", dir.join("hub.sock"), claude_config);
+    std::fs::write(dir.join("config.toml"), config).unwrap();
+
+    let mut orphan = std::process::Command::new("sleep").arg("300").spawn().unwrap();
+    let record = json!([{ "name": "github", "pid": orphan.id(), "command": "/bin/sleep", "hub": null }]);
+    std::fs::write(dir.join("servers.pid.json"), record.to_string()).unwrap();
+
+    let mut hub = std::process::Command::new(env!("CARGO_BIN_EXE_mcp-citadel"))
+        .args(["start", "--foreground"])
+        .env("MCP_CITADEL_DATA_DIR", &dir)
+        .env_remove("MCP_CITADEL_CONFIG")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while orphan.try_wait().unwrap().is_none() {
+        if std::time::Instant::now() > deadline {
+            let _ = orphan.kill();
+            let _ = hub.kill();
+            panic!("orphan still running (hub exited: {:?})", hub.try_wait().unwrap());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(hub.try_wait().unwrap().is_none(), "hub exited");
+    hub.kill().unwrap();
+    hub.wait().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_restart_policy_and_attempts() {
+    let failing = |name: &str, policy| ServerConfig {
+        name: name.to_string(),
+        mock: Some(
+            serde_json::from_value(json!({
+                "responses": [{ "method": "tools/list", "replies": [{ "error": { "code": -32000, "message": "down" } }] }],
+            }))
+            .unwrap(),
+        ),
+        health_check: Some(HealthCheck::Method { method: "tools/list".to_string(), params: json!({}), timeout_secs: None }),
+        restart_policy: Some(policy),
+        max_restarts: Some(1),
+        ..Default::default()
+    };
+    let router = RouterConfig {
+        restarts: RestartConfig { backoff_initial_ms: 50, ..Default::default() },
+        ..Default::default()
+    };
+    let hub = TestHub::builder()
+        .server(failing("flaky", RestartPolicy::OnFailure))
+        .server(failing("fragile", RestartPolicy::Never))
+        .router(router)
+        .start()
+        .await
+        .unwrap();
+    let running = |infos: Vec<ServerInfo>, name: &str| infos.iter().find(|i| i.name == name).map(|i| (i.running, i.restarts));
+
+    let state = |infos: Vec<ServerInfo>, name: &str| infos.into_iter().find(|i| i.name == name).map(|i| (i.state, i.last_error));
+
+    hub.manager().health_check().await.unwrap();
+    let infos = hub.manager().server_info().await;
+    assert_eq!(running(infos.clone(), "flaky"), Some((true, 1)));
+    assert_eq!(running(infos.clone(), "fragile"), Some((false, 0)));
+    let (fragile, last_error) = state(infos, "fragile").unwrap();
+    assert_eq!(fragile, ServerState::Crashed);
+    assert!(last_error.unwrap().starts_with("health check failed"));
+
+    // Out of attempts
+    hub.manager().health_check().await.unwrap();
+    assert_eq!(running(hub.manager().server_info().await, "flaky"), Some((false, 1)));
+    assert_eq!(state(hub.manager().server_info().await, "flaky").unwrap().0, ServerState::Crashed);
+
+    // Started again and stopped by hand, keeping its last error
+    hub.manager().start_server("flaky").await.unwrap();
+    assert_eq!(state(hub.manager().server_info().await, "flaky").unwrap().0, ServerState::Running);
+    hub.manager().stop_server("flaky").await.unwrap();
+    let (flaky, last_error) = state(hub.manager().server_info().await, "flaky").unwrap();
+    assert_eq!(flaky, ServerState::Disabled);
+    assert!(last_error.is_some());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_failed_request_shown_as_last_error() {
+    let hub = TestHub::builder().mock("github", github()).http(HttpConfig::default()).start().await.unwrap();
+    hub.manager().stop_server("github").await.unwrap();
+
+    let mut client = hub.http_client().unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    client.request("github", "initialize", initialize).await.unwrap();
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    let message = response["error"]["message"].as_str().unwrap().to_string();
+
+    let info = hub.manager().server_info().await.into_iter().find(|i| i.name == "github").unwrap();
+    assert_eq!(info.state, ServerState::Disabled);
+    let last_error = info.last_error.unwrap();
+    assert!(message.contains(&last_error), "{} / {}", message, last_error);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_webhooks_alerted_on_failures() {
+    // A stand-in for the webhook receiver
+    let (posted, mut alerts) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+    let receiver = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+            let _ = posted.send(body);
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+    let failing = ServerConfig {
+        name: "flaky".to_string(),
+        mock: Some(serde_json::from_value(json!({ "responses": [{ "method": "tools/list", "replies": [{ "error": { "code": -32000, "message": "down" } }] }] })).unwrap()),
+        health_check: Some(HealthCheck::Method { method: "tools/list".to_string(), params: json!({}), timeout_secs: None }),
+        max_restarts: Some(1),
+        ..Default::default()
+    };
+    let router = RouterConfig { restarts: RestartConfig { backoff_initial_ms: 50, ..Default::default() }, ..Default::default() };
+    let webhooks = vec![
+        WebhookConfig { url: url.clone(), format: WebhookFormat::Generic, events: vec![], headers: Default::default() },
+        WebhookConfig { url, format: WebhookFormat::Slack, events: vec!["gave_up".to_string()], headers: Default::default() },
+    ];
+    let hub = TestHub::builder().server(failing).router(router).webhooks(webhooks).start().await.unwrap();
+    hub.manager().health_check().await.unwrap();
+    hub.manager().health_check().await.unwrap();
+
+    let mut received = Vec::new();
+    // Two failures to the generic webhook, and running out of restarts to both
+    while received.len() < 4 {
+        received.push(tokio::time::timeout(Duration::from_secs(5), alerts.recv()).await.unwrap().unwrap());
+    }
+    received.sort_by_key(|alert| alert.to_string());
+    assert_eq!((received[0]["event"].as_str(), received[0]["reason"].as_str()), (Some("gave_up"), Some("failed 2 times in a row")));
+    assert_eq!((received[1]["event"].as_str(), received[1]["server"].as_str()), (Some("unresponsive"), Some("flaky")));
+    assert!(received[1]["reason"].as_str().unwrap().starts_with("health check failed"));
+    assert!(received[3]["text"].as_str().unwrap().starts_with("🔴 MCP server *flaky* is out of restarts"));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_hung_server_restarted_after_missed_pings() {
+    let hung = |name: &str, delay_ms: u64| ServerConfig {
+        name: name.to_string(),
+        mock: Some(serde_json::from_value(json!({ "responses": [{ "method": "ping", "replies": [{ "result": {}, "delayMs": delay_ms }] }] })).unwrap()),
+        ..Default::default()
+    };
+    let router = RouterConfig {
+        restarts: RestartConfig { backoff_initial_ms: 50, probe_timeout_ms: 100, probe_failures: 2, ..Default::default() },
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(hung("hung", 10_000)).server(hung("slow", 10)).router(router).start().await.unwrap();
+    let state = |infos: Vec<ServerInfo>, name: &str| infos.into_iter().find(|i| i.name == name).map(|i| (i.state, i.restarts));
+
+    // One missed ping marks it unresponsive; the next in a row restarts it
+    hub.manager().health_check().await.unwrap();
+    assert_eq!(state(hub.manager().server_info().await, "hung"), Some((ServerState::Unresponsive, 0)));
+    hub.manager().health_check().await.unwrap();
+    let infos = hub.manager().server_info().await;
+    assert_eq!(state(infos.clone(), "hung"), Some((ServerState::Running, 1)));
+    assert_eq!(state(infos, "slow"), Some((ServerState::Running, 0)));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_lazy_server_starts_on_first_request() {
+    let journal = Journal::default();
+    let config = ServerConfig {
+        name: "github".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }),
+        lazy: true,
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let state = |infos: Vec<ServerInfo>| infos.into_iter().find(|i| i.name == "github").map(|i| i.state);
+
+    assert_eq!(state(hub.manager().server_info().await), Some(ServerState::Idle));
+    assert_eq!(hub.manager().list_servers().await, ["github"]);
+    assert!(journal.messages().is_empty());
+
+    let mut client = hub.connect().await.unwrap();
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    assert_eq!(state(hub.manager().server_info().await), Some(ServerState::Running));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stopped_lazy_server_not_respawned() {
+    let journal = Journal::default();
+    let config = ServerConfig {
+        name: "github".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }),
+        lazy: true,
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let state = |infos: Vec<ServerInfo>| infos.into_iter().find(|i| i.name == "github").map(|i| i.state);
+    let mut client = hub.connect().await.unwrap();
+    let call = json!({ "name": "search_repositories", "arguments": {} });
+    client.request("github", "tools/call", call.clone()).await.unwrap();
+
+    // The hub refuses before the request has an id to echo
+    hub.manager().stop_server("github").await.unwrap();
+    let request = json!({ "jsonrpc": "2.0", "id": 9, "method": "tools/call", "params": { "server": "github", "name": "search_repositories", "arguments": {} } });
+    client.send(&request).await.unwrap();
+    let response = client.recv().await.unwrap();
+    assert!(response["error"]["message"].as_str().unwrap().contains("stopped"), "{}", response);
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "tools/call").count(), 1);
+    assert_eq!(state(hub.manager().server_info().await), Some(ServerState::Disabled));
+    assert!(hub.manager().list_servers().await.is_empty());
+
+    // Until it's started again
+    hub.manager().start_server("github").await.unwrap();
+    let response = client.request("github", "tools/call", call).await.unwrap();
+    assert!(response.get("result").is_some(), "{}", response);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_idle_server_stopped_and_respawned() {
+    let journal = Journal::default();
+    let config = ServerConfig {
+        name: "github".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }),
+        idle_timeout_secs: Some(1),
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    client.request("github", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    hub.manager().stop_idle_servers().await;
+    let info = hub.manager().server_info().await.into_iter().find(|i| i.name == "github").unwrap();
+    assert_eq!((info.running, info.state), (false, ServerState::Idle));
+
+    // The next request starts a new process and replays the client's handshake to it first
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    let methods: Vec<_> = journal.messages().iter().map(|m| m["method"].as_str().unwrap_or_default().to_string()).collect();
+    assert_eq!(methods, ["initialize", "initialize", "notifications/initialized", "tools/list"]);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dependencies_start_first_and_restart_dependents() {
+    let journal = Journal::default();
+    let db = ServerConfig {
+        name: "db".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..Default::default() }),
+        lazy: true,
+        ..Default::default()
+    };
+    let query = ServerConfig {
+        name: "query".to_string(),
+        mock: Some(github()),
+        depends_on: vec!["db".to_string()],
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(query).server(db).start().await.unwrap();
+
+    // The lazy dependency started, and answered a ping, for the server needing it
+    let running: Vec<_> = hub.manager().server_info().await.into_iter().filter(|i| i.running).map(|i| i.name).collect();
+    assert_eq!(running.len(), 2);
+    assert_eq!(journal.messages()[0]["method"], "ping");
+
+    let mut events = hub.manager().lifecycle_events();
+    hub.manager().restart_server("db").await.unwrap();
+    let restarted: Vec<_> = [events.recv().await.unwrap(), events.recv().await.unwrap()]
+        .iter()
+        .map(|e| serde_json::to_value(e).unwrap())
+        .collect();
+    assert_eq!(restarted[0], json!({ "event": "restarted", "server": "db", "reason": "requested" }));
+    assert_eq!(restarted[1], json!({ "event": "restarted", "server": "query", "reason": "dependency" }));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_handshake_replayed_after_restart() {
+    let journal = Journal::default();
+    let config = ServerConfig {
+        name: "github".to_string(),
+        mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }),
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": { "name": "editor", "version": "1" } });
+    let mut client = hub.connect().await.unwrap();
+    client.request("github", "initialize", initialize.clone()).await.unwrap();
+
+    // The new process gets the client's handshake from the hub before the session's next request
+    hub.manager().restart_server("github").await.unwrap();
+    let response = client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    let methods: Vec<_> = journal.messages().iter().map(|m| m["method"].as_str().unwrap_or_default().to_string()).collect();
+    assert_eq!(methods, ["initialize", "initialize", "notifications/initialized", "tools/list"]);
+    assert_eq!(journal.messages()[1]["params"], journal.messages()[0]["params"]);
+
+    // A new client's handshake is answered by the hub
+    let mut other = hub.connect().await.unwrap();
+    let response = other.request("github", "initialize", initialize).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "github");
+    assert_eq!(journal.messages().len(), 4);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_aggregated_tools() {
+    let slack = serde_json::from_value(json!({ "tools": [{ "name": "post_message", "inputSchema": { "type": "object" } }] })).unwrap();
+    let hub = TestHub::builder().mock("github", github()).mock("slack", slack).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    let response = client.request("citadel", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "mcp-citadel");
+
+    // Without a server, tools/list covers every backend
+    client.send(&json!({ "jsonrpc": "2.0", "id": 10, "method": "tools/list" })).await.unwrap();
+    let response = client.recv().await.unwrap();
+    let names: Vec<_> = response["result"]["tools"].as_array().unwrap().iter().map(|t| t["name"].clone()).collect();
+    assert_eq!(names, [json!("github__search_repositories"), json!("slack__post_message")]);
+
+    // A prefixed tool call reaches its backend under the tool's own name
+    client
+        .send(&json!({ "jsonrpc": "2.0", "id": 11, "method": "tools/call", "params": { "name": "slack__post_message", "arguments": { "text": "hi" } } }))
+        .await
+        .unwrap();
+    let response = client.recv().await.unwrap();
+    assert_eq!(response["id"], 11);
+    assert_eq!(response["result"]["content"][0]["text"], r#"{"text":"hi"}"#);
+    assert_eq!(hub.received_requests("slack", "tools/call")[0]["params"]["name"], "post_message");
+    assert!(hub.received_requests("github", "tools/call").is_empty());
+
+    let response = client.request("citadel", "tools/call", json!({ "name": "jira__create_issue" })).await.unwrap();
+    assert_eq!(response["error"]["code"], -32602);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_aggregated_prompts() {
+    let slack = serde_json::from_value(json!({
+        "prompts": [{ "name": "summarize", "description": "Summarize a channel", "messages": [{ "role": "user", "content": { "type": "text", "text": "Summarize #general" } }] }],
+    }))
+    .unwrap();
+    let hub = TestHub::builder().mock("github", github()).mock("slack", slack).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("citadel", "prompts/list", json!({})).await.unwrap();
+    let prompts = response["result"]["prompts"].as_array().unwrap();
+    assert_eq!(prompts.len(), 1);
+    assert_eq!(prompts[0]["name"], "slack__summarize");
+    assert_eq!(prompts[0]["description"], "Summarize a channel");
+
+    let response = client.request("citadel", "prompts/get", json!({ "name": "slack__summarize" })).await.unwrap();
+    assert_eq!(response["result"]["messages"][0]["content"]["text"], "Summarize #general");
+    assert_eq!(hub.received_requests("slack", "prompts/get")[0]["params"]["name"], "summarize");
+
+    let response = client.request("citadel", "prompts/get", json!({ "name": "github__summarize" })).await.unwrap();
+    assert_eq!(response["error"]["message"], "Unknown prompt: summarize");
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_list_results_cached_until_changed() {
+    let mut github = github();
+    github.responses = serde_json::from_value(json!([{
+        "method": "tools/call",
+        "name": "install",
+        "replies": [{ "result": { "content": [] }, "notifications": [{ "method": "notifications/tools/list_changed" }] }],
+    }]))
+    .unwrap();
+    let router = RouterConfig { list_cache_ttl_secs: [("tools/list".to_string(), 60)].into(), ..Default::default() };
+    let hub = TestHub::builder().mock("github", github).router(router).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    for _ in 0..3 {
+        let response = client.request("github", "tools/list", json!({})).await.unwrap();
+        assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    }
+    assert_eq!(hub.received_requests("github", "tools/list").len(), 1);
+    // Only methods with a TTL are cached
+    client.request("github", "prompts/list", json!({})).await.unwrap();
+    client.request("github", "prompts/list", json!({})).await.unwrap();
+    assert_eq!(hub.received_requests("github", "prompts/list").len(), 2);
+
+    // The server says its tools changed, so it's asked again
+    client.request("github", "tools/call", json!({ "name": "install", "arguments": {} })).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.request("github", "tools/list", json!({})).await.unwrap();
+    client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(hub.received_requests("github", "tools/list").len(), 2);
+
+    // And again after a restart
+    hub.manager().restart_server("github").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.request("github", "tools/list", json!({})).await.unwrap();
+    assert_eq!(hub.received_requests("github", "tools/list").len(), 3);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_servers_added_by_reload_cached() {
+    let router = RouterConfig { list_cache_ttl_secs: [("tools/list".to_string(), 60)].into(), ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).router(router).start().await.unwrap();
+    let journal = Journal::default();
+    let time = ServerConfig { name: "time".to_string(), mock: Some(MockConfig { journal: Some(journal.clone()), ..github() }), ..Default::default() };
+    hub.manager().reload(vec![time]).await.unwrap();
+
+    let mut client = hub.connect().await.unwrap();
+    for _ in 0..2 {
+        let response = client.request("time", "tools/list", json!({})).await.unwrap();
+        assert_eq!(response["result"]["tools"][0]["name"], "search_repositories");
+    }
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "tools/list").count(), 1);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_backend_notifications_forwarded() {
+    let github: MockConfig = serde_json::from_value(json!({
+        "responses": [{
+            "method": "tools/call",
+            "name": "build",
+            "replies": [{
+                "result": { "content": [] },
+                "delayMs": 100,
+                "notifications": [{ "method": "notifications/message", "params": { "level": "info", "data": "building" } }],
+            }],
+        }],
+    }))
+    .unwrap();
+    let hub = TestHub::builder().mock("github", github).mock("slack", MockConfig::default()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    client.request("github", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    let mut slack_client = hub.connect().await.unwrap();
+    slack_client.request("slack", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+
+    client
+        .send(&json!({ "jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": { "server": "github", "name": "build" } }))
+        .await
+        .unwrap();
+    let (first, second) = (client.recv().await.unwrap(), client.recv().await.unwrap());
+    let (notification, response) = if first.get("id").is_some() { (second, first) } else { (first, second) };
+    assert_eq!(response["id"], 5);
+    assert_eq!(notification["method"], "notifications/message");
+    assert_eq!(notification["params"]["data"], "building");
+
+    // Only clients of that server get it
+    let nothing = tokio::time::timeout(Duration::from_millis(100), slack_client.recv()).await;
+    assert!(nothing.is_err());
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_backend_requests_routed_to_client() {
+    let sampling = |server: &str| -> MockConfig {
+        serde_json::from_value(json!({
+            "responses": [{
+                "method": "tools/call",
+                "replies": [{
+                    "result": { "content": [] },
+                    "delayMs": 200,
+                    "requests": [{ "id": 42, "method": "sampling/createMessage", "params": { "messages": [], "maxTokens": 10, "server": server } }],
+                }],
+            }],
+        }))
+        .unwrap()
+    };
+    let hub = TestHub::builder().mock("github", sampling("github")).mock("slack", sampling("slack")).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": { "sampling": {} } });
+    client.request("github", "initialize", initialize).await.unwrap();
+
+    // The client gets the request under the hub's id while its call is in flight, and its answer reaches the backend
+    client.send(&json!({ "jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": { "server": "github", "name": "summarize" } })).await.unwrap();
+    let request = client.recv().await.unwrap();
+    assert_eq!(request["method"], "sampling/createMessage");
+    assert!(request["id"].as_str().unwrap().starts_with("citadel-"));
+    let answer = json!({ "jsonrpc": "2.0", "id": request["id"], "result": { "model": "m", "role": "assistant", "content": { "type": "text", "text": "ok" } } });
+    client.send(&answer).await.unwrap();
+    assert_eq!(client.recv().await.unwrap()["id"], 5);
+    let answered = hub.received("github").into_iter().find(|m| m.get("method").is_none() && m["id"] == 42).unwrap();
+    assert_eq!(answered["result"]["model"], "m");
+
+    // A client that can't sample isn't asked; the backend gets an error instead of waiting
+    let mut plain = hub.connect().await.unwrap();
+    plain.request("slack", "initialize", json!({ "protocolVersion": "2025-06-18", "capabilities": {} })).await.unwrap();
+    let response = plain.request("slack", "tools/call", json!({ "name": "summarize" })).await.unwrap();
+    assert!(response["result"].is_object());
+    let declined = hub.received("slack").into_iter().find(|m| m["id"] == 42).unwrap();
+    assert_eq!(declined["error"]["code"], -32601);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_progress_reaches_requesting_client() {
+    let github: MockConfig = serde_json::from_value(json!({
+        "responses": [{
+            "method": "tools/call",
+            "name": "build",
+            "replies": [{
+                "result": { "content": [] },
+                "delayMs": 100,
+                "notifications": [{ "method": "notifications/progress", "params": { "progress": 50, "total": 100 } }],
+            }],
+        }],
+    }))
+    .unwrap();
+    let hub = TestHub::builder().mock("github", github).start().await.unwrap();
+    let (mut a, mut b) = (hub.connect().await.unwrap(), hub.connect().await.unwrap());
+    a.request("github", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    b.request("github", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+
+    // Both pick the same token; each gets the progress of its own call only, under that token
+    let call = |id: u64| json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": { "server": "github", "name": "build", "_meta": { "progressToken": "t" } } });
+    a.send(&call(1)).await.unwrap();
+    b.send(&call(2)).await.unwrap();
+    for (client, id) in [(&mut a, 1), (&mut b, 2)] {
+        let progress = client.recv().await.unwrap();
+        assert_eq!(progress["method"], "notifications/progress");
+        assert_eq!(progress["params"], json!({ "progressToken": "t", "progress": 50, "total": 100 }));
+        assert_eq!(client.recv().await.unwrap()["id"], id);
+    }
+
+    // The backend saw a token of the hub's for each
+    let tokens: Vec<serde_json::Value> = hub
+        .received_requests("github", "tools/call")
+        .into_iter()
+        .map(|m| m["params"]["_meta"]["progressToken"].clone())
+        .collect();
+    assert_eq!(tokens.len(), 2);
+    assert_ne!(tokens[0], tokens[1]);
+    assert!(tokens.iter().all(|t| t.as_str().unwrap().starts_with("citadel-progress-")));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_per_session_instances() {
+    let journal = Journal::default();
+    let mut mock: MockConfig = serde_json::from_value(json!({
+        "responses": [{ "method": "tools/call", "replies": [{ "result": { "call": 1 } }, { "result": { "call": 2 } }] }],
+    }))
+    .unwrap();
+    mock.journal = Some(journal.clone());
+    let server = ServerConfig {
+        name: "notebook".to_string(),
+        mock: Some(mock),
+        isolation: Isolation::PerSession,
+        ..Default::default()
+    };
+    let hub = TestHub::builder().server(server).start().await.unwrap();
+    let pinned = |infos: Vec<ServerInfo>| infos.into_iter().find(|i| i.name == "notebook").map(|i| (i.pinned, i.running));
+    assert_eq!(pinned(hub.manager().server_info().await), Some((0, false)));
+
+    // Each session's process keeps its own state, and is initialized by its own client
+    let (mut a, mut b) = (hub.connect().await.unwrap(), hub.connect().await.unwrap());
+    let initialize = json!({ "protocolVersion": "2025-06-18" });
+    a.request("notebook", "initialize", initialize.clone()).await.unwrap();
+    b.request("notebook", "initialize", initialize).await.unwrap();
+    let call = json!({ "name": "cell" });
+    assert_eq!(a.request("notebook", "tools/call", call.clone()).await.unwrap()["result"]["call"], 1);
+    assert_eq!(b.request("notebook", "tools/call", call.clone()).await.unwrap()["result"]["call"], 1);
+    assert_eq!(a.request("notebook", "tools/call", call).await.unwrap()["result"]["call"], 2);
+    assert_eq!(journal.messages().iter().filter(|m| m["method"] == "initialize").count(), 2);
+    assert_eq!(pinned(hub.manager().server_info().await), Some((2, false)));
+
+    // A session's process stops when it disconnects
+    drop(a);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while pinned(hub.manager().server_info().await) != Some((1, false)) {
+        assert!(tokio::time::Instant::now() < deadline, "pinned process not stopped");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_remote_streamable_http_server() {
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use std::sync::{Arc, Mutex};
+
+    // A Streamable HTTP server that answers `initialize` with JSON and a
+    // session, and calls with an SSE stream, once it's given that session
+    type Seen = Arc<Mutex<Vec<(String, Option<String>)>>>;
+    let seen: Seen = Arc::default();
+    let session = |headers: &HeaderMap| headers.get("mcp-session-id").and_then(|v| v.to_str().ok()).map(String::from);
+    let post = {
+        let seen = Arc::clone(&seen);
+        move |headers: HeaderMap, body: String| async move {
+            let message: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let method = message["method"].as_str().unwrap_or_default().to_string();
+            seen.lock().unwrap().push((method.clone(), session(&headers)));
+            let id = message["id"].clone();
+            match method.as_str() {
+                "initialize" => {
+                    let result = json!({ "jsonrpc": "2.0", "id": id, "result": { "protocolVersion": "2025-06-18", "capabilities": { "tools": {} }, "serverInfo": { "name": "remote", "version": "1" } } });
+                    ([("mcp-session-id", "remote-1"), ("content-type", "application/json")], result.to_string()).into_response()
+                }
+                _ if id.is_null() => StatusCode::ACCEPTED.into_response(),
+                _ if session(&headers).as_deref() != Some("remote-1") => StatusCode::NOT_FOUND.into_response(),
+                _ => {
+                    let log = json!({ "jsonrpc": "2.0", "method": "notifications/message", "params": { "level": "info", "data": "working" } });
+                    let result = json!({ "jsonrpc": "2.0", "id": id, "result": { "content": [{ "type": "text", "text": "done" }] } });
+                    let body = format!("event: message\ndata: {}\n\nevent: message\ndata: {}\n\n", log, result);
+                    ([("content-type", "text/event-stream")], body).into_response()
+                }
+            }
+        }
+    };
+    let delete = {
+        let seen = Arc::clone(&seen);
+        move |headers: HeaderMap| async move {
+            seen.lock().unwrap().push(("DELETE".to_string(), session(&headers)));
+            StatusCode::OK
+        }
+    };
+    let app = axum::Router::new().route(
+        "/mcp",
+        axum::routing::post(post).get(|| async { StatusCode::METHOD_NOT_ALLOWED }).delete(delete),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let path = std::env::temp_dir().join(format!("mcp-citadel-remote-{}.json", std::process::id()));
+    std::fs::write(&path, json!({ "mcpServers": { "remote": { "url": url } } }).to_string()).unwrap();
+    let config = mcp_citadel::config::load_claude_config(&path).unwrap().remove(0);
+    let _ = std::fs::remove_file(&path);
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    let response = client.request("remote", "initialize", initialize).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "remote");
+    client.notify("remote", "notifications/initialized", json!({})).await.unwrap();
+    let response = client.request("remote", "tools/call", json!({ "name": "work" })).await.unwrap();
+    assert_eq!(response["result"]["content"][0]["text"], "done");
+
+    // Requests after initialize carry the session, which ends with the server
+    hub.stop().await.unwrap();
+    let seen = seen.lock().unwrap().clone();
+    let with_session = |method: &str| seen.iter().any(|(m, s)| m == method && s.as_deref() == Some("remote-1"));
+    assert!(seen.iter().any(|(m, s)| m == "initialize" && s.is_none()));
+    assert!(with_session("notifications/initialized") && with_session("tools/call") && with_session("DELETE"));
+}
+
+/// Requests a recording remote saw: the server name in the path, the client port, and `Authorization`
+type Seen = std::sync::Arc<std::sync::Mutex<Vec<(String, u16, Option<String>)>>>;
+
+/// A remote server answering every POST with JSON, recording the peer port
+/// (one per connection) and `Authorization` of each; requests without the
+/// `accepted` token get 401
+async fn recording_remote(accepted: Option<std::sync::Arc<std::sync::Mutex<String>>>) -> (String, Seen) {
+    use axum::extract::{ConnectInfo, Path};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use std::sync::Arc;
+
+    let seen: Seen = Arc::default();
+    let post = {
+        let seen = Arc::clone(&seen);
+        move |Path(name): Path<String>, ConnectInfo(peer): ConnectInfo<std::net::SocketAddr>, headers: HeaderMap, body: String| async move {
+            let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).map(String::from);
+            seen.lock().unwrap().push((name, peer.port(), auth.clone()));
+            if accepted.is_some_and(|token| auth.as_deref() != Some(token.lock().unwrap().as_str())) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+            let message: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let result = json!({ "jsonrpc": "2.0", "id": message["id"], "result": { "protocolVersion": "2025-06-18", "content": [] } });
+            ([("content-type", "application/json")], result.to_string()).into_response()
+        }
+    };
+    let app = axum::Router::new().route("/:name", axum::routing::post(post).get(|| async { StatusCode::METHOD_NOT_ALLOWED }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap() });
+    (url, seen)
+}
+
+/// Servers from a Claude config's `mcpServers`
+fn claude_servers(servers: serde_json::Value) -> Vec<ServerConfig> {
+    let path = std::env::temp_dir().join(format!("mcp-citadel-remote-{}-{}.json", std::process::id(), uuid::Uuid::new_v4()));
+    std::fs::write(&path, json!({ "mcpServers": servers }).to_string()).unwrap();
+    let configs = mcp_citadel::config::load_claude_config(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    configs
+}
+
+#[tokio::test]
+async fn test_remote_connections_pooled() {
+    let (url, seen) = recording_remote(None).await;
+    let servers = claude_servers(json!({
+        "pooled": { "url": format!("{}/pooled", url) },
+        "unpooled": { "url": format!("{}/unpooled", url), "poolMaxIdlePerHost": 0 },
+        "expiring": { "url": format!("{}/expiring", url), "poolIdleTimeoutSecs": 1 },
+    }));
+    // A hub each, as a hub of several servers routes calls by tool name
+    let mut hubs = Vec::new();
+    for server in servers {
+        let name = server.name.clone();
+        let hub = TestHub::builder().server(server).start().await.unwrap();
+        let mut client = hub.connect().await.unwrap();
+        client.request(&name, "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+        client.request(&name, "tools/call", json!({ "name": "work" })).await.unwrap();
+        hubs.push((name, hub, client));
+    }
+    let ports = |server: &str| seen.lock().unwrap().iter().filter(|(name, ..)| name == server).map(|(_, port, _)| *port).collect::<Vec<_>>();
+    let before = ports("expiring");
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    for (name, hub, mut client) in hubs {
+        client.request(&name, "tools/call", json!({ "name": "work" })).await.unwrap();
+        hub.stop().await.unwrap();
+    }
+
+    // Reused across requests and pauses, a connection per request, or reused until it idled out
+    let distinct = |ports: &[u16]| ports.iter().collect::<std::collections::HashSet<_>>().len();
+    let pooled = ports("pooled");
+    assert_eq!(distinct(&pooled), 1, "{:?}", pooled);
+    let unpooled = ports("unpooled");
+    assert_eq!(distinct(&unpooled), unpooled.len(), "{:?}", unpooled);
+    let expiring = ports("expiring");
+    assert_eq!(distinct(&before), 1, "{:?}", expiring);
+    assert!(!before.contains(expiring.last().unwrap()), "{:?}", expiring);
+}
+
+#[tokio::test]
+async fn test_remote_token_refreshed_on_401() {
+    let token = std::sync::Arc::new(std::sync::Mutex::new("Bearer one".to_string()));
+    let (url, seen) = recording_remote(Some(std::sync::Arc::clone(&token))).await;
+    let file = std::env::temp_dir().join(format!("mcp-citadel-token-{}", std::process::id()));
+    std::fs::write(&file, "one\n").unwrap();
+    let servers = claude_servers(json!({
+        "secured": { "url": format!("{}/secured", url), "authCommand": { "command": "cat", "args": [file] } },
+    }));
+    let hub = TestHub::builder().server(servers.into_iter().next().unwrap()).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+    let response = client.request("secured", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    assert!(response.get("result").is_some(), "{}", response);
+
+    // The token rotates: the next request is refused once, then sent with the new one
+    std::fs::write(&file, "two\n").unwrap();
+    *token.lock().unwrap() = "Bearer two".to_string();
+    let response = client.request("secured", "tools/call", json!({ "name": "work" })).await.unwrap();
+    assert!(response.get("result").is_some(), "{}", response);
+    hub.stop().await.unwrap();
+    let _ = std::fs::remove_file(&file);
+
+    let auths: Vec<Option<String>> = seen.lock().unwrap().iter().map(|(_, _, auth)| auth.clone()).collect();
+    let expected = ["Bearer one", "Bearer one", "Bearer two"].map(|auth| Some(auth.to_string()));
+    assert_eq!(auths, expected);
+}
+
+#[tokio::test]
+async fn test_legacy_sse_server() {
+    use axum::extract::{Query, State};
+    use axum::http::StatusCode;
+    use axum::response::sse::{Event, Sse};
+    use futures::StreamExt;
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::mpsc;
+
+    // An HTTP+SSE server: the stream names the endpoint, and carries the
+    // answers to what's POSTed there
+    type Stream = Arc<Mutex<Option<mpsc::UnboundedSender<serde_json::Value>>>>;
+    let stream: Stream = Arc::default();
+    let sse = |State(stream): State<Stream>| async move {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *stream.lock().unwrap() = Some(tx);
+        let endpoint = futures::stream::once(async { Ok::<_, Infallible>(Event::default().event("endpoint").data("/messages?session=abc")) });
+        let messages = tokio_stream::wrappers::UnboundedReceiverStream::new(rx).map(|m| Ok(Event::default().event("message").data(m.to_string())));
+        Sse::new(endpoint.chain(messages))
+    };
+    let message = |State(stream): State<Stream>, Query(query): Query<HashMap<String, String>>, body: String| async move {
+        if query.get("session").map(String::as_str) != Some("abc") {
+            return StatusCode::NOT_FOUND;
+        }
+        let message: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let result = match message["method"].as_str() {
+            Some("initialize") => json!({ "protocolVersion": "2024-11-05", "capabilities": {}, "serverInfo": { "name": "legacy", "version": "1" } }),
+            Some(_) if !message["id"].is_null() => json!({ "echo": message["params"] }),
+            _ => return StatusCode::ACCEPTED,
+        };
+        let tx = stream.lock().unwrap().clone().unwrap();
+        tx.send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result })).unwrap();
+        StatusCode::ACCEPTED
+    };
+    let app = axum::Router::new()
+        .route("/sse", axum::routing::get(sse))
+        .route("/messages", axum::routing::post(message))
+        .with_state(Arc::clone(&stream));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/sse", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let path = std::env::temp_dir().join(format!("mcp-citadel-legacy-{}.json", std::process::id()));
+    std::fs::write(&path, json!({ "mcpServers": { "legacy": { "type": "sse", "url": url } } }).to_string()).unwrap();
+    let config = mcp_citadel::config::load_claude_config(&path).unwrap().remove(0);
+    let _ = std::fs::remove_file(&path);
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("legacy", "initialize", json!({ "protocolVersion": "2024-11-05" })).await.unwrap();
+    assert_eq!(response["result"]["serverInfo"]["name"], "legacy");
+    client.notify("legacy", "notifications/initialized", json!({})).await.unwrap();
+    let response = client.request("legacy", "tools/call", json!({ "name": "echo" })).await.unwrap();
+    assert_eq!(response["result"]["echo"]["name"], "echo");
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_websocket_server_reconnects() {
+    use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+    use std::sync::{Arc, Mutex};
+
+    // A WebSocket server that hangs up after each `tools/call` it answers,
+    // and records the methods it gets by connection
+    type Seen = Arc<Mutex<Vec<Vec<String>>>>;
+    let seen: Seen = Arc::default();
+    async fn serve(mut socket: WebSocket, seen: Seen) {
+        let connection = {
+            let mut seen = seen.lock().unwrap();
+            seen.push(Vec::new());
+            seen.len() - 1
+        };
+        while let Some(Ok(Message::Text(text))) = socket.recv().await {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let method = message["method"].as_str().unwrap_or_default().to_string();
+            seen.lock().unwrap()[connection].push(method.clone());
+            let result = match method.as_str() {
+                "initialize" => json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "serverInfo": { "name": "ws", "version": "1" } }),
+                "tools/call" => json!({ "connection": connection }),
+                _ => continue,
+            };
+            let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": result });
+            socket.send(Message::Text(response.to_string())).await.unwrap();
+            if method == "tools/call" {
+                return;
+            }
+        }
+    }
+    let app = axum::Router::new().route(
+        "/",
+        axum::routing::get({
+            let seen = Arc::clone(&seen);
+            move |ws: WebSocketUpgrade| async move { ws.on_upgrade(move |socket| serve(socket, seen)) }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let path = std::env::temp_dir().join(format!("mcp-citadel-ws-{}.json", std::process::id()));
+    std::fs::write(&path, json!({ "mcpServers": { "ws": { "url": url } } }).to_string()).unwrap();
+    let config = mcp_citadel::config::load_claude_config(&path).unwrap().remove(0);
+    let _ = std::fs::remove_file(&path);
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    client.request("ws", "initialize", json!({ "protocolVersion": "2025-06-18" })).await.unwrap();
+    client.notify("ws", "notifications/initialized", json!({})).await.unwrap();
+    let response = client.request("ws", "tools/call", json!({ "name": "a" })).await.unwrap();
+    assert_eq!(response["result"]["connection"], 0);
+
+    // The next connection gets the handshake again before the client's call
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while seen.lock().unwrap().get(1).map_or(0, Vec::len) < 2 {
+        assert!(tokio::time::Instant::now() < deadline, "no reconnect");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let response = client.request("ws", "tools/call", json!({ "name": "b" })).await.unwrap();
+    assert_eq!(response["result"]["connection"], 1);
+    assert_eq!(seen.lock().unwrap()[1], ["initialize", "notifications/initialized", "tools/call"]);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tcp_server() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    // A daemon speaking JSON lines over TCP
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                    if message["id"].is_null() {
+                        continue;
+                    }
+                    let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": { "method": message["method"] } });
+                    writer.write_all(format!("{}\n", response).as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+
+    let path = std::env::temp_dir().join(format!("mcp-citadel-tcp-{}.json", std::process::id()));
+    std::fs::write(&path, json!({ "mcpServers": { "daemon": { "transport": "tcp", "port": port } } }).to_string()).unwrap();
+    let config = mcp_citadel::config::load_claude_config(&path).unwrap().remove(0);
+    let _ = std::fs::remove_file(&path);
+    let hub = TestHub::builder().server(config).start().await.unwrap();
+    let mut client = hub.connect().await.unwrap();
+
+    let response = client.request("daemon", "tools/list", json!({})).await.unwrap();
+    assert_eq!(response["result"]["method"], "tools/list");
+    let info = hub.manager().server_info().await;
+    assert_eq!(info[0].url.as_deref(), Some(format!("tcp://127.0.0.1:{}", port).as_str()));
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_inspector_rejects_foreign_origin() {
+    let http = HttpConfig { inspector: true, ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).http(http).start().await.unwrap();
+    let url = hub.http_url().unwrap();
+
+    for path in ["/ui/inspector", "/ui/inspector/api/servers"] {
+        let get = |origin: &'static str| reqwest::Client::new().get(format!("{}{}", url, path)).header("origin", origin).send();
+        assert_eq!(get("https://evil.example").await.unwrap().status(), reqwest::StatusCode::FORBIDDEN);
+        assert!(get("http://localhost").await.unwrap().status().is_success());
+    }
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_openapi_is_scoped_and_checks_origin() {
+    let team = WorkspaceConfig { servers: vec!["github".to_string()], api_keys: vec!["team-key".to_string()], ..Default::default() };
+    let workspaces = Workspaces::new(std::collections::BTreeMap::from([("team".to_string(), team)]));
+    let http = HttpConfig { rest_bridge: true, ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).mock("time", github()).workspaces(workspaces).http(http).start().await.unwrap();
+    let url = format!("{}/api/openapi.json", hub.http_url().unwrap());
+
+    let document: serde_json::Value = reqwest::Client::new().get(&url).bearer_auth("team-key").send().await.unwrap().json().await.unwrap();
+    let paths: Vec<&String> = document["paths"].as_object().unwrap().keys().collect();
+    assert_eq!(paths, ["/api/github/search_repositories"]);
+
+    let response = reqwest::Client::new().get(&url).bearer_auth("team-key").header("origin", "https://evil.example").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_readyz_counts_servers_waiting_to_start() {
+    let lazy = ServerConfig { name: "github".to_string(), lazy: true, mock: Some(github()), ..Default::default() };
+    let hub = TestHub::builder().server(lazy).http(HttpConfig::default()).start().await.unwrap();
+
+    let response = reqwest::get(format!("{}/readyz", hub.http_url().unwrap())).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert!(!hub.manager().server_info().await[0].running);
+    hub.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cloud_start_without_home_directory() {
+    let dir = std::env::temp_dir().join(format!("mcp-citadel-cloud-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    // Found in the data dir, not under a home directory
+    let claude_config = json!({ "mcpServers": { "github": { "command": "github-mcp", "lazy": true } } });
+    std::fs::write(dir.join("claude_desktop_config.json"), claude_config.to_string()).unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    let mut hub = tokio::process::Command::new(env!("CARGO_BIN_EXE_mcp-citadel"))
+        .args(["start", "--cloud", "--enable-http", "--http-host", "127.0.0.1", "--http-port", &port.to_string()])
+        .env_remove("HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("MCP_CITADEL_CONFIG")
+        .env("MCP_CITADEL_DATA_DIR", &dir)
+        .stdout(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let url = format!("http://127.0.0.1:{}/readyz", port);
+    let mut ready = false;
+    for _ in 0..100 {
+        if let Ok(Some(status)) = hub.try_wait() {
+            panic!("Hub exited with {}", status);
+        }
+        if reqwest::get(&url).await.is_ok_and(|r| r.status().is_success()) {
+            ready = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "/readyz never answered 200");
+
+    hub.kill().await.unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_expired_session_is_announced() {
+    let http = HttpConfig { session_timeout_secs: 1, ..Default::default() };
+    let hub = TestHub::builder().mock("github", github()).http(http).start().await.unwrap();
+    let url = hub.http_url().unwrap();
+    let mut events = reqwest::get(format!("{}{}", url, mcp_citadel::router::events::EVENTS_PATH)).await.unwrap();
+
+    let mut client = hub.http_client().unwrap();
+    let initialize = json!({ "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "1" } });
+    client.request("github", "initialize", initialize).await.unwrap();
+    let session = client.session().unwrap().to_string();
+    let mut stream = reqwest::Client::new()
+        .get(format!("{}/mcp", url))
+        .header("mcp-session-id", &session)
+        .header("accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+
+    // The client's stream gets a last event, then ends
+    let mut received = String::new();
+    while let Some(chunk) = tokio::time::timeout(Duration::from_secs(10), stream.chunk()).await.unwrap().unwrap() {
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(received.contains("event: session_expired"));
+    assert!(received.contains(&session));
+
+    let mut received = String::new();
+    while !received.contains("event: session_expired") {
+        let chunk = tokio::time::timeout(Duration::from_secs(5), events.chunk()).await.unwrap().unwrap().unwrap();
+        received.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    let expired = received.lines().find(|l| l.contains("\"session_expired\"")).unwrap();
+    let expired: serde_json::Value = serde_json::from_str(expired.trim_start_matches("data:").trim()).unwrap();
+    assert_eq!(expired["session"], session);
+
+    let response = client.request("github", "tools/list", json!({})).await;
+    assert!(response.is_err());
+    hub.stop().await.unwrap();
+}